@@ -0,0 +1,239 @@
+use std::{fmt, fs::{self, File, OpenOptions}, io::{self, Cursor, Write}, path::PathBuf, sync::{Arc, Mutex}, time::Duration};
+use bytes::Bytes;
+use tracing::{error, info, warn};
+use crate::frame::{self, Frame};
+use crate::snapshot::Snapshot;
+use crate::db::Value;
+use crate::rdb;
+
+//什么时候把aof刷到磁盘
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub(crate) enum FsyncPolicy {
+    //每条写命令都fsync，最安全也最慢
+    Always,
+    //后台任务每秒fsync一次，最多丢1秒的数据
+    EverySec,
+    //只write，什么时候落盘交给操作系统
+    No
+}
+
+//append only file，写命令在执行的同时以RESP格式追加到文件末尾
+#[derive(Debug)]
+pub(crate) struct Aof {
+    path:PathBuf,
+    fsync:FsyncPolicy,
+    state:Mutex<AofState>
+}
+
+#[derive(Debug,Default)]
+struct AofState {
+    //加载完成之前为None，这期间的写入不记录
+    file:Option<File>,
+    //重写期间新的写命令同时记一份，重写完成后追加到新文件末尾
+    rewrite_buf:Option<Vec<u8>>,
+    //上次fsync之后是否有新的写入
+    dirty:bool
+}
+
+impl FsyncPolicy {
+    pub(crate) fn parse(s:&str) -> crate::Result<Self> {
+        match &s.to_lowercase()[..] {
+            "always" => Ok(FsyncPolicy::Always),
+            "everysec" => Ok(FsyncPolicy::EverySec),
+            "no" => Ok(FsyncPolicy::No),
+            _ => Err(format!("invalid appendfsync '{}'",s).into())
+        }
+    }
+}
+
+impl fmt::Display for FsyncPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FsyncPolicy::Always => "always".fmt(f),
+            FsyncPolicy::EverySec => "everysec".fmt(f),
+            FsyncPolicy::No => "no".fmt(f)
+        }
+    }
+}
+
+impl Aof {
+    pub(crate) fn new(path:PathBuf,fsync:FsyncPolicy) -> Self {
+        Self { path, fsync, state: Mutex::new(AofState::default()) }
+    }
+    pub(crate) fn exists(&self) -> bool {
+        self.path.exists()
+    }
+    //读出文件里所有的命令，最后一条不完整(比如写到一半进程挂了)时丢掉它
+    pub(crate) fn load(&self) -> crate::Result<Vec<Frame>> {
+        let data = fs::read(&self.path)?;
+        let mut frames = Vec::new();
+        let mut buf = Cursor::new(&data[..]);
+        while (buf.position() as usize) < data.len() {
+            let start = buf.position();
+            match Frame::check(&mut buf) {
+                Ok(()) => {
+                    buf.set_position(start);
+                    frames.push(Frame::parse(&mut buf)?);
+                }
+                Err(frame::Error::Incomplete) => {
+                    warn!(offset=start,"append only file is truncated, ignoring the last incomplete command");
+                    break;
+                }
+                Err(err) => return Err(err.into())
+            }
+        }
+        Ok(frames)
+    }
+    //加载完成之后才打开文件开始记录
+    pub(crate) fn open(&self) -> io::Result<()> {
+        let file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.state.lock().unwrap().file = Some(file);
+        Ok(())
+    }
+    //在db锁内调用，保证文件里命令的顺序和执行顺序一致
+    pub(crate) fn append(&self,buf:&[u8]) {
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        let file = match state.file.as_mut() {
+            Some(file) => file,
+            None => return
+        };
+        if let Err(err) = file.write_all(buf) {
+            error!(cause=%err,"failed to write append only file");
+            return;
+        }
+        if self.fsync == FsyncPolicy::Always {
+            if let Err(err) = file.sync_data() {
+                error!(cause=%err,"failed to fsync append only file");
+            }
+        } else {
+            state.dirty = true;
+        }
+        if let Some(rewrite_buf) = state.rewrite_buf.as_mut() {
+            rewrite_buf.extend_from_slice(buf);
+        }
+    }
+    //fsync的时候不持有锁，避免卡住正在写入的命令
+    fn fsync(&self) -> io::Result<()> {
+        let file = {
+            let mut state = self.state.lock().unwrap();
+            if !state.dirty {
+                return Ok(());
+            }
+            state.dirty = false;
+            match state.file.as_ref() {
+                Some(file) => file.try_clone()?,
+                None => return Ok(())
+            }
+        };
+        file.sync_data()
+    }
+    //everysec模式下的后台fsync任务
+    pub(crate) fn spawn_fsync_task(self:&Arc<Self>) {
+        if self.fsync != FsyncPolicy::EverySec {
+            return;
+        }
+        let aof = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(1));
+            loop {
+                interval.tick().await;
+                let aof = match aof.upgrade() {
+                    Some(aof) => aof,
+                    None => break
+                };
+                let res = tokio::task::spawn_blocking(move || aof.fsync()).await;
+                if let Ok(Err(err)) = res {
+                    error!(cause=%err,"failed to fsync append only file");
+                }
+            }
+        });
+    }
+    //已经在重写或者还没打开文件时返回false，需要在db锁内调用，之后的写入都会进入rewrite_buf
+    pub(crate) fn start_rewrite(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state.file.is_none() || state.rewrite_buf.is_some() {
+            return false;
+        }
+        state.rewrite_buf = Some(Vec::new());
+        true
+    }
+    //用快照生成最小的命令集写到临时文件，再补上重写期间的写入，最后替换掉原来的文件
+    pub(crate) fn rewrite(self:&Arc<Self>,snapshot:Snapshot) {
+        let aof = self.clone();
+        tokio::task::spawn_blocking(move || {
+            let tmp = aof.path.with_file_name(format!("temp-rewriteaof-{}.aof",std::process::id()));
+            match aof.do_rewrite(&tmp, snapshot) {
+                Ok(()) => info!(path=?aof.path,"background append only file rewriting terminated with success"),
+                Err(err) => {
+                    error!(cause=%err,"background append only file rewriting error");
+                    let _ = fs::remove_file(&tmp);
+                    aof.state.lock().unwrap().rewrite_buf = None;
+                }
+            }
+        });
+    }
+    fn do_rewrite(&self,tmp:&PathBuf,snapshot:Snapshot) -> crate::Result<()> {
+        let mut buf = Vec::new();
+        if !snapshot.functions.is_empty() {
+            Frame::Array(vec![
+                Frame::Bulk(Bytes::from_static(b"FUNCTION")),
+                Frame::Bulk(Bytes::from_static(b"RESTORE")),
+                Frame::Bulk(snapshot.functions),
+                Frame::Bulk(Bytes::from_static(b"REPLACE")),
+            ]).encode(&mut buf);
+        }
+        for (key,value,expire_at) in &snapshot.entries {
+            value_command(key, value, *expire_at).encode(&mut buf);
+        }
+        let mut file = File::create(tmp)?;
+        file.write_all(&buf)?;
+        file.sync_data()?;
+        //补上重写期间的写入并切换文件，这段时间持有锁，新的写入会等切换完成后写进新文件
+        let mut state = self.state.lock().unwrap();
+        let rewrite_buf = state.rewrite_buf.take().unwrap_or_default();
+        let mut file = OpenOptions::new().append(true).open(tmp)?;
+        file.write_all(&rewrite_buf)?;
+        file.sync_data()?;
+        fs::rename(tmp, &self.path)?;
+        state.file = Some(file);
+        state.dirty = false;
+        Ok(())
+    }
+}
+
+//SET key value [PXAT ms]，过期时间用绝对时间，重放时才不会变长
+pub(crate) fn set_command(key:&str,value:&Bytes,expire_at:Option<u64>) -> Frame {
+    let mut v = vec![
+        Frame::Bulk(Bytes::from_static(b"SET")),
+        Frame::Bulk(Bytes::copy_from_slice(key.as_bytes())),
+        Frame::Bulk(value.clone()),
+    ];
+    if let Some(ms) = expire_at {
+        v.push(Frame::Bulk(Bytes::from_static(b"PXAT")));
+        v.push(Frame::Bulk(ms.to_string().into()));
+    }
+    Frame::Array(v)
+}
+
+//字符串用SET，其它类型没有对应的写命令，用RESTORE key 过期时间 payload ABSTTL REPLACE
+pub(crate) fn value_command(key:&str,value:&Value,expire_at:Option<u64>) -> Frame {
+    match value {
+        Value::String(data) => set_command(key, data, expire_at),
+        value => Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(b"RESTORE")),
+            Frame::Bulk(Bytes::copy_from_slice(key.as_bytes())),
+            Frame::Bulk(expire_at.unwrap_or(0).to_string().into()),
+            Frame::Bulk(rdb::dump_value(value)),
+            Frame::Bulk(Bytes::from_static(b"ABSTTL")),
+            Frame::Bulk(Bytes::from_static(b"REPLACE")),
+        ])
+    }
+}
+
+pub(crate) fn del_command(key:&str) -> Frame {
+    Frame::Array(vec![
+        Frame::Bulk(Bytes::from_static(b"DEL")),
+        Frame::Bulk(Bytes::copy_from_slice(key.as_bytes())),
+    ])
+}
@@ -1,15 +1,592 @@
-use my_redis::DEFAULT_PORT;
-use std::str;
-use tokio::{net::TcpSocket, io::{AsyncReadExt, AsyncWriteExt}};
+use std::{collections::{BTreeMap, HashMap}, ffi::OsString, io::{self, Write}, path::PathBuf, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use bytes::{Bytes, BytesMut};
+use my_redis::{DEFAULT_PORT, client::Client, frame::Frame};
+use rustyline::{Context, Editor, Helper, completion::Completer, error::ReadlineError, highlight::Highlighter, hint::Hinter, history::DefaultHistory, validate::Validator};
+use structopt::{StructOpt, clap::AppSettings};
+use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::TcpStream, signal, time::{self, Instant}};
+
+//服务端支持的命令，用来补全
+const COMMANDS:&[&str] = &[
+    "ASKING", "BGREWRITEAOF", "BGSAVE", "CLUSTER", "CONFIG", "DEL", "DISCARD", "DUMP", "EVAL", "EVALSHA",
+    "EXEC", "FCALL", "FCALL_RO", "FUNCTION", "GET", "INFO", "LASTSAVE", "MIGRATE", "MULTI", "OBJECT",
+    "PING", "PSUBSCRIBE", "PSYNC", "PUBLISH", "PUNSUBSCRIBE", "REPLCONF", "REPLICAOF", "RESTORE", "SAVE",
+    "SCAN", "SCRIPT", "SET", "SLAVEOF", "SUBSCRIBE", "UNSUBSCRIBE", "UNWATCH", "WAIT", "WATCH", "TYPE",
+    "STRLEN", "LLEN", "HLEN", "SCARD", "ZCARD", "QUIT", "EXIT"
+];
+//--bigkeys里每种类型的大小用什么命令和单位
+const TYPES:&[(&str,&str,&str)] = &[
+    ("string", "STRLEN", "bytes"),
+    ("list", "LLEN", "items"),
+    ("hash", "HLEN", "fields"),
+    ("set", "SCARD", "members"),
+    ("zset", "ZCARD", "members")
+];
+//--latency直方图的上界，单位毫秒
+const LATENCY_BUCKETS:&[f64] = &[0.1, 0.25, 0.5, 1.0, 2.0, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0];
+
+//和redis-cli一样，带命令参数时执行一条命令后退出，否则进入交互模式
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> my_redis::Result<()> {
-    tracing_subscriber::fmt::try_init()?;
-    let addr = format!("{}:{}","127.0.0.1",DEFAULT_PORT).parse().unwrap();
-    let socket = TcpSocket::new_v4()?;
-    let mut  stream = socket.connect(addr).await?;
-    let mut buf = [0;10];
-    stream.write(b"fuck you!").await?;
-    stream.read(&mut buf[..]).await?;
-    println!("{:?}",str::from_utf8(&buf[..]));
+    let cli = Cli::from_args();
+    let port = cli.port.as_deref().unwrap_or(DEFAULT_PORT);
+    let addr = format!("{}:{}",cli.host,port);
+    //--pipe直接在TCP连接上转发原始数据，不经过Client
+    if cli.pipe {
+        return pipe(&addr).await;
+    }
+    let mut client = Client::new(addr.as_str()).await?;
+    //服务端不支持AUTH/SELECT时会回复错误，打印出来继续
+    if let Some(password) = &cli.password {
+        if let Err(err) = client.cmd("AUTH").arg(password.as_str()).query::<()>().await {
+            eprintln!("AUTH failed: {}",err);
+        }
+    }
+    if cli.db != 0 {
+        if let Err(err) = client.cmd("SELECT").arg(cli.db).query::<()>().await {
+            eprintln!("SELECT failed: {}",err);
+        }
+    }
+    let interval = Duration::from_secs_f64(cli.interval);
+    if cli.scan {
+        return scan(&mut client, cli.pattern.as_deref(), cli.count, |key| println!("{}",key)).await;
+    }
+    if cli.bigkeys {
+        return bigkeys(&mut client, cli.pattern.as_deref(), cli.count).await;
+    }
+    if cli.stat {
+        return stat(&mut client, interval).await;
+    }
+    if cli.latency {
+        return latency(&mut client, interval, cli.samples).await;
+    }
+    if !cli.command.is_empty() {
+        let args = cli.command.iter().map(|arg| Bytes::from(arg.to_string_lossy().into_owned())).collect();
+        return execute(client, args, cli.raw).await;
+    }
+    let prompt = if cli.db != 0 { format!("{}[{}]> ",addr,cli.db) } else { format!("{}> ",addr) };
+    repl(client, &prompt, cli.raw).await
+}
+
+#[derive(Debug,StructOpt)]
+#[structopt(name="my-redis-cli",global_settings=&[AppSettings::TrailingVarArg])]
+struct Cli {
+    #[structopt(name="hostname",long="--host",default_value="127.0.0.1")]
+    host:String,
+    #[structopt(name="port",long="--port")]
+    port:Option<String>,
+    //连接之后SELECT的数据库
+    #[structopt(name="db",long="--db",default_value="0")]
+    db:u64,
+    //连接之后发送AUTH
+    #[structopt(name="password",long="--password")]
+    password:Option<String>,
+    //直接输出回复的内容，不加引号和类型
+    #[structopt(name="raw",long="--raw")]
+    raw:bool,
+    //从标准输入读取RESP格式的命令批量发送，结束时输出回复和错误的数量
+    #[structopt(name="pipe",long="--pipe")]
+    pipe:bool,
+    //用SCAN列出所有key
+    #[structopt(name="scan",long="--scan")]
+    scan:bool,
+    //--scan和--bigkeys只看匹配的key
+    #[structopt(name="pattern",long="--pattern")]
+    pattern:Option<String>,
+    //--scan和--bigkeys每次SCAN的COUNT
+    #[structopt(name="count",long="--count")]
+    count:Option<u64>,
+    //找出每种类型里最大的key，并统计各类型的数量和平均大小
+    #[structopt(name="bigkeys",long="--bigkeys")]
+    bigkeys:bool,
+    //每隔--interval秒输出一次key数量、客户端数量和请求数
+    #[structopt(name="stat",long="--stat")]
+    stat:bool,
+    //一直PING，每隔--interval秒刷新最小/最大/平均延迟，Ctrl-C或者达到--samples之后输出直方图
+    #[structopt(name="latency",long="--latency")]
+    latency:bool,
+    #[structopt(name="samples",long="--samples")]
+    samples:Option<u64>,
+    //--stat和--latency的刷新间隔，单位秒
+    #[structopt(name="interval",long="--interval",default_value="1")]
+    interval:f64,
+    //要执行的命令，为空时进入交互模式，不是UTF-8的参数按lossy转换
+    #[structopt(name="command",parse(from_os_str))]
+    command:Vec<OsString>
+}
+
+//命令名补全，只补全第一个词
+struct CliHelper;
+
+impl Completer for CliHelper {
+    type Candidate = String;
+
+    fn complete(&self,line:&str,pos:usize,_ctx:&Context<'_>) -> rustyline::Result<(usize,Vec<String>)> {
+        let prefix = line[..pos].trim_start();
+        if prefix.contains(char::is_whitespace) {
+            return Ok((pos, Vec::new()));
+        }
+        let lower = prefix.chars().any(|c| c.is_ascii_lowercase());
+        let candidates = COMMANDS.iter()
+            .filter(|cmd| cmd.starts_with(&prefix.to_ascii_uppercase()))
+            .map(|cmd| if lower { cmd.to_ascii_lowercase() } else { cmd.to_string() })
+            .collect();
+        Ok((pos - prefix.len(), candidates))
+    }
+}
+
+impl Hinter for CliHelper {
+    type Hint = String;
+}
+
+impl Highlighter for CliHelper {}
+
+impl Validator for CliHelper {}
+
+impl Helper for CliHelper {}
+
+async fn repl(mut client:Client,prompt:&str,raw:bool) -> my_redis::Result<()> {
+    let mut editor:Editor<CliHelper,DefaultHistory> = Editor::new()?;
+    editor.set_helper(Some(CliHelper));
+    let history = history_path();
+    if let Some(path) = &history {
+        //第一次运行时文件不存在
+        let _ = editor.load_history(path);
+    }
+    loop {
+        let line = match editor.readline(prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(err.into())
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        editor.add_history_entry(line)?;
+        let args = match split_args(line) {
+            Ok(args) => args,
+            Err(err) => {
+                println!("Invalid argument(s): {}",err);
+                continue;
+            }
+        };
+        let name = String::from_utf8_lossy(&args[0]).to_ascii_lowercase();
+        match name.as_str() {
+            "quit" | "exit" => break,
+            //订阅之后连接只能收消息，一直打印到连接关闭
+            "subscribe" | "psubscribe" => {
+                if let Some(path) = &history {
+                    let _ = editor.save_history(path);
+                }
+                return execute(client, args, raw).await;
+            }
+            _ => {
+                if let Err(err) = execute_one(&mut client, args, raw).await {
+                    println!("{}",err);
+                }
+            }
+        }
+    }
+    if let Some(path) = &history {
+        editor.save_history(path)?;
+    }
+    Ok(())
+}
+
+//执行一条命令并打印回复，SUBSCRIBE/PSUBSCRIBE会一直打印收到的消息
+async fn execute(mut client:Client,args:Vec<Bytes>,raw:bool) -> my_redis::Result<()> {
+    let name = String::from_utf8_lossy(&args[0]).to_ascii_lowercase();
+    let targets = args[1..].iter().map(|arg| String::from_utf8_lossy(arg).into_owned()).collect();
+    let mut subscriber = match name.as_str() {
+        "subscribe" => client.subscribe(targets).await?,
+        "psubscribe" => client.psubscribe(targets).await?,
+        _ => return execute_one(&mut client, args, raw).await
+    };
+    println!("Reading messages... (press Ctrl-C to quit)");
+    while let Some(message) = subscriber.next_message().await? {
+        let mut reply = vec![];
+        match message.pattern {
+            Some(pattern) => reply.extend([bulk("pmessage"), bulk(&pattern)]),
+            None => reply.push(bulk("message"))
+        }
+        reply.extend([bulk(&message.channel), Frame::Bulk(message.content)]);
+        println!("{}", format_reply(&Frame::Array(reply), 0, raw));
+    }
+    Ok(())
+}
+
+//错误回复照常打印，只有连接出错时返回Err
+async fn execute_one(client:&mut Client,args:Vec<Bytes>,raw:bool) -> my_redis::Result<()> {
+    let name = String::from_utf8_lossy(&args[0]).into_owned();
+    match client.cmd(&name).arg(&args[1..]).query::<Frame>().await {
+        Ok(frame) => println!("{}", format_reply(&frame, 0, raw)),
+        Err(err) if err.downcast_ref::<io::Error>().is_some() => return Err(format!("Could not connect: {}",err).into()),
+        Err(err) => println!("{}", format_reply(&Frame::Error(err.to_string()), 0, raw))
+    }
+    Ok(())
+}
+
+fn bulk(s:&str) -> Frame {
+    Frame::Bulk(Bytes::copy_from_slice(s.as_bytes()))
+}
+
+fn history_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".my_redis_cli_history"))
+}
+
+//按redis-cli的规则拆分参数：空白分隔，双引号里支持\n \t \xHH等转义，单引号里只转义\'
+fn split_args(line:&str) -> Result<Vec<Bytes>,String> {
+    let mut args = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let first = match chars.peek() {
+            Some(&c) => c,
+            None => return Ok(args)
+        };
+        let mut arg:Vec<u8> = Vec::new();
+        let mut buf = [0; 4];
+        match first {
+            '"' => {
+                chars.next();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('x') => {
+                                let hex:String = chars.by_ref().take(2).collect();
+                                match u8::from_str_radix(&hex, 16) {
+                                    Ok(byte) if hex.len() == 2 => arg.push(byte),
+                                    _ => {
+                                        arg.push(b'x');
+                                        arg.extend_from_slice(hex.as_bytes());
+                                    }
+                                }
+                            }
+                            Some('n') => arg.push(b'\n'),
+                            Some('r') => arg.push(b'\r'),
+                            Some('t') => arg.push(b'\t'),
+                            Some('b') => arg.push(8),
+                            Some('a') => arg.push(7),
+                            Some(c) => arg.extend_from_slice(c.encode_utf8(&mut buf).as_bytes()),
+                            None => return Err("unbalanced quotes".to_string())
+                        },
+                        Some(c) => arg.extend_from_slice(c.encode_utf8(&mut buf).as_bytes()),
+                        None => return Err("unbalanced quotes".to_string())
+                    }
+                }
+            }
+            '\'' => {
+                chars.next();
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some('\\') if chars.peek() == Some(&'\'') => {
+                            chars.next();
+                            arg.push(b'\'');
+                        }
+                        Some(c) => arg.extend_from_slice(c.encode_utf8(&mut buf).as_bytes()),
+                        None => return Err("unbalanced quotes".to_string())
+                    }
+                }
+            }
+            _ => {
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                    arg.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                }
+            }
+        }
+        //引号后面必须是空白或者结尾，"foo"bar这种不合法
+        if first == '"' || first == '\'' {
+            if let Some(c) = chars.peek() {
+                if !c.is_whitespace() {
+                    return Err("closing quote must be followed by a space".to_string());
+                }
+            }
+        }
+        args.push(arg.into());
+    }
+}
+
+//和redis-cli一样的格式，数组带编号，嵌套的数组缩进对齐
+fn format_reply(frame:&Frame,indent:usize,raw:bool) -> String {
+    match frame {
+        Frame::Simple(s) => s.clone(),
+        Frame::Error(msg) if raw => msg.clone(),
+        Frame::Error(msg) => format!("(error) {}",msg),
+        Frame::Integer(n) if raw => n.to_string(),
+        Frame::Integer(n) => format!("(integer) {}",n),
+        Frame::Bulk(data) if raw => String::from_utf8_lossy(data).into_owned(),
+        Frame::Bulk(data) => quote(data),
+        Frame::Null | Frame::NullArray if raw => String::new(),
+        Frame::Null | Frame::NullArray => "(nil)".to_string(),
+        Frame::Array(items) if raw => items.iter().map(|item| format_reply(item, 0, raw)).collect::<Vec<_>>().join("\n"),
+        Frame::Array(items) if items.is_empty() => "(empty array)".to_string(),
+        Frame::Array(items) => {
+            let width = items.len().to_string().len();
+            let mut out = String::new();
+            for (i,item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push('\n');
+                    out.push_str(&" ".repeat(indent));
+                }
+                let prefix = format!("{:>width$}) ", i + 1, width = width);
+                out.push_str(&prefix);
+                out.push_str(&format_reply(item, indent + prefix.len(), raw));
+            }
+            out
+        }
+    }
+}
+
+//加上双引号，不可打印的字节转义
+fn quote(data:&[u8]) -> String {
+    let mut out = String::with_capacity(data.len() + 2);
+    out.push('"');
+    for &byte in data {
+        match byte {
+            b'\\' => out.push_str("\\\\"),
+            b'"' => out.push_str("\\\""),
+            b'\n' => out.push_str("\\n"),
+            b'\r' => out.push_str("\\r"),
+            b'\t' => out.push_str("\\t"),
+            7 => out.push_str("\\a"),
+            8 => out.push_str("\\b"),
+            0x20..=0x7e => out.push(byte as char),
+            _ => out.push_str(&format!("\\x{:02x}",byte))
+        }
+    }
+    out.push('"');
+    out
+}
+
+//读取标准输入原样发给服务端，同时读取回复，最后发一个带随机内容的PING，收到它的回复说明前面的命令都执行完了
+async fn pipe(addr:&str) -> my_redis::Result<()> {
+    let (mut reader,mut writer) = TcpStream::connect(addr).await?.into_split();
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos();
+    let marker = format!("{:020x}",nanos ^ ((std::process::id() as u128) << 64));
+    let marker = &marker[marker.len() - 20..];
+    let marker_cmd = format!("*2\r\n$4\r\nPING\r\n$20\r\n{}\r\n",marker);
+    let marker_reply = format!("$20\r\n{}\r\n",marker);
+    let sender = tokio::spawn(async move {
+        let mut stdin = tokio::io::stdin();
+        tokio::io::copy(&mut stdin, &mut writer).await?;
+        eprintln!("All data transferred. Waiting for the last reply...");
+        writer.write_all(marker_cmd.as_bytes()).await?;
+        writer.flush().await?;
+        //写端留到回复读完再关
+        Ok::<_,io::Error>(writer)
+    });
+    let mut buf = BytesMut::with_capacity(64 * 1024);
+    let (mut replies,mut errors) = (0u64,0u64);
+    'read: loop {
+        while let Some(len) = reply_len(&buf)? {
+            let reply = buf.split_to(len);
+            if reply[..] == *marker_reply.as_bytes() {
+                break 'read;
+            }
+            replies += 1;
+            if reply[0] == b'-' {
+                errors += 1;
+                eprintln!("{}",String::from_utf8_lossy(&reply[1..len - 2]));
+            }
+        }
+        if reader.read_buf(&mut buf).await? == 0 {
+            return Err("connection closed before the last reply".into());
+        }
+    }
+    sender.await??;
+    println!("Last reply received from server.");
+    println!("errors: {}, replies: {}",errors,replies);
+    if errors > 0 {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+//buf开头一个完整回复的长度，数据还不完整时返回None
+fn reply_len(buf:&[u8]) -> my_redis::Result<Option<usize>> {
+    let end = match buf.windows(2).position(|w| w == b"\r\n") {
+        Some(end) => end,
+        None => return Ok(None)
+    };
+    let header = end + 2;
+    let number = || -> my_redis::Result<i64> {
+        Ok(std::str::from_utf8(&buf[1..end])?.parse()?)
+    };
+    match buf[0] {
+        b'+' | b'-' | b':' => Ok(Some(header)),
+        b'$' => match number()? {
+            len if len < 0 => Ok(Some(header)),
+            len => {
+                let total = header + len as usize + 2;
+                Ok((buf.len() >= total).then_some(total))
+            }
+        },
+        b'*' => {
+            let mut total = header;
+            for _ in 0..number()?.max(0) {
+                match reply_len(&buf[total..])? {
+                    Some(len) => total += len,
+                    None => return Ok(None)
+                }
+            }
+            Ok(Some(total))
+        }
+        byte => Err(format!("protocol error: unexpected byte '{}'",byte as char).into())
+    }
+}
+
+//SCAN直到cursor回到0，每个key调用一次f
+async fn scan(client:&mut Client,pattern:Option<&str>,count:Option<u64>,mut f:impl FnMut(String)) -> my_redis::Result<()> {
+    let mut cursor = 0u64;
+    loop {
+        let (next,keys):(u64,Vec<String>) = client.cmd("SCAN").arg(cursor)
+            .arg(pattern.map(|pattern| ("MATCH", pattern)))
+            .arg(count.map(|count| ("COUNT", count)))
+            .query().await?;
+        keys.into_iter().for_each(&mut f);
+        if next == 0 {
+            return Ok(());
+        }
+        cursor = next;
+    }
+}
+
+#[derive(Default)]
+struct TypeStat {
+    count:u64,
+    total:u64,
+    biggest:Option<(String,u64)>
+}
+
+//和redis-cli --bigkeys一样的输出
+async fn bigkeys(client:&mut Client,pattern:Option<&str>,count:Option<u64>) -> my_redis::Result<()> {
+    let total_keys = info(client, "keyspace").await?.get("db0")
+        .and_then(|db| db.split(',').find_map(|field| field.strip_prefix("keys=")))
+        .and_then(|keys| keys.parse::<u64>().ok())
+        .unwrap_or(0);
+    println!();
+    println!("# Scanning the entire keyspace to find biggest keys as well as");
+    println!("# average sizes per key type.");
+    println!();
+    let mut keys = Vec::new();
+    scan(client, pattern, count, |key| keys.push(key)).await?;
+    let mut stats:BTreeMap<&str,TypeStat> = BTreeMap::new();
+    let (mut sampled,mut key_bytes) = (0u64,0u64);
+    for key in keys {
+        let kind:String = client.cmd("TYPE").arg(&key).query().await?;
+        //扫描期间被删掉或者过期了
+        let (name,command,unit) = match TYPES.iter().find(|(name,_,_)| *name == kind) {
+            Some(t) => *t,
+            None => continue
+        };
+        let size:u64 = client.cmd(command).arg(&key).query().await?;
+        sampled += 1;
+        key_bytes += key.len() as u64;
+        let stat = stats.entry(name).or_default();
+        stat.count += 1;
+        stat.total += size;
+        if stat.biggest.as_ref().map(|(_,biggest)| size > *biggest).unwrap_or(true) {
+            let percent = if total_keys > 0 { sampled as f64 * 100.0 / total_keys as f64 } else { 100.0 };
+            println!("[{:05.2}%] Biggest {:<6} found so far '{}' with {} {}",percent.min(100.0),name,quote(key.as_bytes()),size,unit);
+            stat.biggest = Some((key,size));
+        }
+    }
+    println!();
+    println!("-------- summary -------");
+    println!();
+    println!("Sampled {} keys in the keyspace!",sampled);
+    println!("Total key length in bytes is {} (avg len {:.2})",key_bytes,if sampled > 0 { key_bytes as f64 / sampled as f64 } else { 0.0 });
+    println!();
+    for (name,_,unit) in TYPES {
+        if let Some(TypeStat { biggest: Some((key,size)), .. }) = stats.get(name) {
+            println!("Biggest {:>6} found '{}' has {} {}",name,quote(key.as_bytes()),size,unit);
+        }
+    }
+    println!();
+    for (name,_,unit) in TYPES {
+        let stat = stats.remove(name).unwrap_or_default();
+        let percent = if sampled > 0 { stat.count as f64 * 100.0 / sampled as f64 } else { 0.0 };
+        let avg = if stat.count > 0 { stat.total as f64 / stat.count as f64 } else { 0.0 };
+        println!("{} {}s with {} {} ({:05.2}% of keys, avg size {:.2})",stat.count,name,stat.total,unit,percent,avg);
+    }
+    Ok(())
+}
+
+//每20行重复一次表头，requests后面是和上一次相比的增量
+async fn stat(client:&mut Client,interval:Duration) -> my_redis::Result<()> {
+    let mut last_requests:Option<u64> = None;
+    for line in 0u64.. {
+        if line % 20 == 0 {
+            println!("------- data ------ --------------------- load --------------------");
+            println!("keys       clients  requests            connections");
+        }
+        let info = info(client, "all").await?;
+        let field = |name:&str| info.get(name).and_then(|v| v.parse::<u64>().ok()).unwrap_or(0);
+        let keys = info.get("db0")
+            .and_then(|db| db.split(',').find_map(|field| field.strip_prefix("keys=")))
+            .unwrap_or("0");
+        //INFO本身也算一次请求
+        let requests = field("total_commands_processed");
+        let delta = last_requests.map(|last| format!("(+{})",requests.saturating_sub(last))).unwrap_or_default();
+        last_requests = Some(requests);
+        println!("{:<10} {:<8} {:<19} {}",keys,field("connected_clients"),format!("{} {}",requests,delta),field("total_connections_received"));
+        time::sleep(interval).await;
+    }
+    Ok(())
+}
+
+//INFO的key:value行，#开头的是分组标题
+async fn info(client:&mut Client,section:&str) -> my_redis::Result<HashMap<String,String>> {
+    let text:String = client.cmd("INFO").arg(section).query().await?;
+    Ok(text.lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.split_once(':'))
+        .map(|(k,v)| (k.to_string(),v.trim().to_string()))
+        .collect())
+}
+
+//每10毫秒PING一次
+async fn latency(client:&mut Client,interval:Duration,samples:Option<u64>) -> my_redis::Result<()> {
+    let mut buckets = vec![0u64; LATENCY_BUCKETS.len() + 1];
+    let (mut min,mut max,mut sum,mut count) = (f64::MAX,0f64,0f64,0u64);
+    let mut next_report = Instant::now() + interval;
+    let ctrl_c = signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+    while samples.map(|samples| count < samples).unwrap_or(true) {
+        let start = Instant::now();
+        tokio::select! {
+            res = client.ping(None) => { res?; }
+            _ = &mut ctrl_c => break
+        }
+        let ms = start.elapsed().as_secs_f64() * 1000.0;
+        min = min.min(ms);
+        max = max.max(ms);
+        sum += ms;
+        count += 1;
+        buckets[LATENCY_BUCKETS.iter().position(|&bound| ms <= bound).unwrap_or(LATENCY_BUCKETS.len())] += 1;
+        if Instant::now() >= next_report {
+            print!("\rmin: {:.3}, max: {:.3}, avg: {:.3} ({} samples)",min,max,sum / count as f64,count);
+            io::stdout().flush()?;
+            next_report += interval;
+        }
+        time::sleep(Duration::from_millis(10)).await;
+    }
+    if count == 0 {
+        return Ok(());
+    }
+    println!("\rmin: {:.3}, max: {:.3}, avg: {:.3} ({} samples)",min,max,sum / count as f64,count);
+    println!();
+    let widest = *buckets.iter().max().unwrap();
+    for (i,&n) in buckets.iter().enumerate() {
+        let label = match LATENCY_BUCKETS.get(i) {
+            Some(bound) => format!("<= {:.2} ms",bound),
+            None => format!(" > {:.2} ms",LATENCY_BUCKETS[i - 1])
+        };
+        let bar = "#".repeat((n * 40).div_ceil(widest) as usize);
+        println!("{:>14}: {:>8} ({:6.2}%) {}",label,n,n as f64 * 100.0 / count as f64,bar);
+    }
     Ok(())
 }
@@ -0,0 +1,37 @@
+use std::{fs, path::PathBuf};
+
+use structopt::StructOpt;
+
+//redis的rdb文件和my-redis快照文件之间互相转换
+pub fn main() -> my_redis::Result<()> {
+    match Cli::from_args() {
+        Cli::Import { rdb, snapshot } => {
+            let data = my_redis::rdb::import(&fs::read(rdb)?)?;
+            fs::write(snapshot, data)?;
+        }
+        Cli::Export { snapshot, rdb } => {
+            let data = my_redis::rdb::export(&fs::read(snapshot)?)?;
+            fs::write(rdb, data)?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug,StructOpt)]
+#[structopt(name="my-redis-rdb")]
+enum Cli {
+    //rdb -> 快照
+    Import {
+        #[structopt(parse(from_os_str))]
+        rdb:PathBuf,
+        #[structopt(parse(from_os_str))]
+        snapshot:PathBuf
+    },
+    //快照 -> rdb
+    Export {
+        #[structopt(parse(from_os_str))]
+        snapshot:PathBuf,
+        #[structopt(parse(from_os_str))]
+        rdb:PathBuf
+    }
+}
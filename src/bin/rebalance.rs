@@ -0,0 +1,151 @@
+use std::{collections::HashMap, time::Duration};
+
+use my_redis::{DEFAULT_PORT, client::Client, cmd::SlotState};
+
+use structopt::StructOpt;
+
+//在集群节点之间迁移slot，迁移期间集群照常提供服务
+//不指定--from/--to时把slot平均分给所有节点
+#[tokio::main]
+pub async fn main() -> my_redis::Result<()> {
+    let cli = Cli::from_args();
+    let port = cli.port.as_deref().unwrap_or(DEFAULT_PORT);
+    let mut entry = Client::new(format!("{}:{}",cli.host,port)).await?;
+    let nodes = parse_nodes(&entry.cluster_nodes().await?)?;
+    let moves = match (&cli.from, &cli.to) {
+        (Some(from),Some(to)) => {
+            let source = find_node(&nodes, from)?;
+            find_node(&nodes, to)?;
+            let count = cli.slots.unwrap_or(source.slots.len());
+            source.slots.iter().rev().take(count).map(|&slot| (slot,source.id.clone(),to.clone())).collect()
+        }
+        (None,None) => balance(&nodes),
+        _ => return Err("--from and --to must be used together".into())
+    };
+    if moves.is_empty() {
+        println!("cluster is already balanced");
+        return Ok(());
+    }
+    let mut clients:HashMap<String,Client> = HashMap::new();
+    for node in &nodes {
+        clients.insert(node.id.clone(), Client::new((node.host.as_str(),node.port)).await?);
+    }
+    let timeout = Duration::from_millis(cli.timeout);
+    for (i,(slot,source,target)) in moves.iter().enumerate() {
+        let keys = move_slot(&mut clients, &nodes, *slot, source, target, cli.batch, timeout).await?;
+        println!("[{}/{}] moved slot {} from {} to {} ({} keys)", i + 1, moves.len(), slot, source, target, keys);
+    }
+    Ok(())
+}
+
+//和redis-cli --cluster reshard一样的步骤:
+//目标IMPORTING -> 源MIGRATING -> 分批MIGRATE所有key -> 所有节点SETSLOT NODE
+async fn move_slot(clients:&mut HashMap<String,Client>,nodes:&[Node],slot:u16,source:&str,target:&str,batch:u64,timeout:Duration) -> my_redis::Result<usize> {
+    let target_node = find_node(nodes, target)?;
+    client(clients, target)?.cluster_setslot(slot, SlotState::Importing(source.to_string())).await?;
+    client(clients, source)?.cluster_setslot(slot, SlotState::Migrating(target.to_string())).await?;
+    let mut moved = 0;
+    loop {
+        let source_client = client(clients, source)?;
+        let keys = source_client.cluster_getkeysinslot(slot, batch).await?;
+        if keys.is_empty() {
+            break;
+        }
+        source_client.migrate(&target_node.host, target_node.port, &keys, timeout).await?;
+        moved += keys.len();
+    }
+    //先通知目标节点，它会增大配置纪元，再通知源节点和其它节点
+    client(clients, target)?.cluster_setslot(slot, SlotState::Node(target.to_string())).await?;
+    client(clients, source)?.cluster_setslot(slot, SlotState::Node(target.to_string())).await?;
+    for node in nodes.iter().filter(|node| node.id != source && node.id != target) {
+        client(clients, &node.id)?.cluster_setslot(slot, SlotState::Node(target.to_string())).await?;
+    }
+    Ok(moved)
+}
+
+//每个节点应该有的slot数量，多出来的slot从后往前挪给不够的节点
+fn balance(nodes:&[Node]) -> Vec<(u16,String,String)> {
+    let total:usize = nodes.iter().map(|node| node.slots.len()).sum();
+    let mut sorted:Vec<&Node> = nodes.iter().collect();
+    sorted.sort_by(|a,b| b.slots.len().cmp(&a.slots.len()).then(a.id.cmp(&b.id)));
+    let expected:Vec<usize> = (0..sorted.len())
+        .map(|i| total / sorted.len() + usize::from(i < total % sorted.len()))
+        .collect();
+    let mut surplus:Vec<(u16,String)> = Vec::new();
+    for (node,&expected) in sorted.iter().zip(&expected) {
+        if node.slots.len() > expected {
+            surplus.extend(node.slots[expected..].iter().map(|&slot| (slot,node.id.clone())));
+        }
+    }
+    let mut moves = Vec::new();
+    for (node,&expected) in sorted.iter().zip(&expected) {
+        for _ in node.slots.len()..expected {
+            if let Some((slot,source)) = surplus.pop() {
+                moves.push((slot,source,node.id.clone()));
+            }
+        }
+    }
+    moves
+}
+
+fn client<'a>(clients:&'a mut HashMap<String,Client>,id:&str) -> my_redis::Result<&'a mut Client> {
+    clients.get_mut(id).ok_or_else(|| format!("no connection to node {}",id).into())
+}
+
+fn find_node<'a>(nodes:&'a [Node],id:&str) -> my_redis::Result<&'a Node> {
+    nodes.iter().find(|node| node.id == id).ok_or_else(|| format!("unknown node {}",id).into())
+}
+
+struct Node {
+    id:String,
+    host:String,
+    port:u16,
+    slots:Vec<u16>
+}
+
+//只关心id、地址和负责的slot，迁移状态[...]忽略
+fn parse_nodes(text:&str) -> my_redis::Result<Vec<Node>> {
+    let mut nodes = Vec::new();
+    for line in text.lines().filter(|line| !line.is_empty()) {
+        let parts:Vec<&str> = line.split_whitespace().collect();
+        if parts.len() < 8 {
+            return Err(format!("invalid CLUSTER NODES line '{}'",line).into());
+        }
+        let addr = parts[1].split('@').next().unwrap_or(parts[1]);
+        let (host,port) = addr.rsplit_once(':').ok_or_else(|| format!("invalid node address '{}'",addr))?;
+        let mut slots = Vec::new();
+        for range in parts[8..].iter().filter(|range| !range.starts_with('[')) {
+            match range.split_once('-') {
+                Some((start,end)) => slots.extend(start.parse::<u16>()?..=end.parse::<u16>()?),
+                None => slots.push(range.parse()?)
+            }
+        }
+        nodes.push(Node { id: parts[0].to_string(), host: host.to_string(), port: port.parse()?, slots });
+    }
+    Ok(nodes)
+}
+
+#[derive(Debug,StructOpt)]
+#[structopt(name="my-redis-rebalance")]
+struct Cli {
+    //集群里任意一个节点
+    #[structopt(name="hostname",long="--host",default_value="127.0.0.1")]
+    host:String,
+    #[structopt(name="port",long="--port")]
+    port:Option<String>,
+    //源节点id
+    #[structopt(name="from",long="--from")]
+    from:Option<String>,
+    //目标节点id
+    #[structopt(name="to",long="--to")]
+    to:Option<String>,
+    //和--from/--to一起用，迁移的slot数量，默认全部
+    #[structopt(name="slots",long="--slots")]
+    slots:Option<usize>,
+    //每次MIGRATE的key数量
+    #[structopt(name="batch",long="--batch",default_value="10")]
+    batch:u64,
+    //MIGRATE的超时时间，单位毫秒
+    #[structopt(name="timeout",long="--timeout",default_value="5000")]
+    timeout:u64
+}
@@ -0,0 +1,39 @@
+use std::time::Duration;
+
+use my_redis::sentinel::{self, SentinelConfig};
+
+use structopt::StructOpt;
+use tokio::net::TcpListener;
+use tokio::signal;
+#[tokio::main]
+pub async fn main() -> my_redis::Result<()>{
+    tracing_subscriber::fmt::try_init()?;
+    let cli = Cli::from_args();
+    let listener = TcpListener::bind(format!("127.0.0.1:{}",cli.port)).await?;
+    let mut config = SentinelConfig::default();
+    config.set_monitor(&cli.monitor)?;
+    if let Some(ms) = cli.down_after {
+        config.down_after = Duration::from_millis(ms);
+    }
+    if let Some(ms) = cli.failover_timeout {
+        config.failover_timeout = Duration::from_millis(ms);
+    }
+    sentinel::run(listener,config,signal::ctrl_c()).await;
+    Ok(())
+}
+
+#[derive(Debug,StructOpt)]
+#[structopt(name="my-redis-sentinel")]
+struct Cli {
+    #[structopt(name="port",long="--port",default_value="26379")]
+    port:u16,
+    //"name host port quorum"，比如"mymaster 127.0.0.1 36379 2"
+    #[structopt(name="monitor",long="--monitor")]
+    monitor:String,
+    //主多久没有回复就认为它挂了，单位毫秒，默认5000
+    #[structopt(name="down-after-milliseconds",long="--down-after-milliseconds")]
+    down_after:Option<u64>,
+    //故障转移超时时间，单位毫秒，默认60000
+    #[structopt(name="failover-timeout",long="--failover-timeout")]
+    failover_timeout:Option<u64>
+}
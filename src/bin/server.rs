@@ -1,4 +1,6 @@
-use my_redis::{DEFAULT_PORT,server};
+use std::{path::PathBuf, time::Duration};
+
+use my_redis::{DEFAULT_PORT,server,config::Config};
 
 use structopt::StructOpt;
 use tokio::net::TcpListener;
@@ -9,7 +11,48 @@ pub async fn main() -> my_redis::Result<()>{
     let cli = Cli::from_args();
     let port = cli.port.as_deref().unwrap_or(DEFAULT_PORT);
     let listenr = TcpListener::bind(format!("127.0.0.1:{}",port)).await?;
-    server::run(listenr,signal::ctrl_c()).await;
+    let mut config = Config::default();
+    if let Some(ms) = cli.script_timeout {
+        config.script_timeout = Duration::from_millis(ms);
+    }
+    if let Some(events) = cli.notify_keyspace_events.as_deref() {
+        config.set_notify_keyspace_events(events)?;
+    }
+    if let Some(dir) = cli.dir {
+        config.dir = dir;
+    }
+    if let Some(dbfilename) = cli.dbfilename {
+        config.dbfilename = dbfilename;
+    }
+    config.appendonly = cli.appendonly;
+    if let Some(appendfilename) = cli.appendfilename {
+        config.appendfilename = appendfilename;
+    }
+    if let Some(appendfsync) = cli.appendfsync.as_deref() {
+        config.set_appendfsync(appendfsync)?;
+    }
+    config.load_rdb = cli.load_rdb;
+    if let Some(replicaof) = cli.replicaof.as_deref() {
+        config.set_replicaof(replicaof)?;
+    }
+    if let Some(read_only) = cli.replica_read_only.as_deref() {
+        config.replica_read_only = match read_only {
+            "yes" => true,
+            "no" => false,
+            _ => return Err(format!("invalid replica-read-only '{}', expected yes or no",read_only).into())
+        };
+    }
+    if let Some(size) = cli.repl_backlog_size {
+        config.repl_backlog_size = size;
+    }
+    config.cluster_enabled = cli.cluster_enabled;
+    if let Some(file) = cli.cluster_config_file {
+        config.cluster_config_file = file;
+    }
+    if let Some(ms) = cli.cluster_node_timeout {
+        config.cluster_node_timeout = Duration::from_millis(ms);
+    }
+    server::run(listenr,config,signal::ctrl_c()).await;
     Ok(())
 }
 
@@ -17,5 +60,45 @@ pub async fn main() -> my_redis::Result<()>{
 #[structopt(name="my-redis-server")]
 struct Cli {
     #[structopt(name="port",long="--port")]
-    port:Option<String>
+    port:Option<String>,
+    //lua脚本超时时间，单位毫秒
+    #[structopt(name="script-timeout",long="--script-timeout")]
+    script_timeout:Option<u64>,
+    //keyspace事件通知，比如"KEA"
+    #[structopt(name="notify-keyspace-events",long="--notify-keyspace-events")]
+    notify_keyspace_events:Option<String>,
+    //快照文件所在目录，默认当前目录
+    #[structopt(name="dir",long="--dir",parse(from_os_str))]
+    dir:Option<PathBuf>,
+    #[structopt(name="dbfilename",long="--dbfilename")]
+    dbfilename:Option<String>,
+    //开启aof持久化
+    #[structopt(name="appendonly",long="--appendonly")]
+    appendonly:bool,
+    #[structopt(name="appendfilename",long="--appendfilename")]
+    appendfilename:Option<String>,
+    //always/everysec/no，默认everysec
+    #[structopt(name="appendfsync",long="--appendfsync")]
+    appendfsync:Option<String>,
+    //启动时导入redis的rdb文件，导入的key会覆盖同名的key
+    #[structopt(name="load-rdb",long="--load-rdb",parse(from_os_str))]
+    load_rdb:Option<PathBuf>,
+    //"host port"，启动后成为它的副本
+    #[structopt(name="replicaof",long="--replicaof")]
+    replicaof:Option<String>,
+    //yes/no，默认yes
+    #[structopt(name="replica-read-only",long="--replica-read-only")]
+    replica_read_only:Option<String>,
+    //复制backlog的大小，单位字节，默认1MB
+    #[structopt(name="repl-backlog-size",long="--repl-backlog-size")]
+    repl_backlog_size:Option<usize>,
+    //开启集群模式
+    #[structopt(name="cluster-enabled",long="--cluster-enabled")]
+    cluster_enabled:bool,
+    //集群配置文件名，默认nodes.conf
+    #[structopt(name="cluster-config-file",long="--cluster-config-file")]
+    cluster_config_file:Option<String>,
+    //节点超时时间，单位毫秒，默认15000
+    #[structopt(name="cluster-node-timeout",long="--cluster-node-timeout")]
+    cluster_node_timeout:Option<u64>
 }
\ No newline at end of file
@@ -0,0 +1,250 @@
+use std::time::Duration;
+use bytes::Bytes;
+use tokio::{net::ToSocketAddrs, runtime::{self, Runtime}};
+use crate::client::{Client, Cmd, FromReply, Message, Pipeline, ReconnectPolicy, Reply, Subscriber, Timeouts, ToArgs};
+use crate::cmd::SlotState;
+
+//同步版本的Client，内部用一个current_thread的runtime执行异步的Client
+//不能在异步上下文里使用，block_on会panic
+pub struct BlockingClient {
+    inner:Client,
+    rt:Runtime
+}
+
+//同步版本的Subscriber
+pub struct BlockingSubscriber {
+    inner:Subscriber,
+    rt:Runtime
+}
+
+//BlockingSubscriber的迭代器，连接关闭时结束
+pub struct SubscriberIterator {
+    inner:Subscriber,
+    rt:Runtime
+}
+
+//同步版本的Cmd
+pub struct BlockingCmd<'a> {
+    inner:Cmd<'a>,
+    rt:&'a Runtime
+}
+
+//同步版本的Pipeline
+pub struct BlockingPipeline<'a> {
+    inner:Pipeline<'a>,
+    rt:&'a Runtime
+}
+
+impl BlockingClient {
+    pub fn connect<T: ToSocketAddrs>(addr:T) -> crate::Result<BlockingClient> {
+        let rt = runtime::Builder::new_current_thread().enable_all().build()?;
+        let inner = rt.block_on(Client::new(addr))?;
+        Ok(BlockingClient { inner, rt })
+    }
+
+    pub fn set_reconnect_policy(&mut self,policy:ReconnectPolicy) {
+        self.inner.set_reconnect_policy(policy)
+    }
+
+    pub fn set_timeouts(&mut self,timeouts:Timeouts) {
+        self.inner.set_timeouts(timeouts)
+    }
+
+    pub fn reconnect(&mut self) -> crate::Result<()> {
+        self.rt.block_on(self.inner.reconnect())
+    }
+
+    pub fn get(&mut self,key:&str) -> crate::Result<Option<Bytes>> {
+        self.rt.block_on(self.inner.get(key))
+    }
+
+    pub fn set(&mut self,key:&str,value:Bytes) -> crate::Result<()> {
+        self.rt.block_on(self.inner.set(key, value))
+    }
+
+    pub fn del(&mut self,keys:&[&str]) -> crate::Result<u64> {
+        self.rt.block_on(self.inner.del(keys))
+    }
+
+    pub fn publish(&mut self,channel:&str,message:Bytes) -> crate::Result<u64> {
+        self.rt.block_on(self.inner.publish(channel, message))
+    }
+
+    pub fn ping(&mut self,msg:Option<Bytes>) -> crate::Result<Bytes> {
+        self.rt.block_on(self.inner.ping(msg))
+    }
+
+    pub fn info(&mut self,section:Option<&str>) -> crate::Result<String> {
+        self.rt.block_on(self.inner.info(section))
+    }
+
+    pub fn replicaof(&mut self,leader:Option<(&str,u16)>) -> crate::Result<()> {
+        self.rt.block_on(self.inner.replicaof(leader))
+    }
+
+    pub fn wait(&mut self,numreplicas:u64,timeout:Duration) -> crate::Result<u64> {
+        self.rt.block_on(self.inner.wait(numreplicas, timeout))
+    }
+
+    pub fn cluster_nodes(&mut self) -> crate::Result<String> {
+        self.rt.block_on(self.inner.cluster_nodes())
+    }
+
+    pub fn cluster_setslot(&mut self,slot:u16,state:SlotState) -> crate::Result<()> {
+        self.rt.block_on(self.inner.cluster_setslot(slot, state))
+    }
+
+    pub fn cluster_getkeysinslot(&mut self,slot:u16,count:u64) -> crate::Result<Vec<String>> {
+        self.rt.block_on(self.inner.cluster_getkeysinslot(slot, count))
+    }
+
+    pub fn migrate(&mut self,host:&str,port:u16,keys:&[String],timeout:Duration) -> crate::Result<bool> {
+        self.rt.block_on(self.inner.migrate(host, port, keys, timeout))
+    }
+
+    pub fn cmd(&mut self,name:&str) -> BlockingCmd<'_> {
+        BlockingCmd { inner: self.inner.cmd(name), rt: &self.rt }
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn set_json<T: serde::Serialize + ?Sized>(&mut self,key:&str,value:&T) -> crate::Result<()> {
+        self.rt.block_on(self.inner.set_json(key, value))
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn get_json<T: serde::de::DeserializeOwned>(&mut self,key:&str) -> crate::Result<Option<T>> {
+        self.rt.block_on(self.inner.get_json(key))
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn set_bincode<T: serde::Serialize + ?Sized>(&mut self,key:&str,value:&T) -> crate::Result<()> {
+        self.rt.block_on(self.inner.set_bincode(key, value))
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn get_bincode<T: serde::de::DeserializeOwned>(&mut self,key:&str) -> crate::Result<Option<T>> {
+        self.rt.block_on(self.inner.get_bincode(key))
+    }
+
+    pub fn pipeline(&mut self) -> BlockingPipeline<'_> {
+        BlockingPipeline { inner: self.inner.pipeline(), rt: &self.rt }
+    }
+
+    pub fn subscribe(self,channels:Vec<String>) -> crate::Result<BlockingSubscriber> {
+        let inner = self.rt.block_on(self.inner.subscribe(channels))?;
+        Ok(BlockingSubscriber { inner, rt: self.rt })
+    }
+
+    pub fn psubscribe(self,patterns:Vec<String>) -> crate::Result<BlockingSubscriber> {
+        let inner = self.rt.block_on(self.inner.psubscribe(patterns))?;
+        Ok(BlockingSubscriber { inner, rt: self.rt })
+    }
+}
+
+impl BlockingSubscriber {
+    pub fn get_subscribed(&self) -> &[String] {
+        self.inner.get_subscribed()
+    }
+
+    pub fn get_psubscribed(&self) -> &[String] {
+        self.inner.get_psubscribed()
+    }
+
+    //阻塞直到收到消息，连接关闭并且重连失败时返回None
+    pub fn next_message(&mut self) -> crate::Result<Option<Message>> {
+        self.rt.block_on(self.inner.next_message())
+    }
+
+    pub fn subscribe(&mut self,channels:&[String]) -> crate::Result<()> {
+        self.rt.block_on(self.inner.subscribe(channels))
+    }
+
+    pub fn psubscribe(&mut self,patterns:&[String]) -> crate::Result<()> {
+        self.rt.block_on(self.inner.psubscribe(patterns))
+    }
+
+    pub fn unsubscribe(&mut self,channels:&[String]) -> crate::Result<()> {
+        self.rt.block_on(self.inner.unsubscribe(channels))
+    }
+
+    pub fn punsubscribe(&mut self,patterns:&[String]) -> crate::Result<()> {
+        self.rt.block_on(self.inner.punsubscribe(patterns))
+    }
+}
+
+//for message in subscriber {...}，每次迭代阻塞直到收到下一条消息
+impl IntoIterator for BlockingSubscriber {
+    type Item = crate::Result<Message>;
+    type IntoIter = SubscriberIterator;
+
+    fn into_iter(self) -> SubscriberIterator {
+        SubscriberIterator { inner: self.inner, rt: self.rt }
+    }
+}
+
+impl Iterator for SubscriberIterator {
+    type Item = crate::Result<Message>;
+
+    fn next(&mut self) -> Option<crate::Result<Message>> {
+        self.rt.block_on(self.inner.next_message()).transpose()
+    }
+}
+
+impl<'a> BlockingCmd<'a> {
+    pub fn arg<T: ToArgs>(self,arg:T) -> Self {
+        BlockingCmd { inner: self.inner.arg(arg), rt: self.rt }
+    }
+
+    pub fn query<T: FromReply>(self) -> crate::Result<T> {
+        self.rt.block_on(self.inner.query())
+    }
+
+    #[cfg(feature = "serde")]
+    pub fn query_as<T: serde::de::DeserializeOwned>(self) -> crate::Result<T> {
+        self.rt.block_on(self.inner.query_as())
+    }
+}
+
+impl<'a> BlockingPipeline<'a> {
+    pub fn get(&mut self,key:&str) -> &mut Self {
+        self.inner.get(key);
+        self
+    }
+
+    pub fn set(&mut self,key:&str,value:Bytes) -> &mut Self {
+        self.inner.set(key, value);
+        self
+    }
+
+    pub fn set_expires(&mut self,key:&str,value:Bytes,expiration:Duration) -> &mut Self {
+        self.inner.set_expires(key, value, expiration);
+        self
+    }
+
+    pub fn del(&mut self,keys:&[&str]) -> &mut Self {
+        self.inner.del(keys);
+        self
+    }
+
+    pub fn publish(&mut self,channel:&str,message:Bytes) -> &mut Self {
+        self.inner.publish(channel, message);
+        self
+    }
+
+    pub fn ping(&mut self) -> &mut Self {
+        self.inner.ping();
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    pub fn execute(self) -> crate::Result<Vec<crate::Result<Reply>>> {
+        self.rt.block_on(self.inner.execute())
+    }
+}
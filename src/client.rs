@@ -1,20 +1,245 @@
-
-use std::time::Duration;
-
-use tokio::{net::{TcpStream, ToSocketAddrs}};
+use std::{collections::VecDeque, future::Future, io, net::SocketAddr, time::{Duration, SystemTime, UNIX_EPOCH}};
+use tokio::{net::{TcpStream, ToSocketAddrs, lookup_host}, time};
+use tracing::debug;
+use tokio_stream::Stream;
 use bytes::Bytes;
-use crate::{connection::Connection, cmd::{Command, Set, Get}, frame::Frame};
+use crate::{connection::Connection, cmd::{Set, Get, Del, Publish, Subscribe, Unsubscribe, PSubscribe, PUnsubscribe, SlotState}, frame::Frame};
+pub use crate::types::{ToArgs, FromReply};
 
 
 pub struct Client {
-    conn:Connection
+    conn:Connection,
+    //连接时解析出来的地址，重连时用
+    addrs:Vec<SocketAddr>,
+    reconnect:ReconnectPolicy,
+    timeouts:Timeouts,
+    //已经发出去但还没读到回复的命令数，命令超时或者被取消时会留下，下次发送前先读掉
+    pending:usize,
+    //命令写到一半被中断，连接上可能有半条命令，只能换一个连接
+    broken:bool,
+    //通过cmd发了MULTI或者SUBSCRIBE，连接带着事务/订阅状态，连接池不能再借给别人
+    in_multi:bool,
+    //WATCH过还没有被EXEC/DISCARD/UNWATCH清掉
+    watching:bool,
+    subscribed:bool,
+    //通过cmd成功执行过的AUTH参数和SELECT的库，重连之后先重放再发命令
+    auth:Option<Vec<Bytes>>,
+    db:Option<Bytes>
+}
+
+//连接和读写的超时时间，None表示一直等
+//读超时之后迟到的回复在下一条命令之前丢掉，不会被当成下一条命令的回复
+#[derive(Debug,Clone,Default)]
+pub struct Timeouts {
+    pub connect:Option<Duration>,
+    pub read:Option<Duration>,
+    pub write:Option<Duration>
+}
+
+//连接断开之后的重连策略，默认不重连，需要时用exponential()打开
+#[derive(Debug,Clone)]
+pub struct ReconnectPolicy {
+    //最多尝试重连几次，0表示不重连
+    pub max_attempts:u32,
+    //第一次失败后等待的时间，之后每次翻倍直到max_backoff，实际等待时间在它的一半到全部之间随机
+    pub initial_backoff:Duration,
+    pub max_backoff:Duration,
+    //重连之后是否重发幂等的命令，非幂等的命令不知道服务端有没有执行过，只返回错误
+    pub retry_idempotent:bool
+}
+
+//调用subscribe之后client进入订阅模式，只能收消息和修改订阅
+pub struct Subscriber {
+    client:Client,
+    subscribed_channels:Vec<String>,
+    subscribed_patterns:Vec<String>,
+    //等待订阅/取消订阅的确认时收到的消息，next_message先返回这些
+    pending:VecDeque<Message>
+}
+
+//一次发送多条命令，按顺序返回每条命令的结果，单条命令出错不影响其它命令
+pub struct Pipeline<'a> {
+    client:&'a mut Client,
+    commands:Vec<(Frame,ReplyKind)>
+}
+
+//没有专门方法的命令，client.cmd("HSET").arg("key").arg(("field", 1)).query::<u64>()
+pub struct Cmd<'a> {
+    client:&'a mut Client,
+    args:Vec<Bytes>
+}
+
+//管道里一条命令的回复
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub enum Reply {
+    //SET
+    Ok,
+    //GET，key不存在时为None
+    Value(Option<Bytes>),
+    //DEL/PUBLISH
+    Count(u64),
+    //PING
+    Pong(Bytes)
+}
+
+//每条命令期望的回复类型
+#[derive(Debug,Clone,Copy)]
+enum ReplyKind {
+    Ok,
+    Value,
+    Count,
+    Pong
+}
+
+#[derive(Debug,Clone)]
+pub struct Message {
+    pub channel:String,
+    pub content:Bytes,
+    //通过psubscribe收到的消息会带上匹配的pattern
+    pub pattern:Option<String>
 }
 
 impl Client {
     pub async fn new<A: ToSocketAddrs> (addr:A) -> crate::Result<Client> {
-        let socket = TcpStream::connect(addr).await?;
-        let conn = Connection::new(socket);
-       Ok( Client {conn} )
+        Client::new_with_timeouts(addr, Timeouts::default()).await
+    }
+
+    pub async fn new_with_timeouts<A: ToSocketAddrs>(addr:A,timeouts:Timeouts) -> crate::Result<Client> {
+        let addrs:Vec<SocketAddr> = lookup_host(addr).await?.collect();
+        let conn = connect(&addrs, timeouts.connect).await?;
+        Ok(Client { conn, addrs, reconnect: ReconnectPolicy::default(), timeouts, pending: 0, broken: false, in_multi: false, watching: false, subscribed: false, auth: None, db: None })
+    }
+
+    pub fn set_reconnect_policy(&mut self,policy:ReconnectPolicy) {
+        self.reconnect = policy;
+    }
+
+    pub fn set_timeouts(&mut self,timeouts:Timeouts) {
+        self.timeouts = timeouts;
+    }
+
+    //按重连策略重新建立连接，全部失败时返回最后一次的错误
+    pub async fn reconnect(&mut self) -> crate::Result<()> {
+        let mut last_err:crate::Error = io::Error::new(io::ErrorKind::NotConnected, "reconnect disabled").into();
+        for attempt in 0..self.reconnect.max_attempts {
+            if attempt > 0 {
+                time::sleep(self.reconnect.backoff(attempt - 1)).await;
+            }
+            match self.reopen().await {
+                Ok(()) => {
+                    debug!(attempt,"reconnected");
+                    return Ok(());
+                }
+                Err(err) => last_err = err
+            }
+        }
+        Err(last_err)
+    }
+
+    //换一个新连接，旧连接上没读的回复一起丢掉，再恢复原来的认证和库
+    async fn reopen(&mut self) -> crate::Result<()> {
+        self.conn = connect(&self.addrs, self.timeouts.connect).await?;
+        self.pending = 0;
+        self.broken = false;
+        self.in_multi = false;
+        self.watching = false;
+        self.subscribed = false;
+        if let Some(auth) = self.auth.clone() {
+            let args = std::iter::once(Bytes::from_static(b"AUTH")).chain(auth);
+            self.replay(&Frame::Array(args.map(Frame::Bulk).collect())).await?;
+        }
+        if let Some(db) = self.db.clone() {
+            self.replay(&Frame::Array(vec![Frame::Bulk(Bytes::from_static(b"SELECT")),Frame::Bulk(db)])).await?;
+        }
+        Ok(())
+    }
+
+    //在刚建立的连接上直接收发，不经过send/recover
+    async fn replay(&mut self,frame:&Frame) -> crate::Result<()> {
+        let conn = &mut self.conn;
+        with_timeout(self.timeouts.write, "write", conn.write_frame(frame)).await?;
+        match with_timeout(self.timeouts.read, "read", conn.read_frame()).await? {
+            Some(Frame::Error(msg)) => Err(msg.into()),
+            Some(_) => Ok(()),
+            None => Err(io::Error::new(io::ErrorKind::ConnectionReset, "connection reset by peer").into())
+        }
+    }
+
+    //记下cmd发出的会改变连接状态的命令
+    fn track_state(&mut self,name:&[u8]) {
+        match &name.to_ascii_lowercase()[..] {
+            b"multi" => self.in_multi = true,
+            b"watch" => self.watching = true,
+            b"exec" | b"discard" => {
+                self.in_multi = false;
+                self.watching = false;
+            }
+            b"unwatch" => self.watching = false,
+            b"subscribe" | b"psubscribe" | b"ssubscribe" => self.subscribed = true,
+            _ => {}
+        }
+    }
+
+    //AUTH/SELECT成功之后记下参数，重连时重放
+    fn track_session(&mut self,args:&[Bytes]) {
+        match args.split_first() {
+            Some((name,rest)) if name.eq_ignore_ascii_case(b"auth") => self.auth = Some(rest.to_vec()),
+            Some((name,[db])) if name.eq_ignore_ascii_case(b"select") => self.db = Some(db.clone()),
+            _ => {}
+        }
+    }
+
+    //连接是否停在事务或者订阅模式里，或者还在WATCH
+    pub(crate) fn has_session_state(&self) -> bool {
+        self.in_multi || self.watching || self.subscribed
+    }
+
+    //上一条命令超时或者被取消时，先把连接恢复到回复和命令一一对应的状态
+    async fn recover(&mut self) -> crate::Result<()> {
+        if self.broken {
+            debug!("previous command was interrupted while writing, reopening connection");
+            return self.reopen().await;
+        }
+        while self.pending > 0 {
+            match with_timeout(self.timeouts.read, "read", self.conn.read_frame()).await {
+                Ok(Some(_)) => self.pending -= 1,
+                Ok(None) => return Err(io::Error::new(io::ErrorKind::ConnectionReset, "connection reset by peer").into()),
+                //迟到的回复还是没来，不再等了
+                Err(err) if is_timeout(&err) => {
+                    debug!(pending=self.pending,"late replies did not arrive, reopening connection");
+                    return self.reopen().await;
+                }
+                Err(err) => return Err(err)
+            }
+        }
+        Ok(())
+    }
+
+    //写的过程中被取消或者超时的话broken保持为true
+    async fn send(&mut self,frames:&[&Frame]) -> crate::Result<()> {
+        self.recover().await?;
+        self.broken = true;
+        let conn = &mut self.conn;
+        with_timeout(self.timeouts.write, "write", async {
+            for frame in frames {
+                conn.buffer_frame(frame).await?;
+            }
+            conn.flush().await
+        }).await?;
+        self.broken = false;
+        self.pending += frames.len();
+        Ok(())
+    }
+
+    //读到完整的回复之后pending才减一，读的过程中被取消或者超时的话回复留到下次丢掉
+    async fn receive(&mut self) -> crate::Result<Frame> {
+        match with_timeout(self.timeouts.read, "read", self.conn.read_frame()).await? {
+            Some(frame) => {
+                self.pending -= 1;
+                Ok(frame)
+            }
+            None => Err(io::Error::new(io::ErrorKind::ConnectionReset, "connection reset by peer").into())
+        }
     }
     pub async fn set(&mut self,key:&str,value:Bytes) -> crate::Result<()> {
         self.set_cmd(Set{key:key.to_string(),value,expiration:None}).await
@@ -22,21 +247,535 @@ impl Client {
 
     pub async fn get(&mut self,key:&str) -> crate::Result<Option<Bytes>> {
         let cmd = Get{key:key.to_string()};
-        let frame = cmd.into_frame();
-        self.conn.write_frame(&frame).await?;
-        match self.conn.read_response().await? {
+        match self.call(&cmd.into_frame(), true).await? {
             Frame::Bulk(bytes) => Ok(Some(bytes)),
             Frame::Null => Ok(None),
-            frame => Err(frame.to_err())
+            frame => Err(frame.into_err())
+        }
+    }
+
+    //返回删除的key数量
+    pub async fn del(&mut self,keys:&[&str]) -> crate::Result<u64> {
+        let cmd = Del{keys:keys.iter().map(|key| key.to_string()).collect()};
+        //重发的话返回的数量不对
+        match self.call(&cmd.into_frame(), false).await? {
+            Frame::Integer(n) => Ok(n as u64),
+            frame => Err(frame.into_err())
+        }
+    }
+
+    //返回收到消息的订阅者数量
+    pub async fn publish(&mut self,channel:&str,message:Bytes) -> crate::Result<u64> {
+        let cmd = Publish{channel:channel.to_string(),message};
+        match self.call(&cmd.into_frame(), false).await? {
+            Frame::Integer(n) => Ok(n as u64),
+            frame => Err(frame.into_err())
+        }
+    }
+
+    //不带参数返回PONG，否则原样返回msg
+    pub async fn ping(&mut self,msg:Option<Bytes>) -> crate::Result<Bytes> {
+        let mut v = vec![Frame::Bulk("PING".into())];
+        v.extend(msg.map(Frame::Bulk));
+        match self.call(&Frame::Array(v), true).await? {
+            Frame::Simple(s) => Ok(s.into()),
+            Frame::Bulk(msg) => Ok(msg),
+            frame => Err(frame.into_err())
+        }
+    }
+
+    //INFO [section]，返回服务端的文本信息
+    pub async fn info(&mut self,section:Option<&str>) -> crate::Result<String> {
+        let mut v = vec![Frame::Bulk("INFO".into())];
+        v.extend(section.map(|section| Frame::Bulk(section.to_string().into())));
+        match self.call(&Frame::Array(v), true).await? {
+            Frame::Bulk(info) => Ok(String::from_utf8(info.to_vec())?),
+            frame => Err(frame.into_err())
+        }
+    }
+
+    //Some((host,port))让服务端成为它的副本，None相当于REPLICAOF NO ONE
+    pub async fn replicaof(&mut self,leader:Option<(&str,u16)>) -> crate::Result<()> {
+        let (host,port) = match leader {
+            Some((host,port)) => (host.to_string(), port.to_string()),
+            None => ("NO".to_string(), "ONE".to_string())
+        };
+        let frame = Frame::Array(vec![
+            Frame::Bulk("REPLICAOF".into()),
+            Frame::Bulk(host.into()),
+            Frame::Bulk(port.into()),
+        ]);
+        match self.call(&frame, true).await? {
+            Frame::Simple(s) if s.starts_with("OK") => Ok(()),
+            frame => Err(frame.into_err())
+        }
+    }
+
+    //等待至少numreplicas个副本确认收到之前的写入，timeout为0时一直等，返回确认的副本数
+    pub async fn wait(&mut self,numreplicas:u64,timeout:Duration) -> crate::Result<u64> {
+        let frame = Frame::Array(vec![
+            Frame::Bulk("WAIT".into()),
+            Frame::Bulk(numreplicas.to_string().into()),
+            Frame::Bulk((timeout.as_millis() as u64).to_string().into()),
+        ]);
+        match self.call(&frame, false).await? {
+            Frame::Integer(n) => Ok(n as u64),
+            frame => Err(frame.into_err())
+        }
+    }
+
+    //CLUSTER NODES，每行一个节点
+    pub async fn cluster_nodes(&mut self) -> crate::Result<String> {
+        match self.call(&command(&["CLUSTER", "NODES"]), true).await? {
+            Frame::Bulk(nodes) => Ok(String::from_utf8(nodes.to_vec())?),
+            frame => Err(frame.into_err())
+        }
+    }
+
+    //CLUSTER SETSLOT slot MIGRATING|IMPORTING|NODE node-id / STABLE
+    pub async fn cluster_setslot(&mut self,slot:u16,state:SlotState) -> crate::Result<()> {
+        let slot = slot.to_string();
+        let frame = match &state {
+            SlotState::Migrating(node) => command(&["CLUSTER", "SETSLOT", &slot, "MIGRATING", node]),
+            SlotState::Importing(node) => command(&["CLUSTER", "SETSLOT", &slot, "IMPORTING", node]),
+            SlotState::Node(node) => command(&["CLUSTER", "SETSLOT", &slot, "NODE", node]),
+            SlotState::Stable => command(&["CLUSTER", "SETSLOT", &slot, "STABLE"])
+        };
+        match self.call(&frame, true).await? {
+            Frame::Simple(_) => Ok(()),
+            frame => Err(frame.into_err())
+        }
+    }
+
+    //CLUSTER GETKEYSINSLOT，最多返回count个key
+    pub async fn cluster_getkeysinslot(&mut self,slot:u16,count:u64) -> crate::Result<Vec<String>> {
+        match self.call(&command(&["CLUSTER", "GETKEYSINSLOT", &slot.to_string(), &count.to_string()]), true).await? {
+            Frame::Array(keys) => keys.into_iter().map(|key| match key {
+                Frame::Bulk(key) => Ok(String::from_utf8(key.to_vec())?),
+                frame => Err(frame.into_err())
+            }).collect(),
+            frame => Err(frame.into_err())
+        }
+    }
+
+    //把keys搬到host:port，一个key都不存在时返回false
+    pub async fn migrate(&mut self,host:&str,port:u16,keys:&[String],timeout:Duration) -> crate::Result<bool> {
+        let port = port.to_string();
+        let timeout = (timeout.as_millis() as u64).to_string();
+        let mut args = vec!["MIGRATE", host, &port, "", "0", &timeout, "KEYS"];
+        args.extend(keys.iter().map(|key| key.as_str()));
+        match self.call(&command(&args), false).await? {
+            Frame::Simple(s) if s == "OK" => Ok(true),
+            Frame::Simple(s) if s == "NOKEY" => Ok(false),
+            frame => Err(frame.into_err())
+        }
+    }
+
+    pub fn cmd(&mut self,name:&str) -> Cmd<'_> {
+        Cmd { client: self, args: vec![Bytes::copy_from_slice(name.as_bytes())] }
+    }
+
+    pub fn pipeline(&mut self) -> Pipeline<'_> {
+        Pipeline { client: self, commands: Vec::new() }
+    }
+
+    //连接断开时按重连策略重连，idempotent的命令重连之后再发一次
+    async fn call(&mut self,frame:&Frame,idempotent:bool) -> crate::Result<Frame> {
+        match self.request(frame).await {
+            Err(err) if is_disconnect(&err) && self.reconnect.max_attempts > 0 => {
+                debug!(cause=%err,"connection lost, reconnecting");
+                self.reconnect().await?;
+                if idempotent && self.reconnect.retry_idempotent {
+                    self.request(frame).await
+                } else {
+                    Err(err)
+                }
+            }
+            res => res
+        }
+    }
+
+    //发送一条命令并读取回复，错误回复转成Err，不重连
+    pub(crate) async fn request(&mut self,frame:&Frame) -> crate::Result<Frame> {
+        self.send(&[frame]).await?;
+        match self.receive().await? {
+            Frame::Error(msg) => Err(msg.into()),
+            frame => Ok(frame)
+        }
+    }
+
+    //复制时握手完成之后直接在连接上收发原始数据
+    pub(crate) fn into_connection(self) -> Connection {
+        self.conn
+    }
+
+    pub async fn subscribe(mut self,channels:Vec<String>) -> crate::Result<Subscriber> {
+        self.recover().await?;
+        let mut pending = VecDeque::new();
+        self.subscribe_cmd(&channels, &mut pending).await?;
+        Ok(Subscriber { client: self, subscribed_channels: channels, subscribed_patterns: Vec::new(), pending })
+    }
+
+    pub async fn psubscribe(mut self,patterns:Vec<String>) -> crate::Result<Subscriber> {
+        self.recover().await?;
+        let mut pending = VecDeque::new();
+        self.psubscribe_cmd(&patterns, &mut pending).await?;
+        Ok(Subscriber { client: self, subscribed_channels: Vec::new(), subscribed_patterns: patterns, pending })
+    }
+
+    async fn subscribe_cmd(&mut self,channels:&[String],pending:&mut VecDeque<Message>) -> crate::Result<()> {
+        let cmd = Subscribe{channels:channels.to_vec()};
+        with_timeout(self.timeouts.write, "write", self.conn.write_frame(&cmd.into_frame())).await?;
+        for channel in channels {
+            self.read_subscription_reply("subscribe", channel, pending).await?;
+        }
+        Ok(())
+    }
+
+    async fn psubscribe_cmd(&mut self,patterns:&[String],pending:&mut VecDeque<Message>) -> crate::Result<()> {
+        let cmd = PSubscribe{patterns:patterns.to_vec()};
+        with_timeout(self.timeouts.write, "write", self.conn.write_frame(&cmd.into_frame())).await?;
+        for pattern in patterns {
+            self.read_subscription_reply("psubscribe", pattern, pending).await?;
+        }
+        Ok(())
+    }
+
+    // [kind, name, count]
+    //确认之前可能先收到已订阅的channel的消息，放进pending里
+    async fn read_subscription_reply(&mut self,kind:&str,name:&str,pending:&mut VecDeque<Message>) -> crate::Result<()> {
+        loop {
+            let frame = with_timeout(self.timeouts.read, "read", self.conn.read_response()).await?;
+            if let Frame::Array(ref v) = frame {
+                if let [Frame::Bulk(k), Frame::Bulk(n), Frame::Integer(_)] = v.as_slice() {
+                    if **k == *kind.as_bytes() && **n == *name.as_bytes() {
+                        return Ok(());
+                    }
+                }
+            }
+            match parse_message(frame)? {
+                Some(message) => pending.push_back(message),
+                None => return Err(format!("unexpected {} reply",kind).into())
+            }
         }
     }
 
     async fn set_cmd(&mut self,cmd:Set) -> crate::Result<()>{
-        let frame = cmd.into_frame();
-        self.conn.write_frame(&frame).await?;
-        match self.conn.read_response().await? {
+        match self.call(&cmd.into_frame(), true).await? {
             Frame::Simple(s) if s == "OK" => Ok(()),
-            frame => Err(frame.to_err())
+            frame => Err(frame.into_err())
         }
      }
-}
\ No newline at end of file
+}
+
+//值编码之后当成普通字符串存取
+#[cfg(feature = "serde")]
+impl Client {
+    pub async fn set_json<T: serde::Serialize + ?Sized>(&mut self,key:&str,value:&T) -> crate::Result<()> {
+        self.set(key, serde_json::to_vec(value)?.into()).await
+    }
+
+    pub async fn get_json<T: serde::de::DeserializeOwned>(&mut self,key:&str) -> crate::Result<Option<T>> {
+        match self.get(key).await? {
+            Some(data) => Ok(Some(serde_json::from_slice(&data)?)),
+            None => Ok(None)
+        }
+    }
+
+    pub async fn set_bincode<T: serde::Serialize + ?Sized>(&mut self,key:&str,value:&T) -> crate::Result<()> {
+        self.set(key, bincode::serialize(value)?.into()).await
+    }
+
+    pub async fn get_bincode<T: serde::de::DeserializeOwned>(&mut self,key:&str) -> crate::Result<Option<T>> {
+        match self.get(key).await? {
+            Some(data) => Ok(Some(bincode::deserialize(&data)?)),
+            None => Ok(None)
+        }
+    }
+}
+
+impl<'a> Cmd<'a> {
+    pub fn arg<T: ToArgs>(mut self,arg:T) -> Self {
+        arg.write_args(&mut self.args);
+        self
+    }
+
+    //不知道命令是否幂等，连接断开时重连但不重发
+    pub async fn query<T: FromReply>(self) -> crate::Result<T> {
+        if let Some(name) = self.args.first() {
+            self.client.track_state(name);
+        }
+        let frame = Frame::Array(self.args.iter().cloned().map(Frame::Bulk).collect());
+        let reply = self.client.call(&frame, false).await?;
+        self.client.track_session(&self.args);
+        T::from_reply(reply)
+    }
+
+    //回复通过serde转换，比如把HGETALL的回复转成结构体
+    #[cfg(feature = "serde")]
+    pub async fn query_as<T: serde::de::DeserializeOwned>(self) -> crate::Result<T> {
+        crate::serde_frame::from_frame(self.query::<Frame>().await?)
+    }
+}
+
+impl<'a> Pipeline<'a> {
+    pub fn get(&mut self,key:&str) -> &mut Self {
+        self.push(Get{key:key.to_string()}.into_frame(), ReplyKind::Value)
+    }
+
+    pub fn set(&mut self,key:&str,value:Bytes) -> &mut Self {
+        self.push(Set{key:key.to_string(),value,expiration:None}.into_frame(), ReplyKind::Ok)
+    }
+
+    pub fn set_expires(&mut self,key:&str,value:Bytes,expiration:Duration) -> &mut Self {
+        self.push(Set{key:key.to_string(),value,expiration:Some(expiration)}.into_frame(), ReplyKind::Ok)
+    }
+
+    pub fn del(&mut self,keys:&[&str]) -> &mut Self {
+        self.push(Del{keys:keys.iter().map(|key| key.to_string()).collect()}.into_frame(), ReplyKind::Count)
+    }
+
+    pub fn publish(&mut self,channel:&str,message:Bytes) -> &mut Self {
+        self.push(Publish{channel:channel.to_string(),message}.into_frame(), ReplyKind::Count)
+    }
+
+    pub fn ping(&mut self) -> &mut Self {
+        self.push(command(&["PING"]), ReplyKind::Pong)
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    //所有命令写进缓冲区后只flush一次，再依次读取回复
+    //外层的Err表示连接出错，内层的Err是对应命令的错误回复
+    //连接断开时不知道哪些命令已经执行了，不重发，重连之后返回原来的错误
+    pub async fn execute(self) -> crate::Result<Vec<crate::Result<Reply>>> {
+        let client = self.client;
+        let commands = self.commands;
+        match Self::send(client, commands).await {
+            Err(err) if is_disconnect(&err) && client.reconnect.max_attempts > 0 => {
+                debug!(cause=%err,"pipeline connection lost, reconnecting");
+                client.reconnect().await?;
+                Err(err)
+            }
+            res => res
+        }
+    }
+
+    async fn send(client:&mut Client,commands:Vec<(Frame,ReplyKind)>) -> crate::Result<Vec<crate::Result<Reply>>> {
+        let frames:Vec<&Frame> = commands.iter().map(|(frame,_)| frame).collect();
+        client.send(&frames).await?;
+        let mut replies = Vec::with_capacity(commands.len());
+        for (_,kind) in commands {
+            replies.push(kind.reply(client.receive().await?));
+        }
+        Ok(replies)
+    }
+
+    fn push(&mut self,frame:Frame,kind:ReplyKind) -> &mut Self {
+        self.commands.push((frame,kind));
+        self
+    }
+}
+
+impl ReplyKind {
+    fn reply(self,frame:Frame) -> crate::Result<Reply> {
+        match (self,frame) {
+            (_,Frame::Error(msg)) => Err(msg.into()),
+            (ReplyKind::Ok,Frame::Simple(s)) if s == "OK" => Ok(Reply::Ok),
+            (ReplyKind::Value,Frame::Bulk(data)) => Ok(Reply::Value(Some(data))),
+            (ReplyKind::Value,Frame::Null) => Ok(Reply::Value(None)),
+            (ReplyKind::Count,Frame::Integer(n)) => Ok(Reply::Count(n as u64)),
+            (ReplyKind::Pong,Frame::Simple(s)) => Ok(Reply::Pong(s.into())),
+            (ReplyKind::Pong,Frame::Bulk(data)) => Ok(Reply::Pong(data)),
+            (_,frame) => Err(frame.into_err())
+        }
+    }
+}
+
+impl Subscriber {
+    pub fn get_subscribed(&self) -> &[String] {
+        &self.subscribed_channels
+    }
+
+    pub fn get_psubscribed(&self) -> &[String] {
+        &self.subscribed_patterns
+    }
+
+    //连接断开时按重连策略重连并重新订阅，不重连或者重连失败时返回None
+    //断开期间发布的消息会丢失
+    pub async fn next_message(&mut self) -> crate::Result<Option<Message>> {
+        if let Some(message) = self.pending.pop_front() {
+            return Ok(Some(message));
+        }
+        loop {
+            let frame = match self.client.conn.read_frame().await {
+                Ok(Some(frame)) => frame,
+                Ok(None) if self.client.reconnect.max_attempts == 0 => return Ok(None),
+                Err(err) if !is_disconnect(&err) || self.client.reconnect.max_attempts == 0 => return Err(err),
+                _ => {
+                    if let Err(err) = self.resubscribe().await {
+                        debug!(cause=%err,"failed to resubscribe");
+                        return Ok(None);
+                    }
+                    continue;
+                }
+            };
+            //订阅/取消订阅的确认在对应的方法里已经处理过，这里忽略
+            if let Some(message) = parse_message(frame)? {
+                return Ok(Some(message));
+            }
+        }
+    }
+
+    //重新连接，恢复原来的订阅
+    async fn resubscribe(&mut self) -> crate::Result<()> {
+        self.client.reconnect().await?;
+        if !self.subscribed_channels.is_empty() {
+            self.client.subscribe_cmd(&self.subscribed_channels, &mut self.pending).await?;
+        }
+        if !self.subscribed_patterns.is_empty() {
+            self.client.psubscribe_cmd(&self.subscribed_patterns, &mut self.pending).await?;
+        }
+        debug!(channels=self.subscribed_channels.len(),patterns=self.subscribed_patterns.len(),"resubscribed");
+        Ok(())
+    }
+
+    pub fn into_stream(mut self) -> impl Stream<Item = crate::Result<Message>> {
+        async_stream::try_stream! {
+            while let Some(message) = self.next_message().await? {
+                yield message;
+            }
+        }
+    }
+
+    pub async fn subscribe(&mut self,channels:&[String]) -> crate::Result<()> {
+        self.client.subscribe_cmd(channels, &mut self.pending).await?;
+        self.subscribed_channels.extend(channels.iter().cloned());
+        Ok(())
+    }
+
+    pub async fn psubscribe(&mut self,patterns:&[String]) -> crate::Result<()> {
+        self.client.psubscribe_cmd(patterns, &mut self.pending).await?;
+        self.subscribed_patterns.extend(patterns.iter().cloned());
+        Ok(())
+    }
+
+    //channels为空表示取消所有订阅
+    pub async fn unsubscribe(&mut self,channels:&[String]) -> crate::Result<()> {
+        let channels = if channels.is_empty() { self.subscribed_channels.clone() } else { channels.to_vec() };
+        if channels.is_empty() {
+            return Ok(());
+        }
+        let cmd = Unsubscribe{channels:channels.clone()};
+        let client = &mut self.client;
+        with_timeout(client.timeouts.write, "write", client.conn.write_frame(&cmd.into_frame())).await?;
+        for channel in &channels {
+            self.client.read_subscription_reply("unsubscribe", channel, &mut self.pending).await?;
+        }
+        self.subscribed_channels.retain(|c| !channels.contains(c));
+        Ok(())
+    }
+
+    pub async fn punsubscribe(&mut self,patterns:&[String]) -> crate::Result<()> {
+        let patterns = if patterns.is_empty() { self.subscribed_patterns.clone() } else { patterns.to_vec() };
+        if patterns.is_empty() {
+            return Ok(());
+        }
+        let cmd = PUnsubscribe{patterns:patterns.clone()};
+        let client = &mut self.client;
+        with_timeout(client.timeouts.write, "write", client.conn.write_frame(&cmd.into_frame())).await?;
+        for pattern in &patterns {
+            self.client.read_subscription_reply("punsubscribe", pattern, &mut self.pending).await?;
+        }
+        self.subscribed_patterns.retain(|p| !patterns.contains(p));
+        Ok(())
+    }
+}
+
+//message/pmessage转成Message，其它数组(订阅的确认)返回None
+fn parse_message(frame:Frame) -> crate::Result<Option<Message>> {
+    let v = match frame {
+        Frame::Array(v) => v,
+        frame => return Err(frame.into_err())
+    };
+    let mut parts = v.into_iter();
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(Frame::Bulk(kind)), Some(Frame::Bulk(channel)), Some(Frame::Bulk(content)), None) if &kind[..] == b"message" => {
+            let channel = String::from_utf8(channel.to_vec())?;
+            Ok(Some(Message { channel, content, pattern: None }))
+        }
+        (Some(Frame::Bulk(kind)), Some(Frame::Bulk(pattern)), Some(Frame::Bulk(channel)), Some(Frame::Bulk(content))) if &kind[..] == b"pmessage" => {
+            let channel = String::from_utf8(channel.to_vec())?;
+            let pattern = String::from_utf8(pattern.to_vec())?;
+            Ok(Some(Message { channel, content, pattern: Some(pattern) }))
+        }
+        _ => Ok(None)
+    }
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 0,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            retry_idempotent: false
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    //不重连，连接断开时直接返回错误
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    //最多重连5次，指数退避，重连之后重发幂等的命令
+    pub fn exponential() -> Self {
+        Self { max_attempts: 5, retry_idempotent: true, ..Self::default() }
+    }
+
+    //第attempt次重连失败后的等待时间，指数增长，加上随机抖动避免大量客户端同时重连
+    fn backoff(&self,attempt:u32) -> Duration {
+        let backoff = self.initial_backoff.saturating_mul(1 << attempt.min(16)).min(self.max_backoff);
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().subsec_nanos();
+        let half = backoff / 2;
+        half + half.mul_f64(nanos as f64 / 1e9)
+    }
+}
+
+//连接层面的错误，错误回复、协议错误和超时不算
+fn is_disconnect(err:&crate::Error) -> bool {
+    err.downcast_ref::<io::Error>().map(|err| err.kind() != io::ErrorKind::TimedOut).unwrap_or(false)
+}
+
+fn is_timeout(err:&crate::Error) -> bool {
+    err.downcast_ref::<io::Error>().map(|err| err.kind() == io::ErrorKind::TimedOut).unwrap_or(false)
+}
+
+//超时返回ErrorKind::TimedOut的io::Error
+async fn with_timeout<T>(timeout:Option<Duration>,what:&str,future:impl Future<Output = crate::Result<T>>) -> crate::Result<T> {
+    match timeout {
+        Some(timeout) => match time::timeout(timeout, future).await {
+            Ok(res) => res,
+            Err(_) => Err(io::Error::new(io::ErrorKind::TimedOut, format!("{} timed out",what)).into())
+        },
+        None => future.await
+    }
+}
+
+async fn connect(addrs:&[SocketAddr],timeout:Option<Duration>) -> crate::Result<Connection> {
+    let socket = with_timeout(timeout, "connect", async { Ok(TcpStream::connect(addrs).await?) }).await?;
+    Ok(Connection::new(socket))
+}
+
+//由字符串参数组成的命令
+pub(crate) fn command(args:&[&str]) -> Frame {
+    Frame::Array(args.iter().map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_bytes()))).collect())
+}
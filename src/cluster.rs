@@ -0,0 +1,648 @@
+use std::{collections::{BTreeMap, HashMap}, fmt::Write, fs, io, path::PathBuf, sync::Mutex, time::Duration};
+use bytes::Bytes;
+use tokio::time::{self, Instant};
+use tracing::{debug, error, info, warn};
+use crate::client::{Client, command};
+use crate::config::Config;
+use crate::db::Db;
+use crate::frame::Frame;
+use crate::script;
+
+//key空间分成16384个slot，每个slot属于一个节点
+pub(crate) const SLOTS:usize = 16384;
+//每隔一段时间从其它节点拉取它们看到的集群状态
+const GOSSIP_PERIOD:Duration = Duration::from_secs(1);
+const REQUEST_TIMEOUT:Duration = Duration::from_secs(1);
+
+//集群模式下节点的状态，锁顺序在db的Stat之后
+//节点之间没有单独的总线，直接在普通端口上用CLUSTER NODES互相拉取对方的视图
+#[derive(Debug)]
+pub(crate) struct Cluster {
+    //nodes.conf，保存节点id、其它节点和slot的分配，重启之后还能接着用
+    path:PathBuf,
+    node_timeout:Duration,
+    state:Mutex<State>
+}
+
+#[derive(Debug)]
+struct State {
+    myself:String,
+    //见过的最大的配置纪元
+    current_epoch:u64,
+    nodes:HashMap<String,Node>,
+    //slot -> 负责它的节点id
+    slots:Vec<Option<String>>,
+    //正在迁出和迁入的slot，迁出时本节点没有的key让客户端ASK目标节点，迁入时只接受带ASKING的请求
+    migrating:BTreeMap<u16,String>,
+    importing:BTreeMap<u16,String>
+}
+
+#[derive(Debug)]
+struct Node {
+    host:String,
+    port:u16,
+    //同一个slot被多个节点声明时，纪元大的胜出
+    config_epoch:u64,
+    //最近一次从它那里拉取成功的时间
+    last_pong:Option<Instant>
+}
+
+//CLUSTER NODES里的一行
+struct NodeLine {
+    id:String,
+    host:String,
+    port:u16,
+    myself:bool,
+    config_epoch:u64,
+    slots:Vec<(u16,u16)>
+}
+
+impl Cluster {
+    pub(crate) fn new(config:&Config) -> Self {
+        Self {
+            path: config.dir.join(&config.cluster_config_file),
+            node_timeout: config.cluster_node_timeout,
+            state: Mutex::new(State {
+                myself: new_node_id(),
+                current_epoch: 0,
+                nodes: HashMap::new(),
+                slots: vec![None; SLOTS],
+                migrating: BTreeMap::new(),
+                importing: BTreeMap::new()
+            })
+        }
+    }
+
+    //读取nodes.conf，不存在时用新生成的id创建一个，最后记下本节点的地址
+    pub(crate) fn load(&self,host:&str,port:u16) -> crate::Result<()> {
+        match fs::read_to_string(&self.path) {
+            Ok(content) => {
+                let mut state = self.state.lock().unwrap();
+                for line in content.lines() {
+                    if let Some(vars) = line.strip_prefix("vars ") {
+                        let mut parts = vars.split_whitespace();
+                        while let (Some(name),Some(value)) = (parts.next(),parts.next()) {
+                            if name == "currentEpoch" {
+                                state.current_epoch = value.parse()?;
+                            }
+                        }
+                        continue;
+                    }
+                    let node = parse_node_line(line).ok_or_else(|| format!("invalid cluster config line '{}'",line))?;
+                    if node.myself {
+                        state.myself = node.id.clone();
+                    }
+                    state.add_node(&node);
+                    for &(start,end) in &node.slots {
+                        for slot in start..=end {
+                            state.slots[slot as usize] = Some(node.id.clone());
+                        }
+                    }
+                }
+                info!(id=%state.myself,path=?self.path,"cluster config loaded");
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into())
+        }
+        {
+            let mut state = self.state.lock().unwrap();
+            let myself = state.myself.clone();
+            let node = state.nodes.entry(myself).or_insert_with(|| Node { host: String::new(), port: 0, config_epoch: 0, last_pong: None });
+            node.host = host.to_string();
+            node.port = port;
+        }
+        self.save();
+        Ok(())
+    }
+
+    //写nodes.conf，失败只记日志
+    fn save(&self) {
+        let content = {
+            let state = self.state.lock().unwrap();
+            format!("{}vars currentEpoch {}\n", state.nodes_text(self.node_timeout), state.current_epoch)
+        };
+        let tmp = self.path.with_file_name(format!("temp-{}.nodes.conf",std::process::id()));
+        if let Err(err) = fs::write(&tmp, content).and_then(|()| fs::rename(&tmp, &self.path)) {
+            error!(cause=%err,path=?self.path,"failed to save cluster config");
+        }
+    }
+
+    pub(crate) fn myself(&self) -> String {
+        self.state.lock().unwrap().myself.clone()
+    }
+
+    //keys所在的slot不归本节点处理时返回MOVED/ASK等错误，exists用来判断key在本节点是否存在
+    pub(crate) fn redirect(&self,keys:&[&[u8]],asking:bool,exists:impl Fn(&str) -> bool) -> Option<Frame> {
+        let slot = key_slot(keys.first()?);
+        if keys.iter().any(|key| key_slot(key) != slot) {
+            return Some(Frame::Error("CROSSSLOT Keys in request don't hash to the same slot".to_string()));
+        }
+        let migrating_to = {
+            let state = self.state.lock().unwrap();
+            match state.slots[slot as usize].as_ref() {
+                None => return Some(Frame::Error("CLUSTERDOWN Hash slot not served".to_string())),
+                Some(owner) if *owner == state.myself => match state.migrating.get(&slot) {
+                    Some(target) => state.address(target),
+                    None => return None
+                },
+                Some(_) if asking && state.importing.contains_key(&slot) => return None,
+                Some(owner) => return Some(Frame::Error(format!("MOVED {} {}",slot,state.address(owner)?)))
+            }
+        };
+        //迁移中的slot，本节点还有的key照常处理，没有的让客户端去目标节点问
+        let target = migrating_to?;
+        let missing = keys.iter().filter(|key| !exists(&String::from_utf8_lossy(key))).count();
+        if missing == 0 {
+            None
+        } else if missing == keys.len() {
+            Some(Frame::Error(format!("ASK {} {}",slot,target)))
+        } else {
+            Some(Frame::Error("TRYAGAIN Multiple keys request during rehashing of slot".to_string()))
+        }
+    }
+
+    //CLUSTER MEET之后记下对方，返回是否是新认识的节点
+    pub(crate) fn add_node(&self,id:&str,host:&str,port:u16) -> bool {
+        let added = {
+            let mut state = self.state.lock().unwrap();
+            if state.nodes.contains_key(id) {
+                false
+            } else {
+                state.add_node(&NodeLine { id: id.to_string(), host: host.to_string(), port, myself: false, config_epoch: 0, slots: Vec::new() });
+                true
+            }
+        };
+        if added {
+            info!(id,host,port,"cluster node added");
+            self.save();
+        }
+        added
+    }
+
+    //把slots分配给本节点，已经属于别的节点的slot返回错误
+    pub(crate) fn add_slots(&self,slots:&[u16]) -> Result<(),String> {
+        {
+            let mut state = self.state.lock().unwrap();
+            if let Some(slot) = slots.iter().find(|slot| state.slots[**slot as usize].is_some()) {
+                return Err(format!("ERR Slot {} is already busy",slot));
+            }
+            let myself = state.myself.clone();
+            for slot in slots {
+                state.slots[*slot as usize] = Some(myself.clone());
+            }
+        }
+        self.save();
+        Ok(())
+    }
+
+    pub(crate) fn del_slots(&self,slots:&[u16]) -> Result<(),String> {
+        {
+            let mut state = self.state.lock().unwrap();
+            if let Some(slot) = slots.iter().find(|slot| state.slots[**slot as usize].is_none()) {
+                return Err(format!("ERR Slot {} is already unassigned",slot));
+            }
+            for slot in slots {
+                state.slots[*slot as usize] = None;
+            }
+        }
+        self.save();
+        Ok(())
+    }
+
+    //SETSLOT MIGRATING，只能迁出本节点负责的slot
+    pub(crate) fn set_slot_migrating(&self,slot:u16,target:&str) -> Result<(),String> {
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.slots[slot as usize].as_ref() != Some(&state.myself) {
+                return Err(format!("ERR I'm not the owner of hash slot {}",slot));
+            }
+            if !state.nodes.contains_key(target) || target == state.myself {
+                return Err(format!("ERR I don't know about node {}",target));
+            }
+            state.migrating.insert(slot, target.to_string());
+        }
+        self.save();
+        Ok(())
+    }
+
+    //SETSLOT IMPORTING，slot已经归本节点时不需要迁入
+    pub(crate) fn set_slot_importing(&self,slot:u16,source:&str) -> Result<(),String> {
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.slots[slot as usize].as_ref() == Some(&state.myself) {
+                return Err(format!("ERR I'm already the owner of hash slot {}",slot));
+            }
+            if !state.nodes.contains_key(source) || source == state.myself {
+                return Err(format!("ERR I don't know about node {}",source));
+            }
+            state.importing.insert(slot, source.to_string());
+        }
+        self.save();
+        Ok(())
+    }
+
+    pub(crate) fn set_slot_stable(&self,slot:u16) {
+        {
+            let mut state = self.state.lock().unwrap();
+            state.migrating.remove(&slot);
+            state.importing.remove(&slot);
+        }
+        self.save();
+    }
+
+    //SETSLOT NODE，迁移完成后把slot交给node，keys是本节点在这个slot里还有的key数量
+    //迁入方拿到slot时增大自己的配置纪元，这样其它节点通过gossip会认它为新的主
+    pub(crate) fn set_slot_node(&self,slot:u16,node:&str,keys:usize) -> Result<(),String> {
+        {
+            let mut state = self.state.lock().unwrap();
+            if !state.nodes.contains_key(node) {
+                return Err(format!("ERR Unknown node {}",node));
+            }
+            let mine = state.slots[slot as usize].as_ref() == Some(&state.myself);
+            if mine && node != state.myself && keys > 0 {
+                return Err(format!("ERR Can't assign hashslot {} to a different node while I still hold keys for this hash slot.",slot));
+            }
+            state.migrating.remove(&slot);
+            //已经是最大的纪元时不需要再增大
+            let my_epoch = state.nodes.get(&state.myself).map(|me| me.config_epoch).unwrap_or(0);
+            if state.importing.remove(&slot).is_some() && node == state.myself && (my_epoch == 0 || my_epoch < state.current_epoch) {
+                state.current_epoch += 1;
+                let epoch = state.current_epoch;
+                let myself = state.myself.clone();
+                if let Some(me) = state.nodes.get_mut(&myself) {
+                    me.config_epoch = epoch;
+                }
+                info!(slot,epoch,"slot imported, config epoch bumped");
+            }
+            state.slots[slot as usize] = Some(node.to_string());
+        }
+        self.save();
+        Ok(())
+    }
+
+    pub(crate) fn info(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let assigned = state.slots.iter().filter(|owner| owner.is_some()).count();
+        let size = state.nodes.keys().filter(|id| state.slots.iter().any(|owner| owner.as_ref() == Some(*id))).count();
+        let mut s = String::new();
+        let _ = write!(s, "cluster_enabled:1\r\ncluster_state:{}\r\ncluster_slots_assigned:{}\r\ncluster_slots_ok:{}\r\ncluster_known_nodes:{}\r\ncluster_size:{}\r\ncluster_current_epoch:{}\r\ncluster_my_epoch:{}\r\n",
+            if assigned == SLOTS { "ok" } else { "fail" }, assigned, assigned, state.nodes.len(), size,
+            state.current_epoch, state.nodes.get(&state.myself).map(|node| node.config_epoch).unwrap_or(0));
+        s
+    }
+
+    pub(crate) fn nodes(&self) -> String {
+        self.state.lock().unwrap().nodes_text(self.node_timeout)
+    }
+
+    //CLUSTER SLOTS: [[start,end,[ip,port,id]],...]
+    pub(crate) fn slots(&self) -> Frame {
+        let state = self.state.lock().unwrap();
+        Frame::Array(state.ranges().into_iter().filter_map(|(start,end,id)| {
+            let node = state.nodes.get(id)?;
+            Some(Frame::Array(vec![
+                Frame::Integer(start as i64),
+                Frame::Integer(end as i64),
+                Frame::Array(vec![
+                    Frame::Bulk(node.host.clone().into()),
+                    Frame::Integer(node.port as i64),
+                    Frame::Bulk(id.clone().into()),
+                ]),
+            ]))
+        }).collect())
+    }
+
+    //CLUSTER SHARDS: 每个节点一个分片，[slots,[start,end,...],nodes,[[id,..,port,..,ip,..,...]]]
+    pub(crate) fn shards(&self) -> Frame {
+        let state = self.state.lock().unwrap();
+        let ranges = state.ranges();
+        let mut ids:Vec<&String> = state.nodes.keys().collect();
+        ids.sort();
+        Frame::Array(ids.into_iter().map(|id| {
+            let node = &state.nodes[id];
+            let slots = ranges.iter()
+                .filter(|(_,_,owner)| *owner == id)
+                .flat_map(|(start,end,_)| [Frame::Integer(*start as i64), Frame::Integer(*end as i64)])
+                .collect();
+            let health = if state.is_failing(id, node, self.node_timeout) { "fail" } else { "online" };
+            Frame::Array(vec![
+                Frame::Bulk(Bytes::from_static(b"slots")),
+                Frame::Array(slots),
+                Frame::Bulk(Bytes::from_static(b"nodes")),
+                Frame::Array(vec![Frame::Array(vec![
+                    Frame::Bulk(Bytes::from_static(b"id")),
+                    Frame::Bulk(id.clone().into()),
+                    Frame::Bulk(Bytes::from_static(b"port")),
+                    Frame::Integer(node.port as i64),
+                    Frame::Bulk(Bytes::from_static(b"ip")),
+                    Frame::Bulk(node.host.clone().into()),
+                    Frame::Bulk(Bytes::from_static(b"endpoint")),
+                    Frame::Bulk(node.host.clone().into()),
+                    Frame::Bulk(Bytes::from_static(b"role")),
+                    Frame::Bulk(Bytes::from_static(b"master")),
+                    Frame::Bulk(Bytes::from_static(b"replication-offset")),
+                    Frame::Integer(0),
+                    Frame::Bulk(Bytes::from_static(b"health")),
+                    Frame::Bulk(Bytes::from(health)),
+                ])]),
+            ])
+        }).collect())
+    }
+
+    //合并从peer拉取到的CLUSTER NODES，返回是否有变化
+    fn merge(&self,peer:&str,text:&str) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let mut changed = false;
+        if let Some(node) = state.nodes.get_mut(peer) {
+            node.last_pong = Some(Instant::now());
+        }
+        for line in text.lines().filter_map(parse_node_line) {
+            if line.id == state.myself {
+                continue;
+            }
+            if !state.nodes.contains_key(&line.id) {
+                info!(id=%line.id,host=%line.host,port=line.port,"cluster node discovered");
+                state.add_node(&line);
+                changed = true;
+            }
+            if line.config_epoch > state.current_epoch {
+                state.current_epoch = line.config_epoch;
+                changed = true;
+            }
+            //每个节点只对自己负责的slot说了算
+            if line.myself && line.id == peer {
+                changed |= state.apply_claims(&line);
+            }
+        }
+        changed
+    }
+}
+
+impl State {
+    fn add_node(&mut self,line:&NodeLine) {
+        self.nodes.insert(line.id.clone(), Node { host: line.host.clone(), port: line.port, config_epoch: line.config_epoch, last_pong: None });
+    }
+
+    fn address(&self,id:&str) -> Option<String> {
+        self.nodes.get(id).map(|node| format!("{}:{}",node.host,node.port))
+    }
+
+    fn is_failing(&self,id:&str,node:&Node,timeout:Duration) -> bool {
+        id != self.myself && node.last_pong.map(|pong| pong.elapsed() > timeout).unwrap_or(true)
+    }
+
+    //连续的slot合并成区间: (start,end,节点id)
+    fn ranges(&self) -> Vec<(u16,u16,&String)> {
+        let mut ranges:Vec<(u16,u16,&String)> = Vec::new();
+        for (slot,owner) in self.slots.iter().enumerate() {
+            let owner = match owner {
+                Some(owner) => owner,
+                None => continue
+            };
+            match ranges.last_mut() {
+                Some((_,end,id)) if *id == owner && *end as usize + 1 == slot => *end = slot as u16,
+                _ => ranges.push((slot as u16,slot as u16,owner))
+            }
+        }
+        ranges
+    }
+
+    //节点声明的slot，没有主或者原来的主纪元更小时归它，原来归它现在不再声明的slot变成没有主
+    fn apply_claims(&mut self,line:&NodeLine) -> bool {
+        let mut changed = false;
+        let epoch = line.config_epoch;
+        if let Some(node) = self.nodes.get_mut(&line.id) {
+            if node.config_epoch != epoch || node.host != line.host || node.port != line.port {
+                node.config_epoch = epoch;
+                node.host = line.host.clone();
+                node.port = line.port;
+                changed = true;
+            }
+        }
+        let mut claimed = vec![false; SLOTS];
+        for &(start,end) in &line.slots {
+            for slot in start..=end {
+                claimed[slot as usize] = true;
+            }
+        }
+        for (slot,&claim) in claimed.iter().enumerate() {
+            let owner = self.slots[slot].clone();
+            if claim {
+                let take = match owner.as_ref() {
+                    None => true,
+                    Some(owner) if *owner == line.id => false,
+                    Some(owner) => self.nodes.get(owner).map(|node| node.config_epoch < epoch).unwrap_or(true)
+                };
+                if take {
+                    if owner.as_ref() == Some(&self.myself) {
+                        warn!(slot,node=%line.id,"slot taken over by node with a bigger config epoch");
+                        self.migrating.remove(&(slot as u16));
+                    }
+                    self.slots[slot] = Some(line.id.clone());
+                    changed = true;
+                }
+            } else if owner.as_ref() == Some(&line.id) && !self.importing.contains_key(&(slot as u16)) {
+                //正在迁入的slot等本节点SETSLOT NODE接手，不然中间会有一段时间没有主
+                self.slots[slot] = None;
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    //和redis的CLUSTER NODES格式一样，节点之间也用它交换状态
+    fn nodes_text(&self,timeout:Duration) -> String {
+        let ranges = self.ranges();
+        let mut ids:Vec<&String> = self.nodes.keys().collect();
+        ids.sort();
+        let mut s = String::new();
+        for id in ids {
+            let node = &self.nodes[id];
+            let myself = *id == self.myself;
+            let flags = if myself {
+                "myself,master"
+            } else if self.is_failing(id, node, timeout) {
+                "master,fail?"
+            } else {
+                "master"
+            };
+            let pong = node.last_pong.map(|pong| crate::snapshot::unix_time_millis().saturating_sub(pong.elapsed().as_millis() as u64)).unwrap_or(0);
+            let link = if myself || !self.is_failing(id, node, timeout) { "connected" } else { "disconnected" };
+            let _ = write!(s, "{} {}:{}@0 {} - 0 {} {} {}", id, node.host, node.port, flags, pong, node.config_epoch, link);
+            for (start,end,_) in ranges.iter().filter(|(_,_,owner)| *owner == id) {
+                if start == end {
+                    let _ = write!(s, " {}", start);
+                } else {
+                    let _ = write!(s, " {}-{}", start, end);
+                }
+            }
+            if myself {
+                for (slot,target) in &self.migrating {
+                    let _ = write!(s, " [{}->-{}]", slot, target);
+                }
+                for (slot,source) in &self.importing {
+                    let _ = write!(s, " [{}-<-{}]", slot, source);
+                }
+            }
+            s.push('\n');
+        }
+        s
+    }
+}
+
+//<id> <ip:port@cport> <flags> <master> <ping-sent> <pong-recv> <config-epoch> <link-state> <slot> ...
+fn parse_node_line(line:&str) -> Option<NodeLine> {
+    let parts:Vec<&str> = line.split_whitespace().collect();
+    if parts.len() < 8 {
+        return None;
+    }
+    let addr = parts[1].split('@').next()?;
+    let (host,port) = addr.rsplit_once(':')?;
+    let mut slots = Vec::new();
+    for slot in &parts[8..] {
+        //迁移状态只对本节点有意义
+        if slot.starts_with('[') {
+            continue;
+        }
+        let (start,end) = match slot.split_once('-') {
+            Some((start,end)) => (start.parse().ok()?,end.parse().ok()?),
+            None => {
+                let slot = slot.parse().ok()?;
+                (slot,slot)
+            }
+        };
+        if start > end || end as usize >= SLOTS {
+            return None;
+        }
+        slots.push((start,end));
+    }
+    Some(NodeLine {
+        id: parts[0].to_string(),
+        host: host.to_string(),
+        port: port.parse().ok()?,
+        myself: parts[2].split(',').any(|flag| flag == "myself"),
+        config_epoch: parts[6].parse().ok()?,
+        slots
+    })
+}
+
+//定期从每个认识的节点拉取它的CLUSTER NODES
+pub(crate) fn spawn_gossip_task(db:Db) {
+    tokio::spawn(async move {
+        let mut clients:HashMap<String,Client> = HashMap::new();
+        let mut interval = time::interval(GOSSIP_PERIOD);
+        loop {
+            interval.tick().await;
+            let cluster = match db.cluster() {
+                Some(cluster) => cluster,
+                None => return
+            };
+            let peers:Vec<(String,String,u16)> = {
+                let state = cluster.state.lock().unwrap();
+                state.nodes.iter()
+                    .filter(|(id,_)| **id != state.myself)
+                    .map(|(id,node)| (id.clone(),node.host.clone(),node.port))
+                    .collect()
+            };
+            let mut changed = false;
+            for (id,host,port) in peers {
+                match fetch_nodes(&mut clients, &id, &host, port).await {
+                    Ok(text) => changed |= cluster.merge(&id, &text),
+                    Err(err) => {
+                        clients.remove(&id);
+                        debug!(cause=%err,node=%id,"failed to fetch cluster nodes");
+                    }
+                }
+            }
+            if changed {
+                cluster.save();
+            }
+        }
+    });
+}
+
+async fn fetch_nodes(clients:&mut HashMap<String,Client>,id:&str,host:&str,port:u16) -> crate::Result<String> {
+    if !clients.contains_key(id) {
+        let client = time::timeout(REQUEST_TIMEOUT, Client::new((host,port))).await.map_err(|_| "connect timeout")??;
+        clients.insert(id.to_string(), client);
+    }
+    let client = clients.get_mut(id).unwrap();
+    let frame = Frame::Array(vec![Frame::Bulk(Bytes::from_static(b"CLUSTER")), Frame::Bulk(Bytes::from_static(b"NODES"))]);
+    match time::timeout(REQUEST_TIMEOUT, client.request(&frame)).await.map_err(|_| "request timeout")?? {
+        Frame::Bulk(text) => Ok(String::from_utf8(text.to_vec())?),
+        frame => Err(format!("unexpected reply to CLUSTER NODES: {:?}",frame).into())
+    }
+}
+
+//CLUSTER MEET：先问对方的id，再让对方也MEET自己
+pub(crate) async fn meet(db:&Db,host:&str,port:u16) -> crate::Result<()> {
+    let cluster = db.cluster().ok_or("This instance has cluster support disabled")?;
+    let mut client = time::timeout(REQUEST_TIMEOUT, Client::new((host,port))).await.map_err(|_| "connect timeout")??;
+    let id = match client.request(&command(&["CLUSTER", "MYID"])).await? {
+        Frame::Bulk(id) => String::from_utf8(id.to_vec())?,
+        frame => return Err(format!("unexpected reply to CLUSTER MYID: {:?}",frame).into())
+    };
+    let myself = cluster.myself();
+    if id == myself {
+        return Ok(());
+    }
+    if cluster.add_node(&id, host, port) {
+        let (my_host,my_port) = {
+            let state = cluster.state.lock().unwrap();
+            let node = &state.nodes[&myself];
+            (node.host.clone(),node.port)
+        };
+        client.request(&command(&["CLUSTER", "MEET", &my_host, &my_port.to_string()])).await?;
+    }
+    Ok(())
+}
+
+fn new_node_id() -> String {
+    script::sha1hex(format!("{:?}-{}",std::time::SystemTime::now(),std::process::id()).as_bytes())
+}
+
+//{}里面非空时只用它计算slot，这样相关的key可以放到同一个节点
+pub(crate) fn key_slot(key:&[u8]) -> u16 {
+    let key = match key.iter().position(|&b| b == b'{') {
+        Some(start) => match key[start + 1..].iter().position(|&b| b == b'}') {
+            Some(len) if len > 0 => &key[start + 1..start + 1 + len],
+            _ => key
+        },
+        None => key
+    };
+    crc16(key) & (SLOTS as u16 - 1)
+}
+
+//crc16-xmodem，多项式0x1021，初始值0
+fn crc16(data:&[u8]) -> u16 {
+    let mut crc:u16 = 0;
+    for &b in data {
+        crc ^= (b as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc16_xmodem() {
+        assert_eq!(crc16(b"123456789"), 0x31c3);
+        assert_eq!(crc16(b""), 0);
+    }
+
+    #[test]
+    fn key_slot_and_hash_tags() {
+        assert_eq!(key_slot(b"foo"), 12182);
+        assert_eq!(key_slot(b"{user1000}.following"), key_slot(b"{user1000}.followers"));
+        assert_eq!(key_slot(b"{user1000}.following"), key_slot(b"user1000"));
+        //空的{}不算hash tag
+        assert_eq!(key_slot(b"foo{}{bar}"), crc16(b"foo{}{bar}") & 16383);
+        assert_eq!(key_slot(b"foo{{bar}}zap"), key_slot(b"{bar"));
+        assert_eq!(key_slot(b"foo{bar}{zap}"), key_slot(b"bar"));
+    }
+}
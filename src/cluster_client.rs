@@ -0,0 +1,237 @@
+use std::{collections::{BTreeMap, HashMap}, io, time::Duration};
+use bytes::Bytes;
+use tokio::time;
+use crate::{client::{Client, command}, cluster::{self, SLOTS}, cmd::{Set, Get, Del}, frame::Frame, types::{ToArgs, FromReply}};
+
+//一条命令最多跟随的重定向次数
+const MAX_REDIRECTS:usize = 16;
+//TRYAGAIN和CLUSTERDOWN时等一会再重试，迁移或者故障转移通常很快就结束
+const RETRY_DELAY:Duration = Duration::from_millis(100);
+
+//集群模式的客户端，按key的slot把命令发到负责的节点
+//本地缓存slot -> 节点的映射，收到MOVED时重新拉取，ASK只对这一条命令生效
+pub struct ClusterClient {
+    //创建时给的地址，缓存的节点都连不上时从这里重新发现
+    seeds:Vec<String>,
+    //slot -> "host:port"
+    slots:Vec<Option<String>>,
+    connections:HashMap<String,Client>
+}
+
+//没有专门方法的命令，cluster.cmd("HSET").key("user:1").arg(("name", "a")).query::<u64>()
+//按key()加的参数所在的slot发送，没有key时发给任意一个节点
+pub struct ClusterCmd<'a> {
+    client:&'a mut ClusterClient,
+    args:Vec<Bytes>,
+    slots:Vec<u16>
+}
+
+//服务端返回的需要客户端处理的集群错误
+enum Redirect {
+    Moved(u16,String),
+    Ask(String),
+    Retry
+}
+
+impl ClusterClient {
+    //seeds是集群里任意几个节点的"host:port"，至少要有一个能连上
+    pub async fn new(seeds:&[&str]) -> crate::Result<ClusterClient> {
+        let mut client = ClusterClient {
+            seeds: seeds.iter().map(|seed| seed.to_string()).collect(),
+            slots: vec![None; SLOTS],
+            connections: HashMap::new()
+        };
+        client.refresh_slots().await?;
+        Ok(client)
+    }
+
+    pub async fn get(&mut self,key:&str) -> crate::Result<Option<Bytes>> {
+        let slot = cluster::key_slot(key.as_bytes());
+        match self.request(Some(slot), || Get{key:key.to_string()}.into_frame()).await? {
+            Frame::Bulk(bytes) => Ok(Some(bytes)),
+            Frame::Null => Ok(None),
+            frame => Err(frame.into_err())
+        }
+    }
+
+    pub async fn set(&mut self,key:&str,value:Bytes) -> crate::Result<()> {
+        let slot = cluster::key_slot(key.as_bytes());
+        match self.request(Some(slot), || Set{key:key.to_string(),value:value.clone(),expiration:None}.into_frame()).await? {
+            Frame::Simple(s) if s == "OK" => Ok(()),
+            frame => Err(frame.into_err())
+        }
+    }
+
+    //key可能分布在不同节点上，按slot拆成多条DEL，返回删除的总数
+    pub async fn del(&mut self,keys:&[&str]) -> crate::Result<u64> {
+        let mut groups:BTreeMap<u16,Vec<String>> = BTreeMap::new();
+        for key in keys {
+            groups.entry(cluster::key_slot(key.as_bytes())).or_default().push(key.to_string());
+        }
+        let mut count = 0;
+        for (slot,keys) in groups {
+            match self.request(Some(slot), || Del{keys:keys.clone()}.into_frame()).await? {
+                Frame::Integer(n) => count += n as u64,
+                frame => return Err(frame.into_err())
+            }
+        }
+        Ok(count)
+    }
+
+    pub fn cmd(&mut self,name:&str) -> ClusterCmd<'_> {
+        ClusterCmd { client: self, args: vec![Bytes::copy_from_slice(name.as_bytes())], slots: Vec::new() }
+    }
+
+    //重新拉取CLUSTER SLOTS，依次尝试已知的节点和seeds，有一个成功就行
+    pub async fn refresh_slots(&mut self) -> crate::Result<()> {
+        let mut addrs:Vec<String> = self.connections.keys().cloned().collect();
+        addrs.extend(self.seeds.iter().filter(|seed| !self.connections.contains_key(*seed)).cloned());
+        let mut last_err:crate::Error = "no cluster node available".into();
+        for addr in addrs {
+            let res = match self.connection(&addr).await {
+                Ok(client) => client.request(&command(&["CLUSTER", "SLOTS"])).await,
+                Err(err) => Err(err)
+            };
+            match res.and_then(parse_slots) {
+                Ok(slots) => {
+                    self.slots = slots;
+                    return Ok(());
+                }
+                Err(err) => {
+                    self.connections.remove(&addr);
+                    last_err = err;
+                }
+            }
+        }
+        Err(last_err)
+    }
+
+    //frame每次重试都要重新生成，slot为None时发给任意一个节点
+    async fn request(&mut self,slot:Option<u16>,frame:impl Fn() -> Frame) -> crate::Result<Frame> {
+        let mut ask:Option<String> = None;
+        let mut last_err:crate::Error = "too many cluster redirections".into();
+        for _ in 0..MAX_REDIRECTS {
+            let asking = ask.is_some();
+            let addr = match ask.take().or_else(|| self.slots[slot? as usize].clone()) {
+                Some(addr) => addr,
+                //slot还没有主，随便找一个节点，让它回复MOVED或者CLUSTERDOWN
+                None => match self.connections.keys().next().or_else(|| self.seeds.first()) {
+                    Some(addr) => addr.clone(),
+                    None => return Err("no cluster node available".into())
+                }
+            };
+            let res = match self.connection(&addr).await {
+                Ok(client) if asking => match client.request(&command(&["ASKING"])).await {
+                    Ok(_) => client.request(&frame()).await,
+                    Err(err) => Err(err)
+                },
+                Ok(client) => client.request(&frame()).await,
+                Err(err) => Err(err)
+            };
+            let err = match res {
+                Ok(frame) => return Ok(frame),
+                Err(err) => err
+            };
+            match Redirect::parse(&err.to_string()) {
+                Some(Redirect::Moved(moved,addr)) => {
+                    //集群拓扑变了，整个映射重新拉一遍
+                    self.slots[moved as usize] = Some(addr);
+                    let _ = self.refresh_slots().await;
+                }
+                Some(Redirect::Ask(addr)) => ask = Some(addr),
+                Some(Redirect::Retry) => time::sleep(RETRY_DELAY).await,
+                //连接断了，节点可能已经下线，换一个节点拉取映射之后重试
+                None if err.downcast_ref::<io::Error>().is_some() => {
+                    self.connections.remove(&addr);
+                    time::sleep(RETRY_DELAY).await;
+                    let _ = self.refresh_slots().await;
+                }
+                None => return Err(err)
+            }
+            last_err = err;
+        }
+        Err(last_err)
+    }
+
+    async fn connection(&mut self,addr:&str) -> crate::Result<&mut Client> {
+        if !self.connections.contains_key(addr) {
+            let client = Client::new(addr).await?;
+            self.connections.insert(addr.to_string(), client);
+        }
+        Ok(self.connections.get_mut(addr).unwrap())
+    }
+}
+
+impl<'a> ClusterCmd<'a> {
+    pub fn arg<T: ToArgs>(mut self,arg:T) -> Self {
+        arg.write_args(&mut self.args);
+        self
+    }
+
+    //作为key的参数，用来决定命令发到哪个节点
+    pub fn key<T: ToArgs>(mut self,key:T) -> Self {
+        let start = self.args.len();
+        key.write_args(&mut self.args);
+        self.slots.extend(self.args[start..].iter().map(|key| cluster::key_slot(key)));
+        self
+    }
+
+    //和get/set一样跟随MOVED/ASK，连接断开时换节点重发，非幂等的命令可能被执行两次
+    pub async fn query<T: FromReply>(self) -> crate::Result<T> {
+        let slot = match self.slots.split_first() {
+            Some((first,rest)) if rest.iter().any(|slot| slot != first) => {
+                return Err("CROSSSLOT Keys in request don't hash to the same slot".into());
+            }
+            first => first.map(|(slot,_)| *slot)
+        };
+        let args = self.args;
+        T::from_reply(self.client.request(slot, || Frame::Array(args.iter().cloned().map(Frame::Bulk).collect())).await?)
+    }
+}
+
+impl Redirect {
+    //MOVED slot host:port / ASK slot host:port / TRYAGAIN ... / CLUSTERDOWN ...
+    fn parse(err:&str) -> Option<Redirect> {
+        let mut parts = err.split_whitespace();
+        match parts.next()? {
+            "MOVED" => {
+                let slot = parts.next()?.parse().ok()?;
+                Some(Redirect::Moved(slot, parts.next()?.to_string()))
+            }
+            "ASK" => {
+                parts.next()?;
+                Some(Redirect::Ask(parts.next()?.to_string()))
+            }
+            "TRYAGAIN" | "CLUSTERDOWN" => Some(Redirect::Retry),
+            _ => None
+        }
+    }
+}
+
+//[[start,end,[host,port,id]],...]
+fn parse_slots(frame:Frame) -> crate::Result<Vec<Option<String>>> {
+    let ranges = match frame {
+        Frame::Array(ranges) => ranges,
+        frame => return Err(frame.into_err())
+    };
+    let mut slots = vec![None; SLOTS];
+    for range in ranges {
+        let (start,end,addr) = match range {
+            Frame::Array(v) => match v.as_slice() {
+                [Frame::Integer(start), Frame::Integer(end), Frame::Array(node), ..] => match node.as_slice() {
+                    [Frame::Bulk(host), Frame::Integer(port), ..] => (*start,*end,format!("{}:{}",String::from_utf8_lossy(host),port)),
+                    _ => return Err("invalid CLUSTER SLOTS node".into())
+                },
+                _ => return Err("invalid CLUSTER SLOTS range".into())
+            },
+            frame => return Err(frame.into_err())
+        };
+        if start < 0 || start > end || end as usize >= SLOTS {
+            return Err("invalid CLUSTER SLOTS range".into());
+        }
+        for slot in start..=end {
+            slots[slot as usize] = Some(addr.clone());
+        }
+    }
+    Ok(slots)
+}
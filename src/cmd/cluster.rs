@@ -0,0 +1,176 @@
+use bytes::Bytes;
+
+use crate::{parse::{Parse, ParseError}, db, connection::Connection, frame::Frame, cluster::{self, SLOTS}};
+
+//CLUSTER子命令，MEET需要连接对方，其它都只读写本节点的集群状态
+#[derive(Debug)]
+pub enum Cluster {
+    MyId,
+    Meet(String,u16),
+    AddSlots(Vec<u16>),
+    //[start,end]闭区间
+    AddSlotsRange(Vec<(u16,u16)>),
+    DelSlots(Vec<u16>),
+    Info,
+    Slots,
+    Shards,
+    Nodes,
+    KeySlot(Bytes),
+    CountKeysInSlot(u16),
+    SetSlot(u16,SlotState),
+    //slot,count
+    GetKeysInSlot(u16,u64)
+}
+
+//CLUSTER SETSLOT slot MIGRATING|IMPORTING|NODE node-id / STABLE
+#[derive(Debug)]
+pub enum SlotState {
+    Migrating(String),
+    Importing(String),
+    Node(String),
+    Stable
+}
+
+//ASKING，下一条命令可以访问本节点正在迁入的slot
+#[derive(Debug)]
+pub struct Asking;
+
+impl Cluster {
+    pub(crate) fn from_parse(parse:&mut Parse) -> crate::Result<Self> {
+        let sub = parse.next_string()?.to_lowercase();
+        let cmd = match &sub[..] {
+            "myid" => Cluster::MyId,
+            "meet" => {
+                let host = parse.next_string()?;
+                let port = parse.next_string()?.parse().map_err(|_| "Invalid TCP base port specified")?;
+                Cluster::Meet(host,port)
+            }
+            "addslots" => Cluster::AddSlots(parse_slots(parse)?),
+            "delslots" => Cluster::DelSlots(parse_slots(parse)?),
+            "addslotsrange" => {
+                let mut ranges = Vec::new();
+                while let Some(start) = next_slot(parse)? {
+                    let end = parse_slot(parse)?;
+                    if start > end {
+                        return Err(format!("start slot number {} is greater than end slot number {}",start,end).into());
+                    }
+                    ranges.push((start,end));
+                }
+                if ranges.is_empty() {
+                    return Err("wrong number of arguments for 'cluster|addslotsrange' command".into());
+                }
+                Cluster::AddSlotsRange(ranges)
+            }
+            "info" => Cluster::Info,
+            "slots" => Cluster::Slots,
+            "shards" => Cluster::Shards,
+            "nodes" => Cluster::Nodes,
+            "keyslot" => Cluster::KeySlot(parse.next_bytes()?),
+            "countkeysinslot" => Cluster::CountKeysInSlot(parse_slot(parse)?),
+            "setslot" => {
+                let slot = parse_slot(parse)?;
+                let sub = parse.next_string()?.to_lowercase();
+                let state = match &sub[..] {
+                    "migrating" => SlotState::Migrating(parse.next_string()?),
+                    "importing" => SlotState::Importing(parse.next_string()?),
+                    "node" => SlotState::Node(parse.next_string()?),
+                    "stable" => SlotState::Stable,
+                    _ => return Err("Invalid CLUSTER SETSLOT action or number of arguments.".into())
+                };
+                Cluster::SetSlot(slot,state)
+            }
+            "getkeysinslot" => {
+                let slot = parse_slot(parse)?;
+                let count = parse.next_int().map_err(|_| "Invalid number of keys")?;
+                Cluster::GetKeysInSlot(slot,count)
+            }
+            _ => return Err(format!("unknown subcommand '{}'",sub).into())
+        };
+        parse.finish()?;
+        Ok(cmd)
+    }
+    pub(crate) async fn apply(self,db:&db::Db,conn:&mut Connection) -> crate::Result<()> {
+        let response = match self {
+            Cluster::Meet(host,port) => match cluster::meet(db, &host, port).await {
+                Ok(()) => Frame::Simple("OK".to_string()),
+                Err(err) => Frame::Error(format!("ERR {}",err))
+            },
+            cmd => cmd.execute(&mut db.lock())
+        };
+        conn.write_frame(&response).await
+    }
+    pub(crate) fn execute(self,db:&mut db::DbGuard) -> Frame {
+        //KEYSLOT只是计算，不需要开启集群模式
+        if let Cluster::KeySlot(key) = &self {
+            return Frame::Integer(cluster::key_slot(key) as i64);
+        }
+        let cluster = match db.cluster() {
+            Some(cluster) => cluster,
+            None => return Frame::Error("ERR This instance has cluster support disabled".to_string())
+        };
+        let ok = |res:Result<(),String>| match res {
+            Ok(()) => Frame::Simple("OK".to_string()),
+            Err(err) => Frame::Error(err)
+        };
+        match self {
+            Cluster::MyId => Frame::Bulk(cluster.myself().into()),
+            Cluster::Meet(..) => Frame::Error("ERR Command not allowed inside a transaction".to_string()),
+            Cluster::AddSlots(slots) => ok(cluster.add_slots(&slots)),
+            Cluster::AddSlotsRange(ranges) => {
+                let slots:Vec<u16> = ranges.into_iter().flat_map(|(start,end)| start..=end).collect();
+                ok(cluster.add_slots(&slots))
+            }
+            Cluster::DelSlots(slots) => ok(cluster.del_slots(&slots)),
+            Cluster::Info => Frame::Bulk(cluster.info().into()),
+            Cluster::Slots => cluster.slots(),
+            Cluster::Shards => cluster.shards(),
+            Cluster::Nodes => Frame::Bulk(cluster.nodes().into()),
+            Cluster::CountKeysInSlot(slot) => Frame::Integer(db.count_keys_in_slot(slot) as i64),
+            Cluster::SetSlot(slot,state) => match state {
+                SlotState::Migrating(target) => ok(cluster.set_slot_migrating(slot, &target)),
+                SlotState::Importing(source) => ok(cluster.set_slot_importing(slot, &source)),
+                SlotState::Node(node) => ok(cluster.set_slot_node(slot, &node, db.count_keys_in_slot(slot))),
+                SlotState::Stable => {
+                    cluster.set_slot_stable(slot);
+                    Frame::Simple("OK".to_string())
+                }
+            },
+            Cluster::GetKeysInSlot(slot,count) => Frame::Array(
+                db.keys_in_slot(slot, count as usize).into_iter().map(|key| Frame::Bulk(key.into())).collect()
+            ),
+            Cluster::KeySlot(_) => unreachable!()
+        }
+    }
+}
+
+impl Asking {
+    pub(crate) fn from_parse(parse:&mut Parse) -> crate::Result<Self> {
+        parse.finish()?;
+        Ok(Asking)
+    }
+}
+
+fn parse_slot(parse:&mut Parse) -> crate::Result<u16> {
+    next_slot(parse)?.ok_or_else(|| ParseError::EndOfStream.into())
+}
+
+//没有更多参数时返回None
+fn next_slot(parse:&mut Parse) -> crate::Result<Option<u16>> {
+    let slot = match parse.next_int() {
+        Ok(slot) => slot,
+        Err(ParseError::EndOfStream) => return Ok(None),
+        Err(err) => return Err(err.into())
+    };
+    if slot as usize >= SLOTS {
+        return Err("Invalid or out of range slot".into());
+    }
+    Ok(Some(slot as u16))
+}
+
+fn parse_slots(parse:&mut Parse) -> crate::Result<Vec<u16>> {
+    let mut slots = vec![parse_slot(parse)?];
+    while let Some(slot) = next_slot(parse)? {
+        slots.push(slot);
+    }
+    Ok(slots)
+}
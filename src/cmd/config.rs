@@ -0,0 +1,49 @@
+use bytes::Bytes;
+
+use crate::{parse::Parse, db, connection::Connection, frame::Frame, notify::NotifyFlags};
+
+//CONFIG GET/SET，目前只支持notify-keyspace-events
+#[derive(Debug)]
+pub enum Config {
+    Get(String),
+    Set(String,String)
+}
+
+impl Config {
+    pub(crate) fn from_parse(parse:&mut Parse) -> crate::Result<Self> {
+        let sub = parse.next_string()?.to_lowercase();
+        let cmd = match &sub[..] {
+            "get" => Config::Get(parse.next_string()?.to_lowercase()),
+            "set" => Config::Set(parse.next_string()?.to_lowercase(), parse.next_string()?),
+            _ => return Err(format!("unknown subcommand '{}'",sub).into())
+        };
+        parse.finish()?;
+        Ok(cmd)
+    }
+    pub(crate) async fn apply(self,db:&db::Db,conn:&mut Connection) -> crate::Result<()> {
+        let response = self.execute(&mut db.lock());
+        conn.write_frame(&response).await
+    }
+    pub(crate) fn execute(self,db:&mut db::DbGuard) -> Frame {
+        match self {
+            Config::Get(name) => {
+                let mut v = Vec::new();
+                if crate::db::glob_match(&name, "notify-keyspace-events") {
+                    v.push(Frame::Bulk(Bytes::from_static(b"notify-keyspace-events")));
+                    v.push(Frame::Bulk(db.notify_flags().to_string().into()));
+                }
+                Frame::Array(v)
+            }
+            Config::Set(name,value) => match &name[..] {
+                "notify-keyspace-events" => match NotifyFlags::parse(&value) {
+                    Ok(flags) => {
+                        db.set_notify_flags(flags);
+                        Frame::Simple("OK".to_string())
+                    }
+                    Err(err) => Frame::Error(format!("ERR Invalid argument '{}' for CONFIG SET 'notify-keyspace-events' - {}",value,err))
+                },
+                _ => Frame::Error(format!("ERR Unknown option or number of arguments for CONFIG SET - '{}'",name))
+            }
+        }
+    }
+}
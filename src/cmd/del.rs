@@ -0,0 +1,33 @@
+use crate::{parse::{Parse, ParseError}, db, connection::Connection, frame::Frame};
+
+#[derive(Debug)]
+pub struct Del {
+    pub(crate) keys:Vec<String>
+}
+
+impl Del {
+    pub(crate) fn from_parse(parse:&mut Parse) -> crate::Result<Self> {
+        let mut keys = vec![parse.next_string()?];
+        loop {
+            match parse.next_string() {
+                Ok(key) => keys.push(key),
+                Err(ParseError::EndOfStream) => break,
+                Err(err) => return Err(err.into())
+            }
+        }
+        Ok(Self { keys })
+    }
+    pub(crate) async fn apply(self,db:&db::Db,conn:&mut Connection) -> crate::Result<()> {
+        let response = self.execute(&mut db.lock());
+        conn.write_frame(&response).await
+    }
+    pub(crate) fn execute(self,db:&mut db::DbGuard) -> Frame {
+        let count = self.keys.iter().filter(|key| db.remove(key)).count();
+        Frame::Integer(count as i64)
+    }
+    pub(crate) fn into_frame(self) -> Frame {
+        let mut v = vec![Frame::Bulk("DEL".into())];
+        v.extend(self.keys.into_iter().map(|key| Frame::Bulk(key.into())));
+        Frame::Array(v)
+    }
+}
@@ -0,0 +1,118 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use tokio::time::Instant;
+
+use crate::{parse::{Parse, ParseError}, db, connection::Connection, frame::Frame, notify::NotifyFlags, rdb, snapshot::unix_time_millis};
+
+//DUMP key，把值序列化成和redis兼容的payload，key不存在返回null
+#[derive(Debug)]
+pub struct Dump {
+    pub(crate) key:String
+}
+
+//RESTORE key ttl payload [REPLACE] [ABSTTL] [IDLETIME seconds] [FREQ frequency]
+#[derive(Debug)]
+pub struct Restore {
+    pub(crate) key:String,
+    //毫秒，0表示不过期，ABSTTL时是unix毫秒时间戳
+    pub(crate) ttl:u64,
+    pub(crate) payload:Bytes,
+    pub(crate) replace:bool,
+    pub(crate) absttl:bool,
+    pub(crate) idletime:Option<u64>,
+    pub(crate) freq:Option<u8>
+}
+
+impl Dump {
+    pub(crate) fn from_parse(parse:&mut Parse) -> crate::Result<Self> {
+        let key = parse.next_string()?;
+        parse.finish()?;
+        Ok(Self { key })
+    }
+    pub(crate) async fn apply(self,db:&db::Db,conn:&mut Connection) -> crate::Result<()> {
+        let response = self.execute(&mut db.lock());
+        conn.write_frame(&response).await
+    }
+    pub(crate) fn execute(self,db:&mut db::DbGuard) -> Frame {
+        if db.version(&self.key).is_none() {
+            return Frame::Null;
+        }
+        match db.get(&self.key) {
+            Some(value) => Frame::Bulk(rdb::dump_value(&value)),
+            None => Frame::Null
+        }
+    }
+}
+
+impl Restore {
+    pub(crate) fn from_parse(parse:&mut Parse) -> crate::Result<Self> {
+        let key = parse.next_string()?;
+        let ttl = parse.next_int().map_err(|_| "Invalid TTL value, must be >= 0")?;
+        let payload = parse.next_bytes()?;
+        let mut restore = Self { key, ttl, payload, replace: false, absttl: false, idletime: None, freq: None };
+        loop {
+            match parse.next_string() {
+                Ok(arg) if arg.eq_ignore_ascii_case("replace") => restore.replace = true,
+                Ok(arg) if arg.eq_ignore_ascii_case("absttl") => restore.absttl = true,
+                //IDLETIME和FREQ不能同时使用
+                Ok(arg) if arg.eq_ignore_ascii_case("idletime") && restore.freq.is_none() => {
+                    let idle = parse.next_int().map_err(|_| "Invalid IDLETIME value, must be >= 0")?;
+                    restore.idletime = Some(idle);
+                }
+                Ok(arg) if arg.eq_ignore_ascii_case("freq") && restore.idletime.is_none() => {
+                    let freq = parse.next_int().ok().filter(|&freq| freq <= 255)
+                        .ok_or("Invalid FREQ value, must be >= 0 and <= 255")?;
+                    restore.freq = Some(freq as u8);
+                }
+                Ok(_) => return Err("syntax error".into()),
+                Err(ParseError::EndOfStream) => break,
+                Err(err) => return Err(err.into())
+            }
+        }
+        Ok(restore)
+    }
+    pub(crate) async fn apply(self,db:&db::Db,conn:&mut Connection) -> crate::Result<()> {
+        let response = self.execute(&mut db.lock());
+        conn.write_frame(&response).await
+    }
+    pub(crate) fn execute(self,db:&mut db::DbGuard) -> Frame {
+        if !self.replace && db.version(&self.key).is_some() {
+            return Frame::Error("BUSYKEY Target key name already exists.".to_string());
+        }
+        let value = match rdb::restore_value(&self.payload) {
+            Ok(value) => value,
+            Err(err) => return Frame::Error(err)
+        };
+        let expiration_at = match (self.ttl, self.absttl) {
+            (0, _) => None,
+            (at, true) => {
+                let now = unix_time_millis();
+                //绝对时间已经过去，相当于写入之后立即过期
+                if at <= now {
+                    db.remove(&self.key);
+                    return Frame::Simple("OK".to_string());
+                }
+                Some(expire_at(at - now, at))
+            }
+            (ttl, false) => Some(expire_at(ttl, ttl.saturating_add(unix_time_millis())))
+        };
+        //持有db锁时不能panic，太大的ttl直接拒绝
+        let expiration_at = match expiration_at {
+            Some(None) => return Frame::Error("ERR Invalid TTL value, must be >= 0".to_string()),
+            at => at.flatten()
+        };
+        db.set_value(self.key.clone(), value, expiration_at);
+        db.set_access_info(&self.key, self.idletime.map(Duration::from_secs), self.freq);
+        db.notify_keyspace_event(NotifyFlags::GENERIC, "restore", &self.key);
+        Frame::Simple("OK".to_string())
+    }
+}
+
+//ttl毫秒之后的Instant，换算成unix毫秒(at)之后也不能超过i64，否则返回None
+fn expire_at(ttl:u64,at:u64) -> Option<Instant> {
+    if at > i64::MAX as u64 {
+        return None;
+    }
+    Instant::now().checked_add(Duration::from_millis(ttl))
+}
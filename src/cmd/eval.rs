@@ -0,0 +1,98 @@
+use bytes::Bytes;
+
+use crate::{parse::{Parse, ParseError}, db, connection::Connection, frame::Frame};
+
+#[derive(Debug)]
+pub struct Eval {
+    pub(crate) script:String,
+    pub(crate) keys:Vec<Bytes>,
+    pub(crate) args:Vec<Bytes>
+}
+#[derive(Debug)]
+pub struct EvalSha {
+    pub(crate) sha1:String,
+    pub(crate) keys:Vec<Bytes>,
+    pub(crate) args:Vec<Bytes>
+}
+
+impl Eval {
+    pub(crate) fn from_parse(parse:&mut Parse) -> crate::Result<Self> {
+        let script = parse.next_string()?;
+        let (keys,args) = parse_keys_and_args(parse)?;
+        Ok(Self { script, keys, args })
+    }
+    //脚本可能执行很久，放到blocking线程上跑，避免卡住其它连接(包括SCRIPT KILL)
+    pub(crate) async fn apply(self,db:&db::Db,conn:&mut Connection) -> crate::Result<()> {
+        let db = db.clone();
+        let response = tokio::task::spawn_blocking(move || self.execute(&mut db.lock())).await?;
+        conn.write_frame(&response).await
+    }
+    pub(crate) fn execute(self,db:&mut db::DbGuard) -> Frame {
+        db.scripts().eval(db, self.script, self.keys, self.args)
+    }
+}
+
+impl EvalSha {
+    pub(crate) fn from_parse(parse:&mut Parse) -> crate::Result<Self> {
+        let sha1 = parse.next_string()?;
+        let (keys,args) = parse_keys_and_args(parse)?;
+        Ok(Self { sha1, keys, args })
+    }
+    //脚本可能执行很久，放到blocking线程上跑，避免卡住其它连接(包括SCRIPT KILL)
+    pub(crate) async fn apply(self,db:&db::Db,conn:&mut Connection) -> crate::Result<()> {
+        let db = db.clone();
+        let response = tokio::task::spawn_blocking(move || self.execute(&mut db.lock())).await?;
+        conn.write_frame(&response).await
+    }
+    pub(crate) fn execute(self,db:&mut db::DbGuard) -> Frame {
+        db.scripts().evalsha(db, &self.sha1, self.keys, self.args)
+    }
+}
+
+//FCALL和FCALL_RO，read_only时函数必须带no-writes标记
+#[derive(Debug)]
+pub struct Fcall {
+    pub(crate) function:String,
+    pub(crate) keys:Vec<Bytes>,
+    pub(crate) args:Vec<Bytes>,
+    pub(crate) read_only:bool
+}
+
+impl Fcall {
+    pub(crate) fn from_parse(parse:&mut Parse,read_only:bool) -> crate::Result<Self> {
+        let function = parse.next_string()?;
+        let (keys,args) = parse_keys_and_args(parse)?;
+        Ok(Self { function, keys, args, read_only })
+    }
+    pub(crate) async fn apply(self,db:&db::Db,conn:&mut Connection) -> crate::Result<()> {
+        let db = db.clone();
+        let response = tokio::task::spawn_blocking(move || self.execute(&mut db.lock())).await?;
+        conn.write_frame(&response).await
+    }
+    pub(crate) fn execute(self,db:&mut db::DbGuard) -> Frame {
+        db.scripts().fcall(db, &self.function, self.read_only, self.keys, self.args)
+    }
+}
+
+// numkeys key [key ...] arg [arg ...]
+fn parse_keys_and_args(parse:&mut Parse) -> crate::Result<(Vec<Bytes>,Vec<Bytes>)> {
+    let numkeys = match parse.next_string()?.parse::<i64>() {
+        Ok(n) if n < 0 => return Err("Number of keys can't be negative".into()),
+        Ok(n) if n as u64 > parse.remaining() as u64 => return Err("Number of keys can't be greater than number of args".into()),
+        Ok(n) => n as usize,
+        Err(_) => return Err("value is not an integer or out of range".into())
+    };
+    let mut keys = Vec::with_capacity(numkeys);
+    for _ in 0..numkeys {
+        keys.push(parse.next_bytes()?);
+    }
+    let mut args = Vec::new();
+    loop {
+        match parse.next_bytes() {
+            Ok(arg) => args.push(arg),
+            Err(ParseError::EndOfStream) => break,
+            Err(err) => return Err(err.into())
+        }
+    }
+    Ok((keys,args))
+}
@@ -0,0 +1,136 @@
+use bytes::Bytes;
+
+use crate::{parse::{Parse, ParseError}, db, connection::Connection, frame::Frame, script::{Scripts, RestorePolicy}};
+
+//FUNCTION LOAD/LIST/DELETE/FLUSH/DUMP/RESTORE，管理函数库
+#[derive(Debug)]
+pub enum Function {
+    Load { code:String, replace:bool },
+    List { pattern:Option<String>, with_code:bool },
+    Delete(String),
+    Flush,
+    Dump,
+    Restore { payload:Bytes, policy:RestorePolicy }
+}
+
+impl Function {
+    pub(crate) fn from_parse(parse:&mut Parse) -> crate::Result<Self> {
+        let sub = parse.next_string()?.to_lowercase();
+        let cmd = match &sub[..] {
+            "load" => {
+                let mut code = parse.next_string()?;
+                let mut replace = false;
+                if code.eq_ignore_ascii_case("replace") {
+                    replace = true;
+                    code = parse.next_string()?;
+                }
+                Function::Load { code, replace }
+            }
+            "list" => {
+                let mut pattern = None;
+                let mut with_code = false;
+                loop {
+                    match parse.next_string() {
+                        Ok(arg) if arg.eq_ignore_ascii_case("withcode") => with_code = true,
+                        Ok(arg) if arg.eq_ignore_ascii_case("libraryname") => pattern = Some(parse.next_string()?),
+                        Ok(arg) => return Err(format!("Unknown argument {}",arg).into()),
+                        Err(ParseError::EndOfStream) => break,
+                        Err(err) => return Err(err.into())
+                    }
+                }
+                Function::List { pattern, with_code }
+            }
+            "delete" => Function::Delete(parse.next_string()?),
+            "flush" => {
+                //ASYNC/SYNC参数可以忽略
+                let _ = parse.next_string();
+                Function::Flush
+            }
+            "dump" => Function::Dump,
+            "restore" => {
+                let payload = parse.next_bytes()?;
+                let policy = match parse.next_string() {
+                    Ok(p) if p.eq_ignore_ascii_case("append") => RestorePolicy::Append,
+                    Ok(p) if p.eq_ignore_ascii_case("replace") => RestorePolicy::Replace,
+                    Ok(p) if p.eq_ignore_ascii_case("flush") => RestorePolicy::Flush,
+                    Ok(p) => return Err(format!("Wrong restore policy given, value should be either FLUSH, APPEND or REPLACE, got {}",p).into()),
+                    Err(ParseError::EndOfStream) => RestorePolicy::Append,
+                    Err(err) => return Err(err.into())
+                };
+                Function::Restore { payload, policy }
+            }
+            _ => return Err(format!("unknown subcommand '{}'",sub).into())
+        };
+        parse.finish()?;
+        Ok(cmd)
+    }
+    //LOAD和RESTORE要执行库的代码，和EVAL一样放到blocking线程上跑
+    pub(crate) async fn apply(self,db:&db::Db,conn:&mut Connection) -> crate::Result<()> {
+        let response = match self {
+            Function::Load { .. } | Function::Restore { .. } => {
+                let db = db.clone();
+                tokio::task::spawn_blocking(move || self.execute(&mut db.lock())).await?
+            }
+            _ => self.execute(&mut db.lock())
+        };
+        conn.write_frame(&response).await
+    }
+    //修改函数库成功之后把命令原样记录下来，重放时重新加载
+    pub(crate) fn execute(self,db:&mut db::DbGuard) -> Frame {
+        let frame = if self.is_write() { self.to_frame() } else { None };
+        let response = self.run(db.scripts());
+        if let (Some(frame), false) = (frame, matches!(response, Frame::Error(_))) {
+            db.propagate(|| frame);
+        }
+        response
+    }
+    fn run(self,scripts:&Scripts) -> Frame {
+        match self {
+            Function::Load { code, replace } => match scripts.function_load(code, replace) {
+                Ok(name) => Frame::Bulk(name.into()),
+                Err(err) => Frame::Error(err)
+            },
+            Function::List { pattern, with_code } => scripts.function_list(pattern.as_deref(), with_code),
+            Function::Delete(name) => {
+                if scripts.function_delete(&name) {
+                    Frame::Simple("OK".to_string())
+                } else {
+                    Frame::Error("ERR Library not found".to_string())
+                }
+            }
+            Function::Flush => {
+                scripts.function_flush();
+                Frame::Simple("OK".to_string())
+            }
+            Function::Dump => Frame::Bulk(scripts.function_dump()),
+            Function::Restore { payload, policy } => match scripts.function_restore(&payload, policy) {
+                Ok(()) => Frame::Simple("OK".to_string()),
+                Err(err) => Frame::Error(err)
+            }
+        }
+    }
+    fn to_frame(&self) -> Option<Frame> {
+        let args:Vec<Bytes> = match self {
+            Function::Load { code, replace: true } => vec!["LOAD".into(), "REPLACE".into(), code.clone().into()],
+            Function::Load { code, replace: false } => vec!["LOAD".into(), code.clone().into()],
+            Function::Delete(name) => vec!["DELETE".into(), name.clone().into()],
+            Function::Flush => vec!["FLUSH".into()],
+            Function::Restore { payload, policy } => {
+                let policy = match policy {
+                    RestorePolicy::Append => "APPEND",
+                    RestorePolicy::Replace => "REPLACE",
+                    RestorePolicy::Flush => "FLUSH"
+                };
+                vec!["RESTORE".into(), payload.clone(), policy.into()]
+            }
+            Function::List { .. } | Function::Dump => return None
+        };
+        let mut v = vec![Frame::Bulk("FUNCTION".into())];
+        v.extend(args.into_iter().map(Frame::Bulk));
+        Some(Frame::Array(v))
+    }
+    //只有LIST和DUMP不修改函数库
+    pub(crate) fn is_write(&self) -> bool {
+        !matches!(self, Function::List { .. } | Function::Dump)
+    }
+}
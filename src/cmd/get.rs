@@ -1,5 +1,6 @@
 use crate::{parse::Parse, db, connection::Connection, frame::Frame};
 
+#[derive(Debug)]
 pub struct Get{
     pub(crate) key:String
 }
@@ -15,15 +16,28 @@ impl Get {
         let value = db.get(&self.key);
         match value {
             None => conn.write_null().await?,
-            Some(v) => conn.write_bytes(&v).await?
+            Some(db::Value::String(v)) => conn.write_bytes(&v).await?,
+            Some(_) => conn.write_frame(&wrong_type()).await?
         }
         
         Ok(())
     }
+    pub(crate) fn execute(self,db:&mut db::DbGuard) -> Frame {
+        match db.get(&self.key) {
+            None => Frame::Null,
+            Some(db::Value::String(v)) => Frame::Bulk(v),
+            Some(_) => wrong_type()
+        }
+    }
     pub(crate) fn into_frame(self) -> Frame {
-        let mut v = Vec::new();
-        v.push(Frame::Simple("GET".to_string()));
-        v.push(Frame::Simple(self.key.to_string()));
+        let v = vec![
+            Frame::Bulk("GET".into()),
+            Frame::Bulk(self.key.into()),
+        ];
         Frame::Array(v)
     }
-}
\ No newline at end of file
+}
+
+fn wrong_type() -> Frame {
+    Frame::Error("WRONGTYPE Operation against a key holding the wrong kind of value".to_string())
+}
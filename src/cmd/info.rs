@@ -0,0 +1,55 @@
+use std::{fmt::Write, sync::atomic::Ordering};
+
+use crate::{parse::{Parse, ParseError}, db, connection::Connection, frame::Frame};
+
+//INFO [section]，目前有server、clients、stats、replication和keyspace几部分
+#[derive(Debug)]
+pub struct Info {
+    pub(crate) section:Option<String>
+}
+
+impl Info {
+    pub(crate) fn from_parse(parse:&mut Parse) -> crate::Result<Self> {
+        let section = match parse.next_string() {
+            Ok(section) => Some(section.to_lowercase()),
+            Err(ParseError::EndOfStream) => None,
+            Err(err) => return Err(err.into())
+        };
+        parse.finish()?;
+        Ok(Self { section })
+    }
+    pub(crate) async fn apply(self,db:&db::Db,conn:&mut Connection) -> crate::Result<()> {
+        let response = self.execute(&mut db.lock());
+        conn.write_frame(&response).await
+    }
+    pub(crate) fn execute(self,db:&mut db::DbGuard) -> Frame {
+        let all = matches!(self.section.as_deref(), None | Some("all") | Some("default") | Some("everything"));
+        let mut sections = Vec::new();
+        if all || self.section.as_deref() == Some("server") {
+            let mut s = String::from("# Server\r\n");
+            let _ = write!(s, "my_redis_version:{}\r\nprocess_id:{}\r\nrun_id:{}\r\n",
+                env!("CARGO_PKG_VERSION"), std::process::id(), db.replication().run_id());
+            sections.push(s);
+        }
+        let counters = db.counters();
+        if all || self.section.as_deref() == Some("clients") {
+            sections.push(format!("# Clients\r\nconnected_clients:{}\r\n", counters.connected_clients.load(Ordering::Relaxed)));
+        }
+        if all || self.section.as_deref() == Some("stats") {
+            sections.push(format!("# Stats\r\ntotal_connections_received:{}\r\ntotal_commands_processed:{}\r\n",
+                counters.total_connections.load(Ordering::Relaxed), counters.total_commands.load(Ordering::Relaxed)));
+        }
+        if all || self.section.as_deref() == Some("replication") {
+            sections.push(db.replication().info());
+        }
+        if all || self.section.as_deref() == Some("keyspace") {
+            let mut s = String::from("# Keyspace\r\n");
+            let (keys,expires) = db.key_count();
+            if keys > 0 {
+                let _ = write!(s, "db0:keys={},expires={}\r\n", keys, expires);
+            }
+            sections.push(s);
+        }
+        Frame::Bulk(sections.join("\r\n").into())
+    }
+}
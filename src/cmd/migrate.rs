@@ -0,0 +1,149 @@
+use std::{io, time::Duration};
+
+use bytes::Bytes;
+use tokio::time;
+
+use crate::{parse::{Parse, ParseError}, db, connection::Connection, frame::Frame, client::{Client, command}, rdb};
+
+//序列化之后key又被改过时重新发送的最多次数
+const MAX_ROUNDS:usize = 5;
+
+//MIGRATE host port key|"" destination-db timeout [COPY] [REPLACE] [KEYS key ...]
+//用DUMP/RESTORE的payload把key搬到另一个节点，对方写入成功之后再删除本地的key
+#[derive(Debug)]
+pub struct Migrate {
+    pub(crate) host:String,
+    pub(crate) port:u16,
+    pub(crate) keys:Vec<String>,
+    //毫秒，0表示用默认的1秒
+    pub(crate) timeout:u64,
+    pub(crate) copy:bool,
+    pub(crate) replace:bool
+}
+
+impl Migrate {
+    pub(crate) fn from_parse(parse:&mut Parse) -> crate::Result<Self> {
+        let host = parse.next_string()?;
+        let port = parse.next_string()?.parse().map_err(|_| "Invalid TCP port")?;
+        let key = parse.next_string()?;
+        //只有一个db
+        if parse.next_int()? != 0 {
+            return Err("invalid DB index".into());
+        }
+        let timeout = parse.next_int()?;
+        let mut migrate = Self { host, port, keys: Vec::new(), timeout, copy: false, replace: false };
+        loop {
+            match parse.next_string() {
+                Ok(arg) if arg.eq_ignore_ascii_case("copy") => migrate.copy = true,
+                Ok(arg) if arg.eq_ignore_ascii_case("replace") => migrate.replace = true,
+                //KEYS之后的参数都是key，这时前面的key必须是空字符串
+                Ok(arg) if arg.eq_ignore_ascii_case("keys") && key.is_empty() => {
+                    loop {
+                        match parse.next_string() {
+                            Ok(key) => migrate.keys.push(key),
+                            Err(ParseError::EndOfStream) => break,
+                            Err(err) => return Err(err.into())
+                        }
+                    }
+                    break;
+                }
+                Ok(_) => return Err("syntax error".into()),
+                Err(ParseError::EndOfStream) => break,
+                Err(err) => return Err(err.into())
+            }
+        }
+        if !key.is_empty() {
+            migrate.keys.push(key);
+        }
+        if migrate.keys.is_empty() {
+            return Err("syntax error".into());
+        }
+        Ok(migrate)
+    }
+    pub(crate) async fn apply(self,db:&db::Db,conn:&mut Connection) -> crate::Result<()> {
+        let response = match self.migrate(db).await {
+            Ok(true) => Frame::Simple("OK".to_string()),
+            Ok(false) => Frame::Simple("NOKEY".to_string()),
+            Err(err) => Frame::Error(err)
+        };
+        conn.write_frame(&response).await
+    }
+    //不能在持有锁的时候访问网络，发送期间key被改了就把改过的key再发一次
+    async fn migrate(self,db:&db::Db) -> Result<bool,String> {
+        let timeout = if self.timeout == 0 { Duration::from_secs(1) } else { Duration::from_millis(self.timeout) };
+        let mut client:Option<Client> = None;
+        let mut pending = self.keys;
+        for round in 0..MAX_ROUNDS {
+            //(key,发送时的版本号)，None表示key在上一轮之后被删掉了，对方也要删掉
+            let batch:Vec<(String,Option<u64>,Frame)> = {
+                let mut db = db.lock();
+                pending.into_iter().filter_map(|key| match db.version(&key) {
+                    Some(version) => {
+                        let payload = rdb::dump_value(&db.get(&key)?);
+                        let frame = restore_command(&key, db.expire_at(&key), payload, self.replace || round > 0);
+                        Some((key,Some(version),frame))
+                    }
+                    None if round > 0 => {
+                        let frame = command(&["DEL", &key]);
+                        Some((key,None,frame))
+                    }
+                    None => None
+                }).collect()
+            };
+            if batch.is_empty() {
+                return Ok(round > 0);
+            }
+            if client.is_none() {
+                let connect = time::timeout(timeout, Client::new((self.host.as_str(),self.port))).await;
+                match connect {
+                    Ok(Ok(c)) => client = Some(c),
+                    _ => return Err("IOERR error or timeout connecting to the client".to_string()),
+                }
+            }
+            let c = client.as_mut().unwrap();
+            for (_,_,frame) in &batch {
+                //目标节点上slot还处于迁入状态，需要先发ASKING
+                request(c, &command(&["ASKING"]), timeout).await?;
+                request(c, frame, timeout).await?;
+            }
+            let mut changed = Vec::new();
+            let mut db = db.lock();
+            for (key,version,_) in batch {
+                if db.version(&key) != version {
+                    changed.push(key);
+                } else if !self.copy && version.is_some() {
+                    db.remove(&key);
+                }
+            }
+            if changed.is_empty() {
+                return Ok(true);
+            }
+            pending = changed;
+        }
+        Err("ERR Keys kept being modified during MIGRATE".to_string())
+    }
+}
+
+async fn request(client:&mut Client,frame:&Frame,timeout:Duration) -> Result<Frame,String> {
+    match time::timeout(timeout, client.request(frame)).await {
+        Err(_) => Err("IOERR error or timeout reading to target instance".to_string()),
+        Ok(Err(err)) if err.downcast_ref::<io::Error>().is_some() => Err(format!("IOERR error or timeout writing to target instance: {}",err)),
+        Ok(Err(err)) => Err(format!("ERR Target instance replied with error: {}",err)),
+        Ok(Ok(frame)) => Ok(frame)
+    }
+}
+
+//RESTORE key 过期时间 payload ABSTTL [REPLACE]
+fn restore_command(key:&str,expire_at:Option<u64>,payload:Bytes,replace:bool) -> Frame {
+    let mut v = vec![
+        Frame::Bulk(Bytes::from_static(b"RESTORE")),
+        Frame::Bulk(Bytes::copy_from_slice(key.as_bytes())),
+        Frame::Bulk(expire_at.unwrap_or(0).to_string().into()),
+        Frame::Bulk(payload),
+        Frame::Bulk(Bytes::from_static(b"ABSTTL")),
+    ];
+    if replace {
+        v.push(Frame::Bulk(Bytes::from_static(b"REPLACE")));
+    }
+    Frame::Array(v)
+}
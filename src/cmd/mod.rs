@@ -3,11 +3,84 @@ use crate::{frame::Frame, parse::Parse, db, connection::Connection, shutdown::Sh
 
  mod get;
  mod set;
+ mod multi;
+ mod watch;
+ mod eval;
+ mod script;
+ mod function;
+ mod del;
+ mod publish;
+ mod subscribe;
+ mod config;
+ mod save;
+ mod dump;
+ mod object;
+ mod ping;
+ mod info;
+ mod replication;
+ mod cluster;
+ mod migrate;
+ mod scan;
  pub use set::Set;
  pub use get::Get;
+ pub use multi::{Multi, Exec, Discard};
+ pub use watch::{Watch, Unwatch};
+ pub use eval::{Eval, EvalSha, Fcall};
+ pub use script::Script;
+ pub use function::Function;
+ pub use del::Del;
+ pub use publish::Publish;
+ pub use subscribe::{Subscribe, Unsubscribe, PSubscribe, PUnsubscribe};
+ pub use config::Config;
+ pub use save::{Save, BgSave, LastSave, BgRewriteAof};
+ pub use dump::{Dump, Restore};
+ pub use object::Object;
+ pub use ping::Ping;
+ pub use info::Info;
+ pub use replication::{ReplicaOf, Psync, ReplConf, Wait};
+ pub use cluster::{Cluster, SlotState, Asking};
+ pub use migrate::Migrate;
+ pub use scan::{Scan, Type, Len};
+#[derive(Debug)]
 pub(crate) enum Command {
     Get(Get),
-    Set(Set)
+    Set(Set),
+    Multi(Multi),
+    Exec(Exec),
+    Discard(Discard),
+    Watch(Watch),
+    Unwatch(Unwatch),
+    Eval(Eval),
+    EvalSha(EvalSha),
+    Script(Script),
+    Function(Function),
+    Fcall(Fcall),
+    Del(Del),
+    Publish(Publish),
+    Subscribe(Subscribe),
+    Unsubscribe(Unsubscribe),
+    PSubscribe(PSubscribe),
+    PUnsubscribe(PUnsubscribe),
+    Config(Config),
+    Save(Save),
+    BgSave(BgSave),
+    LastSave(LastSave),
+    BgRewriteAof(BgRewriteAof),
+    Dump(Dump),
+    Restore(Restore),
+    Object(Object),
+    Ping(Ping),
+    Info(Info),
+    ReplicaOf(ReplicaOf),
+    Psync(Psync),
+    ReplConf(ReplConf),
+    Wait(Wait),
+    Cluster(Cluster),
+    Asking(Asking),
+    Migrate(Migrate),
+    Scan(Scan),
+    Type(Type),
+    Len(Len)
 }
 
 impl Command {
@@ -21,6 +94,117 @@ impl Command {
             "set" => {
                 Ok(Self::Set(Set::from_parse(&mut parse)?))
             },
+            "multi" => {
+                Ok(Self::Multi(Multi::from_parse(&mut parse)?))
+            },
+            "exec" => {
+                Ok(Self::Exec(Exec::from_parse(&mut parse)?))
+            },
+            "discard" => {
+                Ok(Self::Discard(Discard::from_parse(&mut parse)?))
+            },
+            "watch" => {
+                Ok(Self::Watch(Watch::from_parse(&mut parse)?))
+            },
+            "unwatch" => {
+                Ok(Self::Unwatch(Unwatch::from_parse(&mut parse)?))
+            },
+            "eval" => {
+                Ok(Self::Eval(Eval::from_parse(&mut parse)?))
+            },
+            "evalsha" => {
+                Ok(Self::EvalSha(EvalSha::from_parse(&mut parse)?))
+            },
+            "script" => {
+                Ok(Self::Script(Script::from_parse(&mut parse)?))
+            },
+            "function" => {
+                Ok(Self::Function(Function::from_parse(&mut parse)?))
+            },
+            "fcall" => {
+                Ok(Self::Fcall(Fcall::from_parse(&mut parse,false)?))
+            },
+            "fcall_ro" => {
+                Ok(Self::Fcall(Fcall::from_parse(&mut parse,true)?))
+            },
+            "del" => {
+                Ok(Self::Del(Del::from_parse(&mut parse)?))
+            },
+            "publish" => {
+                Ok(Self::Publish(Publish::from_parse(&mut parse)?))
+            },
+            "subscribe" => {
+                Ok(Self::Subscribe(Subscribe::from_parse(&mut parse)?))
+            },
+            "unsubscribe" => {
+                Ok(Self::Unsubscribe(Unsubscribe::from_parse(&mut parse)?))
+            },
+            "psubscribe" => {
+                Ok(Self::PSubscribe(PSubscribe::from_parse(&mut parse)?))
+            },
+            "punsubscribe" => {
+                Ok(Self::PUnsubscribe(PUnsubscribe::from_parse(&mut parse)?))
+            },
+            "config" => {
+                Ok(Self::Config(Config::from_parse(&mut parse)?))
+            },
+            "save" => {
+                Ok(Self::Save(Save::from_parse(&mut parse)?))
+            },
+            "bgsave" => {
+                Ok(Self::BgSave(BgSave::from_parse(&mut parse)?))
+            },
+            "lastsave" => {
+                Ok(Self::LastSave(LastSave::from_parse(&mut parse)?))
+            },
+            "bgrewriteaof" => {
+                Ok(Self::BgRewriteAof(BgRewriteAof::from_parse(&mut parse)?))
+            },
+            "dump" => {
+                Ok(Self::Dump(Dump::from_parse(&mut parse)?))
+            },
+            "restore" => {
+                Ok(Self::Restore(Restore::from_parse(&mut parse)?))
+            },
+            "object" => {
+                Ok(Self::Object(Object::from_parse(&mut parse)?))
+            },
+            "ping" => {
+                Ok(Self::Ping(Ping::from_parse(&mut parse)?))
+            },
+            "info" => {
+                Ok(Self::Info(Info::from_parse(&mut parse)?))
+            },
+            "replicaof" | "slaveof" => {
+                Ok(Self::ReplicaOf(ReplicaOf::from_parse(&mut parse)?))
+            },
+            "psync" => {
+                Ok(Self::Psync(Psync::from_parse(&mut parse)?))
+            },
+            "replconf" => {
+                Ok(Self::ReplConf(ReplConf::from_parse(&mut parse)?))
+            },
+            "wait" => {
+                Ok(Self::Wait(Wait::from_parse(&mut parse)?))
+            },
+            "cluster" => {
+                Ok(Self::Cluster(Cluster::from_parse(&mut parse)?))
+            },
+            "asking" => {
+                Ok(Self::Asking(Asking::from_parse(&mut parse)?))
+            },
+            "migrate" => {
+                Ok(Self::Migrate(Migrate::from_parse(&mut parse)?))
+            },
+            "scan" => {
+                Ok(Self::Scan(Scan::from_parse(&mut parse)?))
+            },
+            "type" => {
+                Ok(Self::Type(Type::from_parse(&mut parse)?))
+            },
+            "strlen" | "llen" | "hlen" | "scard" | "zcard" => {
+                Ok(Self::Len(Len::from_parse(&cmd, &mut parse)?))
+            },
             _ => Err("protocol error;invalid command".into())
         }
     }
@@ -33,7 +217,114 @@ impl Command {
             Command::Set(cmd) => {
                 cmd.apply(db,conn).await?;
                 Ok(())
+            },
+            Command::Eval(cmd) => cmd.apply(db,conn).await,
+            Command::EvalSha(cmd) => cmd.apply(db,conn).await,
+            Command::Script(cmd) => cmd.apply(db,conn).await,
+            Command::Function(cmd) => cmd.apply(db,conn).await,
+            Command::Fcall(cmd) => cmd.apply(db,conn).await,
+            Command::Del(cmd) => cmd.apply(db,conn).await,
+            Command::Publish(cmd) => cmd.apply(db,conn).await,
+            Command::Subscribe(cmd) => cmd.apply(db,conn,shutdown).await,
+            Command::PSubscribe(cmd) => cmd.apply(db,conn,shutdown).await,
+            Command::Unsubscribe(cmd) => cmd.apply(conn).await,
+            Command::PUnsubscribe(cmd) => cmd.apply(conn).await,
+            Command::Config(cmd) => cmd.apply(db,conn).await,
+            Command::Save(cmd) => cmd.apply(db,conn).await,
+            Command::BgSave(cmd) => cmd.apply(db,conn).await,
+            Command::LastSave(cmd) => cmd.apply(db,conn).await,
+            Command::BgRewriteAof(cmd) => cmd.apply(db,conn).await,
+            Command::Dump(cmd) => cmd.apply(db,conn).await,
+            Command::Restore(cmd) => cmd.apply(db,conn).await,
+            Command::Object(cmd) => cmd.apply(db,conn).await,
+            Command::Ping(cmd) => cmd.apply(conn).await,
+            Command::Info(cmd) => cmd.apply(db,conn).await,
+            Command::ReplicaOf(cmd) => cmd.apply(db,conn).await,
+            Command::Cluster(cmd) => cmd.apply(db,conn).await,
+            Command::Migrate(cmd) => cmd.apply(db,conn).await,
+            Command::Scan(cmd) => cmd.apply(db,conn).await,
+            Command::Type(cmd) => cmd.apply(db,conn).await,
+            Command::Len(cmd) => cmd.apply(db,conn).await,
+            //事务、复制握手和ASKING由server::Handler处理
+            Command::Multi(_) | Command::Exec(_) | Command::Discard(_)
+            | Command::Watch(_) | Command::Unwatch(_)
+            | Command::Psync(_) | Command::ReplConf(_) | Command::Wait(_)
+            | Command::Asking(_) => {
+                Err("command must be handled by connection".into())
+            }
+        }
+    }
+    //在已经持有的db锁下执行，用于EXEC
+    pub(crate) fn execute(self,db:&mut db::DbGuard) -> Frame {
+        match self {
+            Command::Get(cmd) => cmd.execute(db),
+            Command::Set(cmd) => cmd.execute(db),
+            Command::Eval(cmd) => cmd.execute(db),
+            Command::EvalSha(cmd) => cmd.execute(db),
+            Command::Script(cmd) => cmd.execute(db.scripts()),
+            Command::Function(cmd) => cmd.execute(db),
+            Command::Fcall(cmd) => cmd.execute(db),
+            Command::Del(cmd) => cmd.execute(db),
+            Command::Publish(cmd) => cmd.execute(db),
+            Command::Config(cmd) => cmd.execute(db),
+            Command::Save(cmd) => cmd.execute(db),
+            Command::BgSave(cmd) => cmd.execute(db),
+            Command::LastSave(cmd) => cmd.execute(db),
+            Command::BgRewriteAof(cmd) => cmd.execute(db),
+            Command::Dump(cmd) => cmd.execute(db),
+            Command::Restore(cmd) => cmd.execute(db),
+            Command::Object(cmd) => cmd.execute(db),
+            Command::Ping(cmd) => cmd.execute(db),
+            Command::Info(cmd) => cmd.execute(db),
+            Command::Wait(cmd) => cmd.execute(db),
+            Command::Cluster(cmd) => cmd.execute(db),
+            Command::Scan(cmd) => cmd.execute(db),
+            Command::Type(cmd) => cmd.execute(db),
+            Command::Len(cmd) => cmd.execute(db),
+            Command::Asking(_) => Frame::Simple("OK".to_string()),
+            Command::ReplicaOf(_) | Command::Psync(_) | Command::ReplConf(_) | Command::Migrate(_) => {
+                Frame::Error("ERR Command not allowed inside a transaction".to_string())
+            }
+            Command::Subscribe(_) | Command::Unsubscribe(_)
+            | Command::PSubscribe(_) | Command::PUnsubscribe(_) => {
+                Frame::Error("ERR subscribe command can not be used in transaction".to_string())
+            }
+            //EXEC之后watch的key本来就会被清空，这里直接返回OK
+            Command::Unwatch(_) => Frame::Simple("OK".to_string()),
+            Command::Multi(_) | Command::Exec(_) | Command::Discard(_) | Command::Watch(_) => {
+                Frame::Error("ERR transaction command can not be queued".to_string())
             }
         }
     }
-}
\ No newline at end of file
+    //会修改数据的命令
+    pub(crate) fn is_write(&self) -> bool {
+        match self {
+            Command::Set(_) | Command::Del(_) | Command::Restore(_) | Command::Migrate(_) => true,
+            Command::Function(cmd) => cmd.is_write(),
+            _ => false
+        }
+    }
+    //命令访问的key，集群模式下用来判断由哪个节点处理
+    pub(crate) fn keys(&self) -> Vec<&[u8]> {
+        match self {
+            Command::Get(cmd) => vec![cmd.key.as_bytes()],
+            Command::Set(cmd) => vec![cmd.key.as_bytes()],
+            Command::Dump(cmd) => vec![cmd.key.as_bytes()],
+            Command::Restore(cmd) => vec![cmd.key.as_bytes()],
+            Command::Type(cmd) => vec![cmd.key.as_bytes()],
+            Command::Len(cmd) => vec![cmd.key.as_bytes()],
+            Command::Object(Object::IdleTime(key) | Object::Freq(key)) => vec![key.as_bytes()],
+            Command::Del(cmd) => cmd.keys.iter().map(|key| key.as_bytes()).collect(),
+            Command::Migrate(cmd) => cmd.keys.iter().map(|key| key.as_bytes()).collect(),
+            Command::Watch(cmd) => cmd.keys.iter().map(|key| key.as_bytes()).collect(),
+            Command::Eval(cmd) => cmd.keys.iter().map(|key| &key[..]).collect(),
+            Command::EvalSha(cmd) => cmd.keys.iter().map(|key| &key[..]).collect(),
+            Command::Fcall(cmd) => cmd.keys.iter().map(|key| &key[..]).collect(),
+            _ => Vec::new()
+        }
+    }
+    //脚本里不能再执行事务和脚本相关的命令
+    pub(crate) fn is_allowed_in_script(&self) -> bool {
+        matches!(self, Command::Get(_) | Command::Set(_) | Command::Del(_) | Command::Publish(_))
+    }
+}
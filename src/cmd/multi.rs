@@ -0,0 +1,30 @@
+use crate::parse::Parse;
+
+//MULTI/EXEC/DISCARD只改变连接上的事务状态，真正的处理在server::Handler里
+#[derive(Debug)]
+pub struct Multi;
+#[derive(Debug)]
+pub struct Exec;
+#[derive(Debug)]
+pub struct Discard;
+
+impl Multi {
+    pub(crate) fn from_parse(parse:&mut Parse) -> crate::Result<Self> {
+        parse.finish()?;
+        Ok(Multi)
+    }
+}
+
+impl Exec {
+    pub(crate) fn from_parse(parse:&mut Parse) -> crate::Result<Self> {
+        parse.finish()?;
+        Ok(Exec)
+    }
+}
+
+impl Discard {
+    pub(crate) fn from_parse(parse:&mut Parse) -> crate::Result<Self> {
+        parse.finish()?;
+        Ok(Discard)
+    }
+}
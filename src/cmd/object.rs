@@ -0,0 +1,39 @@
+use crate::{parse::Parse, db, connection::Connection, frame::Frame};
+
+//OBJECT IDLETIME/FREQ，查看key的访问信息，key不存在返回null
+#[derive(Debug)]
+pub enum Object {
+    IdleTime(String),
+    Freq(String)
+}
+
+impl Object {
+    pub(crate) fn from_parse(parse:&mut Parse) -> crate::Result<Self> {
+        let sub = parse.next_string()?.to_lowercase();
+        let cmd = match &sub[..] {
+            "idletime" => Object::IdleTime(parse.next_string()?),
+            "freq" => Object::Freq(parse.next_string()?),
+            _ => return Err(format!("unknown subcommand '{}'",sub).into())
+        };
+        parse.finish()?;
+        Ok(cmd)
+    }
+    pub(crate) async fn apply(self,db:&db::Db,conn:&mut Connection) -> crate::Result<()> {
+        let response = self.execute(&mut db.lock());
+        conn.write_frame(&response).await
+    }
+    pub(crate) fn execute(self,db:&mut db::DbGuard) -> Frame {
+        let (key,idletime) = match self {
+            Object::IdleTime(key) => (key,true),
+            Object::Freq(key) => (key,false)
+        };
+        if db.version(&key).is_none() {
+            return Frame::Null;
+        }
+        match db.access_info(&key) {
+            Some((idle,_)) if idletime => Frame::Integer(idle.as_secs() as i64),
+            Some((_,freq)) => Frame::Integer(freq as i64),
+            None => Frame::Null
+        }
+    }
+}
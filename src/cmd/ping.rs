@@ -0,0 +1,33 @@
+use bytes::Bytes;
+
+use crate::{parse::{Parse, ParseError}, db, connection::Connection, frame::Frame};
+
+//PING [message]，不带参数返回PONG
+#[derive(Debug)]
+pub struct Ping {
+    pub(crate) msg:Option<Bytes>
+}
+
+impl Ping {
+    pub(crate) fn from_parse(parse:&mut Parse) -> crate::Result<Self> {
+        let msg = match parse.next_bytes() {
+            Ok(msg) => Some(msg),
+            Err(ParseError::EndOfStream) => None,
+            Err(err) => return Err(err.into())
+        };
+        parse.finish()?;
+        Ok(Self { msg })
+    }
+    pub(crate) async fn apply(self,conn:&mut Connection) -> crate::Result<()> {
+        conn.write_frame(&self.reply()).await
+    }
+    pub(crate) fn execute(self,_db:&mut db::DbGuard) -> Frame {
+        self.reply()
+    }
+    fn reply(self) -> Frame {
+        match self.msg {
+            Some(msg) => Frame::Bulk(msg),
+            None => Frame::Simple("PONG".to_string())
+        }
+    }
+}
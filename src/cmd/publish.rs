@@ -0,0 +1,33 @@
+use bytes::Bytes;
+
+use crate::{parse::Parse, db, connection::Connection, frame::Frame};
+
+#[derive(Debug)]
+pub struct Publish {
+    pub(crate) channel:String,
+    pub(crate) message:Bytes
+}
+
+impl Publish {
+    pub(crate) fn from_parse(parse:&mut Parse) -> crate::Result<Self> {
+        let channel = parse.next_string()?;
+        let message = parse.next_bytes()?;
+        Ok(Self { channel, message })
+    }
+    pub(crate) async fn apply(self,db:&db::Db,conn:&mut Connection) -> crate::Result<()> {
+        let response = self.execute(&mut db.lock());
+        conn.write_frame(&response).await
+    }
+    //返回收到消息的订阅者数量
+    pub(crate) fn execute(self,db:&mut db::DbGuard) -> Frame {
+        let count = db.publish(&self.channel, self.message);
+        Frame::Integer(count as i64)
+    }
+    pub(crate) fn into_frame(self) -> Frame {
+        Frame::Array(vec![
+            Frame::Bulk("PUBLISH".into()),
+            Frame::Bulk(self.channel.into()),
+            Frame::Bulk(self.message),
+        ])
+    }
+}
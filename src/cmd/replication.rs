@@ -0,0 +1,115 @@
+use crate::{parse::Parse, db, connection::Connection, frame::Frame, replication};
+
+//REPLICAOF host port / REPLICAOF NO ONE，SLAVEOF是同一个命令
+#[derive(Debug)]
+pub struct ReplicaOf {
+    //None表示NO ONE
+    pub(crate) leader:Option<(String,u16)>
+}
+
+//PSYNC replid offset，副本握手的最后一步，之后连接只用来接收复制流
+#[derive(Debug)]
+pub struct Psync {
+    pub(crate) replid:String,
+    //副本已经收到的offset+1，第一次同步时是-1
+    pub(crate) offset:i64
+}
+
+//REPLCONF option value ...，副本握手时告诉主自己的信息，之后在复制连接上用ACK/GETACK同步offset
+#[derive(Debug)]
+pub struct ReplConf {
+    pub(crate) listening_port:Option<u16>,
+    //副本已经处理到的offset
+    pub(crate) ack:Option<u64>,
+    //主要求副本立即回复ACK
+    pub(crate) getack:bool
+}
+
+//WAIT numreplicas timeout，等到足够多的副本确认收到了这个连接之前的写入，返回确认的副本数
+#[derive(Debug)]
+pub struct Wait {
+    pub(crate) numreplicas:u64,
+    //毫秒，0表示一直等
+    pub(crate) timeout:u64
+}
+
+impl ReplicaOf {
+    pub(crate) fn from_parse(parse:&mut Parse) -> crate::Result<Self> {
+        let host = parse.next_string()?;
+        let port = parse.next_string()?;
+        parse.finish()?;
+        if host.eq_ignore_ascii_case("no") && port.eq_ignore_ascii_case("one") {
+            return Ok(Self { leader: None });
+        }
+        let port = port.parse().map_err(|_| "Invalid master port")?;
+        Ok(Self { leader: Some((host,port)) })
+    }
+    pub(crate) async fn apply(self,db:&db::Db,conn:&mut Connection) -> crate::Result<()> {
+        let response = match self.leader {
+            None => {
+                //和写命令在同一把锁下切换，保证副本看到的offset是连续的
+                if db.lock().replication().promote() {
+                    db.wake_purge_task();
+                }
+                Frame::Simple("OK".to_string())
+            }
+            Some((host,port)) => {
+                if replication::start_replication(db, host, port) {
+                    Frame::Simple("OK".to_string())
+                } else {
+                    Frame::Simple("OK Already connected to specified master".to_string())
+                }
+            }
+        };
+        conn.write_frame(&response).await
+    }
+}
+
+impl Psync {
+    pub(crate) fn from_parse(parse:&mut Parse) -> crate::Result<Self> {
+        let replid = parse.next_string()?;
+        let offset = parse.next_string()?.parse().map_err(|_| "value is not an integer or out of range")?;
+        parse.finish()?;
+        Ok(Self { replid, offset })
+    }
+}
+
+impl ReplConf {
+    pub(crate) fn from_parse(parse:&mut Parse) -> crate::Result<Self> {
+        let mut cmd = Self { listening_port: None, ack: None, getack: false };
+        loop {
+            let option = match parse.next_string() {
+                Ok(option) => option.to_lowercase(),
+                Err(crate::parse::ParseError::EndOfStream) => break,
+                Err(err) => return Err(err.into())
+            };
+            let value = parse.next_string()?;
+            match &option[..] {
+                "listening-port" => cmd.listening_port = Some(value.parse().map_err(|_| "Invalid listening port")?),
+                "ack" => cmd.ack = Some(value.parse().map_err(|_| "Invalid ACK offset")?),
+                "getack" => cmd.getack = true,
+                //ip-address和capa目前用不上，接受但忽略
+                "ip-address" | "capa" => {}
+                _ => return Err(format!("Unrecognized REPLCONF option: {}",option).into())
+            }
+        }
+        Ok(cmd)
+    }
+}
+
+impl Wait {
+    pub(crate) fn from_parse(parse:&mut Parse) -> crate::Result<Self> {
+        let numreplicas = parse.next_int()?;
+        let timeout = parse.next_int()?;
+        parse.finish()?;
+        Ok(Self { numreplicas, timeout })
+    }
+    //事务里不能阻塞，直接返回已经确认了当前offset的副本数
+    pub(crate) fn execute(self,db:&mut db::DbGuard) -> Frame {
+        let replication = db.replication();
+        if replication.is_replica() {
+            return Frame::Error("ERR WAIT cannot be used with replica instances".to_string());
+        }
+        Frame::Integer(replication.acked(replication.offset()) as i64)
+    }
+}
@@ -0,0 +1,107 @@
+use crate::{parse::Parse, db, connection::Connection, frame::Frame};
+
+//SAVE，同步保存快照，保存完成才返回
+#[derive(Debug)]
+pub struct Save;
+
+//BGSAVE，拷贝一份数据之后在后台写文件，立即返回
+#[derive(Debug)]
+pub struct BgSave;
+
+//LASTSAVE，返回上次成功保存的unix时间
+#[derive(Debug)]
+pub struct LastSave;
+
+//BGREWRITEAOF，根据当前数据在后台生成最小的aof，期间的写入继续追加
+#[derive(Debug)]
+pub struct BgRewriteAof;
+
+impl Save {
+    pub(crate) fn from_parse(parse:&mut Parse) -> crate::Result<Self> {
+        parse.finish()?;
+        Ok(Self)
+    }
+    //写文件放到blocking线程上，拷贝完数据之后就不再持有db的锁
+    //和BGSAVE共用一个标记，两个同时写文件会互相覆盖
+    pub(crate) async fn apply(self,db:&db::Db,conn:&mut Connection) -> crate::Result<()> {
+        let persistence = db.persistence().clone();
+        let response = if !persistence.start_background_save() {
+            Frame::Error("ERR Background save already in progress".to_string())
+        } else {
+            let snapshot = db.snapshot();
+            match tokio::task::spawn_blocking(move || persistence.save(&snapshot)).await? {
+                Ok(()) => Frame::Simple("OK".to_string()),
+                Err(err) => Frame::Error(format!("ERR {}",err))
+            }
+        };
+        conn.write_frame(&response).await
+    }
+    //事务里执行时已经持有锁，直接在锁内写完
+    pub(crate) fn execute(self,db:&mut db::DbGuard) -> Frame {
+        let persistence = db.persistence();
+        if !persistence.start_background_save() {
+            return Frame::Error("ERR Background save already in progress".to_string());
+        }
+        match persistence.save(&db.snapshot()) {
+            Ok(()) => Frame::Simple("OK".to_string()),
+            Err(err) => Frame::Error(format!("ERR {}",err))
+        }
+    }
+}
+
+impl BgSave {
+    pub(crate) fn from_parse(parse:&mut Parse) -> crate::Result<Self> {
+        parse.finish()?;
+        Ok(Self)
+    }
+    pub(crate) async fn apply(self,db:&db::Db,conn:&mut Connection) -> crate::Result<()> {
+        let response = self.execute(&mut db.lock());
+        conn.write_frame(&response).await
+    }
+    //锁只在拷贝数据期间持有，序列化和写文件都在后台完成
+    pub(crate) fn execute(self,db:&mut db::DbGuard) -> Frame {
+        let persistence = db.persistence();
+        if !persistence.start_background_save() {
+            return Frame::Error("ERR Background save already in progress".to_string());
+        }
+        persistence.background_save(db.snapshot());
+        Frame::Simple("Background saving started".to_string())
+    }
+}
+
+impl LastSave {
+    pub(crate) fn from_parse(parse:&mut Parse) -> crate::Result<Self> {
+        parse.finish()?;
+        Ok(Self)
+    }
+    pub(crate) async fn apply(self,db:&db::Db,conn:&mut Connection) -> crate::Result<()> {
+        let response = self.execute(&mut db.lock());
+        conn.write_frame(&response).await
+    }
+    pub(crate) fn execute(self,db:&mut db::DbGuard) -> Frame {
+        Frame::Integer(db.persistence().last_save() as i64)
+    }
+}
+
+impl BgRewriteAof {
+    pub(crate) fn from_parse(parse:&mut Parse) -> crate::Result<Self> {
+        parse.finish()?;
+        Ok(Self)
+    }
+    pub(crate) async fn apply(self,db:&db::Db,conn:&mut Connection) -> crate::Result<()> {
+        let response = self.execute(&mut db.lock());
+        conn.write_frame(&response).await
+    }
+    //拷贝数据和开始记录重写期间的写入必须在同一把锁下，否则会漏掉或者重复命令
+    pub(crate) fn execute(self,db:&mut db::DbGuard) -> Frame {
+        let aof = match db.aof() {
+            Some(aof) => aof,
+            None => return Frame::Error("ERR Append only file is disabled".to_string())
+        };
+        if !aof.start_rewrite() {
+            return Frame::Error("ERR Background append only file rewriting already in progress".to_string());
+        }
+        aof.rewrite(db.snapshot());
+        Frame::Simple("Background append only file rewriting started".to_string())
+    }
+}
@@ -0,0 +1,105 @@
+use crate::{parse::{Parse, ParseError}, db::{self, glob_match}, connection::Connection, frame::Frame};
+
+//SCAN cursor [MATCH pattern] [COUNT count] [TYPE type]
+//返回[下一个cursor, [key...]]，cursor为0时遍历结束，MATCH和TYPE在取出一批key之后再过滤，所以一批可能是空的
+#[derive(Debug)]
+pub struct Scan {
+    cursor:u64,
+    pattern:Option<String>,
+    count:usize,
+    kind:Option<String>
+}
+
+//TYPE key，key不存在返回none
+#[derive(Debug)]
+pub struct Type {
+    pub(crate) key:String
+}
+
+//STRLEN/LLEN/HLEN/SCARD/ZCARD key，key不存在返回0
+#[derive(Debug)]
+pub struct Len {
+    kind:&'static str,
+    pub(crate) key:String
+}
+
+impl Scan {
+    pub(crate) fn from_parse(parse:&mut Parse) -> crate::Result<Self> {
+        let cursor = parse.next_string()?.parse().map_err(|_| "invalid cursor")?;
+        let mut scan = Scan { cursor, pattern: None, count: 10, kind: None };
+        loop {
+            let option = match parse.next_string() {
+                Ok(option) => option.to_lowercase(),
+                Err(ParseError::EndOfStream) => break,
+                Err(err) => return Err(err.into())
+            };
+            match &option[..] {
+                "match" => scan.pattern = Some(parse.next_string()?),
+                "count" => match parse.next_int()? {
+                    0 => return Err("syntax error".into()),
+                    count => scan.count = count as usize
+                },
+                "type" => scan.kind = Some(parse.next_string()?.to_lowercase()),
+                _ => return Err("syntax error".into())
+            }
+        }
+        Ok(scan)
+    }
+    pub(crate) async fn apply(self,db:&db::Db,conn:&mut Connection) -> crate::Result<()> {
+        let response = self.execute(&mut db.lock());
+        conn.write_frame(&response).await
+    }
+    pub(crate) fn execute(self,db:&mut db::DbGuard) -> Frame {
+        let (next,keys) = db.scan(self.cursor, self.count, |key,value| {
+            self.pattern.as_deref().map(|pattern| glob_match(pattern, key)).unwrap_or(true)
+                && self.kind.as_deref().map(|kind| kind == value.type_name()).unwrap_or(true)
+        });
+        Frame::Array(vec![
+            Frame::Bulk(next.to_string().into()),
+            Frame::Array(keys.into_iter().map(|key| Frame::Bulk(key.into())).collect())
+        ])
+    }
+}
+
+impl Type {
+    pub(crate) fn from_parse(parse:&mut Parse) -> crate::Result<Self> {
+        let key = parse.next_string()?;
+        parse.finish()?;
+        Ok(Self { key })
+    }
+    pub(crate) async fn apply(self,db:&db::Db,conn:&mut Connection) -> crate::Result<()> {
+        let response = self.execute(&mut db.lock());
+        conn.write_frame(&response).await
+    }
+    pub(crate) fn execute(self,db:&mut db::DbGuard) -> Frame {
+        let kind = db.peek(&self.key).map(|value| value.type_name()).unwrap_or("none");
+        Frame::Simple(kind.to_string())
+    }
+}
+
+impl Len {
+    //name是命令名，决定期望的类型
+    pub(crate) fn from_parse(name:&str,parse:&mut Parse) -> crate::Result<Self> {
+        let kind = match name {
+            "strlen" => "string",
+            "llen" => "list",
+            "hlen" => "hash",
+            "scard" => "set",
+            _ => "zset"
+        };
+        let key = parse.next_string()?;
+        parse.finish()?;
+        Ok(Self { kind, key })
+    }
+    pub(crate) async fn apply(self,db:&db::Db,conn:&mut Connection) -> crate::Result<()> {
+        let response = self.execute(&mut db.lock());
+        conn.write_frame(&response).await
+    }
+    pub(crate) fn execute(self,db:&mut db::DbGuard) -> Frame {
+        match db.peek(&self.key) {
+            None => Frame::Integer(0),
+            Some(value) if value.type_name() == self.kind => Frame::Integer(value.len() as i64),
+            Some(_) => Frame::Error("WRONGTYPE Operation against a key holding the wrong kind of value".to_string())
+        }
+    }
+}
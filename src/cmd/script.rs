@@ -0,0 +1,59 @@
+use crate::{parse::{Parse, ParseError}, db, connection::Connection, frame::Frame, script::Scripts};
+
+//SCRIPT LOAD/EXISTS/FLUSH/KILL，只操作脚本缓存，不需要db锁
+#[derive(Debug)]
+pub enum Script {
+    Load(String),
+    Exists(Vec<String>),
+    Flush,
+    Kill
+}
+
+impl Script {
+    pub(crate) fn from_parse(parse:&mut Parse) -> crate::Result<Self> {
+        let sub = parse.next_string()?.to_lowercase();
+        let cmd = match &sub[..] {
+            "load" => Script::Load(parse.next_string()?),
+            "exists" => {
+                let mut shas = vec![parse.next_string()?];
+                loop {
+                    match parse.next_string() {
+                        Ok(sha) => shas.push(sha),
+                        Err(ParseError::EndOfStream) => break,
+                        Err(err) => return Err(err.into())
+                    }
+                }
+                Script::Exists(shas)
+            }
+            "flush" => {
+                //ASYNC/SYNC参数可以忽略，清空缓存本来就很快
+                let _ = parse.next_string();
+                Script::Flush
+            }
+            "kill" => Script::Kill,
+            _ => return Err(format!("unknown subcommand '{}'",sub).into())
+        };
+        parse.finish()?;
+        Ok(cmd)
+    }
+    pub(crate) async fn apply(self,db:&db::Db,conn:&mut Connection) -> crate::Result<()> {
+        let response = self.execute(db.scripts());
+        conn.write_frame(&response).await
+    }
+    pub(crate) fn execute(self,scripts:&Scripts) -> Frame {
+        match self {
+            Script::Load(body) => Frame::Bulk(scripts.load(body).into()),
+            Script::Exists(shas) => {
+                let v = shas.iter()
+                    .map(|sha| Frame::Integer(scripts.exists(sha) as i64))
+                    .collect();
+                Frame::Array(v)
+            }
+            Script::Flush => {
+                scripts.flush();
+                Frame::Simple("OK".to_string())
+            }
+            Script::Kill => scripts.kill()
+        }
+    }
+}
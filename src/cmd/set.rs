@@ -2,8 +2,9 @@ use std::time::Duration;
 
 use tokio::time::Instant;
 
-use crate::{parse::Parse, db, connection::Connection,frame::Frame};
+use crate::{parse::{Parse, ParseError}, db, connection::Connection,frame::Frame, snapshot::unix_time_millis};
 
+#[derive(Debug)]
 pub struct Set {
     pub(crate) key:String,
     pub(crate) value:bytes::Bytes,
@@ -14,27 +15,77 @@ impl Set {
     pub(crate) fn from_parse(parse:&mut Parse) -> crate::Result<Self> {
         let key = parse.next_string()?;
         let value = parse.next_bytes()?;
+        let mut expiration = None;
+        //选项要一直读到结尾，不认识的选项或者重复的过期时间都是语法错误，不能悄悄忽略
+        loop {
+            let option = match parse.next_string() {
+                Ok(s) => s.to_uppercase(),
+                Err(ParseError::EndOfStream) => break,
+                Err(err) => return Err(err.into())
+            };
+            let unit = if option.starts_with('E') { 1000 } else { 1 };
+            let ms = match option.as_str() {
+                "EX" | "PX" => {
+                    let ms = next_expire(parse, unit)?;
+                    check_overflow(ms.checked_add(unix_time_millis()))?;
+                    ms
+                }
+                //绝对时间，aof里记录的就是这种形式，已经过去的时间会让key立即过期
+                "EXAT" | "PXAT" => next_expire(parse, unit)?.saturating_sub(unix_time_millis()),
+                _ => return Err("syntax error".into())
+            };
+            if expiration.replace(Duration::from_millis(ms)).is_some() {
+                return Err("syntax error".into());
+            }
+        }
         Ok(Self {
             key,
             value,
-            expiration:None,
+            expiration,
         })
     }
     pub(crate) async fn apply(self,db:&db::Db,conn:&mut Connection) -> crate::Result<()> {
-        db.set(self.key, self.value, None);
-        let response = Frame::Simple("OK".to_string());
+        let response = self.execute(&mut db.lock());
         conn.write_frame(&response).await?;
         Ok(())
     }
+    pub(crate) fn execute(self,db:&mut db::DbGuard) -> Frame {
+        //EXEC、脚本、aof和复制都会走到这里，溢出不能panic，否则会毒化db锁
+        let expiration_at = match self.expiration.map(|ex| Instant::now().checked_add(ex)) {
+            Some(None) => return Frame::Error(format!("ERR {}",INVALID_EXPIRE)),
+            at => at.flatten()
+        };
+        db.set(self.key, self.value, expiration_at);
+        Frame::Simple("OK".to_string())
+    }
     pub(crate) fn into_frame(self) -> Frame {
-        let mut  v = Vec::new();
-        v.push(Frame::Simple("SET".to_string()));
-        v.push(Frame::Simple(self.key));
-        v.push(Frame::Bulk(self.value));
+        //参数都用bulk，key里有\r\n也不会破坏协议，服务端也只接受字符串形式的过期时间
+        let mut v = vec![
+            Frame::Bulk("SET".into()),
+            Frame::Bulk(self.key.into()),
+            Frame::Bulk(self.value),
+        ];
         if let Some(ex) = self.expiration {
-            v.push(Frame::Simple("PX".to_string()));
-            v.push(Frame::Integer(ex.as_millis() as u64));
+            v.push(Frame::Bulk("PX".into()));
+            v.push(Frame::Bulk(ex.as_millis().to_string().into()));
         }
         Frame::Array(v)
     }
-}
\ No newline at end of file
+}
+
+const INVALID_EXPIRE:&str = "invalid expire time in 'set' command";
+
+//过期时间必须是正整数，换算成毫秒之后不能超过i64
+fn next_expire(parse:&mut Parse,unit:u64) -> crate::Result<u64> {
+    match parse.next_string()?.parse::<i64>() {
+        Ok(n) if n > 0 => check_overflow((n as u64).checked_mul(unit)),
+        _ => Err(INVALID_EXPIRE.into())
+    }
+}
+
+fn check_overflow(ms:Option<u64>) -> crate::Result<u64> {
+    match ms {
+        Some(ms) if ms <= i64::MAX as u64 => Ok(ms),
+        _ => Err(INVALID_EXPIRE.into())
+    }
+}
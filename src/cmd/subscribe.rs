@@ -0,0 +1,267 @@
+use std::pin::Pin;
+
+use bytes::Bytes;
+use tokio::sync::broadcast;
+use tokio_stream::{Stream, StreamExt, StreamMap};
+
+use crate::{parse::{Parse, ParseError}, db::Db, connection::Connection, frame::Frame, shutdown::Shutdown, cmd::Command};
+
+type Messages<T> = Pin<Box<dyn Stream<Item = T> + Send>>;
+
+#[derive(Debug)]
+pub struct Subscribe {
+    pub(crate) channels:Vec<String>
+}
+#[derive(Debug)]
+pub struct Unsubscribe {
+    pub(crate) channels:Vec<String>
+}
+#[derive(Debug)]
+pub struct PSubscribe {
+    pub(crate) patterns:Vec<String>
+}
+#[derive(Debug)]
+pub struct PUnsubscribe {
+    pub(crate) patterns:Vec<String>
+}
+
+//连接进入订阅模式之后的状态，订阅数变成0时退出订阅模式
+#[derive(Default)]
+struct Subscriptions {
+    channels:StreamMap<String,Messages<Bytes>>,
+    patterns:StreamMap<String,Messages<(String,Bytes)>>
+}
+
+impl Subscribe {
+    pub(crate) fn from_parse(parse:&mut Parse) -> crate::Result<Self> {
+        let mut channels = vec![parse.next_string()?];
+        channels.extend(parse_rest(parse)?);
+        Ok(Self { channels })
+    }
+    pub(crate) async fn apply(self,db:&Db,conn:&mut Connection,shutdown:&mut Shutdown) -> crate::Result<()> {
+        let mut subscriptions = Subscriptions::default();
+        let res = match subscriptions.subscribe(db, conn, self.channels).await {
+            Ok(()) => subscriptions.run(db, conn, shutdown).await,
+            err => err
+        };
+        subscriptions.close(db);
+        res
+    }
+    pub(crate) fn into_frame(self) -> Frame {
+        command_frame("SUBSCRIBE", self.channels)
+    }
+}
+
+impl PSubscribe {
+    pub(crate) fn from_parse(parse:&mut Parse) -> crate::Result<Self> {
+        let mut patterns = vec![parse.next_string()?];
+        patterns.extend(parse_rest(parse)?);
+        Ok(Self { patterns })
+    }
+    pub(crate) async fn apply(self,db:&Db,conn:&mut Connection,shutdown:&mut Shutdown) -> crate::Result<()> {
+        let mut subscriptions = Subscriptions::default();
+        let res = match subscriptions.psubscribe(db, conn, self.patterns).await {
+            Ok(()) => subscriptions.run(db, conn, shutdown).await,
+            err => err
+        };
+        subscriptions.close(db);
+        res
+    }
+    pub(crate) fn into_frame(self) -> Frame {
+        command_frame("PSUBSCRIBE", self.patterns)
+    }
+}
+
+impl Unsubscribe {
+    pub(crate) fn from_parse(parse:&mut Parse) -> crate::Result<Self> {
+        Ok(Self { channels: parse_rest(parse)? })
+    }
+    //不在订阅模式时，直接回复订阅数为0
+    pub(crate) async fn apply(self,conn:&mut Connection) -> crate::Result<()> {
+        reply_not_subscribed(conn, "unsubscribe", self.channels).await
+    }
+    pub(crate) fn into_frame(self) -> Frame {
+        command_frame("UNSUBSCRIBE", self.channels)
+    }
+}
+
+impl PUnsubscribe {
+    pub(crate) fn from_parse(parse:&mut Parse) -> crate::Result<Self> {
+        Ok(Self { patterns: parse_rest(parse)? })
+    }
+    pub(crate) async fn apply(self,conn:&mut Connection) -> crate::Result<()> {
+        reply_not_subscribed(conn, "punsubscribe", self.patterns).await
+    }
+    pub(crate) fn into_frame(self) -> Frame {
+        command_frame("PUNSUBSCRIBE", self.patterns)
+    }
+}
+
+impl Subscriptions {
+    fn count(&self) -> usize {
+        self.channels.len() + self.patterns.len()
+    }
+    async fn run(&mut self,db:&Db,conn:&mut Connection,shutdown:&mut Shutdown) -> crate::Result<()> {
+        while self.count() > 0 {
+            tokio::select! {
+                Some((channel,msg)) = self.channels.next() => {
+                    conn.write_frame(&message_frame(channel,msg)).await?;
+                }
+                Some((pattern,(channel,msg))) = self.patterns.next() => {
+                    let frame = Frame::Array(vec![
+                        Frame::Bulk(Bytes::from_static(b"pmessage")),
+                        Frame::Bulk(pattern.into()),
+                        Frame::Bulk(channel.into()),
+                        Frame::Bulk(msg),
+                    ]);
+                    conn.write_frame(&frame).await?;
+                }
+                res = conn.read_frame() => {
+                    let frame = match res? {
+                        Some(frame) => frame,
+                        None => return Ok(())
+                    };
+                    self.handle_command(db, conn, frame).await?;
+                }
+                _ = shutdown.recv() => {
+                    return Ok(());
+                }
+            }
+        }
+        Ok(())
+    }
+    //订阅模式下只允许(P)SUBSCRIBE和(P)UNSUBSCRIBE
+    async fn handle_command(&mut self,db:&Db,conn:&mut Connection,frame:Frame) -> crate::Result<()> {
+        match Command::from_frame(frame) {
+            Ok(Command::Subscribe(cmd)) => self.subscribe(db, conn, cmd.channels).await,
+            Ok(Command::PSubscribe(cmd)) => self.psubscribe(db, conn, cmd.patterns).await,
+            Ok(Command::Unsubscribe(cmd)) => {
+                let channels = if cmd.channels.is_empty() {
+                    self.channels.keys().cloned().collect()
+                } else {
+                    cmd.channels
+                };
+                for channel in channels {
+                    if self.channels.remove(&channel).is_some() {
+                        db.unsubscribe(&channel);
+                    }
+                    conn.write_frame(&subscription_frame("unsubscribe", channel, self.count())).await?;
+                }
+                Ok(())
+            }
+            Ok(Command::PUnsubscribe(cmd)) => {
+                let patterns = if cmd.patterns.is_empty() {
+                    self.patterns.keys().cloned().collect()
+                } else {
+                    cmd.patterns
+                };
+                for pattern in patterns {
+                    if self.patterns.remove(&pattern).is_some() {
+                        db.punsubscribe(&pattern);
+                    }
+                    conn.write_frame(&subscription_frame("punsubscribe", pattern, self.count())).await?;
+                }
+                Ok(())
+            }
+            Ok(_) => {
+                let err = "ERR only (P)SUBSCRIBE / (P)UNSUBSCRIBE are allowed in this context";
+                conn.write_frame(&Frame::Error(err.to_string())).await
+            }
+            Err(err) => conn.write_frame(&Frame::Error(format!("ERR {}",err))).await
+        }
+    }
+    //连接断开或者退出订阅模式时，把还订阅着的都退掉
+    fn close(self,db:&Db) {
+        let channels:Vec<String> = self.channels.keys().cloned().collect();
+        let patterns:Vec<String> = self.patterns.keys().cloned().collect();
+        //先drop掉receiver，否则订阅数不会变成0
+        drop(self);
+        for channel in channels {
+            db.unsubscribe(&channel);
+        }
+        for pattern in patterns {
+            db.punsubscribe(&pattern);
+        }
+    }
+    async fn subscribe(&mut self,db:&Db,conn:&mut Connection,channels:Vec<String>) -> crate::Result<()> {
+        for channel in channels {
+            if !self.channels.contains_key(&channel) {
+                let rx = db.subscribe(channel.clone());
+                self.channels.insert(channel.clone(), into_stream(rx));
+            }
+            conn.write_frame(&subscription_frame("subscribe", channel, self.count())).await?;
+        }
+        Ok(())
+    }
+    async fn psubscribe(&mut self,db:&Db,conn:&mut Connection,patterns:Vec<String>) -> crate::Result<()> {
+        for pattern in patterns {
+            if !self.patterns.contains_key(&pattern) {
+                let rx = db.psubscribe(pattern.clone());
+                self.patterns.insert(pattern.clone(), into_stream(rx));
+            }
+            conn.write_frame(&subscription_frame("psubscribe", pattern, self.count())).await?;
+        }
+        Ok(())
+    }
+}
+
+fn into_stream<T:Clone+Send+'static>(mut rx:broadcast::Receiver<T>) -> Messages<T> {
+    Box::pin(async_stream::stream! {
+        loop {
+            match rx.recv().await {
+                Ok(msg) => yield msg,
+                //消费太慢丢了消息，继续收后面的
+                Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(_) => break
+            }
+        }
+    })
+}
+
+fn parse_rest(parse:&mut Parse) -> crate::Result<Vec<String>> {
+    let mut v = Vec::new();
+    loop {
+        match parse.next_string() {
+            Ok(s) => v.push(s),
+            Err(ParseError::EndOfStream) => return Ok(v),
+            Err(err) => return Err(err.into())
+        }
+    }
+}
+
+async fn reply_not_subscribed(conn:&mut Connection,kind:&str,names:Vec<String>) -> crate::Result<()> {
+    if names.is_empty() {
+        let frame = Frame::Array(vec![
+            Frame::Bulk(Bytes::from(kind.to_string())),
+            Frame::Null,
+            Frame::Integer(0),
+        ]);
+        return conn.write_frame(&frame).await;
+    }
+    for name in names {
+        conn.write_frame(&subscription_frame(kind, name, 0)).await?;
+    }
+    Ok(())
+}
+
+fn subscription_frame(kind:&str,name:String,count:usize) -> Frame {
+    Frame::Array(vec![
+        Frame::Bulk(Bytes::from(kind.to_string())),
+        Frame::Bulk(name.into()),
+        Frame::Integer(count as i64),
+    ])
+}
+
+fn message_frame(channel:String,msg:Bytes) -> Frame {
+    Frame::Array(vec![
+        Frame::Bulk(Bytes::from_static(b"message")),
+        Frame::Bulk(channel.into()),
+        Frame::Bulk(msg),
+    ])
+}
+
+fn command_frame(name:&str,args:Vec<String>) -> Frame {
+    let mut v = vec![Frame::Bulk(Bytes::from(name.to_string()))];
+    v.extend(args.into_iter().map(|arg| Frame::Bulk(arg.into())));
+    Frame::Array(v)
+}
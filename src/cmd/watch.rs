@@ -0,0 +1,30 @@
+use crate::parse::{Parse, ParseError};
+
+//WATCH/UNWATCH同样只改变连接状态，由server::Handler处理
+#[derive(Debug)]
+pub struct Watch {
+    pub(crate) keys:Vec<String>
+}
+#[derive(Debug)]
+pub struct Unwatch;
+
+impl Watch {
+    pub(crate) fn from_parse(parse:&mut Parse) -> crate::Result<Self> {
+        let mut keys = vec![parse.next_string()?];
+        loop {
+            match parse.next_string() {
+                Ok(key) => keys.push(key),
+                Err(ParseError::EndOfStream) => break,
+                Err(err) => return Err(err.into())
+            }
+        }
+        Ok(Self { keys })
+    }
+}
+
+impl Unwatch {
+    pub(crate) fn from_parse(parse:&mut Parse) -> crate::Result<Self> {
+        parse.finish()?;
+        Ok(Unwatch)
+    }
+}
@@ -0,0 +1,79 @@
+use std::{path::PathBuf, time::Duration};
+
+use crate::aof::FsyncPolicy;
+use crate::notify::NotifyFlags;
+
+//服务端的可配置项，由bin/server.rs根据命令行参数构造
+#[derive(Debug,Clone)]
+pub struct Config {
+    //lua脚本最长执行时间，超过之后没有写过数据的脚本会被中止
+    pub script_timeout:Duration,
+    //keyspace事件通知，默认关闭
+    pub(crate) notify_keyspace_events:NotifyFlags,
+    //快照文件所在的目录和文件名
+    pub dir:PathBuf,
+    pub dbfilename:String,
+    //是否开启aof，aof文件也放在dir目录下
+    pub appendonly:bool,
+    pub appendfilename:String,
+    pub(crate) appendfsync:FsyncPolicy,
+    //启动时额外导入的redis rdb文件
+    pub load_rdb:Option<PathBuf>,
+    //启动后成为这个主的副本
+    pub replicaof:Option<(String,u16)>,
+    //副本是否拒绝客户端的写命令，默认拒绝
+    pub replica_read_only:bool,
+    //backlog越大，副本断开越久之后还能只同步缺少的部分
+    pub repl_backlog_size:usize,
+    //集群模式，key按slot分布在多个节点上
+    pub cluster_enabled:bool,
+    //保存集群节点和slot分配的文件，也放在dir目录下
+    pub cluster_config_file:String,
+    //超过这么久没联系上的节点标记为fail?
+    pub cluster_node_timeout:Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            script_timeout: Duration::from_millis(5000),
+            notify_keyspace_events: NotifyFlags::default(),
+            dir: PathBuf::from("."),
+            dbfilename: "dump.snapshot".to_string(),
+            appendonly: false,
+            appendfilename: "appendonly.aof".to_string(),
+            appendfsync: FsyncPolicy::EverySec,
+            load_rdb: None,
+            replicaof: None,
+            replica_read_only: true,
+            repl_backlog_size: 1024 * 1024,
+            cluster_enabled: false,
+            cluster_config_file: "nodes.conf".to_string(),
+            cluster_node_timeout: Duration::from_millis(15000),
+        }
+    }
+}
+
+impl Config {
+    //解析notify-keyspace-events配置，格式和redis一样，比如"KEA"
+    pub fn set_notify_keyspace_events(&mut self,s:&str) -> crate::Result<()> {
+        self.notify_keyspace_events = NotifyFlags::parse(s)?;
+        Ok(())
+    }
+    //"host port"
+    pub fn set_replicaof(&mut self,s:&str) -> crate::Result<()> {
+        let mut parts = s.split_whitespace();
+        match (parts.next(),parts.next(),parts.next()) {
+            (Some(host),Some(port),None) => {
+                self.replicaof = Some((host.to_string(),port.parse().map_err(|_| format!("invalid replicaof port '{}'",port))?));
+                Ok(())
+            }
+            _ => Err(format!("invalid replicaof '{}', expected \"host port\"",s).into())
+        }
+    }
+    //always/everysec/no
+    pub fn set_appendfsync(&mut self,s:&str) -> crate::Result<()> {
+        self.appendfsync = FsyncPolicy::parse(s)?;
+        Ok(())
+    }
+}
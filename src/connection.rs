@@ -23,7 +23,7 @@ impl Connection {
             },
             Some(frame) => Ok(frame),
             None =>  {
-                let err = Error::new(ErrorKind::ConnectionReset, "connection reset by perr");
+                let err = Error::new(ErrorKind::ConnectionReset, "connection reset by peer");
                 Err(err.into())
             }
         }
@@ -40,76 +40,63 @@ impl Connection {
                 if self.buffer.is_empty() {
                     return Ok(None);
                 }else {
-                    return Err("Connection reset by peer".into());
+                    return Err(Error::new(ErrorKind::ConnectionReset, "connection reset by peer").into());
                 }
             }
         }
     }
-    // pub async fn write_string(&mut  self,data:String) -> crate::Result<()>{
-    //     self.stream.write(value).await.unwrap();
-    //     self.stream.flush().await.unwrap();
-    // }
     // bulk $-1\r\n 表示Null
     pub(crate) async fn write_frame(&mut self ,frame:&Frame) ->crate::Result<()> {
-        match frame  {
-            Frame::Array(v) => {
-                let len = v.len();
-                self.stream.write_u8(b'*').await?;
-                self.write_decimal(len as u64).await?;
-                for frame in v {
-                    self.write_value(frame).await?;
-                }
-            }
-            frame => self.write_value(frame).await?,
-        }
+        let mut buf = Vec::new();
+        frame.encode(&mut buf);
+        self.stream.write_all(&buf).await?;
+        self.stream.flush().await?;
         Ok(())
     }
-    pub(crate) async fn write_value(&mut self,frame:&Frame) -> crate::Result<()> {
-        match frame  {
-            Frame::Null => {self.stream.write(b"$-1\r\n").await?;} ,
-            Frame::Bulk(data) =>{
-                let length = data.len();
-                self.stream.write_u8(b'$').await?;
-                self.write_decimal(length as u64).await?;
-                //self.stream.write(format!("${length}\r\n").as_bytes()).await?;
-                self.stream.write(&data).await?;
-                self.stream.write(b"\r\n").await?;
-            } ,
-            Frame::Integer(num) => {
-                self.stream.write(b":").await?;
-                self.stream.write(num.to_string().as_bytes()).await?;
-                self.stream.write(b"\r\n").await?;
-            },
-            Frame::Simple(data) =>  {
-                self.stream.write_u8(b'+').await?;
-                self.stream.write(data.as_bytes()).await?;
-                self.stream.write(b"\r\n").await?;
-            },
-            _ => panic!("not achieve here"),
-        }
+    //只写进BufWriter不flush，管道里的多条命令攒起来一次发送
+    pub(crate) async fn buffer_frame(&mut self,frame:&Frame) -> crate::Result<()> {
+        let mut buf = Vec::new();
+        frame.encode(&mut buf);
+        self.stream.write_all(&buf).await?;
+        Ok(())
+    }
+    pub(crate) async fn flush(&mut self) -> crate::Result<()> {
         self.stream.flush().await?;
         Ok(())
     }
-    //如果数据不 足够解析出一个frame返回Ok(None),如果数据错误无法继续解析返回Err
     fn parse_frame(&mut self) -> crate::Result<Option<Frame>> {
-        use frame::Error::Incomplete;
-        let mut buf = Cursor::new(&self.buffer[..]);
-        match Frame::check(&mut buf) {
-            Ok(_) => {
-                let len = buf.position() as usize;
-                buf.set_position(0);
-                let frame = Frame::parse(&mut buf)?;
-                self.buffer.advance(len);
-                Ok(Some(frame))
+        parse_frame(&mut self.buffer)
+    }
+    //直接写原始字节，复制时转发backlog里已经编码好的命令
+    pub(crate) async fn write_raw(&mut self,data:&[u8]) -> crate::Result<()> {
+        self.stream.write_all(data).await?;
+        self.stream.flush().await?;
+        Ok(())
+    }
+    //全量同步时主发过来的rdb: $len\r\n后面跟着len个字节，结尾没有\r\n
+    pub(crate) async fn read_bulk_payload(&mut self) -> crate::Result<Bytes> {
+        loop {
+            //主在生成rdb期间可能发换行保持连接
+            while self.buffer.first() == Some(&b'\n') {
+                self.buffer.advance(1);
+            }
+            if let Some(pos) = self.buffer.windows(2).position(|w| w == b"\r\n") {
+                if self.buffer[0] != b'$' {
+                    return Err("protocol error; expected bulk payload".into());
+                }
+                let len:usize = std::str::from_utf8(&self.buffer[1..pos])?.parse()?;
+                if self.buffer.len() >= pos + 2 + len {
+                    self.buffer.advance(pos + 2);
+                    return Ok(self.buffer.split_to(len).freeze());
+                }
+            }
+            if 0 == self.stream.read_buf(&mut self.buffer).await? {
+                return Err(Error::new(ErrorKind::ConnectionReset, "connection reset by peer").into());
             }
-            Err(Incomplete) => Ok(None),
-            Err(err) =>  Err(err.into()),
         }
     }
-    pub(crate) async fn write_string(&mut self,s:String) ->crate::Result<()> {
-        let frame = Frame::Simple(s);
-        self.write_frame(&frame).await?;
-        Ok(())
+    pub(crate) fn peer_addr(&self) -> std::io::Result<std::net::SocketAddr> {
+        self.stream.get_ref().peer_addr()
     }
     pub(crate) async fn write_null(&mut self) ->crate::Result<()>{
         let frame = Frame::Null;
@@ -121,16 +108,21 @@ impl Connection {
         self.write_frame(&frame).await?;
         Ok(())
     }
-    pub(crate) async fn write_decimal(&mut self,val:u64) -> crate::Result<()> {
-        // use std::io::Write;
-        // let mut buf = [0u8;20];
-        // let mut buf = Cursor::new(&mut buf[..]);
-        // write!(&mut buf, "{}", val)?;
-        // let pos = buf.position() as usize;
-        // self.stream.write_all(&buf.get_ref()[..pos]).await?;
-        let num = format!("{}",val);
-        self.stream.write_all(num.as_bytes()).await?;
-        self.stream.write_all(b"\r\n").await?;
-        Ok(())
+}
+
+//如果数据不 足够解析出一个frame返回Ok(None),如果数据错误无法继续解析返回Err
+pub(crate) fn parse_frame(buffer:&mut BytesMut) -> crate::Result<Option<Frame>> {
+    use frame::Error::Incomplete;
+    let mut buf = Cursor::new(&buffer[..]);
+    match Frame::check(&mut buf) {
+        Ok(_) => {
+            let len = buf.position() as usize;
+            buf.set_position(0);
+            let frame = Frame::parse(&mut buf)?;
+            buffer.advance(len);
+            Ok(Some(frame))
+        }
+        Err(Incomplete) => Ok(None),
+        Err(err) =>  Err(err.into()),
     }
-}
\ No newline at end of file
+}
@@ -1,9 +1,22 @@
-use std::{sync::Arc, collections::HashMap};
+use std::{sync::Arc, collections::{HashMap, HashSet, VecDeque, hash_map}};
 use bytes::Bytes;
-use tokio::{sync::{ Notify}, time::{Instant, self}};
-use tracing::debug;
-use std::sync::Mutex;
-use std::collections::BTreeMap;
+use tokio::{sync::{ Notify, broadcast}, time::{Instant, self}};
+use tracing::{debug, warn};
+use crate::config::Config;
+use crate::script::{Scripts, RestorePolicy};
+use std::time::Duration;
+use crate::notify::NotifyFlags;
+use crate::snapshot::{self, Persistence, Snapshot};
+use crate::aof::{self, Aof};
+use crate::frame::Frame;
+use crate::Command;
+use crate::rdb;
+use crate::replication::Replication;
+use crate::cluster::{self, Cluster};
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard, atomic::{AtomicU64, AtomicUsize}};
+use std::hash::{Hash, Hasher};
+use std::collections::{BTreeMap, BTreeSet};
 #[derive(Debug)]
 pub(crate) struct DbDropGuard{
     db:Db
@@ -16,56 +29,511 @@ pub(crate) struct Db {
 #[derive(Debug)]
 pub(crate) struct Shared {
     stat: Mutex<Stat>,
-    notify: Notify
+    notify: Notify,
+    scripts: Scripts,
+    persistence: Arc<Persistence>,
+    //没开appendonly时为None
+    aof: Option<Arc<Aof>>,
+    replication: Replication,
+    //没开集群模式时为None
+    cluster: Option<Cluster>,
+    counters: Counters
+}
+//INFO clients/stats用的计数，不需要持有Stat锁
+#[derive(Debug,Default)]
+pub(crate) struct Counters {
+    pub(crate) connected_clients:AtomicUsize,
+    pub(crate) total_connections:AtomicU64,
+    pub(crate) total_commands:AtomicU64
+}
+//key存在时是entry的id，不存在时是当时db的删除计数
+//key在WATCH之后被创建再删除的话删除计数一定会变，只是其他key被删除也会让事务放弃
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub(crate) enum WatchVersion {
+    Entry(u64),
+    Missing(u64)
 }
 #[derive(Debug)]
 pub(crate) struct Stat {
     entries:HashMap<String,Entry>,
     next_id :u64,
     shutdown:bool,
-    expired:BTreeMap<(Instant,u64),String>
+    expired:BTreeMap<(Instant,u64),String>,
+    //删除(包括过期)过的key数量，WATCH一个不存在的key时用它判断期间有没有key被创建又删除
+    removed:u64,
+    //channel -> 订阅者
+    pub_sub:HashMap<String,broadcast::Sender<Bytes>>,
+    //pattern -> 订阅者，消息里带上实际的channel
+    pattern_subs:HashMap<String,broadcast::Sender<(String,Bytes)>>,
+    notify_flags:NotifyFlags,
+    //集群模式下按(slot,key)索引，CLUSTER COUNTKEYSINSLOT和迁移slot时用
+    slot_keys:Option<BTreeSet<(u16,String)>>,
+    //按(key的哈希值,key)索引，SCAN从游标位置开始往后取
+    hash_keys:BTreeSet<(u64,String)>
+}
+//持有Stat锁期间对db的操作，EXEC需要在同一把锁下执行多个命令
+pub(crate) struct DbGuard<'a> {
+    shared:&'a Shared,
+    stat:MutexGuard<'a,Stat>,
+    //副本执行主发来的命令时关闭，由复制连接记录原始数据
+    propagation:bool
 }
 #[derive(Debug)]
 pub(crate) struct Entry {
     //id用来唯一标示，由于同一过期时间可以有多个key，所以还需要id，来标识
     //set的时候如果某个key已经存在且有过期时间，就需要用id来指定remove old key expiration.
+    //每次写入id都会递增，所以id也是这个key的修改版本号，WATCH用它判断key是否被改过
     id:u64,
-    data:Bytes,
-    expiration_at:Option<Instant>
+    data:Value,
+    expiration_at:Option<Instant>,
+    //最近一次访问的时间和访问次数(到255为止)，OBJECT IDLETIME/FREQ和RESTORE的IDLETIME/FREQ用
+    lru:Instant,
+    freq:u8
+}
+//key对应的值，目前只有字符串有对应的命令，其它类型来自rdb导入
+//集合类型用Arc包起来，快照时拷贝只需要增加引用计数
+#[derive(Debug,Clone)]
+pub(crate) enum Value {
+    String(Bytes),
+    List(Arc<VecDeque<Bytes>>),
+    Hash(Arc<HashMap<Bytes,Bytes>>),
+    Set(Arc<HashSet<Bytes>>),
+    //按(score,member)排好序
+    ZSet(Arc<Vec<(f64,Bytes)>>)
+}
+impl Value {
+    pub(crate) fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::List(_) => "list",
+            Value::Hash(_) => "hash",
+            Value::Set(_) => "set",
+            Value::ZSet(_) => "zset"
+        }
+    }
+    //字符串是字节数，其它类型是元素个数
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            Value::String(data) => data.len(),
+            Value::List(list) => list.len(),
+            Value::Hash(hash) => hash.len(),
+            Value::Set(set) => set.len(),
+            Value::ZSet(zset) => zset.len()
+        }
+    }
 }
 impl DbDropGuard {
-    pub(crate) fn new() -> Self {
-        Self { db: Db::new() }
+    pub(crate) fn new(config:&Config) -> Self {
+        Self { db: Db::new(config) }
     } 
     pub(crate) fn db(&self) -> Db{
         self.db.clone()
     }
 }
 impl Db {
-    pub(crate) fn new() -> Self {
+    pub(crate) fn new(config:&Config) -> Self {
        let shared =Arc::new(Shared {
         stat:Mutex::new(
         Stat{
            shutdown:false,
            next_id:0,
            entries:HashMap::new() ,
-           expired:BTreeMap::new()
+           expired:BTreeMap::new(),
+           removed:0,
+           pub_sub:HashMap::new(),
+           pattern_subs:HashMap::new(),
+           notify_flags:config.notify_keyspace_events,
+           slot_keys:config.cluster_enabled.then(BTreeSet::new),
+           hash_keys:BTreeSet::new()
         }),
-        notify:Notify::new()
+        notify:Notify::new(),
+        scripts:Scripts::new(config.script_timeout),
+        persistence:Arc::new(Persistence::new(config.dir.join(&config.dbfilename))),
+        aof:config.appendonly.then(|| Arc::new(Aof::new(config.dir.join(&config.appendfilename), config.appendfsync))),
+        replication:Replication::new(config),
+        cluster:config.cluster_enabled.then(|| Cluster::new(config)),
+        counters:Counters::default()
        }
     );
     tokio::spawn(purge_expired_keys(shared.clone()));
     Db { shared }
     }
-    pub(crate) fn get(&self,key:&str) -> Option<Bytes> {
-        let stat = self.shared.stat.lock().unwrap();
-        stat.entries.get(key).map(|entry|entry.data.clone())
+    pub(crate) fn lock(&self) -> DbGuard<'_> {
+        DbGuard { shared: &self.shared, stat: self.shared.stat.lock().unwrap(), propagation: true }
+    }
+    pub(crate) fn replication(&self) -> &Replication {
+        &self.shared.replication
+    }
+    pub(crate) fn cluster(&self) -> Option<&Cluster> {
+        self.shared.cluster.as_ref()
+    }
+    pub(crate) fn counters(&self) -> &Counters {
+        &self.shared.counters
+    }
+    //角色变成主之后需要重新开始清理过期key
+    pub(crate) fn wake_purge_task(&self) {
+        self.shared.notify.notify_one();
+    }
+    pub(crate) fn scripts(&self) -> &Scripts {
+        &self.shared.scripts
+    }
+    pub(crate) fn persistence(&self) -> &Arc<Persistence> {
+        &self.shared.persistence
+    }
+    pub(crate) fn get(&self,key:&str) -> Option<Value> {
+        self.lock().get(key)
+    }
+    //只在拷贝数据时持有锁，序列化和写文件由调用方在锁外完成
+    pub(crate) fn snapshot(&self) -> Snapshot {
+        self.lock().snapshot()
+    }
+    //启动时加载快照，返回加载的key数量
+    pub(crate) fn load_snapshot(&self) -> crate::Result<usize> {
+        match self.shared.persistence.load()? {
+            Some(snapshot) => self.restore(snapshot),
+            None => Ok(0)
+        }
+    }
+    //导入redis的rdb文件，已有的同名key会被覆盖
+    pub(crate) fn import_rdb(&self,path:&Path) -> crate::Result<usize> {
+        let data = std::fs::read(path)?;
+        self.restore(rdb::decode(&data)?)
+    }
+    fn restore(&self,snapshot:Snapshot) -> crate::Result<usize> {
+        self.lock().restore(snapshot, RestorePolicy::Replace)
+    }
+    //开了appendonly且aof文件存在时用它代替快照来恢复数据，加载完成后才开始记录新的写入
+    pub(crate) fn load(&self) -> crate::Result<usize> {
+        let aof = match self.shared.aof.as_ref() {
+            Some(aof) => aof,
+            None => return self.load_snapshot()
+        };
+        let count = if aof.exists() { self.load_aof(aof)? } else { self.load_snapshot()? };
+        aof.open()?;
+        aof.spawn_fsync_task();
+        Ok(count)
+    }
+    //通过和客户端命令相同的from_frame路径重放，返回重放的命令数量
+    fn load_aof(&self,aof:&Aof) -> crate::Result<usize> {
+        let frames = aof.load()?;
+        let count = frames.len();
+        let mut db = self.lock();
+        for frame in frames {
+            if let Frame::Error(err) = Command::from_frame(frame)?.execute(&mut db) {
+                warn!(cause=%err,"error replaying append only file");
+            }
+        }
+        Ok(count)
+    }
+    pub(crate) fn subscribe(&self,channel:String) -> broadcast::Receiver<Bytes> {
+        let mut stat = self.shared.stat.lock().unwrap();
+        match stat.pub_sub.entry(channel) {
+            hash_map::Entry::Occupied(e) => e.get().subscribe(),
+            hash_map::Entry::Vacant(e) => {
+                let (tx,rx) = broadcast::channel(1024);
+                e.insert(tx);
+                rx
+            }
+        }
+    }
+    //最后一个订阅者退订之后删掉channel，不然订阅过的channel会一直留在map里
+    pub(crate) fn unsubscribe(&self,channel:&str) {
+        let mut stat = self.shared.stat.lock().unwrap();
+        if stat.pub_sub.get(channel).is_some_and(|tx| tx.receiver_count() == 0) {
+            stat.pub_sub.remove(channel);
+        }
+    }
+    pub(crate) fn psubscribe(&self,pattern:String) -> broadcast::Receiver<(String,Bytes)> {
+        let mut stat = self.shared.stat.lock().unwrap();
+        match stat.pattern_subs.entry(pattern) {
+            hash_map::Entry::Occupied(e) => e.get().subscribe(),
+            hash_map::Entry::Vacant(e) => {
+                let (tx,rx) = broadcast::channel(1024);
+                e.insert(tx);
+                rx
+            }
+        }
     }
-    pub(crate) fn set(&self,key:String,value:Bytes,expiration_at:Option<Instant>) {
+    pub(crate) fn punsubscribe(&self,pattern:&str) {
         let mut stat = self.shared.stat.lock().unwrap();
+        if stat.pattern_subs.get(pattern).is_some_and(|tx| tx.receiver_count() == 0) {
+            stat.pattern_subs.remove(pattern);
+        }
+    }
+}
+impl<'a> DbGuard<'a> {
+    //返回的引用不依赖guard本身的借用，这样脚本执行时可以同时拿着&mut DbGuard
+    pub(crate) fn scripts(&self) -> &'a Scripts {
+        &self.shared.scripts
+    }
+    pub(crate) fn aof(&self) -> Option<&'a Arc<Aof>> {
+        self.shared.aof.as_ref()
+    }
+    //把写命令的效果记录到aof和复制backlog，需要在锁内调用以保证顺序
+    pub(crate) fn propagate(&self,frame:impl FnOnce() -> Frame) {
+        if self.propagation {
+            self.shared.propagate(frame)
+        }
+    }
+    pub(crate) fn set_propagation(&mut self,propagation:bool) {
+        self.propagation = propagation;
+    }
+    //副本收到的原始数据原样记录，保证和主的offset一致，PING不需要写进aof
+    pub(crate) fn propagate_raw(&self,data:&[u8],to_aof:bool) {
+        if to_aof {
+            if let Some(aof) = self.shared.aof.as_ref() {
+                aof.append(data);
+            }
+        }
+        self.shared.replication.feed(data);
+    }
+    pub(crate) fn replication(&self) -> &'a Replication {
+        &self.shared.replication
+    }
+    pub(crate) fn cluster(&self) -> Option<&'a Cluster> {
+        self.shared.cluster.as_ref()
+    }
+    pub(crate) fn counters(&self) -> &'a Counters {
+        &self.shared.counters
+    }
+    //(key数量,设置了过期时间的key数量)，包括已经过期还没清理的
+    pub(crate) fn key_count(&self) -> (usize,usize) {
+        (self.stat.entries.len(),self.stat.expired.len())
+    }
+    //不更新访问信息，TYPE和STRLEN等命令用
+    pub(crate) fn peek(&self,key:&str) -> Option<&Value> {
+        let entry = self.stat.entries.get(key)?;
+        match entry.expiration_at {
+            Some(when) if when <= Instant::now() => None,
+            _ => Some(&entry.data)
+        }
+    }
+    //按key的哈希值从小到大遍历，cursor是下一次开始的哈希值，返回0表示遍历完了
+    //哈希值不受插入删除影响，所以遍历期间一直存在的key一定会返回，新增的key可能返回也可能不返回
+    //每次最多看count个key(包括已过期没清理的)，哈希值相同的key在同一批返回
+    pub(crate) fn scan(&self,cursor:u64,count:usize,filter:impl Fn(&str,&Value) -> bool) -> (u64,Vec<String>) {
+        let now = Instant::now();
+        let mut keys = Vec::new();
+        let mut last = None;
+        for (visited,(hash,key)) in self.stat.hash_keys.range((cursor,String::new())..).enumerate() {
+            //哈希值是0的key只能在第一批，所以返回的游标不会是0
+            if visited >= count.max(1) && last != Some(*hash) {
+                return (*hash,keys);
+            }
+            last = Some(*hash);
+            let entry = &self.stat.entries[key];
+            if entry.expiration_at.map(|when| when > now).unwrap_or(true) && filter(key, &entry.data) {
+                keys.push(key.clone());
+            }
+        }
+        (0,keys)
+    }
+    //slot里key的数量，没开集群模式时为0
+    pub(crate) fn count_keys_in_slot(&self,slot:u16) -> usize {
+        self.stat.slot_keys.as_ref()
+            .map(|index| index.range((slot,String::new())..).take_while(|(s,_)| *s == slot).count())
+            .unwrap_or(0)
+    }
+    //slot里最多count个key，按key排序
+    pub(crate) fn keys_in_slot(&self,slot:u16,count:usize) -> Vec<String> {
+        self.stat.slot_keys.as_ref()
+            .map(|index| index.range((slot,String::new())..).take_while(|(s,_)| *s == slot).take(count).map(|(_,key)| key.clone()).collect())
+            .unwrap_or_default()
+    }
+    //把快照里的数据写进db，已经过期的key直接丢弃，返回写入的key数量
+    pub(crate) fn restore(&mut self,snapshot:Snapshot,policy:RestorePolicy) -> crate::Result<usize> {
+        if policy == RestorePolicy::Flush {
+            self.shared.scripts.function_flush();
+        }
+        if !snapshot.functions.is_empty() {
+            self.shared.scripts.function_restore(&snapshot.functions, policy)?;
+        }
+        let now = Instant::now();
+        let now_ms = snapshot::unix_time_millis();
+        let mut count = 0;
+        for (key,value,expire_at) in snapshot.entries {
+            let expiration_at = match expire_at {
+                Some(ms) if ms <= now_ms => continue,
+                Some(ms) => Some(now + Duration::from_millis(ms - now_ms)),
+                None => None
+            };
+            self.set_value(key, value, expiration_at);
+            count += 1;
+        }
+        Ok(count)
+    }
+    //清空所有key，副本全量同步时用，不产生事件也不记录
+    pub(crate) fn clear(&mut self) {
+        self.stat.removed += self.stat.entries.len() as u64;
+        self.stat.entries.clear();
+        self.stat.expired.clear();
+        self.stat.hash_keys.clear();
+        if let Some(index) = self.stat.slot_keys.as_mut() {
+            index.clear();
+        }
+    }
+    pub(crate) fn persistence(&self) -> &'a Arc<Persistence> {
+        &self.shared.persistence
+    }
+    //拷贝当前所有没过期的数据，Value的clone只是增加引用计数
+    pub(crate) fn snapshot(&self) -> Snapshot {
+        let now = Instant::now();
+        let now_ms = snapshot::unix_time_millis();
+        let mut entries = Vec::with_capacity(self.stat.entries.len());
+        for (key,entry) in &self.stat.entries {
+            let expire_at = match entry.expiration_at {
+                Some(when) if when <= now => continue,
+                Some(when) => Some(now_ms + (when - now).as_millis() as u64),
+                None => None
+            };
+            entries.push((key.clone(),entry.data.clone(),expire_at));
+        }
+        Snapshot { entries, functions: self.shared.scripts.function_dump() }
+    }
+    //读取同时更新访问时间和次数
+    pub(crate) fn get(&mut self,key:&str) -> Option<Value> {
+        let entry = self.stat.entries.get_mut(key)?;
+        //副本不主动删除过期key，等主发DEL过来，这期间读不到
+        if entry.expiration_at.map(|when| when <= Instant::now()).unwrap_or(false) {
+            return None;
+        }
+        entry.lru = Instant::now();
+        entry.freq = entry.freq.saturating_add(1);
+        Some(entry.data.clone())
+    }
+    //不更新访问信息，返回(空闲时间,访问次数)
+    pub(crate) fn access_info(&self,key:&str) -> Option<(Duration,u8)> {
+        self.stat.entries.get(key).map(|entry| (entry.lru.elapsed(), entry.freq))
+    }
+    pub(crate) fn set_access_info(&mut self,key:&str,idle:Option<Duration>,freq:Option<u8>) {
+        if let Some(entry) = self.stat.entries.get_mut(key) {
+            if let Some(idle) = idle {
+                let now = Instant::now();
+                entry.lru = now.checked_sub(idle).unwrap_or(now);
+            }
+            if let Some(freq) = freq {
+                entry.freq = freq;
+            }
+        }
+    }
+    pub(crate) fn notify_keyspace_event(&self,class:u8,event:&str,key:&str) {
+        self.stat.notify_keyspace_event(class, event, key)
+    }
+    //key的过期时间，unix毫秒
+    pub(crate) fn expire_at(&self,key:&str) -> Option<u64> {
+        self.stat.entries.get(key)?.expiration_at.map(unix_millis)
+    }
+    //WATCH记录的状态，EXEC时和当前状态不一样就放弃事务
+    pub(crate) fn watch_version(&self,key:&str) -> WatchVersion {
+        match self.version(key) {
+            Some(id) => WatchVersion::Entry(id),
+            None => WatchVersion::Missing(self.stat.removed)
+        }
+    }
+    //key当前的版本号，不存在或者已经过期(还没来得及被purge)返回None
+    pub(crate) fn version(&self,key:&str) -> Option<u64> {
+        let entry = self.stat.entries.get(key)?;
+        match entry.expiration_at {
+            Some(when) if when <= Instant::now() => None,
+            _ => Some(entry.id)
+        }
+    }
+    pub(crate) fn set(&mut self,key:String,value:Bytes,expiration_at:Option<Instant>) {
+        self.stat.notify_keyspace_event(NotifyFlags::STRING, "set", &key);
+        self.propagate(|| aof::set_command(&key, &value, expiration_at.map(unix_millis)));
+        self.insert(key, Value::String(value), expiration_at);
+    }
+    //写入任意类型的值，用于RESTORE和从快照或者rdb恢复数据
+    pub(crate) fn set_value(&mut self,key:String,value:Value,expiration_at:Option<Instant>) {
+        self.propagate(|| aof::value_command(&key, &value, expiration_at.map(unix_millis)));
+        self.insert(key, value, expiration_at);
+    }
+    fn insert(&mut self,key:String,value:Value,expiration_at:Option<Instant>) {
+        let stat = &mut *self.stat;
         let id = stat.next_id;
         stat.next_id += 1;
-        stat.entries.insert(key, Entry { id, data: value, expiration_at });
+        let mut notify = false;
+        if let Some(when) = expiration_at {
+            //新的过期时间比当前最早的还早，需要唤醒purge任务重新计算sleep时间
+            notify = stat.expired.keys().next().map(|&(first,_)| first > when).unwrap_or(true);
+            stat.expired.insert((when,id), key.clone());
+        }
+        if let Some(index) = stat.slot_keys.as_mut() {
+            index.insert((cluster::key_slot(key.as_bytes()),key.clone()));
+        }
+        stat.hash_keys.insert((key_hash(&key),key.clone()));
+        let prev = stat.entries.insert(key, Entry { id, data: value, expiration_at, lru: Instant::now(), freq: 0 });
+        if let Some(Entry { id, expiration_at: Some(when), .. }) = prev {
+            stat.expired.remove(&(when,id));
+        }
+        if notify {
+            self.shared.notify.notify_one();
+        }
+    }
+    //删除key，返回key是否存在
+    pub(crate) fn remove(&mut self,key:&str) -> bool {
+        let stat = &mut *self.stat;
+        match stat.entries.remove(key) {
+            Some(entry) => {
+                if let Some(when) = entry.expiration_at {
+                    stat.expired.remove(&(when,entry.id));
+                }
+                stat.removed += 1;
+                remove_slot_key(&mut stat.slot_keys, key);
+                stat.hash_keys.remove(&(key_hash(key),key.to_string()));
+                stat.notify_keyspace_event(NotifyFlags::GENERIC, "del", key);
+                self.propagate(|| aof::del_command(key));
+                true
+            }
+            None => false
+        }
+    }
+    pub(crate) fn publish(&self,channel:&str,msg:Bytes) -> usize {
+        self.stat.publish(channel, msg)
+    }
+    pub(crate) fn notify_flags(&self) -> NotifyFlags {
+        self.stat.notify_flags
+    }
+    pub(crate) fn set_notify_flags(&mut self,flags:NotifyFlags) {
+        self.stat.notify_flags = flags;
+    }
+}
+
+impl Stat {
+    //返回收到消息的订阅者数量
+    fn publish(&self,channel:&str,msg:Bytes) -> usize {
+        let mut count = self.pub_sub.get(channel)
+            .map(|tx| tx.send(msg.clone()).unwrap_or(0))
+            .unwrap_or(0);
+        for (pattern,tx) in &self.pattern_subs {
+            if glob_match(pattern, channel) {
+                count += tx.send((channel.to_string(),msg.clone())).unwrap_or(0);
+            }
+        }
+        count
+    }
+    fn notify_keyspace_event(&self,class:u8,event:&str,key:&str) {
+        let flags = self.notify_flags;
+        if !flags.enabled(class) {
+            return;
+        }
+        if flags.keyspace() {
+            self.publish(&format!("__keyspace@0__:{}",key), Bytes::from(event.to_string()));
+        }
+        if flags.keyevent() {
+            self.publish(&format!("__keyevent@0__:{}",event), Bytes::from(key.to_string()));
+        }
+    }
+}
+//SCAN的游标，DefaultHasher::new()的key是固定的，重启之后也不变
+fn key_hash(key:&str) -> u64 {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+fn remove_slot_key(index:&mut Option<BTreeSet<(u16,String)>>,key:&str) {
+    if let Some(index) = index.as_mut() {
+        index.remove(&(cluster::key_slot(key.as_bytes()),key.to_string()));
     }
 }
 async fn purge_expired_keys(shared: Arc<Shared>) {
@@ -83,13 +551,26 @@ async fn purge_expired_keys(shared: Arc<Shared>) {
 }
 
 impl Shared {
+    //只编码一次，同时写进aof和复制backlog
+    fn propagate(&self,frame:impl FnOnce() -> Frame) {
+        if self.aof.is_none() && !self.replication.has_backlog() {
+            return;
+        }
+        let mut buf = Vec::new();
+        frame().encode(&mut buf);
+        if let Some(aof) = self.aof.as_ref() {
+            aof.append(&buf);
+        }
+        self.replication.feed(&buf);
+    }
     fn is_shutdown(&self) -> bool {
         self.stat.lock().unwrap().shutdown
     }
 
     fn purge_expired_keys(&self) -> Option<Instant> {
         let mut stat = self.stat.lock().unwrap();
-        if stat.shutdown {
+        //副本上的过期key由主通过DEL删除
+        if stat.shutdown || self.replication.is_replica() {
             return None;
         }
         let now = Instant::now();
@@ -99,8 +580,93 @@ impl Shared {
                 return Some(instant);
             }
             stat.entries.remove(key);
+            stat.removed += 1;
+            remove_slot_key(&mut stat.slot_keys, key);
+            stat.hash_keys.remove(&(key_hash(key),key.to_string()));
+            stat.notify_keyspace_event(NotifyFlags::EXPIRED, "expired", key);
+            self.propagate(|| aof::del_command(key));
             stat.expired.remove(&(instant,uid));
         }
         None
     }
 }
+
+//把Instant换算成unix毫秒，用于记录绝对的过期时间
+fn unix_millis(when:Instant) -> u64 {
+    snapshot::unix_time_millis() + when.saturating_duration_since(Instant::now()).as_millis() as u64
+}
+
+//redis风格的glob匹配，只支持*和?
+//遇到不匹配时回到最近的*多吃一个字符重试，不递归，最坏O(len(pattern)*len(s))
+pub(crate) fn glob_match(pattern:&str,s:&str) -> bool {
+    let (p,s) = (pattern.as_bytes(),s.as_bytes());
+    let (mut pi,mut si) = (0,0);
+    //最近的*在pattern里的位置，以及它之后从s的哪里开始匹配
+    let mut star:Option<(usize,usize)> = None;
+    while si < s.len() {
+        match p.get(pi) {
+            Some(b'*') => {
+                star = Some((pi,si));
+                pi += 1;
+            }
+            Some(&c) if c == b'?' || c == s[si] => {
+                pi += 1;
+                si += 1;
+            }
+            _ => match star {
+                Some((star_pi,star_si)) => {
+                    pi = star_pi + 1;
+                    si = star_si + 1;
+                    star = Some((star_pi,si));
+                }
+                None => return false
+            }
+        }
+    }
+    p[pi..].iter().all(|&c| c == b'*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{glob_match, Db};
+    use crate::config::Config;
+
+    #[test]
+    fn glob() {
+        assert!(glob_match("*", ""));
+        assert!(glob_match("news.*", "news.tech"));
+        assert!(glob_match("h?llo", "hello"));
+        assert!(glob_match("a*b*c", "aXXbYYc"));
+        assert!(glob_match("*a", "aaa"));
+        assert!(!glob_match("h?llo", "hllo"));
+        assert!(!glob_match("a*b", "aXXc"));
+        assert!(!glob_match("", "a"));
+    }
+
+    #[test]
+    fn glob_pathological_pattern_is_fast() {
+        let pattern = "a*".repeat(1000) + "b";
+        let s = "a".repeat(10000);
+        let start = std::time::Instant::now();
+        assert!(!glob_match(&pattern, &s));
+        assert!(glob_match(&pattern[..pattern.len() - 1], &s));
+        assert!(start.elapsed() < std::time::Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn last_unsubscribe_removes_channel() {
+        let db = Db::new(&Config::default());
+        let first = db.subscribe("a".to_string());
+        let second = db.subscribe("a".to_string());
+        drop(first);
+        db.unsubscribe("a");
+        assert!(db.shared.stat.lock().unwrap().pub_sub.contains_key("a"));
+        drop(second);
+        db.unsubscribe("a");
+        assert!(db.shared.stat.lock().unwrap().pub_sub.is_empty());
+
+        drop(db.psubscribe("a*".to_string()));
+        db.punsubscribe("a*");
+        assert!(db.shared.stat.lock().unwrap().pattern_subs.is_empty());
+    }
+}
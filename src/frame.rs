@@ -5,13 +5,15 @@ use bytes::{Bytes, Buf};
 use std::num::TryFromIntError;
 use std::string::FromUtf8Error;
 #[derive(Debug)]
-pub(crate) enum Frame {
+pub enum Frame {
     Bulk(Bytes),
     Array(Vec<Frame>),
-    Integer(u64),
+    Integer(i64),
     Simple(String),
     Error(String),
-    Null
+    Null,
+    //*-1\r\n，WATCH的key被修改时EXEC返回的就是这个
+    NullArray
 }
 #[derive(Debug)]
 pub(crate) enum Error {
@@ -49,7 +51,7 @@ impl From<TryFromIntError> for Error {
     }
 }
 impl Frame {
-    pub(crate) fn to_err(self) -> crate::Error {
+    pub(crate) fn into_err(self) -> crate::Error {
         format!("unexpected frame: {}",self).into()
     }
     //需要读取一个字符，
@@ -67,13 +69,17 @@ impl Frame {
             }
             // number 
             b':'=> {
-                get_decimal(src)?;
+                get_int(src)?;
                 Ok(()) 
             }
             //array
             b'*'=> {
+                if peek_u8(src)? == b'-' {
+                    //"-1\r\n"
+                    return skip(src,4);
+                }
                 let len = get_decimal(src)?;
-                for i in  0..len {
+                for _ in  0..len {
                     Frame::check(src)?;
                 } 
                 Ok(()) 
@@ -108,14 +114,20 @@ impl Frame {
              }
              // number 
              b':'=> {
-                let num = get_decimal(src)?;
+                let num = get_int(src)?;
                  Ok(Frame::Integer(num)) 
              }
              //array
              b'*'=> {
+                 if peek_u8(src)? == b'-' {
+                     if get_line(src)? != b"-1" {
+                        return Err("invalid protocol format".into());
+                     }
+                     return Ok(Frame::NullArray);
+                 }
                  let len = get_decimal(src)?.try_into()?;
                  let mut vec = Vec::with_capacity(len);
-                 for i in  0..len {
+                 for _ in  0..len {
                      let frame = Frame::parse(src)?;
                      vec.push(frame);
                  } 
@@ -137,15 +149,49 @@ impl Frame {
                     return Err(Error::Incomplete);
                  }
                  let data = Bytes::copy_from_slice(&src.chunk()[..len]);
-                 skip(src, end);
+                 skip(src, end)?;
                  Ok(Frame::Bulk(data))
                  }
              }
              _ => panic!()
         }
     }
-    pub(crate) fn from_string(s:String) -> Self {
-        Self::Simple(s)
+    //序列化成RESP格式，数组可以嵌套(EXEC的回复就是数组套数组)
+    pub(crate) fn encode(&self,dst:&mut Vec<u8>) {
+        match self {
+            Frame::Simple(data) => {
+                dst.push(b'+');
+                dst.extend_from_slice(data.as_bytes());
+                dst.extend_from_slice(b"\r\n");
+            }
+            Frame::Error(msg) => {
+                dst.push(b'-');
+                dst.extend_from_slice(msg.as_bytes());
+                dst.extend_from_slice(b"\r\n");
+            }
+            Frame::Integer(num) => {
+                dst.push(b':');
+                dst.extend_from_slice(num.to_string().as_bytes());
+                dst.extend_from_slice(b"\r\n");
+            }
+            Frame::Bulk(data) => {
+                dst.push(b'$');
+                dst.extend_from_slice(data.len().to_string().as_bytes());
+                dst.extend_from_slice(b"\r\n");
+                dst.extend_from_slice(data);
+                dst.extend_from_slice(b"\r\n");
+            }
+            Frame::Null => dst.extend_from_slice(b"$-1\r\n"),
+            Frame::NullArray => dst.extend_from_slice(b"*-1\r\n"),
+            Frame::Array(v) => {
+                dst.push(b'*');
+                dst.extend_from_slice(v.len().to_string().as_bytes());
+                dst.extend_from_slice(b"\r\n");
+                for frame in v {
+                    frame.encode(dst);
+                }
+            }
+        }
     }
 }
 
@@ -177,12 +223,20 @@ fn skip(src:&mut Cursor<&[u8]>,n:usize) -> Result<(),Error> {
     if src.remaining() < n {
         return Err(Error::Incomplete);
     }
-    Ok(src.advance(n))
+    src.advance(n);
+    Ok(())
 }
 fn get_decimal(src:&mut Cursor<&[u8]>)->Result<u64,Error> {
     let data = get_line(src)?;
     atoi::atoi::<u64>(data).ok_or_else(||"protocol parse error".into())
 }
+//integer frame可以是负数，比如lua脚本返回的值
+fn get_int(src:&mut Cursor<&[u8]>)->Result<i64,Error> {
+    let data = get_line(src)?;
+    std::str::from_utf8(data).ok()
+        .and_then(|s| s.parse::<i64>().ok())
+        .ok_or_else(||"protocol parse error".into())
+}
 
 impl fmt::Display for Frame {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
@@ -196,7 +250,7 @@ impl fmt::Display for Frame {
                 Ok(string) => string.fmt(fmt),
                 Err(_) => write!(fmt, "{:?}", msg),
             },
-            Frame::Null => "(nil)".fmt(fmt),
+            Frame::Null | Frame::NullArray => "(nil)".fmt(fmt),
             Frame::Array(parts) => {
                 for (i, part) in parts.iter().enumerate() {
                     if i > 0 {
@@ -209,4 +263,62 @@ impl fmt::Display for Frame {
             }
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(frame:&Frame) -> Vec<u8> {
+        let mut buf = Vec::new();
+        frame.encode(&mut buf);
+        buf
+    }
+
+    fn parse(data:&[u8]) -> Result<Frame,Error> {
+        let mut cursor = Cursor::new(data);
+        Frame::check(&mut cursor)?;
+        assert_eq!(cursor.position() as usize, data.len());
+        cursor.set_position(0);
+        Frame::parse(&mut cursor)
+    }
+
+    #[test]
+    fn encode_each_type() {
+        assert_eq!(encode(&Frame::Simple("OK".into())), b"+OK\r\n");
+        assert_eq!(encode(&Frame::Error("ERR x".into())), b"-ERR x\r\n");
+        assert_eq!(encode(&Frame::Integer(-3)), b":-3\r\n");
+        assert_eq!(encode(&Frame::Bulk(Bytes::from_static(b"a\r\nb"))), b"$4\r\na\r\nb\r\n");
+        assert_eq!(encode(&Frame::Null), b"$-1\r\n");
+        assert_eq!(encode(&Frame::NullArray), b"*-1\r\n");
+    }
+
+    #[test]
+    fn nested_array_round_trip() {
+        let frame = Frame::Array(vec![
+            Frame::Array(vec![Frame::Integer(1),Frame::Null]),
+            Frame::Bulk(Bytes::new()),
+            Frame::NullArray,
+            Frame::Simple("QUEUED".into())
+        ]);
+        let data = encode(&frame);
+        assert_eq!(encode(&parse(&data).unwrap()), data);
+    }
+
+    #[test]
+    fn null_array() {
+        assert!(matches!(parse(b"*-1\r\n").unwrap(), Frame::NullArray));
+        assert!(matches!(parse(b"$-1\r\n").unwrap(), Frame::Null));
+    }
+
+    #[test]
+    fn incomplete() {
+        for data in [&b"$5\r\nab"[..], b"*2\r\n:1\r\n", b"+OK", b"*"] {
+            assert!(matches!(Frame::check(&mut Cursor::new(data)), Err(Error::Incomplete)), "{:?}", data);
+        }
+    }
+
+    #[test]
+    fn invalid_type_byte() {
+        assert!(matches!(Frame::check(&mut Cursor::new(&b"!x\r\n"[..])), Err(Error::Other(_))));
+    }
+}
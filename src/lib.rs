@@ -1,19 +1,30 @@
 pub mod server;
 mod shutdown;
 mod db;
-use db::Db;
-use db::DbDropGuard;
 pub mod cmd;
  use cmd::Command;
- use cmd::Set;
- use cmd::Get;
 mod connection;
 mod parse;
 use connection::Connection;
 pub const DEFAULT_PORT: &str = "36379";
-mod frame;
+pub mod frame;
+mod script;
+mod notify;
+mod snapshot;
+mod aof;
+mod replication;
+mod cluster;
+pub mod rdb;
+pub mod config;
+pub mod sentinel;
+pub mod types;
+#[cfg(feature = "serde")]
+pub mod serde_frame;
 pub mod client;
-use client::{Client};
+pub mod cluster_client;
+pub mod multiplexed_client;
+pub mod pool;
+pub mod blocking_clinet;
 pub type Error = Box<dyn std::error::Error + Send + Sync>;
 
-pub type  Result<T> = std::result::Result<T,Error>;
\ No newline at end of file
+pub type  Result<T> = std::result::Result<T,Error>;
@@ -0,0 +1,148 @@
+use std::io;
+use bytes::{Bytes, BytesMut};
+use tokio::{io::{AsyncReadExt, AsyncWriteExt, BufWriter}, net::{TcpStream, ToSocketAddrs, tcp::{OwnedReadHalf, OwnedWriteHalf}}, sync::{mpsc, oneshot}};
+use tracing::debug;
+use crate::{connection::parse_frame, cmd::{Set, Get, Del, Publish}, client::command, frame::Frame};
+
+//排队等待发送的请求数上限，满了之后调用方等待
+const CHANNEL_CAPACITY:usize = 1024;
+
+type Reply = oneshot::Sender<crate::Result<Frame>>;
+
+//可以clone的客户端，所有clone共用一个连接
+//连接拆成读写两半，分别由两个后台任务持有，请求通过channel发过去，攒在一起发送，回复按顺序还给调用方
+//读写互不等待，两个方向都是大value时也不会因为双方的缓冲区都写满而卡死
+//订阅模式下回复和请求对不上，所以不支持SUBSCRIBE
+#[derive(Clone)]
+pub struct MultiplexedClient {
+    tx:mpsc::Sender<Request>
+}
+
+struct Request {
+    frame:Frame,
+    reply:Reply
+}
+
+impl MultiplexedClient {
+    pub async fn new<A: ToSocketAddrs>(addr:A) -> crate::Result<MultiplexedClient> {
+        let (read,write) = TcpStream::connect(addr).await?.into_split();
+        let (tx,rx) = mpsc::channel(CHANNEL_CAPACITY);
+        let (pending_tx,pending_rx) = mpsc::unbounded_channel();
+        tokio::spawn(write_requests(BufWriter::new(write), rx, pending_tx));
+        tokio::spawn(read_replies(read, pending_rx));
+        Ok(MultiplexedClient { tx })
+    }
+
+    pub async fn get(&self,key:&str) -> crate::Result<Option<Bytes>> {
+        match self.request(Get{key:key.to_string()}.into_frame()).await? {
+            Frame::Bulk(bytes) => Ok(Some(bytes)),
+            Frame::Null => Ok(None),
+            frame => Err(frame.into_err())
+        }
+    }
+
+    pub async fn set(&self,key:&str,value:Bytes) -> crate::Result<()> {
+        match self.request(Set{key:key.to_string(),value,expiration:None}.into_frame()).await? {
+            Frame::Simple(s) if s == "OK" => Ok(()),
+            frame => Err(frame.into_err())
+        }
+    }
+
+    //返回删除的key数量
+    pub async fn del(&self,keys:&[&str]) -> crate::Result<u64> {
+        match self.request(Del{keys:keys.iter().map(|key| key.to_string()).collect()}.into_frame()).await? {
+            Frame::Integer(n) => Ok(n as u64),
+            frame => Err(frame.into_err())
+        }
+    }
+
+    //返回收到消息的订阅者数量
+    pub async fn publish(&self,channel:&str,message:Bytes) -> crate::Result<u64> {
+        match self.request(Publish{channel:channel.to_string(),message}.into_frame()).await? {
+            Frame::Integer(n) => Ok(n as u64),
+            frame => Err(frame.into_err())
+        }
+    }
+
+    pub async fn ping(&self) -> crate::Result<Bytes> {
+        match self.request(command(&["PING"])).await? {
+            Frame::Simple(s) => Ok(s.into()),
+            Frame::Bulk(msg) => Ok(msg),
+            frame => Err(frame.into_err())
+        }
+    }
+
+    //错误回复转成Err
+    async fn request(&self,frame:Frame) -> crate::Result<Frame> {
+        let (reply,rx) = oneshot::channel();
+        self.tx.send(Request { frame, reply }).await.map_err(|_| "connection closed")?;
+        rx.await.map_err(|_| "connection closed")?
+    }
+}
+
+//写任务，把channel里已有的请求都写进缓冲区再flush
+//请求写出去之前先把回复的发送端交给读任务，保证读到回复时一定能找到对应的调用方
+//所有clone都drop了或者写失败时退出，drop写的一半会关掉连接的写方向，读任务读完剩下的回复之后也会退出
+async fn write_requests(mut stream:BufWriter<OwnedWriteHalf>,mut rx:mpsc::Receiver<Request>,pending:mpsc::UnboundedSender<Reply>) {
+    let mut buf = Vec::new();
+    while let Some(mut req) = rx.recv().await {
+        loop {
+            buf.clear();
+            req.frame.encode(&mut buf);
+            //读任务已经因为连接出错退出了
+            if pending.send(req.reply).is_err() {
+                return;
+            }
+            if let Err(err) = stream.write_all(&buf).await {
+                debug!(cause=%err,"multiplexed connection write failed");
+                return;
+            }
+            req = match rx.try_recv() {
+                Ok(req) => req,
+                Err(_) => break
+            };
+        }
+        if let Err(err) = stream.flush().await {
+            debug!(cause=%err,"multiplexed connection write failed");
+            return;
+        }
+    }
+}
+
+//读任务，按顺序把回复交给等待中的调用方，连接出错后所有等待中的请求都返回错误
+async fn read_replies(mut stream:OwnedReadHalf,mut pending:mpsc::UnboundedReceiver<Reply>) {
+    let mut buffer = BytesMut::with_capacity(4 * 1024);
+    //写任务退出并且没有等待中的请求时结束
+    while let Some(reply) = pending.recv().await {
+        let res = match read_frame(&mut stream, &mut buffer).await {
+            Ok(frame) => frame,
+            Err(err) => {
+                debug!(cause=%err,"multiplexed connection failed");
+                let msg = format!("connection failed: {}",err);
+                let _ = reply.send(Err(msg.clone().into()));
+                pending.close();
+                while let Some(reply) = pending.recv().await {
+                    let _ = reply.send(Err(msg.clone().into()));
+                }
+                return;
+            }
+        };
+        let res = match res {
+            Frame::Error(msg) => Err(msg.into()),
+            frame => Ok(frame)
+        };
+        //调用方已经不等了就丢掉
+        let _ = reply.send(res);
+    }
+}
+
+async fn read_frame(stream:&mut OwnedReadHalf,buffer:&mut BytesMut) -> crate::Result<Frame> {
+    loop {
+        if let Some(frame) = parse_frame(buffer)? {
+            return Ok(frame);
+        }
+        if 0 == stream.read_buf(buffer).await? {
+            return Err(io::Error::new(io::ErrorKind::ConnectionReset, "connection reset by peer").into());
+        }
+    }
+}
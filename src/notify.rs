@@ -0,0 +1,80 @@
+//notify-keyspace-events配置，字符含义和redis一致:
+//K keyspace事件(__keyspace@0__:<key>)，E keyevent事件(__keyevent@0__:<event>)
+//g 通用命令(del)，$ 字符串命令(set)，x 过期事件，A 等同于g$x
+#[derive(Debug,Clone,Copy,Default,PartialEq)]
+pub(crate) struct NotifyFlags(u8);
+
+impl NotifyFlags {
+    pub(crate) const KEYSPACE:u8 = 1;
+    pub(crate) const KEYEVENT:u8 = 1 << 1;
+    pub(crate) const GENERIC:u8 = 1 << 2;
+    pub(crate) const STRING:u8 = 1 << 3;
+    pub(crate) const EXPIRED:u8 = 1 << 4;
+    const ALL:u8 = Self::GENERIC | Self::STRING | Self::EXPIRED;
+
+    pub(crate) fn parse(s:&str) -> Result<Self,String> {
+        let mut flags = 0;
+        for c in s.chars() {
+            flags |= match c {
+                'K' => Self::KEYSPACE,
+                'E' => Self::KEYEVENT,
+                'g' => Self::GENERIC,
+                '$' => Self::STRING,
+                'x' => Self::EXPIRED,
+                'A' => Self::ALL,
+                c => return Err(format!("Invalid event class character '{}'",c))
+            };
+        }
+        Ok(NotifyFlags(flags))
+    }
+    //class对应的事件是否需要通知，K和E至少要有一个
+    pub(crate) fn enabled(&self,class:u8) -> bool {
+        self.0 & class != 0 && self.0 & (Self::KEYSPACE | Self::KEYEVENT) != 0
+    }
+    pub(crate) fn keyspace(&self) -> bool {
+        self.0 & Self::KEYSPACE != 0
+    }
+    pub(crate) fn keyevent(&self) -> bool {
+        self.0 & Self::KEYEVENT != 0
+    }
+}
+
+impl std::fmt::Display for NotifyFlags {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut s = String::new();
+        if self.0 & Self::ALL == Self::ALL {
+            s.push('A');
+        } else {
+            if self.0 & Self::GENERIC != 0 { s.push('g') }
+            if self.0 & Self::STRING != 0 { s.push('$') }
+            if self.0 & Self::EXPIRED != 0 { s.push('x') }
+        }
+        if self.keyspace() { s.push('K') }
+        if self.keyevent() { s.push('E') }
+        f.write_str(&s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_display() {
+        assert_eq!(NotifyFlags::parse("").unwrap(), NotifyFlags::default());
+        assert_eq!(NotifyFlags::parse("KEA").unwrap().to_string(), "AKE");
+        assert_eq!(NotifyFlags::parse("Kg$x").unwrap().to_string(), "AK");
+        assert_eq!(NotifyFlags::parse("Ex").unwrap().to_string(), "xE");
+        assert_eq!(NotifyFlags::parse("Kz").unwrap_err(), "Invalid event class character 'z'");
+    }
+
+    #[test]
+    fn enabled_needs_keyspace_or_keyevent() {
+        let flags = NotifyFlags::parse("A").unwrap();
+        assert!(!flags.enabled(NotifyFlags::GENERIC));
+        let flags = NotifyFlags::parse("Kg").unwrap();
+        assert!(flags.enabled(NotifyFlags::GENERIC));
+        assert!(!flags.enabled(NotifyFlags::EXPIRED));
+        assert!(flags.keyspace() && !flags.keyevent());
+    }
+}
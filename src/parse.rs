@@ -47,6 +47,10 @@ impl Parse {
     pub(crate) fn next(&mut self) -> Result<Frame,ParseError>  {
         self.into_iter.next().ok_or(ParseError::EndOfStream)
     }
+    //还没读取的参数个数
+    pub(crate) fn remaining(&self) -> usize {
+        self.into_iter.len()
+    }
     pub(crate) fn finish(&mut self) -> Result<(),ParseError> {
         if self.into_iter.next().is_none() {
             return Ok(());
@@ -61,9 +65,11 @@ impl Parse {
         }
     }
     pub(crate) fn next_int(&mut self) -> Result<u64,ParseError> {
-       // use atoi::atoi;
+        use atoi::atoi;
         match self.next()? {
-            Frame::Integer(num) => Ok(num),
+            Frame::Integer(num) => u64::try_from(num).map_err(|_| "protocol error;invalid number".into()),
+            Frame::Simple(data) => atoi::<u64>(data.as_bytes()).ok_or_else(|| "protocol error;invalid number".into()),
+            Frame::Bulk(data) => atoi::<u64>(&data).ok_or_else(|| "protocol error;invalid number".into()),
             f => Err(format!("protocol error;a number ,got {:?}",f).into())
         }
     }
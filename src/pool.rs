@@ -0,0 +1,211 @@
+use std::{ops::{Deref, DerefMut}, sync::{Arc, Mutex}, time::Duration};
+use tokio::{sync::{OwnedSemaphorePermit, Semaphore}, time::{self, Instant}};
+use tracing::debug;
+use crate::client::Client;
+
+//连接池的配置
+#[derive(Debug,Clone)]
+pub struct PoolConfig {
+    //最多同时存在的连接数，包括借出去的和空闲的
+    pub max_size:usize,
+    //空闲超过这么久的连接关掉，None表示不限制
+    pub idle_timeout:Option<Duration>,
+    //连接最长使用时间，到期之后归还时关掉
+    pub max_lifetime:Option<Duration>,
+    //连接都被借走时最多等多久
+    pub checkout_timeout:Duration,
+    //新建连接最多等多久，包括解析地址
+    pub connect_timeout:Duration,
+    //借出之前先PING一下，连接已经断了就换一个
+    pub test_on_checkout:bool
+}
+
+//从创建开始累计的统计
+#[derive(Debug,Clone,Default)]
+pub struct PoolMetrics {
+    pub checkouts:u64,
+    //等不到连接超时的次数
+    pub timeouts:u64,
+    pub created:u64,
+    //因为过期或者检查失败关掉的连接数
+    pub closed:u64,
+    pub total_wait:Duration,
+    pub max_wait:Duration,
+    pub idle:usize,
+    pub in_use:usize
+}
+
+//Client的连接池，可以clone之后在多个任务里用
+//适合阻塞命令和事务这些不能和别人共用连接的场景
+#[derive(Clone)]
+pub struct Pool {
+    shared:Arc<Shared>
+}
+
+//借出的连接，drop时还回池子
+pub struct PooledClient {
+    client:Option<Client>,
+    created:Instant,
+    shared:Arc<Shared>,
+    _permit:OwnedSemaphorePermit
+}
+
+struct Shared {
+    addr:String,
+    config:PoolConfig,
+    //每个借出的连接占一个permit，借不到permit说明连接都被借走了
+    permits:Arc<Semaphore>,
+    idle:Mutex<Vec<IdleClient>>,
+    metrics:Mutex<PoolMetrics>
+}
+
+struct IdleClient {
+    client:Client,
+    created:Instant,
+    idle_since:Instant
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            max_size: 10,
+            idle_timeout: Some(Duration::from_secs(600)),
+            max_lifetime: Some(Duration::from_secs(1800)),
+            checkout_timeout: Duration::from_secs(30),
+            connect_timeout: Duration::from_secs(10),
+            test_on_checkout: true
+        }
+    }
+}
+
+impl Pool {
+    //addr是"host:port"，连接在第一次借用时才建立
+    pub fn new(addr:&str,config:PoolConfig) -> Pool {
+        Pool {
+            shared: Arc::new(Shared {
+                addr: addr.to_string(),
+                permits: Arc::new(Semaphore::new(config.max_size)),
+                config,
+                idle: Mutex::new(Vec::new()),
+                metrics: Mutex::new(PoolMetrics::default())
+            })
+        }
+    }
+
+    //优先用最近归还的空闲连接，没有空闲的就新建一个
+    pub async fn get(&self) -> crate::Result<PooledClient> {
+        let shared = &self.shared;
+        let start = Instant::now();
+        let permit = match time::timeout(shared.config.checkout_timeout, shared.permits.clone().acquire_owned()).await {
+            Ok(permit) => permit?,
+            Err(_) => {
+                shared.metrics.lock().unwrap().timeouts += 1;
+                return Err("timed out waiting for a pooled connection".into());
+            }
+        };
+        let (client,created) = match self.checkout_idle().await {
+            Some(idle) => idle,
+            None => {
+                let client = match time::timeout(shared.config.connect_timeout, Client::new(shared.addr.as_str())).await {
+                    Ok(client) => client?,
+                    Err(_) => return Err(format!("timed out connecting to {}",shared.addr).into())
+                };
+                shared.metrics.lock().unwrap().created += 1;
+                (client,Instant::now())
+            }
+        };
+        let wait = start.elapsed();
+        {
+            let mut metrics = shared.metrics.lock().unwrap();
+            metrics.checkouts += 1;
+            metrics.total_wait += wait;
+            metrics.max_wait = metrics.max_wait.max(wait);
+        }
+        Ok(PooledClient { client: Some(client), created, shared: shared.clone(), _permit: permit })
+    }
+
+    pub fn metrics(&self) -> PoolMetrics {
+        let mut metrics = self.shared.metrics.lock().unwrap().clone();
+        metrics.idle = self.shared.idle.lock().unwrap().len();
+        //借出去的连接都持有permit，空闲的不持有
+        metrics.in_use = self.shared.config.max_size - self.shared.permits.available_permits();
+        metrics
+    }
+
+    //跳过过期的和PING不通的空闲连接
+    async fn checkout_idle(&self) -> Option<(Client,Instant)> {
+        let shared = &self.shared;
+        loop {
+            let mut idle = shared.idle.lock().unwrap().pop()?;
+            if shared.is_expired(idle.created, Some(idle.idle_since)) {
+                shared.metrics.lock().unwrap().closed += 1;
+                continue;
+            }
+            if shared.config.test_on_checkout {
+                if let Err(err) = idle.client.ping(None).await {
+                    debug!(cause=%err,"pooled connection failed health check");
+                    shared.metrics.lock().unwrap().closed += 1;
+                    continue;
+                }
+            }
+            return Some((idle.client,idle.created));
+        }
+    }
+}
+
+impl Shared {
+    fn is_expired(&self,created:Instant,idle_since:Option<Instant>) -> bool {
+        let now = Instant::now();
+        let too_old = self.config.max_lifetime.map(|max| now - created >= max).unwrap_or(false);
+        let too_idle = match (self.config.idle_timeout,idle_since) {
+            (Some(timeout),Some(since)) => now - since >= timeout,
+            _ => false
+        };
+        too_old || too_idle
+    }
+}
+
+impl PooledClient {
+    //连接状态不确定时(比如命令执行到一半出错)调用，直接关掉不还回池子
+    pub fn discard(mut self) {
+        self.client = None;
+        self.shared.metrics.lock().unwrap().closed += 1;
+    }
+}
+
+impl Deref for PooledClient {
+    type Target = Client;
+    fn deref(&self) -> &Client {
+        self.client.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledClient {
+    fn deref_mut(&mut self) -> &mut Client {
+        self.client.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledClient {
+    fn drop(&mut self) {
+        let client = match self.client.take() {
+            Some(client) => client,
+            None => return
+        };
+        let shared = &self.shared;
+        let mut idle = shared.idle.lock().unwrap();
+        //顺便清理空闲太久的连接
+        let before = idle.len();
+        idle.retain(|idle| !shared.is_expired(idle.created, Some(idle.idle_since)));
+        let mut closed = (before - idle.len()) as u64;
+        //停在MULTI、WATCH或者订阅模式的连接没法在drop里复位，直接关掉
+        if shared.is_expired(self.created, None) || client.has_session_state() {
+            closed += 1;
+        } else {
+            idle.push(IdleClient { client, created: self.created, idle_since: Instant::now() });
+        }
+        if closed > 0 {
+            shared.metrics.lock().unwrap().closed += closed;
+        }
+    }
+}
@@ -0,0 +1,722 @@
+use std::{collections::{HashMap, HashSet, VecDeque}, sync::Arc};
+use bytes::{BufMut, Bytes};
+use tracing::warn;
+use crate::db::Value;
+use crate::script;
+use crate::snapshot::{self, Snapshot};
+
+//redis rdb文件的读写，只支持字符串、list、hash、set、zset这几种类型
+//读的时候支持这些类型常见的编码(ziplist、listpack、intset、quicklist等)，写的时候统一用最简单的编码
+const MAGIC:&[u8] = b"REDIS";
+//没有函数库时写版本9，redis 5.0以后都能读；有函数库需要版本10(redis 7.0)
+const VERSION:u32 = 9;
+const VERSION_FUNCTIONS:u32 = 10;
+const MAX_VERSION:u32 = 12;
+
+const OPCODE_SLOT_INFO:u8 = 244;
+const OPCODE_FUNCTION2:u8 = 245;
+const OPCODE_FUNCTION_PRE_GA:u8 = 246;
+const OPCODE_MODULE_AUX:u8 = 247;
+const OPCODE_IDLE:u8 = 248;
+const OPCODE_FREQ:u8 = 249;
+const OPCODE_AUX:u8 = 250;
+const OPCODE_RESIZEDB:u8 = 251;
+const OPCODE_EXPIRETIME_MS:u8 = 252;
+const OPCODE_EXPIRETIME:u8 = 253;
+const OPCODE_SELECTDB:u8 = 254;
+const OPCODE_EOF:u8 = 255;
+
+const TYPE_STRING:u8 = 0;
+const TYPE_LIST:u8 = 1;
+const TYPE_SET:u8 = 2;
+const TYPE_ZSET:u8 = 3;
+const TYPE_HASH:u8 = 4;
+const TYPE_ZSET_2:u8 = 5;
+const TYPE_HASH_ZIPMAP:u8 = 9;
+const TYPE_LIST_ZIPLIST:u8 = 10;
+const TYPE_SET_INTSET:u8 = 11;
+const TYPE_ZSET_ZIPLIST:u8 = 12;
+const TYPE_HASH_ZIPLIST:u8 = 13;
+const TYPE_LIST_QUICKLIST:u8 = 14;
+const TYPE_HASH_LISTPACK:u8 = 16;
+const TYPE_ZSET_LISTPACK:u8 = 17;
+const TYPE_LIST_QUICKLIST_2:u8 = 18;
+const TYPE_SET_LISTPACK:u8 = 20;
+
+//quicklist2里每个节点的类型
+const QUICKLIST_NODE_PLAIN:u64 = 1;
+
+//字符串的特殊编码
+const ENC_INT8:u8 = 0;
+const ENC_INT16:u8 = 1;
+const ENC_INT32:u8 = 2;
+const ENC_LZF:u8 = 3;
+
+//rdb -> 快照文件，给转换工具用
+pub fn import(rdb:&[u8]) -> crate::Result<Vec<u8>> {
+    Ok(decode(rdb)?.encode())
+}
+
+//快照文件 -> rdb，给转换工具用
+pub fn export(snapshot:&[u8]) -> crate::Result<Vec<u8>> {
+    encode(&Snapshot::decode(snapshot)?)
+}
+
+pub(crate) fn decode(data:&[u8]) -> crate::Result<Snapshot> {
+    if data.len() < 9 || &data[..5] != MAGIC {
+        return Err("invalid rdb: bad header".into());
+    }
+    let version:u32 = std::str::from_utf8(&data[5..9])?.parse()?;
+    if version == 0 || version > MAX_VERSION {
+        return Err(format!("invalid rdb: unsupported version {}",version).into());
+    }
+    let mut reader = Reader { buf: data, pos: 9 };
+    let mut snapshot = Snapshot::default();
+    let mut libraries = Vec::new();
+    let mut expire_at = None;
+    let mut warned_db = false;
+    loop {
+        let op = reader.u8()?;
+        match op {
+            OPCODE_EOF => break,
+            OPCODE_SELECTDB => {
+                let db = reader.len()?;
+                //my-redis只有一个db，其它db的数据合并进来
+                if db != 0 && !warned_db {
+                    warn!(db,"rdb contains more than one database, merging all of them into db 0");
+                    warned_db = true;
+                }
+            }
+            OPCODE_RESIZEDB => {
+                reader.len()?;
+                reader.len()?;
+            }
+            OPCODE_SLOT_INFO => {
+                reader.len()?;
+                reader.len()?;
+                reader.len()?;
+            }
+            OPCODE_AUX => {
+                reader.string()?;
+                reader.string()?;
+            }
+            OPCODE_EXPIRETIME => {
+                let secs = u32::from_le_bytes(reader.array()?);
+                expire_at = Some(secs as u64 * 1000);
+            }
+            OPCODE_EXPIRETIME_MS => {
+                expire_at = Some(u64::from_le_bytes(reader.array()?));
+            }
+            //淘汰相关的信息用不上
+            OPCODE_IDLE => {
+                reader.len()?;
+            }
+            OPCODE_FREQ => {
+                reader.u8()?;
+            }
+            OPCODE_FUNCTION2 => {
+                libraries.push(String::from_utf8(reader.string()?.to_vec())?);
+            }
+            OPCODE_FUNCTION_PRE_GA | OPCODE_MODULE_AUX => {
+                return Err(format!("invalid rdb: unsupported opcode {}",op).into());
+            }
+            kind => {
+                let key = String::from_utf8(reader.string()?.to_vec())?;
+                let value = reader.value(kind)?;
+                //过期时间只作用于紧跟着的key
+                snapshot.entries.push((key,value,expire_at.take()));
+            }
+        }
+    }
+    //版本5开始文件末尾有8字节的crc64，全0表示没有开启校验
+    if version >= 5 {
+        let body = &data[..reader.pos];
+        let checksum = u64::from_le_bytes(reader.array()?);
+        if checksum != 0 && checksum != snapshot::crc64(0, body) {
+            return Err("invalid rdb: checksum mismatch".into());
+        }
+    }
+    if !libraries.is_empty() {
+        snapshot.functions = script::encode_libraries(libraries.iter().map(|code| &code[..]));
+    }
+    Ok(snapshot)
+}
+
+pub(crate) fn encode(snapshot:&Snapshot) -> crate::Result<Vec<u8>> {
+    let libraries = if snapshot.functions.is_empty() {
+        Vec::new()
+    } else {
+        script::decode_libraries(&snapshot.functions)?
+    };
+    let version = if libraries.is_empty() { VERSION } else { VERSION_FUNCTIONS };
+    let mut buf = Vec::new();
+    buf.put_slice(MAGIC);
+    buf.put_slice(format!("{:04}",version).as_bytes());
+    for code in &libraries {
+        buf.put_u8(OPCODE_FUNCTION2);
+        put_string(&mut buf, code.as_bytes());
+    }
+    buf.put_u8(OPCODE_SELECTDB);
+    put_len(&mut buf, 0);
+    buf.put_u8(OPCODE_RESIZEDB);
+    put_len(&mut buf, snapshot.entries.len() as u64);
+    put_len(&mut buf, snapshot.entries.iter().filter(|(_,_,expire_at)| expire_at.is_some()).count() as u64);
+    for (key,value,expire_at) in &snapshot.entries {
+        if let Some(ms) = expire_at {
+            buf.put_u8(OPCODE_EXPIRETIME_MS);
+            buf.put_u64_le(*ms);
+        }
+        buf.put_u8(value_type(value));
+        put_string(&mut buf, key.as_bytes());
+        put_value(&mut buf, value);
+    }
+    buf.put_u8(OPCODE_EOF);
+    let crc = snapshot::crc64(0, &buf);
+    buf.put_u64_le(crc);
+    Ok(buf)
+}
+
+//DUMP的格式和redis一样: 类型 值 rdb版本(u16) crc64，整数都是小端
+pub(crate) fn dump_value(value:&Value) -> Bytes {
+    let mut buf = Vec::new();
+    buf.put_u8(value_type(value));
+    put_value(&mut buf, value);
+    buf.put_u16_le(VERSION as u16);
+    let crc = snapshot::crc64(0, &buf);
+    buf.put_u64_le(crc);
+    buf.into()
+}
+
+pub(crate) fn restore_value(payload:&[u8]) -> Result<Value,String> {
+    if payload.len() < 10 {
+        return Err("ERR DUMP payload version or checksum are wrong".to_string());
+    }
+    let (body,footer) = payload.split_at(payload.len() - 8);
+    let version = u16::from_le_bytes([body[body.len() - 2], body[body.len() - 1]]) as u32;
+    if version > MAX_VERSION || snapshot::crc64(0, body) != u64::from_le_bytes(footer.try_into().unwrap()) {
+        return Err("ERR DUMP payload version or checksum are wrong".to_string());
+    }
+    let mut reader = Reader { buf: &body[..body.len() - 2], pos: 0 };
+    let value = reader.u8().and_then(|kind| reader.value(kind)).map_err(|_| "ERR Bad data format".to_string())?;
+    if reader.pos != reader.buf.len() {
+        return Err("ERR Bad data format".to_string());
+    }
+    Ok(value)
+}
+
+//写的时候每种类型只用最简单的编码
+fn value_type(value:&Value) -> u8 {
+    match value {
+        Value::String(_) => TYPE_STRING,
+        Value::List(_) => TYPE_LIST,
+        Value::Set(_) => TYPE_SET,
+        Value::ZSet(_) => TYPE_ZSET_2,
+        Value::Hash(_) => TYPE_HASH
+    }
+}
+
+fn put_value(buf:&mut Vec<u8>,value:&Value) {
+    match value {
+        Value::String(data) => put_string(buf, data),
+        Value::List(list) => {
+            put_len(buf, list.len() as u64);
+            list.iter().for_each(|item| put_string(buf, item));
+        }
+        Value::Set(set) => {
+            put_len(buf, set.len() as u64);
+            set.iter().for_each(|member| put_string(buf, member));
+        }
+        Value::ZSet(zset) => {
+            put_len(buf, zset.len() as u64);
+            for (score,member) in zset.iter() {
+                put_string(buf, member);
+                buf.put_f64_le(*score);
+            }
+        }
+        Value::Hash(hash) => {
+            put_len(buf, hash.len() as u64);
+            for (field,value) in hash.iter() {
+                put_string(buf, field);
+                put_string(buf, value);
+            }
+        }
+    }
+}
+
+fn put_len(buf:&mut Vec<u8>,len:u64) {
+    if len < 1 << 6 {
+        buf.put_u8(len as u8);
+    } else if len < 1 << 14 {
+        buf.put_u8(0x40 | (len >> 8) as u8);
+        buf.put_u8(len as u8);
+    } else if len <= u32::MAX as u64 {
+        buf.put_u8(0x80);
+        buf.put_u32(len as u32);
+    } else {
+        buf.put_u8(0x81);
+        buf.put_u64(len);
+    }
+}
+
+fn put_string(buf:&mut Vec<u8>,s:&[u8]) {
+    put_len(buf, s.len() as u64);
+    buf.put_slice(s);
+}
+
+//长度字段可能是普通长度，也可能表示字符串的特殊编码
+enum Length {
+    Len(u64),
+    Encoded(u8)
+}
+
+struct Reader<'a> {
+    buf:&'a [u8],
+    pos:usize
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self,n:usize) -> crate::Result<&'a [u8]> {
+        if self.buf.len() - self.pos < n {
+            return Err("invalid rdb: unexpected end of file".into());
+        }
+        let s = &self.buf[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(s)
+    }
+    fn u8(&mut self) -> crate::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+    fn array<const N:usize>(&mut self) -> crate::Result<[u8;N]> {
+        let mut a = [0;N];
+        a.copy_from_slice(self.take(N)?);
+        Ok(a)
+    }
+    fn length(&mut self) -> crate::Result<Length> {
+        let first = self.u8()?;
+        match first >> 6 {
+            0 => Ok(Length::Len((first & 0x3f) as u64)),
+            1 => Ok(Length::Len((((first & 0x3f) as u64) << 8) | self.u8()? as u64)),
+            2 => match first {
+                0x80 => Ok(Length::Len(u32::from_be_bytes(self.array()?) as u64)),
+                0x81 => Ok(Length::Len(u64::from_be_bytes(self.array()?))),
+                _ => Err(format!("invalid rdb: unknown length encoding {:#x}",first).into())
+            },
+            _ => Ok(Length::Encoded(first & 0x3f))
+        }
+    }
+    fn len(&mut self) -> crate::Result<u64> {
+        match self.length()? {
+            Length::Len(len) => Ok(len),
+            Length::Encoded(_) => Err("invalid rdb: expected a length".into())
+        }
+    }
+    fn string(&mut self) -> crate::Result<Bytes> {
+        match self.length()? {
+            Length::Len(len) => Ok(Bytes::copy_from_slice(self.take(len as usize)?)),
+            Length::Encoded(ENC_INT8) => Ok((self.u8()? as i8).to_string().into()),
+            Length::Encoded(ENC_INT16) => Ok(i16::from_le_bytes(self.array()?).to_string().into()),
+            Length::Encoded(ENC_INT32) => Ok(i32::from_le_bytes(self.array()?).to_string().into()),
+            Length::Encoded(ENC_LZF) => {
+                let clen = self.len()? as usize;
+                let len = self.len()? as usize;
+                Ok(lzf_decompress(self.take(clen)?, len)?.into())
+            }
+            Length::Encoded(enc) => Err(format!("invalid rdb: unknown string encoding {}",enc).into())
+        }
+    }
+    //老版本zset的score以字符串保存，第一个字节是长度，253/254/255分别表示nan/+inf/-inf
+    fn double_string(&mut self) -> crate::Result<f64> {
+        match self.u8()? {
+            253 => Ok(f64::NAN),
+            254 => Ok(f64::INFINITY),
+            255 => Ok(f64::NEG_INFINITY),
+            len => Ok(std::str::from_utf8(self.take(len as usize)?)?.parse()?)
+        }
+    }
+    fn strings(&mut self) -> crate::Result<Vec<Bytes>> {
+        let len = self.len()?;
+        (0..len).map(|_| self.string()).collect()
+    }
+    fn value(&mut self,kind:u8) -> crate::Result<Value> {
+        let value = match kind {
+            TYPE_STRING => Value::String(self.string()?),
+            TYPE_LIST => Value::List(Arc::new(self.strings()?.into())),
+            TYPE_SET => Value::Set(Arc::new(self.strings()?.into_iter().collect())),
+            TYPE_ZSET | TYPE_ZSET_2 => {
+                let len = self.len()?;
+                let mut zset = Vec::new();
+                for _ in 0..len {
+                    let member = self.string()?;
+                    let score = if kind == TYPE_ZSET { self.double_string()? } else { f64::from_le_bytes(self.array()?) };
+                    zset.push((score,member));
+                }
+                zset_value(zset)
+            }
+            TYPE_HASH => {
+                let len = self.len()?;
+                let mut hash = HashMap::new();
+                for _ in 0..len {
+                    hash.insert(self.string()?, self.string()?);
+                }
+                Value::Hash(Arc::new(hash))
+            }
+            TYPE_HASH_ZIPMAP => Value::Hash(Arc::new(pairs(zipmap_entries(&self.string()?)?).into_iter().collect())),
+            TYPE_LIST_ZIPLIST => Value::List(Arc::new(ziplist_entries(&self.string()?)?.into())),
+            TYPE_SET_INTSET => Value::Set(Arc::new(intset_entries(&self.string()?)?.into_iter().collect())),
+            TYPE_ZSET_ZIPLIST => zset_from_pairs(ziplist_entries(&self.string()?)?)?,
+            TYPE_HASH_ZIPLIST => Value::Hash(Arc::new(pairs(ziplist_entries(&self.string()?)?).into_iter().collect())),
+            TYPE_LIST_QUICKLIST => {
+                let mut list = VecDeque::new();
+                for node in self.strings()? {
+                    list.extend(ziplist_entries(&node)?);
+                }
+                Value::List(Arc::new(list))
+            }
+            TYPE_HASH_LISTPACK => Value::Hash(Arc::new(pairs(listpack_entries(&self.string()?)?).into_iter().collect())),
+            TYPE_ZSET_LISTPACK => zset_from_pairs(listpack_entries(&self.string()?)?)?,
+            TYPE_LIST_QUICKLIST_2 => {
+                let len = self.len()?;
+                let mut list = VecDeque::new();
+                for _ in 0..len {
+                    let container = self.len()?;
+                    let node = self.string()?;
+                    //PLAIN节点本身就是一个大元素，PACKED节点是一个listpack
+                    if container == QUICKLIST_NODE_PLAIN {
+                        list.push_back(node);
+                    } else {
+                        list.extend(listpack_entries(&node)?);
+                    }
+                }
+                Value::List(Arc::new(list))
+            }
+            TYPE_SET_LISTPACK => Value::Set(Arc::new(listpack_entries(&self.string()?)?.into_iter().collect::<HashSet<_>>())),
+            kind => return Err(format!("invalid rdb: unsupported value type {}",kind).into())
+        };
+        Ok(value)
+    }
+}
+
+fn pairs(entries:Vec<Bytes>) -> Vec<(Bytes,Bytes)> {
+    let mut iter = entries.into_iter();
+    let mut pairs = Vec::new();
+    while let (Some(a),Some(b)) = (iter.next(),iter.next()) {
+        pairs.push((a,b));
+    }
+    pairs
+}
+
+//ziplist和listpack编码的zset是member,score交替排列，score以字符串或者整数保存
+fn zset_from_pairs(entries:Vec<Bytes>) -> crate::Result<Value> {
+    let mut zset = Vec::new();
+    for (member,score) in pairs(entries) {
+        zset.push((std::str::from_utf8(&score)?.parse()?,member));
+    }
+    Ok(zset_value(zset))
+}
+
+fn zset_value(mut zset:Vec<(f64,Bytes)>) -> Value {
+    zset.sort_by(|(a,ma),(b,mb)| a.total_cmp(b).then_with(|| ma.cmp(mb)));
+    Value::ZSet(Arc::new(zset))
+}
+
+fn truncated() -> crate::Error {
+    "invalid rdb: truncated encoded value".into()
+}
+
+fn slice(buf:&[u8],pos:usize,n:usize) -> crate::Result<&[u8]> {
+    buf.get(pos..pos + n).ok_or_else(truncated)
+}
+
+//ziplist: zlbytes(u32) zltail(u32) zllen(u16) entry* 0xff，整数都是小端
+fn ziplist_entries(buf:&[u8]) -> crate::Result<Vec<Bytes>> {
+    let mut pos = 10;
+    let mut entries = Vec::new();
+    loop {
+        //prevlen
+        match *buf.get(pos).ok_or_else(truncated)? {
+            0xff => break,
+            0xfe => pos += 5,
+            _ => pos += 1
+        }
+        let enc = *buf.get(pos).ok_or_else(truncated)?;
+        pos += 1;
+        let entry:Bytes = match enc >> 6 {
+            0 => {
+                let len = (enc & 0x3f) as usize;
+                pos += len;
+                Bytes::copy_from_slice(slice(buf, pos - len, len)?)
+            }
+            1 => {
+                let len = (((enc & 0x3f) as usize) << 8) | *buf.get(pos).ok_or_else(truncated)? as usize;
+                pos += 1 + len;
+                Bytes::copy_from_slice(slice(buf, pos - len, len)?)
+            }
+            2 => {
+                let len = u32::from_be_bytes(slice(buf, pos, 4)?.try_into()?) as usize;
+                pos += 4 + len;
+                Bytes::copy_from_slice(slice(buf, pos - len, len)?)
+            }
+            _ => {
+                let (n,value) = match enc {
+                    0xc0 => (2, i16::from_le_bytes(slice(buf, pos, 2)?.try_into()?) as i64),
+                    0xd0 => (4, i32::from_le_bytes(slice(buf, pos, 4)?.try_into()?) as i64),
+                    0xe0 => (8, i64::from_le_bytes(slice(buf, pos, 8)?.try_into()?)),
+                    0xf0 => {
+                        let b = slice(buf, pos, 3)?;
+                        (3, (i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8) as i64)
+                    }
+                    0xfe => (1, *buf.get(pos).ok_or_else(truncated)? as i8 as i64),
+                    //1111xxxx，xxxx-1就是值(0到12)
+                    0xf1..=0xfd => (0, (enc & 0x0f) as i64 - 1),
+                    _ => return Err(format!("invalid rdb: unknown ziplist encoding {:#x}",enc).into())
+                };
+                pos += n;
+                value.to_string().into()
+            }
+        };
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+//listpack: total_bytes(u32) num_elements(u16) entry* 0xff，每个entry后面跟着entry的长度(backlen)
+fn listpack_entries(buf:&[u8]) -> crate::Result<Vec<Bytes>> {
+    let mut pos = 6;
+    let mut entries = Vec::new();
+    loop {
+        let enc = *buf.get(pos).ok_or_else(truncated)?;
+        if enc == 0xff {
+            break;
+        }
+        let (header,len,entry):(usize,usize,Bytes) = if enc & 0x80 == 0 {
+            (1, 0, (enc & 0x7f).to_string().into())
+        } else if enc & 0xc0 == 0x80 {
+            let len = (enc & 0x3f) as usize;
+            (1, len, Bytes::copy_from_slice(slice(buf, pos + 1, len)?))
+        } else if enc & 0xe0 == 0xc0 {
+            let raw = (((enc & 0x1f) as u16) << 8) | *buf.get(pos + 1).ok_or_else(truncated)? as u16;
+            //13位有符号整数
+            let value = ((raw << 3) as i16) >> 3;
+            (2, 0, value.to_string().into())
+        } else if enc & 0xf0 == 0xe0 {
+            let len = (((enc & 0x0f) as usize) << 8) | *buf.get(pos + 1).ok_or_else(truncated)? as usize;
+            (2, len, Bytes::copy_from_slice(slice(buf, pos + 2, len)?))
+        } else {
+            match enc {
+                0xf0 => {
+                    let len = u32::from_le_bytes(slice(buf, pos + 1, 4)?.try_into()?) as usize;
+                    (5, len, Bytes::copy_from_slice(slice(buf, pos + 5, len)?))
+                }
+                0xf1 => (1, 2, i16::from_le_bytes(slice(buf, pos + 1, 2)?.try_into()?).to_string().into()),
+                0xf2 => {
+                    let b = slice(buf, pos + 1, 3)?;
+                    (1, 3, (i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8).to_string().into())
+                }
+                0xf3 => (1, 4, i32::from_le_bytes(slice(buf, pos + 1, 4)?.try_into()?).to_string().into()),
+                0xf4 => (1, 8, i64::from_le_bytes(slice(buf, pos + 1, 8)?.try_into()?).to_string().into()),
+                _ => return Err(format!("invalid rdb: unknown listpack encoding {:#x}",enc).into())
+            }
+        };
+        let size = header + len;
+        pos += size + backlen_size(size);
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+fn backlen_size(size:usize) -> usize {
+    match size {
+        0..=127 => 1,
+        128..=16382 => 2,
+        16383..=2097150 => 3,
+        2097151..=268435454 => 4,
+        _ => 5
+    }
+}
+
+//intset: encoding(u32，每个整数的字节数) length(u32) 整数*，都是小端
+fn intset_entries(buf:&[u8]) -> crate::Result<Vec<Bytes>> {
+    let width = u32::from_le_bytes(slice(buf, 0, 4)?.try_into()?) as usize;
+    let len = u32::from_le_bytes(slice(buf, 4, 4)?.try_into()?) as usize;
+    if ![2, 4, 8].contains(&width) {
+        return Err(format!("invalid rdb: unknown intset encoding {}",width).into());
+    }
+    //长度来自payload，RESTORE时可以伪造，先确认数据真的有这么多再分配
+    if len > (buf.len() - 8) / width {
+        return Err(truncated());
+    }
+    let mut entries = Vec::with_capacity(len);
+    for i in 0..len {
+        let b = slice(buf, 8 + i * width, width)?;
+        let value = match width {
+            2 => i16::from_le_bytes(b.try_into()?) as i64,
+            4 => i32::from_le_bytes(b.try_into()?) as i64,
+            _ => i64::from_le_bytes(b.try_into()?)
+        };
+        entries.push(value.to_string().into());
+    }
+    Ok(entries)
+}
+
+//zipmap: zmlen(u8) (len key len free value free_bytes)* 0xff，长度小于254时1个字节，否则254加4字节小端
+fn zipmap_entries(buf:&[u8]) -> crate::Result<Vec<Bytes>> {
+    fn zipmap_len(buf:&[u8],pos:&mut usize) -> crate::Result<Option<usize>> {
+        match *buf.get(*pos).ok_or_else(truncated)? {
+            0xff => Ok(None),
+            254 => {
+                let len = u32::from_le_bytes(slice(buf, *pos + 1, 4)?.try_into()?) as usize;
+                *pos += 5;
+                Ok(Some(len))
+            }
+            len => {
+                *pos += 1;
+                Ok(Some(len as usize))
+            }
+        }
+    }
+    let mut pos = 1;
+    let mut entries = Vec::new();
+    while let Some(len) = zipmap_len(buf, &mut pos)? {
+        entries.push(Bytes::copy_from_slice(slice(buf, pos, len)?));
+        pos += len;
+        let len = zipmap_len(buf, &mut pos)?.ok_or_else(truncated)?;
+        let free = *buf.get(pos).ok_or_else(truncated)? as usize;
+        pos += 1;
+        entries.push(Bytes::copy_from_slice(slice(buf, pos, len)?));
+        pos += len + free;
+    }
+    Ok(entries)
+}
+
+fn lzf_decompress(input:&[u8],len:usize) -> crate::Result<Vec<u8>> {
+    //len来自payload，不能直接按它分配，解压过程中超过len就报错
+    let mut out = Vec::with_capacity(len.min(input.len()));
+    let mut i = 0;
+    while i < input.len() {
+        let ctrl = input[i] as usize;
+        i += 1;
+        if ctrl < 32 {
+            //字面量
+            let run = ctrl + 1;
+            if out.len() + run > len {
+                return Err("invalid rdb: bad lzf data".into());
+            }
+            out.extend_from_slice(input.get(i..i + run).ok_or("invalid rdb: bad lzf data")?);
+            i += run;
+        } else {
+            //回溯引用，可能和正在写的部分重叠，只能逐字节拷贝
+            let mut run = ctrl >> 5;
+            if run == 7 {
+                run += *input.get(i).ok_or("invalid rdb: bad lzf data")? as usize;
+                i += 1;
+            }
+            let offset = ((ctrl & 0x1f) << 8) + *input.get(i).ok_or("invalid rdb: bad lzf data")? as usize + 1;
+            i += 1;
+            if offset > out.len() || out.len() + run + 2 > len {
+                return Err("invalid rdb: bad lzf data".into());
+            }
+            let start = out.len() - offset;
+            for k in 0..run + 2 {
+                out.push(out[start + k]);
+            }
+        }
+    }
+    if out.len() != len {
+        return Err("invalid rdb: bad lzf data".into());
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Snapshot {
+        let b = |s:&'static str| Bytes::from_static(s.as_bytes());
+        Snapshot {
+            entries: vec![
+                ("s".to_string(),Value::String(b("v")),Some(1234)),
+                ("l".to_string(),Value::List(Arc::new(VecDeque::from([b("a"),b("")]))),None),
+                ("h".to_string(),Value::Hash(Arc::new(HashMap::from([(b("f"),b("v"))]))),None),
+                ("set".to_string(),Value::Set(Arc::new(HashSet::from([b("m")]))),None),
+                ("z".to_string(),Value::ZSet(Arc::new(vec![(-1.5,b("x")),(2.0,b("y"))])),Some(5))
+            ],
+            functions: script::encode_libraries(["#!lua name=lib\n"].into_iter())
+        }
+    }
+
+    #[test]
+    fn export_import_round_trip() {
+        let data = sample().encode();
+        let rdb = export(&data).unwrap();
+        assert_eq!(&rdb[..9], b"REDIS0010");
+        assert_eq!(import(&rdb).unwrap(), data);
+    }
+
+    #[test]
+    fn rejects_bad_header_and_checksum() {
+        assert!(decode(b"REDIS").is_err());
+        assert!(decode(b"RADIS0009\xff").is_err());
+        assert!(decode(b"REDIS0099\xff").is_err());
+        let mut rdb = export(&sample().encode()).unwrap();
+        let last = rdb.len() - 1;
+        rdb[last] ^= 1;
+        assert!(decode(&rdb).is_err());
+    }
+
+    #[test]
+    fn lzf() {
+        //字面量"abc"，再引用前面3个字节
+        assert_eq!(lzf_decompress(&[2, b'a', b'b', b'c', 0x20, 2], 6).unwrap(), b"abcabc");
+        //和正在写的部分重叠的长引用
+        assert_eq!(lzf_decompress(&[0, b'a', 0xe0, 10, 0], 20).unwrap(), vec![b'a'; 20]);
+    }
+
+    #[test]
+    fn lzf_rejects_bad_input() {
+        //声明的长度和实际不符
+        assert!(lzf_decompress(&[2, b'a', b'b', b'c'], 4).is_err());
+        assert!(lzf_decompress(&[2, b'a', b'b', b'c', 0x20, 2], 5).is_err());
+        //引用超出已经解压的部分
+        assert!(lzf_decompress(&[0, b'a', 0x20, 5], 4).is_err());
+        //截断
+        assert!(lzf_decompress(&[5, b'a'], 6).is_err());
+        //伪造很大的长度也不会先分配
+        assert!(lzf_decompress(&[0, b'a'], usize::MAX).is_err());
+    }
+
+    #[test]
+    fn intset() {
+        let buf = [2, 0, 0, 0, 2, 0, 0, 0, 1, 0, 0xff, 0xff];
+        assert_eq!(intset_entries(&buf).unwrap(), [Bytes::from("1"),Bytes::from("-1")]);
+        //宽度不对，长度比数据多
+        assert!(intset_entries(&[3, 0, 0, 0, 0, 0, 0, 0]).is_err());
+        assert!(intset_entries(&[8, 0, 0, 0, 0xff, 0xff, 0xff, 0xff, 0]).is_err());
+        assert!(intset_entries(&[2, 0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn dump_restore_round_trip() {
+        for (_,value,_) in sample().entries {
+            let payload = dump_value(&value);
+            let restored = restore_value(&payload).unwrap();
+            assert_eq!(dump_value(&restored), payload);
+        }
+    }
+
+    #[test]
+    fn restore_rejects_bad_payload() {
+        let payload = dump_value(&Value::String(Bytes::from_static(b"v"))).to_vec();
+        assert!(restore_value(&payload[..payload.len() - 1]).is_err());
+        let mut corrupted = payload.clone();
+        corrupted[1] ^= 1;
+        assert_eq!(restore_value(&corrupted).unwrap_err(), "ERR DUMP payload version or checksum are wrong");
+        assert!(restore_value(b"").is_err());
+        //校验和正确但数据不完整
+        let mut body = vec![TYPE_STRING, 5, b'v'];
+        body.put_u16_le(VERSION as u16);
+        let crc = snapshot::crc64(0, &body);
+        body.put_u64_le(crc);
+        assert_eq!(restore_value(&body).unwrap_err(), "ERR Bad data format");
+    }
+}
@@ -0,0 +1,549 @@
+use std::{collections::{HashMap, VecDeque}, fmt::Write, sync::Mutex, time::Duration};
+use bytes::Bytes;
+use tokio::{sync::watch, time};
+use tracing::{debug, info, warn};
+use crate::client::Client;
+use crate::cmd::{Psync, ReplConf};
+use crate::config::Config;
+use crate::connection::Connection;
+use crate::db::Db;
+use crate::frame::Frame;
+use crate::rdb;
+use crate::script::{self, RestorePolicy};
+use crate::shutdown::Shutdown;
+use crate::Command;
+
+//主每隔一段时间往复制流里写一个PING，副本超过REPL_TIMEOUT没收到任何数据就认为连接断了
+const PING_PERIOD:Duration = Duration::from_secs(10);
+const REPL_TIMEOUT:Duration = Duration::from_secs(60);
+//连不上主时的重试间隔
+const RETRY_INTERVAL:Duration = Duration::from_secs(1);
+//副本定期向主汇报自己的offset
+const ACK_PERIOD:Duration = Duration::from_secs(1);
+
+//主从复制的状态，锁顺序在db的Stat之后
+#[derive(Debug)]
+pub(crate) struct Replication {
+    state:Mutex<State>,
+    //复制流的offset，有新数据写进backlog时通知给各个副本连接
+    offset_tx:watch::Sender<u64>,
+    //每次执行REPLICAOF加一，旧的复制任务发现变了就退出
+    epoch_tx:watch::Sender<u64>,
+    //收到副本的ACK时通知等待中的WAIT
+    ack_tx:watch::Sender<()>,
+    backlog_size:usize,
+    read_only:bool,
+    //进程启动时生成，INFO里的run_id
+    run_id:String
+}
+
+#[derive(Debug)]
+struct State {
+    role:Role,
+    //当前复制历史的id和offset，副本PSYNC时带上自己的replid和offset+1，能接上就只发缺少的部分
+    replid:String,
+    //上一段历史的id，从副本提升为主之后，原来的兄弟副本还能用旧id接上
+    replid2:String,
+    second_replid_offset:Option<u64>,
+    offset:u64,
+    //第一个副本连上来之后才创建
+    backlog:Option<VecDeque<u8>>,
+    //全量同步或者replid变了的时候加一，已经连着的副本需要断开重新PSYNC
+    history:u64,
+    replicas:HashMap<u64,ReplicaInfo>,
+    next_replica_id:u64,
+    epoch:u64,
+    //本节点监听的端口，握手时告诉主
+    port:u16
+}
+
+#[derive(Debug)]
+enum Role {
+    Master,
+    Replica { host:String, port:u16, link_up:bool }
+}
+
+#[derive(Debug)]
+struct ReplicaInfo {
+    ip:String,
+    port:u16,
+    //副本最近一次ACK的offset和时间
+    ack_offset:u64,
+    last_ack:time::Instant
+}
+
+//副本连接退出时从列表里去掉
+struct Registration<'a> {
+    replication:&'a Replication,
+    id:u64
+}
+
+impl Replication {
+    pub(crate) fn new(config:&Config) -> Self {
+        Self {
+            state: Mutex::new(State {
+                role: Role::Master,
+                replid: new_replid(),
+                replid2: "0".repeat(40),
+                second_replid_offset: None,
+                offset: 0,
+                backlog: None,
+                history: 0,
+                replicas: HashMap::new(),
+                next_replica_id: 0,
+                epoch: 0,
+                port: 0
+            }),
+            offset_tx: watch::channel(0).0,
+            epoch_tx: watch::channel(0).0,
+            ack_tx: watch::channel(()).0,
+            backlog_size: config.repl_backlog_size,
+            read_only: config.replica_read_only,
+            run_id: new_replid()
+        }
+    }
+    pub(crate) fn set_port(&self,port:u16) {
+        self.state.lock().unwrap().port = port;
+    }
+    pub(crate) fn is_replica(&self) -> bool {
+        matches!(self.state.lock().unwrap().role, Role::Replica { .. })
+    }
+    //副本默认只读，只有复制连接能写入
+    pub(crate) fn is_read_only(&self) -> bool {
+        self.read_only && self.is_replica()
+    }
+    pub(crate) fn has_backlog(&self) -> bool {
+        self.state.lock().unwrap().backlog.is_some()
+    }
+    pub(crate) fn offset(&self) -> u64 {
+        self.state.lock().unwrap().offset
+    }
+    //已经确认收到offset之前所有数据的副本数
+    pub(crate) fn acked(&self,offset:u64) -> u64 {
+        self.state.lock().unwrap().replicas.values().filter(|replica| replica.ack_offset >= offset).count() as u64
+    }
+    fn ack(&self,id:u64,offset:u64) {
+        if let Some(replica) = self.state.lock().unwrap().replicas.get_mut(&id) {
+            replica.ack_offset = offset;
+            replica.last_ack = time::Instant::now();
+        }
+        self.ack_tx.send_replace(());
+    }
+    pub(crate) fn run_id(&self) -> &str {
+        &self.run_id
+    }
+    //把编码好的写命令追加到backlog，需要在db锁内调用
+    pub(crate) fn feed(&self,data:&[u8]) {
+        let mut state = self.state.lock().unwrap();
+        let backlog = match state.backlog.as_mut() {
+            Some(backlog) => backlog,
+            None => return
+        };
+        backlog.extend(data);
+        if backlog.len() > self.backlog_size {
+            let excess = backlog.len() - self.backlog_size;
+            backlog.drain(..excess);
+        }
+        state.offset += data.len() as u64;
+        self.offset_tx.send_replace(state.offset);
+    }
+    //换一个新的replid，旧的留给还在用它的副本继续PSYNC
+    fn shift_replid(state:&mut State,replid:String) {
+        state.replid2 = std::mem::replace(&mut state.replid, replid);
+        state.second_replid_offset = Some(state.offset + 1);
+    }
+    //REPLICAOF NO ONE，需要在db锁内调用，保证切换前后的offset是连续的
+    pub(crate) fn promote(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if let Role::Master = state.role {
+            return false;
+        }
+        Self::shift_replid(&mut state, new_replid());
+        //让下面的副本重连，通过CONTINUE拿到新的replid
+        state.history += 1;
+        self.offset_tx.send_replace(state.offset);
+        state.role = Role::Master;
+        state.epoch += 1;
+        self.epoch_tx.send_replace(state.epoch);
+        info!(replid=%state.replid,offset=state.offset,"promoted to master");
+        true
+    }
+    //REPLICAOF host port，已经是它的副本时返回None，否则返回新的epoch
+    pub(crate) fn set_leader(&self,host:&str,port:u16) -> Option<u64> {
+        let mut state = self.state.lock().unwrap();
+        if let Role::Replica { host: h, port: p, .. } = &state.role {
+            if h == host && *p == port {
+                return None;
+            }
+        }
+        state.role = Role::Replica { host: host.to_string(), port, link_up: false };
+        state.epoch += 1;
+        self.epoch_tx.send_replace(state.epoch);
+        Some(state.epoch)
+    }
+    fn is_current(&self,epoch:u64) -> bool {
+        self.state.lock().unwrap().epoch == epoch
+    }
+    fn set_link_up(&self,epoch:u64,up:bool) {
+        let mut state = self.state.lock().unwrap();
+        if state.epoch != epoch {
+            return;
+        }
+        if let Role::Replica { link_up, .. } = &mut state.role {
+            *link_up = up;
+        }
+    }
+    //全量同步完成，复制历史换成主的，已经连着本节点的副本都要重新同步
+    fn reset(&self,replid:String,offset:u64) {
+        let mut state = self.state.lock().unwrap();
+        state.replid = replid;
+        state.replid2 = "0".repeat(40);
+        state.second_replid_offset = None;
+        state.offset = offset;
+        state.backlog = Some(VecDeque::new());
+        state.history += 1;
+        self.offset_tx.send_replace(offset);
+    }
+    //副本带着replid和offset+1来PSYNC，能从backlog接上时返回true
+    fn can_continue(state:&State,replid:&str,offset:i64) -> bool {
+        //offset是副本发来的，负数(包括i64::MIN)直接全量同步
+        let offset = match offset.checked_sub(1).map(u64::try_from) {
+            Some(Ok(offset)) => offset,
+            _ => return false
+        };
+        let same_history = replid == state.replid
+            || (replid == state.replid2 && state.second_replid_offset.map(|second| offset < second).unwrap_or(false));
+        let backlog = match state.backlog.as_ref() {
+            Some(backlog) => backlog,
+            None => return false
+        };
+        same_history && offset >= state.offset - backlog.len() as u64 && offset <= state.offset
+    }
+    //返回从sent开始还没发给副本的数据，历史变了或者落后太多已经不在backlog里时返回Err
+    fn pending(&self,history:u64,sent:u64) -> crate::Result<Vec<u8>> {
+        let state = self.state.lock().unwrap();
+        let backlog = match state.backlog.as_ref() {
+            Some(backlog) if state.history == history => backlog,
+            _ => return Err("replication history changed".into())
+        };
+        let start = state.offset - backlog.len() as u64;
+        if sent < start || sent > state.offset {
+            return Err("replica is out of the replication backlog".into());
+        }
+        Ok(backlog.range((sent - start) as usize..).copied().collect())
+    }
+    fn unregister(&self,id:u64) {
+        self.state.lock().unwrap().replicas.remove(&id);
+    }
+    //INFO replication的内容
+    pub(crate) fn info(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let mut s = String::from("# Replication\r\n");
+        match &state.role {
+            Role::Master => {
+                let _ = write!(s, "role:master\r\nconnected_slaves:{}\r\n", state.replicas.len());
+                let mut replicas:Vec<_> = state.replicas.iter().collect();
+                replicas.sort_by_key(|(id,_)| **id);
+                for (i,(_,replica)) in replicas.into_iter().enumerate() {
+                    let _ = write!(s, "slave{}:ip={},port={},state=online,offset={},lag={}\r\n",
+                        i, replica.ip, replica.port, replica.ack_offset, replica.last_ack.elapsed().as_secs());
+                }
+            }
+            Role::Replica { host, port, link_up } => {
+                let _ = write!(s, "role:slave\r\nmaster_host:{}\r\nmaster_port:{}\r\nmaster_link_status:{}\r\nslave_repl_offset:{}\r\nslave_read_only:{}\r\nconnected_slaves:{}\r\n",
+                    host, port, if *link_up { "up" } else { "down" }, state.offset, self.read_only as u8, state.replicas.len());
+            }
+        }
+        let backlog_len = state.backlog.as_ref().map(|backlog| backlog.len() as u64);
+        let _ = write!(s, "master_replid:{}\r\nmaster_replid2:{}\r\nmaster_repl_offset:{}\r\nsecond_repl_offset:{}\r\nrepl_backlog_active:{}\r\nrepl_backlog_size:{}\r\nrepl_backlog_first_byte_offset:{}\r\nrepl_backlog_histlen:{}\r\n",
+            state.replid, state.replid2, state.offset,
+            state.second_replid_offset.map(|offset| offset as i64).unwrap_or(-1),
+            backlog_len.is_some() as u8, self.backlog_size,
+            backlog_len.map(|len| state.offset - len + 1).unwrap_or(0), backlog_len.unwrap_or(0));
+        s
+    }
+}
+
+impl Drop for Registration<'_> {
+    fn drop(&mut self) {
+        self.replication.unregister(self.id);
+    }
+}
+
+//40个字符的随机id
+fn new_replid() -> String {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    static COUNTER:AtomicU64 = AtomicU64::new(0);
+    let seed = format!("{:?}-{}-{}", std::time::SystemTime::now(), std::process::id(), COUNTER.fetch_add(1, Ordering::SeqCst));
+    script::sha1hex(seed.as_bytes())
+}
+
+//主这边处理PSYNC，之后这个连接只用来给副本发复制流
+pub(crate) async fn serve_replica(db:&Db,conn:&mut Connection,shutdown:&mut Shutdown,psync:Psync,listening_port:Option<u16>) -> crate::Result<()> {
+    let replication = db.replication();
+    let peer = conn.peer_addr()?;
+    //拷贝数据、决定从哪里开始发、登记副本都要在同一把锁下完成
+    let accepted = {
+        let guard = db.lock();
+        let mut state = replication.state.lock().unwrap();
+        if let Role::Replica { link_up: false, .. } = state.role {
+            None
+        } else {
+            if state.backlog.is_none() {
+                state.backlog = Some(VecDeque::new());
+                spawn_ping_task(db.clone());
+            }
+            let (reply,snapshot,sent) = if Replication::can_continue(&state, &psync.replid, psync.offset) {
+                (format!("CONTINUE {}",state.replid), None, psync.offset as u64 - 1)
+            } else {
+                (format!("FULLRESYNC {} {}",state.replid,state.offset), Some(guard.snapshot()), state.offset)
+            };
+            let id = state.next_replica_id;
+            state.next_replica_id += 1;
+            state.replicas.insert(id, ReplicaInfo {
+                ip: peer.ip().to_string(),
+                port: listening_port.unwrap_or(peer.port()),
+                ack_offset: 0,
+                last_ack: time::Instant::now()
+            });
+            Some((reply,snapshot,state.history,sent,Registration { replication, id }))
+        }
+    };
+    let (reply,snapshot,history,sent,registration) = match accepted {
+        Some(accepted) => accepted,
+        None => return conn.write_frame(&Frame::Error("NOMASTERLINK Can't SYNC while not connected with my master".to_string())).await
+    };
+    info!(replica=%peer,reply=%reply,"replica connected");
+    conn.write_frame(&Frame::Simple(reply)).await?;
+    if let Some(snapshot) = snapshot {
+        let payload = tokio::task::spawn_blocking(move || rdb::encode(&snapshot)).await??;
+        let mut data = format!("${}\r\n",payload.len()).into_bytes();
+        data.extend_from_slice(&payload);
+        conn.write_raw(&data).await?;
+    }
+    let res = stream_to_replica(replication, conn, shutdown, registration.id, history, sent).await;
+    drop(registration);
+    info!(replica=%peer,"replica disconnected");
+    res
+}
+
+async fn stream_to_replica(replication:&Replication,conn:&mut Connection,shutdown:&mut Shutdown,id:u64,history:u64,mut sent:u64) -> crate::Result<()> {
+    let mut offset_rx = replication.offset_tx.subscribe();
+    loop {
+        offset_rx.borrow_and_update();
+        let data = replication.pending(history, sent)?;
+        if !data.is_empty() {
+            conn.write_raw(&data).await?;
+            sent += data.len() as u64;
+        }
+        tokio::select! {
+            res = offset_rx.changed() => if res.is_err() { return Ok(()) },
+            res = conn.read_frame() => match res? {
+                None => return Ok(()),
+                Some(frame) => match Command::from_frame(frame) {
+                    Ok(Command::ReplConf(ReplConf { ack: Some(offset), .. })) => replication.ack(id, offset),
+                    command => debug!(?command,"ignoring command from replica")
+                }
+            },
+            _ = shutdown.recv() => return Ok(())
+        }
+    }
+}
+
+//WAIT，先让副本立即汇报offset，然后等到足够多的副本确认或者超时
+pub(crate) async fn wait(db:&Db,shutdown:&mut Shutdown,offset:u64,numreplicas:u64,timeout:Option<Duration>) -> Frame {
+    let replication = db.replication();
+    if replication.is_replica() {
+        return Frame::Error("ERR WAIT cannot be used with replica instances".to_string());
+    }
+    let mut ack_rx = replication.ack_tx.subscribe();
+    if replication.acked(offset) < numreplicas {
+        let mut getack = Vec::new();
+        command(&["REPLCONF", "GETACK", "*"]).encode(&mut getack);
+        db.lock().propagate_raw(&getack, false);
+        let deadline = timeout.map(|timeout| time::Instant::now() + timeout);
+        loop {
+            ack_rx.borrow_and_update();
+            if replication.acked(offset) >= numreplicas {
+                break;
+            }
+            let sleep = async {
+                match deadline {
+                    Some(deadline) => time::sleep_until(deadline).await,
+                    None => std::future::pending().await
+                }
+            };
+            tokio::select! {
+                res = ack_rx.changed() => if res.is_err() { break },
+                _ = sleep => break,
+                _ = shutdown.recv() => break
+            }
+        }
+    }
+    Frame::Integer(replication.acked(offset) as i64)
+}
+
+//有副本连着的时候定期往复制流里写PING，副本靠它判断连接是否还活着
+fn spawn_ping_task(db:Db) {
+    tokio::spawn(async move {
+        let mut ping = Vec::new();
+        Frame::Array(vec![Frame::Bulk(Bytes::from_static(b"PING"))]).encode(&mut ping);
+        let mut interval = time::interval_at(time::Instant::now() + PING_PERIOD, PING_PERIOD);
+        loop {
+            interval.tick().await;
+            let guard = db.lock();
+            let replication = guard.replication();
+            if !replication.state.lock().unwrap().replicas.is_empty() {
+                guard.propagate_raw(&ping, false);
+            }
+        }
+    });
+}
+
+//REPLICAOF host port，后台连接主并一直同步，直到再次执行REPLICAOF
+pub(crate) fn start_replication(db:&Db,host:String,port:u16) -> bool {
+    let epoch = match db.replication().set_leader(&host, port) {
+        Some(epoch) => epoch,
+        None => return false
+    };
+    let db = db.clone();
+    tokio::spawn(async move {
+        let mut epoch_rx = db.replication().epoch_tx.subscribe();
+        loop {
+            match sync_with_leader(&db, &host, port, epoch, &mut epoch_rx).await {
+                Ok(()) => break,
+                Err(err) => warn!(cause=%err,host=%host,port,"replication link error")
+            }
+            db.replication().set_link_up(epoch, false);
+            tokio::select! {
+                _ = time::sleep(RETRY_INTERVAL) => {},
+                _ = epoch_rx.changed() => {}
+            }
+            if !db.replication().is_current(epoch) {
+                break;
+            }
+        }
+        debug!(host=%host,port,"replication link stopped");
+    });
+    true
+}
+
+//握手、同步、然后一直执行主发过来的命令，REPLICAOF换了目标时返回Ok
+async fn sync_with_leader(db:&Db,host:&str,port:u16,epoch:u64,epoch_rx:&mut watch::Receiver<u64>) -> crate::Result<()> {
+    let replication = db.replication();
+    let mut client = Client::new((host,port)).await?;
+    client.ping(None).await?;
+    let (replid,offset,listening_port) = {
+        let state = replication.state.lock().unwrap();
+        (state.replid.clone(),state.offset,state.port)
+    };
+    client.request(&command(&["REPLCONF", "listening-port", &listening_port.to_string()])).await?;
+    client.request(&command(&["REPLCONF", "capa", "psync2"])).await?;
+    let reply = match client.request(&command(&["PSYNC", &replid, &(offset + 1).to_string()])).await? {
+        Frame::Simple(reply) => reply,
+        frame => return Err(format!("unexpected reply to PSYNC: {:?}",frame).into())
+    };
+    let mut conn = client.into_connection();
+    let mut parts = reply.split_whitespace();
+    match (parts.next(),parts.next(),parts.next()) {
+        (Some("FULLRESYNC"),Some(replid),Some(offset)) => {
+            let offset:u64 = offset.parse()?;
+            let replid = replid.to_string();
+            info!(replid=%replid,offset,"full resync from master");
+            let payload = conn.read_bulk_payload().await?;
+            let snapshot = tokio::task::spawn_blocking(move || rdb::decode(&payload)).await??;
+            let mut guard = db.lock();
+            if !replication.is_current(epoch) {
+                return Ok(());
+            }
+            guard.set_propagation(false);
+            guard.clear();
+            let count = guard.restore(snapshot, RestorePolicy::Flush)?;
+            replication.reset(replid, offset);
+            //aof里原来的内容已经没用了，用新数据重写
+            if let Some(aof) = guard.aof() {
+                if aof.start_rewrite() {
+                    aof.rewrite(guard.snapshot());
+                } else {
+                    warn!("append only file rewriting already in progress, the file may be inconsistent after full resync");
+                }
+            }
+            info!(keys=count,"full resync finished");
+        }
+        (Some("CONTINUE"),new_replid,_) => {
+            let _guard = db.lock();
+            let mut state = replication.state.lock().unwrap();
+            if let Some(new_replid) = new_replid.filter(|id| *id != state.replid) {
+                Replication::shift_replid(&mut state, new_replid.to_string());
+            }
+            info!(replid=%state.replid,offset=state.offset,"partial resync accepted");
+        }
+        _ => return Err(format!("unexpected reply to PSYNC: {}",reply).into())
+    }
+    replication.set_link_up(epoch, true);
+    let mut ack_interval = time::interval(ACK_PERIOD);
+    loop {
+        let frame = tokio::select! {
+            _ = ack_interval.tick() => {
+                send_ack(replication, &mut conn).await?;
+                continue;
+            },
+            res = time::timeout(REPL_TIMEOUT, conn.read_frame()) => match res {
+                Ok(res) => res?,
+                Err(_) => return Err("timeout no data received from master".into())
+            },
+            _ = epoch_rx.changed() => {
+                if replication.is_current(epoch) {
+                    continue;
+                }
+                return Ok(());
+            }
+        };
+        let frame = match frame {
+            Some(frame) => frame,
+            None => return Err("connection closed by master".into())
+        };
+        match apply_from_master(db, epoch, frame) {
+            None => return Ok(()),
+            Some(true) => send_ack(replication, &mut conn).await?,
+            Some(false) => {}
+        }
+    }
+}
+
+//执行主发过来的一条命令，REPLICAOF换了目标时返回None，否则返回是否需要立即回复ACK
+fn apply_from_master(db:&Db,epoch:u64,frame:Frame) -> Option<bool> {
+    let mut raw = Vec::new();
+    frame.encode(&mut raw);
+    let command = Command::from_frame(frame);
+    let mut guard = db.lock();
+    if !db.replication().is_current(epoch) {
+        return None;
+    }
+    guard.set_propagation(false);
+    //PING和GETACK只是用来维持连接和同步offset，不需要执行也不需要写进aof
+    let (to_aof,getack) = match &command {
+        Ok(Command::Ping(_)) => (false,false),
+        Ok(Command::ReplConf(cmd)) => (false,cmd.getack),
+        _ => (true,false)
+    };
+    if to_aof {
+        match command.map(|command| command.execute(&mut guard)) {
+            Ok(Frame::Error(err)) => warn!(cause=%err,"error executing command from master"),
+            Err(err) => warn!(cause=%err,"invalid command from master"),
+            Ok(_) => {}
+        }
+    }
+    //执行结果不回复给主，原始数据原样写进自己的aof和backlog
+    guard.propagate_raw(&raw, to_aof);
+    Some(getack)
+}
+
+async fn send_ack(replication:&Replication,conn:&mut Connection) -> crate::Result<()> {
+    let offset = replication.offset();
+    conn.write_frame(&command(&["REPLCONF", "ACK", &offset.to_string()])).await
+}
+
+fn command(args:&[&str]) -> Frame {
+    Frame::Array(args.iter().map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_bytes()))).collect())
+}
@@ -0,0 +1,655 @@
+use std::{collections::HashMap, sync::{Arc, Mutex, atomic::{AtomicBool, Ordering}}, time::{Duration, Instant}, cell::RefCell};
+
+use bytes::{Bytes, BytesMut, BufMut, Buf};
+use mlua::{Lua, LuaOptions, StdLib, Value, Variadic, HookTriggers, Table, RegistryKey};
+use tracing::warn;
+
+use crate::{db::{DbGuard, glob_match}, frame::Frame, cmd::Command};
+
+//每执行多少条lua指令检查一次超时和SCRIPT KILL
+const HOOK_INSTRUCTIONS:u32 = 1000;
+//真正的redis表放在registry里，全局变量redis只是它的只读代理
+const REDIS_TABLE:&str = "redis";
+//基础库里能读文件、加载代码或者绕过只读保护的函数
+const UNSAFE_GLOBALS:&[&str] = &["loadfile", "dofile", "load", "loadstring", "rawset", "getfenv", "setfenv"];
+
+//EVAL/EVALSHA用到的lua环境和脚本缓存，整个服务共用一个lua虚拟机
+//FUNCTION LOAD加载的函数库也注册在同一个虚拟机里
+pub(crate) struct Scripts {
+    lua:Mutex<Lua>,
+    //sha1 -> 脚本内容
+    cache:Mutex<HashMap<String,String>>,
+    functions:Mutex<Functions>,
+    state:Arc<RunState>,
+    timeout:Duration
+}
+//正在运行的脚本的状态，SCRIPT KILL只需要改这里，不需要拿db锁
+#[derive(Default)]
+struct RunState {
+    running:AtomicBool,
+    //脚本已经执行过写命令，这时候中止会破坏原子性，所以不能kill
+    dirty:AtomicBool,
+    kill:AtomicBool,
+    //FCALL_RO或者带no-writes标记的函数，不允许执行写命令
+    read_only:AtomicBool
+}
+#[derive(Default)]
+struct Functions {
+    //库名 -> 库
+    libraries:HashMap<String,Library>,
+    //函数名 -> 函数，函数名在所有库里唯一
+    functions:HashMap<String,LuaFunction>
+}
+struct Library {
+    code:String,
+    functions:Vec<String>
+}
+struct LuaFunction {
+    library:String,
+    //lua函数保存在registry里，调用时再取出来
+    key:RegistryKey,
+    no_writes:bool
+}
+//FUNCTION RESTORE遇到同名库时的处理方式
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum RestorePolicy {
+    Append,
+    Replace,
+    Flush
+}
+enum Code<'a> {
+    Script(&'a str),
+    Function(&'a RegistryKey)
+}
+
+impl std::fmt::Debug for Scripts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Scripts").field("timeout", &self.timeout).finish()
+    }
+}
+
+impl Scripts {
+    pub(crate) fn new(timeout:Duration) -> Self {
+        //不加载io/os/package/debug，脚本不能访问文件和执行系统命令
+        let lua = Lua::new_with(StdLib::TABLE | StdLib::STRING | StdLib::MATH, LuaOptions::default())
+            .and_then(|lua| {
+                register_helpers(&lua)?;
+                sandbox(&lua)?;
+                Ok(lua)
+            });
+        let lua = match lua {
+            Ok(lua) => lua,
+            Err(err) => panic!("failed to init lua: {}",err)
+        };
+        Self {
+            lua: Mutex::new(lua),
+            cache: Mutex::new(HashMap::new()),
+            functions: Mutex::new(Functions::default()),
+            state: Arc::new(RunState::default()),
+            timeout
+        }
+    }
+    pub(crate) fn load(&self,body:String) -> String {
+        let sha = sha1hex(body.as_bytes());
+        self.cache.lock().unwrap().insert(sha.clone(), body);
+        sha
+    }
+    pub(crate) fn exists(&self,sha:&str) -> bool {
+        self.cache.lock().unwrap().contains_key(&sha.to_lowercase())
+    }
+    pub(crate) fn flush(&self) {
+        self.cache.lock().unwrap().clear();
+    }
+    pub(crate) fn kill(&self) -> Frame {
+        if !self.state.running.load(Ordering::SeqCst) {
+            return Frame::Error("NOTBUSY No scripts in execution right now.".to_string());
+        }
+        if self.state.dirty.load(Ordering::SeqCst) {
+            return Frame::Error("UNKILLABLE Sorry the script already executed write commands against the dataset.".to_string());
+        }
+        self.state.kill.store(true, Ordering::SeqCst);
+        Frame::Simple("OK".to_string())
+    }
+    pub(crate) fn eval(&self,db:&mut DbGuard,body:String,keys:Vec<Bytes>,args:Vec<Bytes>) -> Frame {
+        let sha = self.load(body.clone());
+        let lua = self.lua.lock().unwrap();
+        self.run(&lua, db, &sha, Code::Script(&body), false, keys, args)
+    }
+    pub(crate) fn evalsha(&self,db:&mut DbGuard,sha:&str,keys:Vec<Bytes>,args:Vec<Bytes>) -> Frame {
+        let sha = sha.to_lowercase();
+        let body = match self.cache.lock().unwrap().get(&sha) {
+            Some(body) => body.clone(),
+            None => return Frame::Error("NOSCRIPT No matching script. Please use EVAL.".to_string())
+        };
+        let lua = self.lua.lock().unwrap();
+        self.run(&lua, db, &sha, Code::Script(&body), false, keys, args)
+    }
+    //加载函数库，返回库名。库代码第一行必须是 #!lua name=<库名>
+    pub(crate) fn function_load(&self,code:String,replace:bool) -> Result<String,String> {
+        let lua = self.lua.lock().unwrap();
+        let mut functions = self.functions.lock().unwrap();
+        //库的顶层代码也可能死循环，和脚本一样受超时限制
+        self.guarded(&lua, false, || load_library(&lua, &mut functions, code, replace)).0
+    }
+    pub(crate) fn function_delete(&self,name:&str) -> bool {
+        let lua = self.lua.lock().unwrap();
+        let mut functions = self.functions.lock().unwrap();
+        functions.remove_library(&lua, name)
+    }
+    pub(crate) fn function_flush(&self) {
+        let lua = self.lua.lock().unwrap();
+        let mut functions = self.functions.lock().unwrap();
+        let names:Vec<String> = functions.libraries.keys().cloned().collect();
+        for name in names {
+            functions.remove_library(&lua, &name);
+        }
+    }
+    //FUNCTION LIST的回复，pattern只支持*通配符
+    pub(crate) fn function_list(&self,pattern:Option<&str>,with_code:bool) -> Frame {
+        let functions = self.functions.lock().unwrap();
+        let mut names:Vec<&String> = functions.libraries.keys()
+            .filter(|name| pattern.map(|p| glob_match(p, name)).unwrap_or(true))
+            .collect();
+        names.sort();
+        let mut list = Vec::with_capacity(names.len());
+        for name in names {
+            let library = &functions.libraries[name];
+            let funcs = library.functions.iter().map(|fname| {
+                let func = &functions.functions[fname];
+                let flags = if func.no_writes {
+                    vec![Frame::Bulk(Bytes::from_static(b"no-writes"))]
+                } else {
+                    vec![]
+                };
+                Frame::Array(vec![
+                    Frame::Bulk(Bytes::from_static(b"name")),
+                    Frame::Bulk(Bytes::from(fname.clone())),
+                    Frame::Bulk(Bytes::from_static(b"description")),
+                    Frame::Null,
+                    Frame::Bulk(Bytes::from_static(b"flags")),
+                    Frame::Array(flags),
+                ])
+            }).collect();
+            let mut v = vec![
+                Frame::Bulk(Bytes::from_static(b"library_name")),
+                Frame::Bulk(Bytes::from(name.clone())),
+                Frame::Bulk(Bytes::from_static(b"engine")),
+                Frame::Bulk(Bytes::from_static(b"LUA")),
+                Frame::Bulk(Bytes::from_static(b"functions")),
+                Frame::Array(funcs),
+            ];
+            if with_code {
+                v.push(Frame::Bulk(Bytes::from_static(b"library_code")));
+                v.push(Frame::Bulk(Bytes::from(library.code.clone())));
+            }
+            list.push(Frame::Array(v));
+        }
+        Frame::Array(list)
+    }
+    //所有库的代码，格式见encode_libraries
+    pub(crate) fn function_dump(&self) -> Bytes {
+        let functions = self.functions.lock().unwrap();
+        let mut names:Vec<&String> = functions.libraries.keys().collect();
+        names.sort();
+        encode_libraries(names.into_iter().map(|name| &functions.libraries[name].code[..]))
+    }
+    pub(crate) fn function_restore(&self,payload:&[u8],policy:RestorePolicy) -> Result<(),String> {
+        let codes = decode_libraries(payload)?;
+        if policy == RestorePolicy::Flush {
+            self.function_flush();
+        }
+        let lua = self.lua.lock().unwrap();
+        let mut functions = self.functions.lock().unwrap();
+        for code in codes {
+            self.guarded(&lua, false, || load_library(&lua, &mut functions, code, policy == RestorePolicy::Replace)).0?;
+        }
+        Ok(())
+    }
+    pub(crate) fn fcall(&self,db:&mut DbGuard,name:&str,read_only:bool,keys:Vec<Bytes>,args:Vec<Bytes>) -> Frame {
+        //加锁顺序总是先lua再functions
+        let lua = self.lua.lock().unwrap();
+        let functions = self.functions.lock().unwrap();
+        let func = match functions.functions.get(name) {
+            Some(func) => func,
+            None => return Frame::Error("ERR Function not found".to_string())
+        };
+        if read_only && !func.no_writes {
+            return Frame::Error("ERR Can not execute a script with write flag using *_ro command.".to_string());
+        }
+        self.run(&lua, db, name, Code::Function(&func.key), read_only || func.no_writes, keys, args)
+    }
+    //执行期间一直持有db锁，所以脚本对其它客户端是原子的
+    #[allow(clippy::too_many_arguments)]
+    fn run(&self,lua:&Lua,db:&mut DbGuard,name:&str,code:Code,read_only:bool,keys:Vec<Bytes>,args:Vec<Bytes>) -> Frame {
+        let (result,elapsed) = self.guarded(lua, read_only, || run_script(lua, db, &self.state, name, code, keys, args));
+        if elapsed > self.timeout {
+            warn!(name, ?elapsed, "slow script");
+        }
+        match result {
+            Ok(frame) => frame,
+            Err(err) => {
+                //lua的错误信息带有stack traceback，只保留第一行，否则会破坏协议格式
+                let err = err.to_string();
+                let msg = err.lines().next().unwrap_or_default();
+                Frame::Error(format!("ERR Error running script (call to f_{}): {}", name, msg))
+            }
+        }
+    }
+    //执行f期间安装hook，超时或者SCRIPT KILL时让lua代码报错退出，返回f的结果和耗时
+    fn guarded<R>(&self,lua:&Lua,read_only:bool,f:impl FnOnce() -> R) -> (R,Duration) {
+        self.state.dirty.store(false, Ordering::SeqCst);
+        self.state.kill.store(false, Ordering::SeqCst);
+        self.state.read_only.store(read_only, Ordering::SeqCst);
+        self.state.running.store(true, Ordering::SeqCst);
+        let state = self.state.clone();
+        let timeout = self.timeout;
+        let start = Instant::now();
+        lua.set_hook(HookTriggers::new().every_nth_instruction(HOOK_INSTRUCTIONS), move |_,_| {
+            if state.kill.load(Ordering::SeqCst) {
+                return Err(mlua::Error::RuntimeError("Script killed by user with SCRIPT KILL...".to_string()));
+            }
+            if start.elapsed() > timeout && !state.dirty.load(Ordering::SeqCst) {
+                return Err(mlua::Error::RuntimeError("Script exceeded the configured timeout".to_string()));
+            }
+            Ok(())
+        });
+        let result = f();
+        lua.remove_hook();
+        self.state.running.store(false, Ordering::SeqCst);
+        (result,start.elapsed())
+    }
+}
+
+impl Functions {
+    fn remove_library(&mut self,lua:&Lua,name:&str) -> bool {
+        match self.libraries.remove(name) {
+            Some(library) => {
+                for fname in library.functions {
+                    if let Some(func) = self.functions.remove(&fname) {
+                        let _ = lua.remove_registry_value(func.key);
+                    }
+                }
+                true
+            }
+            None => false
+        }
+    }
+}
+
+fn load_library(lua:&Lua,functions:&mut Functions,code:String,replace:bool) -> Result<String,String> {
+    let (first,body) = code.split_once('\n').unwrap_or((&code,""));
+    let name = first.strip_prefix("#!lua")
+        .and_then(|rest| rest.trim().strip_prefix("name="))
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .ok_or_else(|| "ERR Missing library metadata".to_string())?;
+    if functions.libraries.contains_key(&name) && !replace {
+        return Err(format!("ERR Library '{}' already exists",name));
+    }
+    //shebang那一行lua不认识，换成空行保持行号不变
+    let source = format!("\n{}",body);
+    let mut registered:Vec<(String,RegistryKey,bool)> = Vec::new();
+    let result = lua.scope(|scope| {
+        let register = scope.create_function_mut(|lua,args:Variadic<Value>| {
+            let (fname,callback,no_writes) = parse_register_args(args)?;
+            registered.push((fname, lua.create_registry_value(callback)?, no_writes));
+            Ok(())
+        })?;
+        let redis:Table = lua.named_registry_value(REDIS_TABLE)?;
+        redis.set("register_function", register)?;
+        //库的顶层代码定义的全局变量只在这个库里可见
+        let result = lua.load(&source).set_name(format!("@user_function_{}",name))
+            .set_environment(script_env(lua)?)
+            .exec();
+        redis.set("register_function", Value::Nil)?;
+        result
+    });
+    if let Err(err) = result {
+        //已经放进registry的函数会在drop之后被回收
+        drop(registered);
+        lua.expire_registry_values();
+        let err = err.to_string();
+        return Err(format!("ERR Error registering functions: {}",err.lines().next().unwrap_or_default()));
+    }
+    if registered.is_empty() {
+        return Err("ERR No functions registered".to_string());
+    }
+    for (fname,_,_) in &registered {
+        if let Some(func) = functions.functions.get(fname) {
+            if func.library != name {
+                let err = format!("ERR Function {} already exists",fname);
+                drop(registered);
+                lua.expire_registry_values();
+                return Err(err);
+            }
+        }
+    }
+    functions.remove_library(lua, &name);
+    let mut names = Vec::with_capacity(registered.len());
+    for (fname,key,no_writes) in registered {
+        functions.functions.insert(fname.clone(), LuaFunction { library: name.clone(), key, no_writes });
+        names.push(fname);
+    }
+    functions.libraries.insert(name.clone(), Library { code, functions: names });
+    Ok(name)
+}
+
+// redis.register_function('name', callback) 或者
+// redis.register_function{function_name='name', callback=callback, flags={'no-writes'}}
+fn parse_register_args<'lua>(args:Variadic<Value<'lua>>) -> mlua::Result<(String,mlua::Function<'lua>,bool)> {
+    let mut args = args.into_iter();
+    match (args.next(), args.next()) {
+        (Some(Value::String(name)), Some(Value::Function(callback))) => {
+            Ok((name.to_str()?.to_string(), callback, false))
+        }
+        (Some(Value::Table(t)), None) => {
+            let name:String = t.get("function_name")?;
+            let callback:mlua::Function = t.get("callback")?;
+            let mut no_writes = false;
+            if let Some(flags) = t.get::<_,Option<Table>>("flags")? {
+                for flag in flags.sequence_values::<String>() {
+                    match &flag?[..] {
+                        "no-writes" => no_writes = true,
+                        "allow-oom" | "allow-stale" | "no-cluster" | "allow-cross-slot-keys" => {}
+                        flag => return Err(mlua::Error::RuntimeError(format!("unknown flag given: {}",flag)))
+                    }
+                }
+            }
+            Ok((name, callback, no_writes))
+        }
+        _ => Err(mlua::Error::RuntimeError("wrong arguments to redis.register_function".to_string()))
+    }
+}
+
+fn run_script(lua:&Lua,db:&mut DbGuard,state:&RunState,name:&str,code:Code,keys:Vec<Bytes>,args:Vec<Bytes>) -> mlua::Result<Frame> {
+    let keys = bytes_table(lua, keys)?;
+    let args = bytes_table(lua, args)?;
+    let redis:Table = lua.named_registry_value(REDIS_TABLE)?;
+    let db = RefCell::new(db);
+    lua.scope(|scope| {
+        let call = scope.create_function(|lua,args:Variadic<Value>| {
+            match call_command(&mut db.borrow_mut(), state, args) {
+                Frame::Error(err) => Err(mlua::Error::RuntimeError(err)),
+                frame => frame_to_lua(lua, frame)
+            }
+        })?;
+        let pcall = scope.create_function(|lua,args:Variadic<Value>| {
+            frame_to_lua(lua, call_command(&mut db.borrow_mut(), state, args))
+        })?;
+        redis.set("call", call)?;
+        redis.set("pcall", pcall)?;
+        let result = match code {
+            Code::Script(body) => {
+                //EVAL的脚本通过全局变量KEYS/ARGV拿参数，每次执行都用新的环境，全局变量不会留给下一个脚本
+                let env = script_env(lua)?;
+                env.raw_set("KEYS", keys)?;
+                env.raw_set("ARGV", args)?;
+                lua.load(body).set_name(format!("@user_script_{}",name)).set_environment(env).eval::<Value>()
+            }
+            Code::Function(key) => {
+                //函数的参数是(keys,args)
+                lua.registry_value::<mlua::Function>(key)
+                    .and_then(|func| func.call::<_,Value>((keys,args)))
+            }
+        };
+        redis.set("call", Value::Nil)?;
+        redis.set("pcall", Value::Nil)?;
+        Ok(lua_to_frame(result?))
+    })
+}
+
+//redis.call/redis.pcall的实现，参数转成命令在当前的db锁下执行
+fn call_command(db:&mut DbGuard,state:&RunState,args:Variadic<Value>) -> Frame {
+    let mut parts = Vec::with_capacity(args.len());
+    for arg in args.iter() {
+        match arg {
+            Value::String(s) => parts.push(Frame::Bulk(Bytes::copy_from_slice(s.as_bytes()))),
+            Value::Integer(n) => parts.push(Frame::Bulk(Bytes::from(n.to_string()))),
+            Value::Number(n) => parts.push(Frame::Bulk(Bytes::from(n.to_string()))),
+            _ => return Frame::Error("ERR Lua redis() command arguments must be strings or integers".to_string())
+        }
+    }
+    if parts.is_empty() {
+        return Frame::Error("ERR Please specify at least one argument for redis.call()".to_string());
+    }
+    let command = match Command::from_frame(Frame::Array(parts)) {
+        Ok(command) => command,
+        Err(err) => return Frame::Error(format!("ERR {}",err))
+    };
+    if !command.is_allowed_in_script() {
+        return Frame::Error("ERR This Redis command is not allowed from script".to_string());
+    }
+    if command.is_write() {
+        if state.read_only.load(Ordering::SeqCst) {
+            return Frame::Error("ERR Write commands are not allowed from read-only scripts".to_string());
+        }
+        if db.replication().is_read_only() {
+            return Frame::Error("READONLY You can't write against a read only replica.".to_string());
+        }
+        state.dirty.store(true, Ordering::SeqCst);
+    }
+    command.execute(db)
+}
+
+//redis.error_reply/status_reply/sha1hex这些不需要访问db的函数只注册一次
+fn register_helpers(lua:&Lua) -> mlua::Result<()> {
+    let redis = lua.create_table()?;
+    redis.set("error_reply", lua.create_function(|lua,msg:String| {
+        let t = lua.create_table()?;
+        t.set("err", msg)?;
+        Ok(t)
+    })?)?;
+    redis.set("status_reply", lua.create_function(|lua,msg:String| {
+        let t = lua.create_table()?;
+        t.set("ok", msg)?;
+        Ok(t)
+    })?)?;
+    redis.set("sha1hex", lua.create_function(|_,s:mlua::String| {
+        Ok(sha1hex(s.as_bytes()))
+    })?)?;
+    lua.set_named_registry_value(REDIS_TABLE, redis.clone())?;
+    lua.globals().set("redis", redis)?;
+    Ok(())
+}
+
+//去掉不安全的函数，全局变量和库都换成只读代理，脚本只能修改自己的环境(见script_env)
+fn sandbox(lua:&Lua) -> mlua::Result<()> {
+    //字符串的metatable的__index就是真正的string表，不让脚本拿到
+    lua.load("getmetatable('').__metatable = false").exec()?;
+    let globals = lua.globals();
+    for name in UNSAFE_GLOBALS {
+        globals.raw_remove(*name)?;
+    }
+    for name in ["string", "table", "math", "redis"] {
+        let table:Table = globals.raw_get(name)?;
+        globals.raw_set(name, readonly(lua, table)?)?;
+    }
+    globals.raw_set("_G", readonly(lua, globals.clone())?)?;
+    Ok(())
+}
+
+//读取转发到原来的表，写入报错，metatable也拿不到
+fn readonly<'lua>(lua:&'lua Lua,table:Table<'lua>) -> mlua::Result<Table<'lua>> {
+    let meta = lua.create_table()?;
+    meta.set("__index", table)?;
+    meta.set("__newindex", lua.create_function(|_,_:Variadic<Value>| -> mlua::Result<()> {
+        Err(mlua::Error::RuntimeError("Attempt to modify a readonly table".to_string()))
+    })?)?;
+    meta.set("__metatable", false)?;
+    let proxy = lua.create_table()?;
+    proxy.set_metatable(Some(meta));
+    Ok(proxy)
+}
+
+//脚本和函数库的全局环境，读不到的变量从只读的_G里找
+fn script_env(lua:&Lua) -> mlua::Result<Table<'_>> {
+    let meta = lua.create_table()?;
+    meta.set("__index", lua.globals().raw_get::<_,Table>("_G")?)?;
+    meta.set("__metatable", false)?;
+    let env = lua.create_table()?;
+    env.set_metatable(Some(meta));
+    Ok(env)
+}
+
+fn bytes_table(lua:&Lua,items:Vec<Bytes>) -> mlua::Result<Table<'_>> {
+    let t = lua.create_table()?;
+    for (i,item) in items.iter().enumerate() {
+        t.raw_set(i+1, lua.create_string(item)?)?;
+    }
+    Ok(t)
+}
+
+//FUNCTION DUMP的格式: [u32 库数量] ([u32 长度] 代码)*，快照和rdb里的函数库也用它在模块间传递
+pub(crate) fn encode_libraries<'a>(codes:impl Iterator<Item = &'a str>) -> Bytes {
+    let codes:Vec<&str> = codes.collect();
+    let mut buf = BytesMut::new();
+    buf.put_u32(codes.len() as u32);
+    for code in codes {
+        buf.put_u32(code.len() as u32);
+        buf.put_slice(code.as_bytes());
+    }
+    buf.freeze()
+}
+
+pub(crate) fn decode_libraries(mut payload:&[u8]) -> Result<Vec<String>,String> {
+    let mut codes = Vec::new();
+    if payload.remaining() < 4 {
+        return Err("ERR payload version or checksum are wrong".to_string());
+    }
+    for _ in 0..payload.get_u32() {
+        if payload.remaining() < 4 {
+            return Err("ERR payload version or checksum are wrong".to_string());
+        }
+        let len = payload.get_u32() as usize;
+        if payload.remaining() < len {
+            return Err("ERR payload version or checksum are wrong".to_string());
+        }
+        let code = String::from_utf8(payload[..len].to_vec())
+            .map_err(|_| "ERR payload version or checksum are wrong".to_string())?;
+        payload.advance(len);
+        codes.push(code);
+    }
+    Ok(codes)
+}
+
+pub(crate) fn sha1hex(data:&[u8]) -> String {
+    sha1_smol::Sha1::from(data).digest().to_string()
+}
+
+//redis命令的返回值转成lua值，规则和redis一致:
+//integer -> number, bulk -> string, null -> false, status -> {ok=..}, error -> {err=..}, array -> table
+fn frame_to_lua(lua:&Lua,frame:Frame) -> mlua::Result<Value<'_>> {
+    let value = match frame {
+        Frame::Integer(n) => Value::Integer(n),
+        Frame::Bulk(data) => Value::String(lua.create_string(&data)?),
+        Frame::Null | Frame::NullArray => Value::Boolean(false),
+        Frame::Simple(s) => {
+            let t = lua.create_table()?;
+            t.set("ok", s)?;
+            Value::Table(t)
+        }
+        Frame::Error(e) => {
+            let t = lua.create_table()?;
+            t.set("err", e)?;
+            Value::Table(t)
+        }
+        Frame::Array(v) => {
+            let t = lua.create_table()?;
+            for (i,frame) in v.into_iter().enumerate() {
+                t.raw_set(i+1, frame_to_lua(lua, frame)?)?;
+            }
+            Value::Table(t)
+        }
+    };
+    Ok(value)
+}
+
+//lua返回值转成frame，number会被截断成整数，true -> 1，false/nil -> null，
+//数组遇到第一个nil就结束
+fn lua_to_frame(value:Value) -> Frame {
+    match value {
+        Value::Nil => Frame::Null,
+        Value::Boolean(true) => Frame::Integer(1),
+        Value::Boolean(false) => Frame::Null,
+        Value::Integer(n) => Frame::Integer(n),
+        Value::Number(n) => Frame::Integer(n as i64),
+        Value::String(s) => Frame::Bulk(Bytes::copy_from_slice(s.as_bytes())),
+        Value::Table(t) => {
+            if let Ok(Value::String(err)) = t.raw_get::<_,Value>("err") {
+                return Frame::Error(err.to_string_lossy().into_owned());
+            }
+            if let Ok(Value::String(ok)) = t.raw_get::<_,Value>("ok") {
+                return Frame::Simple(ok.to_string_lossy().into_owned());
+            }
+            let mut v = Vec::new();
+            for i in 1.. {
+                match t.raw_get::<_,Value>(i) {
+                    Ok(Value::Nil) | Err(_) => break,
+                    Ok(value) => v.push(lua_to_frame(value))
+                }
+            }
+            Frame::Array(v)
+        }
+        Value::Error(err) => Frame::Error(err.to_string()),
+        _ => Frame::Null
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encoded(frame:&Frame) -> Vec<u8> {
+        let mut buf = Vec::new();
+        frame.encode(&mut buf);
+        buf
+    }
+
+    fn eval(lua:&Lua,code:&str) -> Frame {
+        lua_to_frame(lua.load(code).eval::<Value>().unwrap())
+    }
+
+    #[test]
+    fn lua_values_to_frames() {
+        let lua = Lua::new();
+        assert_eq!(encoded(&eval(&lua, "return nil")), b"$-1\r\n");
+        assert_eq!(encoded(&eval(&lua, "return false")), b"$-1\r\n");
+        assert_eq!(encoded(&eval(&lua, "return true")), b":1\r\n");
+        assert_eq!(encoded(&eval(&lua, "return 3.9")), b":3\r\n");
+        assert_eq!(encoded(&eval(&lua, "return 'a'")), b"$1\r\na\r\n");
+        assert_eq!(encoded(&eval(&lua, "return {ok='OK'}")), b"+OK\r\n");
+        assert_eq!(encoded(&eval(&lua, "return {err='ERR x'}")), b"-ERR x\r\n");
+        //遇到nil数组就结束
+        assert_eq!(encoded(&eval(&lua, "return {1,'b',nil,4}")), b"*2\r\n:1\r\n$1\r\nb\r\n");
+    }
+
+    #[test]
+    fn frames_round_trip_through_lua() {
+        let lua = Lua::new();
+        let frame = Frame::Array(vec![
+            Frame::Integer(-7),
+            Frame::Bulk(Bytes::from_static(b"\x00\xff")),
+            Frame::Simple("OK".to_string()),
+            Frame::Error("ERR x".to_string()),
+            Frame::Array(vec![Frame::Integer(1)])
+        ]);
+        let expected = encoded(&frame);
+        let value = frame_to_lua(&lua, frame).unwrap();
+        assert_eq!(encoded(&lua_to_frame(value)), expected);
+        //null转成false，再转回来还是null
+        let value = frame_to_lua(&lua, Frame::NullArray).unwrap();
+        assert_eq!(encoded(&lua_to_frame(value)), b"$-1\r\n");
+    }
+
+    #[test]
+    fn libraries_round_trip() {
+        let codes = ["#!lua name=a\n", "#!lua name=b\nredis.register_function('f', function() end)"];
+        let payload = encode_libraries(codes.iter().copied());
+        assert_eq!(decode_libraries(&payload).unwrap(), codes);
+        //截断的和长度字段超出数据的都要报错
+        assert!(decode_libraries(&payload[..payload.len() - 1]).is_err());
+        assert!(decode_libraries(&[0, 0, 0, 1, 0xff, 0xff, 0xff, 0xff]).is_err());
+        assert!(decode_libraries(&[]).is_err());
+    }
+}
@@ -0,0 +1,848 @@
+use std::{collections::HashMap, fmt::Write, future::Future, sync::{Arc, Mutex}, time::Duration};
+use bytes::Bytes;
+use tokio::{net::TcpListener, sync::{broadcast, mpsc}, task::JoinHandle, time::{self, Instant}};
+use tracing::{debug, error, info, warn};
+use crate::client::Client;
+use crate::config::Config;
+use crate::connection::Connection;
+use crate::db::DbDropGuard;
+use crate::frame::Frame;
+use crate::parse::Parse;
+use crate::script;
+use crate::shutdown::Shutdown;
+use crate::Command;
+
+//所有监控者在被监控的节点上通过这个channel互相发现，并交换主的最新配置
+const HELLO_CHANNEL:&str = "__sentinel__:hello";
+const PING_PERIOD:Duration = Duration::from_secs(1);
+const HELLO_PERIOD:Duration = Duration::from_secs(2);
+//每个请求的超时时间，超时就认为这次没有回复
+const REQUEST_TIMEOUT:Duration = Duration::from_secs(1);
+//超过这个时间没收到hello的监控者不再参与投票
+const PEER_TIMEOUT:Duration = Duration::from_secs(30);
+
+//监控者的配置，由bin/sentinel.rs根据命令行参数构造
+#[derive(Debug,Clone)]
+pub struct SentinelConfig {
+    //被监控的主的名字，客户端用它查询当前的主
+    pub name:String,
+    pub master_host:String,
+    pub master_port:u16,
+    //至少这么多监控者认为主挂了才开始故障转移
+    pub quorum:usize,
+    //主超过这个时间没有回复PING就认为它挂了
+    pub down_after:Duration,
+    //一次故障转移的最长时间，失败之后至少等这么久才会再次尝试
+    pub failover_timeout:Duration,
+}
+
+type Addr = (String,u16);
+
+#[derive(Debug)]
+struct Sentinel {
+    config:SentinelConfig,
+    run_id:String,
+    //告诉其它监控者的地址
+    addr:Addr,
+    state:Mutex<State>,
+    //只用来给客户端发布事件
+    pubsub:DbDropGuard
+}
+
+#[derive(Debug)]
+struct State {
+    master:Addr,
+    //每次故障转移成功之后的配置版本，hello里带着，版本高的配置覆盖版本低的
+    config_epoch:u64,
+    //选举用的纪元，每次发起选举加一
+    current_epoch:u64,
+    master_last_ok:Instant,
+    sdown:bool,
+    odown:bool,
+    replicas:HashMap<Addr,Replica>,
+    //run_id -> 其它监控者
+    peers:HashMap<String,Peer>,
+    //投过票的(run_id,纪元)，每个纪元只投一次
+    leader:Option<(String,u64)>,
+    //上次发起或者投票支持故障转移的时间，failover_timeout之内不会自己再发起
+    failover_start:Option<Instant>
+}
+
+#[derive(Debug,Default)]
+struct Replica {
+    last_ok:Option<Instant>,
+    run_id:String,
+    offset:u64,
+    //INFO里的role是master
+    is_master:bool,
+    //它正在复制的主
+    master:Option<Addr>
+}
+
+#[derive(Debug)]
+struct Peer {
+    addr:Addr,
+    last_hello:Instant
+}
+
+//到各个节点的连接，请求失败时断开，下次用到时重连
+#[derive(Default)]
+struct Links {
+    clients:HashMap<Addr,Client>
+}
+
+//每个节点上订阅hello的任务，monitor被取消时drop，一起取消
+#[derive(Default)]
+struct HelloTasks {
+    tasks:HashMap<Addr,JoinHandle<()>>
+}
+
+impl Default for SentinelConfig {
+    fn default() -> Self {
+        Self {
+            name: "mymaster".to_string(),
+            master_host: "127.0.0.1".to_string(),
+            master_port: crate::DEFAULT_PORT.parse().unwrap(),
+            quorum: 2,
+            down_after: Duration::from_secs(5),
+            failover_timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+impl SentinelConfig {
+    //"name host port quorum"，和redis的sentinel monitor一样
+    pub fn set_monitor(&mut self,s:&str) -> crate::Result<()> {
+        let parts:Vec<&str> = s.split_whitespace().collect();
+        match parts.as_slice() {
+            [name,host,port,quorum] => {
+                self.name = name.to_string();
+                self.master_host = host.to_string();
+                self.master_port = port.parse().map_err(|_| format!("invalid master port '{}'",port))?;
+                self.quorum = quorum.parse().map_err(|_| format!("invalid quorum '{}'",quorum))?;
+                if self.quorum == 0 {
+                    return Err("quorum must be at least 1".into());
+                }
+                Ok(())
+            }
+            _ => Err(format!("invalid monitor '{}', expected \"name host port quorum\"",s).into())
+        }
+    }
+}
+
+pub async fn run(listener:TcpListener,config:SentinelConfig,shutdown:impl Future) {
+    let local = match listener.local_addr() {
+        Ok(addr) => addr,
+        Err(err) => {
+            error!(cause=%err,"failed to get local address");
+            return;
+        }
+    };
+    let run_id = script::sha1hex(format!("{:?}-{}-{}",std::time::SystemTime::now(),std::process::id(),local).as_bytes());
+    let sentinel = Arc::new(Sentinel::new(config, (local.ip().to_string(),local.port()), run_id));
+    info!(name=%sentinel.config.name,run_id=%sentinel.run_id,"sentinel started");
+    let (notify_shutdown,_) = broadcast::channel(1);
+    let (shutdown_complete_tx,mut shutdown_complete_rx) = mpsc::channel(1);
+    let monitor = tokio::spawn(monitor(sentinel.clone()));
+    tokio::select! {
+        res = accept(&sentinel,&listener,&notify_shutdown,&shutdown_complete_tx) => {
+            if let Err(err) = res {
+                error!(cause=%err,"failed to accept");
+            }
+        }
+        _ = shutdown => {
+            info!("shutting down");
+        }
+    }
+    monitor.abort();
+    let _ = monitor.await;
+    drop(notify_shutdown);
+    drop(shutdown_complete_tx);
+    //等待所有连接处理完毕
+    let _ = shutdown_complete_rx.recv().await;
+}
+
+async fn accept(sentinel:&Arc<Sentinel>,listener:&TcpListener,notify_shutdown:&broadcast::Sender<()>,shutdown_complete:&mpsc::Sender<()>) -> crate::Result<()> {
+    loop {
+        let (stream,_) = listener.accept().await?;
+        let sentinel = sentinel.clone();
+        let mut shutdown = Shutdown::new(notify_shutdown.subscribe());
+        let shutdown_complete = shutdown_complete.clone();
+        tokio::spawn(async move {
+            let mut conn = Connection::new(stream);
+            if let Err(err) = sentinel.handle(&mut conn, &mut shutdown).await {
+                debug!(cause=%err,"connection error");
+            }
+            drop(shutdown_complete);
+        });
+    }
+}
+
+impl Sentinel {
+    //客户端和其它监控者发来的命令
+    async fn handle(&self,conn:&mut Connection,shutdown:&mut Shutdown) -> crate::Result<()> {
+        while !shutdown.is_shutdown() {
+            let frame = tokio::select! {
+                res = conn.read_frame() => match res? {
+                    Some(frame) => frame,
+                    None => return Ok(())
+                },
+                _ = shutdown.recv() => return Ok(())
+            };
+            let name = match &frame {
+                Frame::Array(v) => match v.first() {
+                    Some(Frame::Bulk(name)) => String::from_utf8_lossy(name).to_lowercase(),
+                    _ => String::new()
+                },
+                _ => String::new()
+            };
+            let response = match &name[..] {
+                "sentinel" => self.command(frame),
+                "info" => Frame::Bulk(self.info().into()),
+                //PING和订阅相关的命令和普通节点一样
+                "ping" | "subscribe" | "psubscribe" => match Command::from_frame(frame) {
+                    Ok(command) => {
+                        command.apply(&self.pubsub.db(), conn, shutdown).await?;
+                        continue;
+                    }
+                    Err(err) => Frame::Error(format!("ERR {}",err))
+                },
+                _ => Frame::Error(format!("ERR unknown command '{}'",name))
+            };
+            conn.write_frame(&response).await?;
+        }
+        Ok(())
+    }
+
+    fn command(&self,frame:Frame) -> Frame {
+        match self.try_command(frame) {
+            Ok(frame) => frame,
+            Err(err) => Frame::Error(format!("ERR {}",err))
+        }
+    }
+
+    fn try_command(&self,frame:Frame) -> crate::Result<Frame> {
+        let mut parse = Parse::new(frame)?;
+        let parse = &mut parse;
+        parse.next_string()?;
+        let sub = parse.next_string()?.to_lowercase();
+        let frame = match &sub[..] {
+            "myid" => Frame::Bulk(self.run_id.clone().into()),
+            "get-master-addr-by-name" => {
+                let name = parse.next_string()?;
+                if name != self.config.name {
+                    return Ok(Frame::Null);
+                }
+                let (host,port) = self.state.lock().unwrap().master.clone();
+                Frame::Array(vec![Frame::Bulk(host.into()), Frame::Bulk(port.to_string().into())])
+            }
+            //SENTINEL is-master-down-by-addr ip port epoch run_id，run_id为*时只询问主是否挂了，否则同时请求投票
+            "is-master-down-by-addr" => {
+                let host = parse.next_string()?;
+                let port:u16 = parse.next_string()?.parse()?;
+                let epoch = parse.next_int()?;
+                let run_id = parse.next_string()?;
+                let (down,leader,leader_epoch) = self.vote((host,port), epoch, &run_id);
+                Frame::Array(vec![
+                    Frame::Integer(down as i64),
+                    Frame::Bulk(leader.into()),
+                    Frame::Integer(leader_epoch as i64),
+                ])
+            }
+            "masters" => Frame::Array(vec![self.master_fields()]),
+            "master" => {
+                self.check_name(parse)?;
+                self.master_fields()
+            }
+            "replicas" | "slaves" => {
+                self.check_name(parse)?;
+                let state = self.state.lock().unwrap();
+                Frame::Array(state.replicas.iter().map(|((host,port),replica)| fields(vec![
+                    ("ip", host.clone()),
+                    ("port", port.to_string()),
+                    ("runid", replica.run_id.clone()),
+                    ("flags", if replica.is_responsive() { "slave" } else { "slave,s_down" }.to_string()),
+                    ("slave-repl-offset", replica.offset.to_string()),
+                ])).collect())
+            }
+            "sentinels" => {
+                self.check_name(parse)?;
+                let state = self.state.lock().unwrap();
+                Frame::Array(state.active_peers().map(|(run_id,peer)| fields(vec![
+                    ("ip", peer.addr.0.clone()),
+                    ("port", peer.addr.1.to_string()),
+                    ("runid", run_id.clone()),
+                ])).collect())
+            }
+            _ => return Err(format!("unknown sentinel subcommand '{}'",sub).into())
+        };
+        Ok(frame)
+    }
+
+    fn check_name(&self,parse:&mut Parse) -> crate::Result<()> {
+        let name = parse.next_string()?;
+        if name != self.config.name {
+            return Err("No such master with that name".into());
+        }
+        Ok(())
+    }
+
+    fn master_fields(&self) -> Frame {
+        let state = self.state.lock().unwrap();
+        fields(vec![
+            ("name", self.config.name.clone()),
+            ("ip", state.master.0.clone()),
+            ("port", state.master.1.to_string()),
+            ("flags", state.flags().to_string()),
+            ("num-slaves", state.replicas.len().to_string()),
+            ("num-other-sentinels", state.active_peers().count().to_string()),
+            ("quorum", self.config.quorum.to_string()),
+            ("config-epoch", state.config_epoch.to_string()),
+        ])
+    }
+
+    fn info(&self) -> String {
+        let state = self.state.lock().unwrap();
+        let mut s = String::from("# Sentinel\r\nsentinel_masters:1\r\n");
+        let _ = write!(s, "master0:name={},status={},address={}:{},slaves={},sentinels={}\r\n",
+            self.config.name, if state.odown { "odown" } else if state.sdown { "sdown" } else { "ok" },
+            state.master.0, state.master.1, state.replicas.len(), state.active_peers().count() + 1);
+        s
+    }
+
+    fn new(config:SentinelConfig,addr:Addr,run_id:String) -> Self {
+        Sentinel {
+            addr,
+            run_id,
+            state: Mutex::new(State {
+                master: (config.master_host.clone(),config.master_port),
+                config_epoch: 0,
+                current_epoch: 0,
+                master_last_ok: Instant::now(),
+                sdown: false,
+                odown: false,
+                replicas: HashMap::new(),
+                peers: HashMap::new(),
+                leader: None,
+                failover_start: None
+            }),
+            pubsub: DbDropGuard::new(&Config::default()),
+            config
+        }
+    }
+
+    //返回(本监控者是否认为主挂了,投票给的run_id,投票的纪元)
+    fn vote(&self,master:Addr,epoch:u64,run_id:&str) -> (bool,String,u64) {
+        let mut state = self.state.lock().unwrap();
+        let down = state.sdown && state.master == master;
+        if run_id != "*" {
+            if epoch > state.current_epoch {
+                state.current_epoch = epoch;
+            }
+            let voted = state.leader.as_ref().map(|(_,leader_epoch)| *leader_epoch >= epoch).unwrap_or(false);
+            if !voted && epoch == state.current_epoch {
+                state.leader = Some((run_id.to_string(),epoch));
+                if run_id != self.run_id {
+                    //投票给了别人，给它留出完成故障转移的时间
+                    state.failover_start = Some(Instant::now());
+                }
+                info!(leader=%run_id,epoch,"voted for leader");
+            }
+        }
+        match state.leader.clone() {
+            Some((leader,leader_epoch)) => (down,leader,leader_epoch),
+            None => (down,"*".to_string(),0)
+        }
+    }
+
+    //发布给订阅了这个监控者的客户端
+    fn publish(&self,event:&str,msg:String) {
+        info!(event,%msg);
+        self.pubsub.db().lock().publish(event, msg.into());
+    }
+
+    fn hello(&self) -> String {
+        let state = self.state.lock().unwrap();
+        format!("{},{},{},{},{},{},{},{}", self.addr.0, self.addr.1, self.run_id, state.current_epoch,
+            self.config.name, state.master.0, state.master.1, state.config_epoch)
+    }
+
+    //其它监控者的hello，版本更高的配置说明已经有人完成了故障转移
+    fn process_hello(&self,msg:&str) {
+        let parts:Vec<&str> = msg.split(',').collect();
+        let (host,port,run_id,current_epoch,name,master_host,master_port,config_epoch) = match parts.as_slice() {
+            [host,port,run_id,current_epoch,name,master_host,master_port,config_epoch] => {
+                match (port.parse::<u16>(),current_epoch.parse::<u64>(),master_port.parse::<u16>(),config_epoch.parse::<u64>()) {
+                    (Ok(port),Ok(current_epoch),Ok(master_port),Ok(config_epoch)) =>
+                        (*host,port,*run_id,current_epoch,*name,*master_host,master_port,config_epoch),
+                    _ => return
+                }
+            }
+            _ => return
+        };
+        if run_id == self.run_id || name != self.config.name {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        let addr = (host.to_string(),port);
+        //同一个地址重启之后run_id会变，去掉旧的
+        state.peers.retain(|id,peer| id == run_id || peer.addr != addr);
+        if state.peers.insert(run_id.to_string(), Peer { addr: addr.clone(), last_hello: Instant::now() }).is_none() {
+            drop(state);
+            self.publish("+sentinel", format!("sentinel {} {} {} @ {}", run_id, addr.0, addr.1, self.config.name));
+            state = self.state.lock().unwrap();
+        }
+        if current_epoch > state.current_epoch {
+            state.current_epoch = current_epoch;
+        }
+        if config_epoch > state.config_epoch {
+            state.config_epoch = config_epoch;
+            let new = (master_host.to_string(),master_port);
+            if new != state.master {
+                let old = state.switch_master(new.clone());
+                drop(state);
+                self.publish("+switch-master", format!("{} {} {} {} {}", self.config.name, old.0, old.1, new.0, new.1));
+            }
+        }
+    }
+}
+
+impl State {
+    fn flags(&self) -> &'static str {
+        if self.odown {
+            "master,o_down"
+        } else if self.sdown {
+            "master,s_down"
+        } else {
+            "master"
+        }
+    }
+    fn active_peers(&self) -> impl Iterator<Item = (&String,&Peer)> {
+        self.peers.iter().filter(|(_,peer)| peer.last_hello.elapsed() < PEER_TIMEOUT)
+    }
+    //能连上的副本里复制进度最新的，一样新时选run_id小的
+    fn select_replica(&self,master:&Addr) -> Option<Addr> {
+        let mut candidates:Vec<(&Addr,&Replica)> = self.replicas.iter()
+            .filter(|(addr,replica)| *addr != master && replica.is_responsive())
+            .collect();
+        candidates.sort_by(|(_,a),(_,b)| b.offset.cmp(&a.offset).then_with(|| a.run_id.cmp(&b.run_id)));
+        candidates.first().map(|(addr,_)| (*addr).clone())
+    }
+    //新的主从副本里去掉，原来的主变成副本，等它恢复之后再让它去复制新的主，返回原来的主
+    fn switch_master(&mut self,new:Addr) -> Addr {
+        let old = std::mem::replace(&mut self.master, new);
+        self.replicas.remove(&self.master);
+        self.replicas.insert(old.clone(), Replica::default());
+        self.master_last_ok = Instant::now();
+        self.sdown = false;
+        self.odown = false;
+        //故障转移已经完成，之后可以马上开始纠正副本的配置
+        self.failover_start = None;
+        old
+    }
+}
+
+impl Replica {
+    fn is_responsive(&self) -> bool {
+        self.last_ok.map(|last_ok| last_ok.elapsed() < PING_PERIOD * 3).unwrap_or(false)
+    }
+}
+
+impl Links {
+    async fn client(&mut self,addr:&Addr) -> crate::Result<&mut Client> {
+        if !self.clients.contains_key(addr) {
+            let client = time::timeout(REQUEST_TIMEOUT, Client::new((addr.0.as_str(),addr.1))).await
+                .map_err(|_| "connect timeout")??;
+            self.clients.insert(addr.clone(), client);
+        }
+        Ok(self.clients.get_mut(addr).unwrap())
+    }
+    async fn request(&mut self,addr:&Addr,frame:&Frame) -> crate::Result<Frame> {
+        let res = match self.client(addr).await {
+            Ok(client) => time::timeout(REQUEST_TIMEOUT, client.request(frame)).await.unwrap_or_else(|_| Err("request timeout".into())),
+            Err(err) => Err(err)
+        };
+        if res.is_err() {
+            self.clients.remove(addr);
+        }
+        res
+    }
+    async fn ping(&mut self,addr:&Addr) -> crate::Result<()> {
+        self.request(addr, &command(&["PING"])).await.map(|_| ())
+    }
+    async fn info(&mut self,addr:&Addr) -> crate::Result<String> {
+        match self.request(addr, &command(&["INFO"])).await? {
+            Frame::Bulk(info) => Ok(String::from_utf8(info.to_vec())?),
+            frame => Err(format!("unexpected reply to INFO: {:?}",frame).into())
+        }
+    }
+    async fn replicaof(&mut self,addr:&Addr,leader:Option<&Addr>) -> crate::Result<()> {
+        let frame = match leader {
+            Some((host,port)) => command(&["REPLICAOF", host, &port.to_string()]),
+            None => command(&["REPLICAOF", "NO", "ONE"])
+        };
+        self.request(addr, &frame).await.map(|_| ())
+    }
+}
+
+//定期检查主和副本的状态，主挂了就和其它监控者协商之后做故障转移
+async fn monitor(sentinel:Arc<Sentinel>) {
+    let mut links = Links::default();
+    let mut hello_tasks = HelloTasks::default();
+    let mut interval = time::interval(PING_PERIOD);
+    let mut last_hello = Instant::now();
+    loop {
+        interval.tick().await;
+        let master = sentinel.state.lock().unwrap().master.clone();
+        check_master(&sentinel, &mut links, &master).await;
+        let replicas:Vec<Addr> = sentinel.state.lock().unwrap().replicas.keys().cloned().collect();
+        for addr in &replicas {
+            check_replica(&sentinel, &mut links, addr).await;
+        }
+        //每个节点上都订阅hello，这样主挂了也能通过副本收到其它监控者的消息
+        for addr in std::iter::once(&master).chain(replicas.iter()) {
+            if !hello_tasks.tasks.contains_key(addr) {
+                hello_tasks.tasks.insert(addr.clone(), tokio::spawn(listen_hello(sentinel.clone(), addr.clone())));
+            }
+        }
+        if last_hello.elapsed() >= HELLO_PERIOD {
+            last_hello = Instant::now();
+            send_hello(&sentinel, &mut links).await;
+        }
+        if sentinel.state.lock().unwrap().sdown {
+            try_failover(&sentinel, &mut links).await;
+        } else {
+            reconfigure_replicas(&sentinel, &mut links).await;
+        }
+    }
+}
+
+impl Drop for HelloTasks {
+    fn drop(&mut self) {
+        for task in self.tasks.values() {
+            task.abort();
+        }
+    }
+}
+
+async fn check_master(sentinel:&Sentinel,links:&mut Links,master:&Addr) {
+    let ok = links.ping(master).await.is_ok();
+    let info = if ok { links.info(master).await.ok() } else { None };
+    let mut state = sentinel.state.lock().unwrap();
+    if state.master != *master {
+        return;
+    }
+    if ok {
+        state.master_last_ok = Instant::now();
+    }
+    let mut discovered = Vec::new();
+    if let Some(info) = info {
+        for (key,value) in parse_info(&info) {
+            if !key.starts_with("slave") || key.starts_with("slave_") {
+                continue;
+            }
+            let fields = parse_fields(value);
+            if let (Some(ip),Some(Ok(port))) = (fields.get("ip"),fields.get("port").map(|port| port.parse::<u16>())) {
+                let addr = (ip.to_string(),port);
+                if !state.replicas.contains_key(&addr) {
+                    state.replicas.insert(addr.clone(), Replica::default());
+                    discovered.push(addr);
+                }
+            }
+        }
+    }
+    let sdown = state.master_last_ok.elapsed() > sentinel.config.down_after;
+    let changed = sdown != state.sdown;
+    state.sdown = sdown;
+    if !sdown {
+        state.odown = false;
+    }
+    drop(state);
+    for (host,port) in discovered {
+        sentinel.publish("+slave", format!("slave {}:{} {} {} @ {} {} {}", host, port, host, port, sentinel.config.name, master.0, master.1));
+    }
+    if changed {
+        let event = if sdown { "+sdown" } else { "-sdown" };
+        sentinel.publish(event, format!("master {} {} {}", sentinel.config.name, master.0, master.1));
+    }
+}
+
+async fn check_replica(sentinel:&Sentinel,links:&mut Links,addr:&Addr) {
+    let info = links.info(addr).await;
+    let mut state = sentinel.state.lock().unwrap();
+    let replica = match state.replicas.get_mut(addr) {
+        Some(replica) => replica,
+        None => return
+    };
+    let info = match info {
+        Ok(info) => info,
+        Err(_) => return
+    };
+    let info = parse_info(&info);
+    replica.last_ok = Some(Instant::now());
+    replica.run_id = info.get("run_id").map(|id| id.to_string()).unwrap_or_default();
+    replica.is_master = info.get("role") == Some(&"master");
+    replica.offset = info.get("slave_repl_offset").and_then(|offset| offset.parse().ok()).unwrap_or(0);
+    replica.master = match (info.get("master_host"),info.get("master_port").and_then(|port| port.parse().ok())) {
+        (Some(host),Some(port)) => Some((host.to_string(),port)),
+        _ => None
+    };
+}
+
+//把hello发到所有节点上
+async fn send_hello(sentinel:&Sentinel,links:&mut Links) {
+    let hello = sentinel.hello();
+    let addrs:Vec<Addr> = {
+        let state = sentinel.state.lock().unwrap();
+        std::iter::once(state.master.clone()).chain(state.replicas.keys().cloned()).collect()
+    };
+    for addr in addrs {
+        if let Err(err) = links.request(&addr, &command(&["PUBLISH", HELLO_CHANNEL, &hello])).await {
+            debug!(cause=%err,host=%addr.0,port=addr.1,"failed to send hello");
+        }
+    }
+}
+
+async fn listen_hello(sentinel:Arc<Sentinel>,addr:Addr) {
+    loop {
+        let res = async {
+            let client = Client::new((addr.0.as_str(),addr.1)).await?;
+            let mut subscriber = client.subscribe(vec![HELLO_CHANNEL.to_string()]).await?;
+            while let Some(msg) = subscriber.next_message().await? {
+                sentinel.process_hello(&String::from_utf8_lossy(&msg.content));
+            }
+            crate::Result::Ok(())
+        }.await;
+        if let Err(err) = res {
+            debug!(cause=%err,host=%addr.0,port=addr.1,"hello subscription error");
+        }
+        time::sleep(PING_PERIOD).await;
+    }
+}
+
+//主正常的时候，还在复制别的节点或者自认为是主的副本(比如故障转移之后恢复的原来的主)都让它复制当前的主
+async fn reconfigure_replicas(sentinel:&Sentinel,links:&mut Links) {
+    let (master,stale) = {
+        let state = sentinel.state.lock().unwrap();
+        if state.failover_start.map(|start| start.elapsed() < sentinel.config.failover_timeout).unwrap_or(false) {
+            return;
+        }
+        let stale:Vec<Addr> = state.replicas.iter()
+            .filter(|(_,replica)| replica.is_responsive() && (replica.is_master || replica.master.as_ref() != Some(&state.master)))
+            .map(|(addr,_)| addr.clone())
+            .collect();
+        (state.master.clone(),stale)
+    };
+    for addr in stale {
+        match links.replicaof(&addr, Some(&master)).await {
+            Ok(()) => sentinel.publish("+convert-to-slave", format!("slave {}:{} {} {} @ {} {} {}", addr.0, addr.1, addr.0, addr.1, sentinel.config.name, master.0, master.1)),
+            Err(err) => warn!(cause=%err,host=%addr.0,port=addr.1,"failed to reconfigure replica")
+        }
+    }
+}
+
+//主客观下线(足够多的监控者都认为主挂了)之后发起选举，当选的监控者执行故障转移
+async fn try_failover(sentinel:&Sentinel,links:&mut Links) {
+    let (master,peers,in_progress) = {
+        let state = sentinel.state.lock().unwrap();
+        let peers:Vec<Addr> = state.active_peers().map(|(_,peer)| peer.addr.clone()).collect();
+        let in_progress = state.failover_start.map(|start| start.elapsed() < sentinel.config.failover_timeout).unwrap_or(false);
+        (state.master.clone(),peers,in_progress)
+    };
+    //先问其它监控者主是不是也挂了
+    let mut down = 1;
+    for peer in &peers {
+        if let Ok((true,_,_)) = ask_peer(links, peer, &master, 0, "*").await {
+            down += 1;
+        }
+    }
+    let odown = down >= sentinel.config.quorum;
+    {
+        let mut state = sentinel.state.lock().unwrap();
+        if state.master != master {
+            return;
+        }
+        let changed = odown != state.odown;
+        state.odown = odown;
+        drop(state);
+        if changed && odown {
+            sentinel.publish("+odown", format!("master {} {} {} #quorum {}/{}", sentinel.config.name, master.0, master.1, down, sentinel.config.quorum));
+        }
+    }
+    if !odown || in_progress {
+        return;
+    }
+    //随机等一会再发起选举，减少多个监控者同时发起导致谁都选不上的情况
+    let jitter = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|d| d.subsec_nanos() % 1000).unwrap_or(0);
+    time::sleep(Duration::from_millis(jitter as u64)).await;
+    let epoch = {
+        let mut state = sentinel.state.lock().unwrap();
+        //等待期间已经给别人投了票，或者配置已经被别人更新了
+        if state.master != master || state.failover_start.map(|start| start.elapsed() < sentinel.config.failover_timeout).unwrap_or(false) {
+            return;
+        }
+        state.current_epoch += 1;
+        state.leader = Some((sentinel.run_id.clone(),state.current_epoch));
+        state.failover_start = Some(Instant::now());
+        state.current_epoch
+    };
+    sentinel.publish("+try-failover", format!("master {} {} {} epoch {}", sentinel.config.name, master.0, master.1, epoch));
+    let mut votes = 1;
+    for peer in &peers {
+        if let Ok((_,leader,leader_epoch)) = ask_peer(links, peer, &master, epoch, &sentinel.run_id).await {
+            if leader == sentinel.run_id && leader_epoch == epoch {
+                votes += 1;
+            }
+        }
+    }
+    //包括自己在内的多数，并且不少于quorum
+    let total = peers.len() + 1;
+    let needed = sentinel.config.quorum.max(total / 2 + 1);
+    if votes < needed {
+        warn!(votes,needed,epoch,"failover aborted, not elected");
+        return;
+    }
+    sentinel.publish("+elected-leader", format!("master {} {} {} epoch {}", sentinel.config.name, master.0, master.1, epoch));
+    if let Err(err) = failover(sentinel, links, &master, epoch).await {
+        warn!(cause=%err,epoch,"failover aborted");
+    }
+}
+
+//SENTINEL is-master-down-by-addr，返回(是否认为主挂了,投票给的run_id,纪元)
+async fn ask_peer(links:&mut Links,peer:&Addr,master:&Addr,epoch:u64,run_id:&str) -> crate::Result<(bool,String,u64)> {
+    let frame = command(&["SENTINEL", "is-master-down-by-addr", &master.0, &master.1.to_string(), &epoch.to_string(), run_id]);
+    match links.request(peer, &frame).await? {
+        Frame::Array(v) => match v.as_slice() {
+            [Frame::Integer(down), Frame::Bulk(leader), Frame::Integer(leader_epoch)] =>
+                Ok((*down == 1, String::from_utf8_lossy(leader).to_string(), *leader_epoch as u64)),
+            _ => Err("unexpected reply to is-master-down-by-addr".into())
+        },
+        frame => Err(format!("unexpected reply to is-master-down-by-addr: {:?}",frame).into())
+    }
+}
+
+//选出复制进度最新的副本提升为主，再让其它副本去复制它
+async fn failover(sentinel:&Sentinel,links:&mut Links,master:&Addr,epoch:u64) -> crate::Result<()> {
+    let candidate = sentinel.state.lock().unwrap().select_replica(master);
+    let promoted = candidate.ok_or("no good replica to promote")?;
+    sentinel.publish("+selected-slave", format!("slave {}:{} {} {} @ {} {} {}", promoted.0, promoted.1, promoted.0, promoted.1, sentinel.config.name, master.0, master.1));
+    links.replicaof(&promoted, None).await?;
+    //等它真的变成主
+    let deadline = Instant::now() + sentinel.config.failover_timeout;
+    loop {
+        if let Ok(info) = links.info(&promoted).await {
+            if parse_info(&info).get("role") == Some(&"master") {
+                break;
+            }
+        }
+        if Instant::now() > deadline {
+            return Err("timeout waiting for the promoted replica".into());
+        }
+        time::sleep(Duration::from_millis(100)).await;
+    }
+    let others:Vec<Addr> = {
+        let mut state = sentinel.state.lock().unwrap();
+        if state.master != *master {
+            return Err("configuration changed during failover".into());
+        }
+        state.config_epoch = epoch;
+        state.switch_master(promoted.clone());
+        state.replicas.keys().filter(|addr| *addr != master).cloned().collect()
+    };
+    sentinel.publish("+switch-master", format!("{} {} {} {} {}", sentinel.config.name, master.0, master.1, promoted.0, promoted.1));
+    //尽快让其它监控者知道新的配置
+    send_hello(sentinel, links).await;
+    for addr in others {
+        if let Err(err) = links.replicaof(&addr, Some(&promoted)).await {
+            warn!(cause=%err,host=%addr.0,port=addr.1,"failed to reconfigure replica, will retry later");
+        }
+    }
+    Ok(())
+}
+
+//INFO的内容按行解析成key:value
+fn parse_info(info:&str) -> HashMap<&str,&str> {
+    info.lines().filter_map(|line| line.split_once(':')).collect()
+}
+
+//INFO里slaveN的值: ip=..,port=..,...
+fn parse_fields(value:&str) -> HashMap<&str,&str> {
+    value.split(',').filter_map(|field| field.split_once('=')).collect()
+}
+
+//SENTINEL MASTERS/REPLICAS返回的扁平的name value数组
+fn fields(v:Vec<(&str,String)>) -> Frame {
+    Frame::Array(v.into_iter().flat_map(|(name,value)| [Frame::Bulk(Bytes::from(name.to_string())), Frame::Bulk(value.into())]).collect())
+}
+
+fn command(args:&[&str]) -> Frame {
+    Frame::Array(args.iter().map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_bytes()))).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(port:u16) -> Addr {
+        ("127.0.0.1".to_string(),port)
+    }
+
+    fn sentinel() -> Sentinel {
+        Sentinel::new(SentinelConfig::default(), addr(26379), "me".to_string())
+    }
+
+    #[tokio::test]
+    async fn vote_once_per_epoch() {
+        let sentinel = sentinel();
+        let master = sentinel.state.lock().unwrap().master.clone();
+        //只问主是否挂了，不投票
+        assert_eq!(sentinel.vote(master.clone(), 0, "*"), (false,"*".to_string(),0));
+        sentinel.state.lock().unwrap().sdown = true;
+        assert_eq!(sentinel.vote(addr(1), 0, "*"), (false,"*".to_string(),0));
+
+        assert_eq!(sentinel.vote(master.clone(), 1, "a"), (true,"a".to_string(),1));
+        assert!(sentinel.state.lock().unwrap().failover_start.is_some());
+        //同一个纪元不会改投
+        assert_eq!(sentinel.vote(master.clone(), 1, "b"), (true,"a".to_string(),1));
+        //更高的纪元可以重新投票，旧纪元的请求不会覆盖
+        assert_eq!(sentinel.vote(master.clone(), 3, "b"), (true,"b".to_string(),3));
+        assert_eq!(sentinel.vote(master.clone(), 2, "c"), (true,"b".to_string(),3));
+        assert_eq!(sentinel.state.lock().unwrap().current_epoch, 3);
+    }
+
+    #[tokio::test]
+    async fn vote_requires_current_epoch() {
+        let sentinel = sentinel();
+        let master = sentinel.state.lock().unwrap().master.clone();
+        //自己发起过更高纪元的选举，低纪元的请求拿不到票
+        sentinel.state.lock().unwrap().current_epoch = 5;
+        assert_eq!(sentinel.vote(master.clone(), 4, "a"), (false,"*".to_string(),0));
+        assert_eq!(sentinel.vote(master.clone(), 5, "me"), (false,"me".to_string(),5));
+        //投给自己不需要等别人完成故障转移
+        assert!(sentinel.state.lock().unwrap().failover_start.is_none());
+    }
+
+    #[tokio::test]
+    async fn failover_candidates_ordered_by_offset_then_run_id() {
+        let sentinel = sentinel();
+        let mut state = sentinel.state.lock().unwrap();
+        let master = state.master.clone();
+        let replica = |offset:u64,run_id:&str,responsive:bool| Replica {
+            last_ok: responsive.then(Instant::now),
+            run_id: run_id.to_string(),
+            offset,
+            ..Replica::default()
+        };
+        assert_eq!(state.select_replica(&master), None);
+        state.replicas.insert(addr(1), replica(10, "a", true));
+        state.replicas.insert(addr(2), replica(20, "c", true));
+        //复制进度最新但连不上的不选
+        state.replicas.insert(addr(3), replica(30, "a", false));
+        assert_eq!(state.select_replica(&master), Some(addr(2)));
+        state.replicas.insert(addr(4), replica(20, "b", true));
+        assert_eq!(state.select_replica(&master), Some(addr(4)));
+        //原来的主还留在副本表里时不能选它
+        state.replicas.insert(master.clone(), replica(100, "a", true));
+        assert_eq!(state.select_replica(&master), Some(addr(4)));
+    }
+}
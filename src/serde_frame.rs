@@ -0,0 +1,670 @@
+use std::fmt;
+use bytes::Bytes;
+use serde::{de::{self, DeserializeOwned, IntoDeserializer, Visitor}, ser::{self, Serialize}};
+use crate::frame::Frame;
+
+//Rust类型和Frame之间的转换
+//map和struct按Redis的习惯展开成[k1, v1, k2, v2, ...]，和HGETALL的回复格式一样
+//数字序列化成Integer，反序列化时也接受内容是数字的Bulk，因为Redis里存的都是字符串
+
+pub fn to_frame<T: Serialize + ?Sized>(value:&T) -> crate::Result<Frame> {
+    Ok(value.serialize(Serializer)?)
+}
+
+pub fn from_frame<T: DeserializeOwned>(frame:Frame) -> crate::Result<T> {
+    Ok(T::deserialize(Deserializer { frame })?)
+}
+
+#[derive(Debug)]
+pub struct Error(String);
+
+impl fmt::Display for Error {
+    fn fmt(&self,f:&mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg:T) -> Self {
+        Error(msg.to_string())
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg:T) -> Self {
+        Error(msg.to_string())
+    }
+}
+
+pub struct Serializer;
+
+//seq和tuple都序列化成Array
+pub struct SeqSerializer {
+    items:Vec<Frame>,
+    //枚举的tuple variant，外面再包一层[name, [...]]
+    variant:Option<&'static str>
+}
+
+//map和struct序列化成展开的Array
+pub struct MapSerializer {
+    items:Vec<Frame>,
+    variant:Option<&'static str>
+}
+
+fn bulk(s:&str) -> Frame {
+    Frame::Bulk(Bytes::copy_from_slice(s.as_bytes()))
+}
+
+fn wrap_variant(variant:Option<&'static str>,frame:Frame) -> Frame {
+    match variant {
+        Some(name) => Frame::Array(vec![bulk(name), frame]),
+        None => frame
+    }
+}
+
+impl ser::Serializer for Serializer {
+    type Ok = Frame;
+    type Error = Error;
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = SeqSerializer;
+    type SerializeTupleVariant = SeqSerializer;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = MapSerializer;
+    type SerializeStructVariant = MapSerializer;
+
+    fn serialize_bool(self,v:bool) -> Result<Frame,Error> {
+        Ok(Frame::Integer(v as i64))
+    }
+
+    fn serialize_i8(self,v:i8) -> Result<Frame,Error> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i16(self,v:i16) -> Result<Frame,Error> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i32(self,v:i32) -> Result<Frame,Error> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_i64(self,v:i64) -> Result<Frame,Error> {
+        Ok(Frame::Integer(v))
+    }
+
+    fn serialize_u8(self,v:u8) -> Result<Frame,Error> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_u16(self,v:u16) -> Result<Frame,Error> {
+        self.serialize_i64(v as i64)
+    }
+
+    fn serialize_u32(self,v:u32) -> Result<Frame,Error> {
+        self.serialize_i64(v as i64)
+    }
+
+    //超出i64范围的用字符串表示
+    fn serialize_u64(self,v:u64) -> Result<Frame,Error> {
+        match i64::try_from(v) {
+            Ok(v) => Ok(Frame::Integer(v)),
+            Err(_) => Ok(bulk(&v.to_string()))
+        }
+    }
+
+    //RESP2没有浮点数类型
+    fn serialize_f32(self,v:f32) -> Result<Frame,Error> {
+        Ok(bulk(&v.to_string()))
+    }
+
+    fn serialize_f64(self,v:f64) -> Result<Frame,Error> {
+        Ok(bulk(&v.to_string()))
+    }
+
+    fn serialize_char(self,v:char) -> Result<Frame,Error> {
+        Ok(bulk(v.encode_utf8(&mut [0; 4])))
+    }
+
+    fn serialize_str(self,v:&str) -> Result<Frame,Error> {
+        Ok(bulk(v))
+    }
+
+    fn serialize_bytes(self,v:&[u8]) -> Result<Frame,Error> {
+        Ok(Frame::Bulk(Bytes::copy_from_slice(v)))
+    }
+
+    fn serialize_none(self) -> Result<Frame,Error> {
+        Ok(Frame::Null)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self,value:&T) -> Result<Frame,Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Frame,Error> {
+        Ok(Frame::Null)
+    }
+
+    fn serialize_unit_struct(self,_name:&'static str) -> Result<Frame,Error> {
+        Ok(Frame::Null)
+    }
+
+    fn serialize_unit_variant(self,_name:&'static str,_index:u32,variant:&'static str) -> Result<Frame,Error> {
+        Ok(bulk(variant))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(self,_name:&'static str,value:&T) -> Result<Frame,Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(self,_name:&'static str,_index:u32,variant:&'static str,value:&T) -> Result<Frame,Error> {
+        Ok(wrap_variant(Some(variant), value.serialize(self)?))
+    }
+
+    fn serialize_seq(self,len:Option<usize>) -> Result<SeqSerializer,Error> {
+        Ok(SeqSerializer { items: Vec::with_capacity(len.unwrap_or(0)), variant: None })
+    }
+
+    fn serialize_tuple(self,len:usize) -> Result<SeqSerializer,Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self,_name:&'static str,len:usize) -> Result<SeqSerializer,Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(self,_name:&'static str,_index:u32,variant:&'static str,len:usize) -> Result<SeqSerializer,Error> {
+        Ok(SeqSerializer { items: Vec::with_capacity(len), variant: Some(variant) })
+    }
+
+    fn serialize_map(self,len:Option<usize>) -> Result<MapSerializer,Error> {
+        Ok(MapSerializer { items: Vec::with_capacity(len.unwrap_or(0) * 2), variant: None })
+    }
+
+    fn serialize_struct(self,_name:&'static str,len:usize) -> Result<MapSerializer,Error> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(self,_name:&'static str,_index:u32,variant:&'static str,len:usize) -> Result<MapSerializer,Error> {
+        Ok(MapSerializer { items: Vec::with_capacity(len * 2), variant: Some(variant) })
+    }
+}
+
+impl SeqSerializer {
+    fn push<T: Serialize + ?Sized>(&mut self,value:&T) -> Result<(),Error> {
+        self.items.push(value.serialize(Serializer)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Frame,Error> {
+        Ok(wrap_variant(self.variant, Frame::Array(self.items)))
+    }
+}
+
+impl ser::SerializeSeq for SeqSerializer {
+    type Ok = Frame;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self,value:&T) -> Result<(),Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Frame,Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for SeqSerializer {
+    type Ok = Frame;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self,value:&T) -> Result<(),Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Frame,Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer {
+    type Ok = Frame;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self,value:&T) -> Result<(),Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Frame,Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for SeqSerializer {
+    type Ok = Frame;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self,value:&T) -> Result<(),Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Frame,Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeMap for MapSerializer {
+    type Ok = Frame;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self,key:&T) -> Result<(),Error> {
+        self.items.push(key.serialize(Serializer)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self,value:&T) -> Result<(),Error> {
+        self.items.push(value.serialize(Serializer)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Frame,Error> {
+        Ok(wrap_variant(self.variant, Frame::Array(self.items)))
+    }
+}
+
+impl ser::SerializeStruct for MapSerializer {
+    type Ok = Frame;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self,key:&'static str,value:&T) -> Result<(),Error> {
+        self.items.push(bulk(key));
+        self.items.push(value.serialize(Serializer)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Frame,Error> {
+        Ok(wrap_variant(self.variant, Frame::Array(self.items)))
+    }
+}
+
+impl ser::SerializeStructVariant for MapSerializer {
+    type Ok = Frame;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self,key:&'static str,value:&T) -> Result<(),Error> {
+        ser::SerializeStruct::serialize_field(self, key, value)
+    }
+
+    fn end(self) -> Result<Frame,Error> {
+        ser::SerializeStruct::end(self)
+    }
+}
+
+pub struct Deserializer {
+    frame:Frame
+}
+
+impl Deserializer {
+    pub fn new(frame:Frame) -> Self {
+        Deserializer { frame }
+    }
+
+    //字符串形式的回复，数字类型从这里解析
+    fn text(self) -> Result<String,Error> {
+        match self.frame {
+            Frame::Bulk(data) => String::from_utf8(data.to_vec()).map_err(de::Error::custom),
+            Frame::Simple(s) => Ok(s),
+            Frame::Integer(n) => Ok(n.to_string()),
+            frame => Err(unexpected(frame, "a string"))
+        }
+    }
+
+    fn parse<T: std::str::FromStr>(self,expected:&str) -> Result<T,Error> {
+        let text = self.text()?;
+        text.parse().map_err(|_| Error(format!("invalid {}: {}",expected,text)))
+    }
+}
+
+fn unexpected(frame:Frame,expected:&str) -> Error {
+    match frame {
+        Frame::Error(msg) => Error(msg),
+        frame => Error(format!("expected {}, found {}",expected,frame))
+    }
+}
+
+//数字先看Integer，不是的话按字符串解析
+macro_rules! deserialize_number {
+    ($($method:ident => $visit:ident : $t:ty),*) => {
+        $(fn $method<V: Visitor<'de>>(self,visitor:V) -> Result<V::Value,Error> {
+            match self.frame {
+                Frame::Integer(n) => visitor.$visit(<$t>::try_from(n).map_err(de::Error::custom)?),
+                _ => visitor.$visit(self.parse::<$t>(stringify!($t))?)
+            }
+        })*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for Deserializer {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self,visitor:V) -> Result<V::Value,Error> {
+        match self.frame {
+            Frame::Bulk(data) => match std::str::from_utf8(&data) {
+                Ok(s) => visitor.visit_str(s),
+                Err(_) => visitor.visit_bytes(&data)
+            },
+            Frame::Simple(s) => visitor.visit_string(s),
+            Frame::Integer(n) => visitor.visit_i64(n),
+            Frame::Null | Frame::NullArray => visitor.visit_none(),
+            Frame::Array(items) => visitor.visit_seq(SeqAccess { items: items.into_iter() }),
+            Frame::Error(msg) => Err(Error(msg))
+        }
+    }
+
+    deserialize_number!(
+        deserialize_i8 => visit_i8: i8,
+        deserialize_i16 => visit_i16: i16,
+        deserialize_i32 => visit_i32: i32,
+        deserialize_i64 => visit_i64: i64,
+        deserialize_u8 => visit_u8: u8,
+        deserialize_u16 => visit_u16: u16,
+        deserialize_u32 => visit_u32: u32,
+        deserialize_u64 => visit_u64: u64
+    );
+
+    fn deserialize_f32<V: Visitor<'de>>(self,visitor:V) -> Result<V::Value,Error> {
+        visitor.visit_f32(self.parse("f32")?)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self,visitor:V) -> Result<V::Value,Error> {
+        visitor.visit_f64(self.parse("f64")?)
+    }
+
+    //Integer非0为true，字符串接受1/0/true/false
+    fn deserialize_bool<V: Visitor<'de>>(self,visitor:V) -> Result<V::Value,Error> {
+        match self.frame {
+            Frame::Integer(n) => visitor.visit_bool(n != 0),
+            _ => match self.text()?.as_str() {
+                "1" | "true" => visitor.visit_bool(true),
+                "0" | "false" => visitor.visit_bool(false),
+                text => Err(Error(format!("invalid bool: {}",text)))
+            }
+        }
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self,visitor:V) -> Result<V::Value,Error> {
+        visitor.visit_char(self.parse("char")?)
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self,visitor:V) -> Result<V::Value,Error> {
+        visitor.visit_string(self.text()?)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self,visitor:V) -> Result<V::Value,Error> {
+        visitor.visit_string(self.text()?)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self,visitor:V) -> Result<V::Value,Error> {
+        match self.frame {
+            Frame::Bulk(data) => visitor.visit_byte_buf(data.to_vec()),
+            Frame::Simple(s) => visitor.visit_byte_buf(s.into_bytes()),
+            frame => Err(unexpected(frame, "bytes"))
+        }
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self,visitor:V) -> Result<V::Value,Error> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self,visitor:V) -> Result<V::Value,Error> {
+        match self.frame {
+            Frame::Null | Frame::NullArray => visitor.visit_none(),
+            Frame::Error(msg) => Err(Error(msg)),
+            frame => visitor.visit_some(Deserializer { frame })
+        }
+    }
+
+    //OK之类的回复也当成unit
+    fn deserialize_unit<V: Visitor<'de>>(self,visitor:V) -> Result<V::Value,Error> {
+        match self.frame {
+            Frame::Error(msg) => Err(Error(msg)),
+            _ => visitor.visit_unit()
+        }
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(self,_name:&'static str,visitor:V) -> Result<V::Value,Error> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self,_name:&'static str,visitor:V) -> Result<V::Value,Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    //Null当成空数组
+    fn deserialize_seq<V: Visitor<'de>>(self,visitor:V) -> Result<V::Value,Error> {
+        match self.frame {
+            Frame::Array(items) => visitor.visit_seq(SeqAccess { items: items.into_iter() }),
+            Frame::Null | Frame::NullArray => visitor.visit_seq(SeqAccess { items: Vec::new().into_iter() }),
+            frame => Err(unexpected(frame, "an array"))
+        }
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self,_len:usize,visitor:V) -> Result<V::Value,Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(self,_name:&'static str,_len:usize,visitor:V) -> Result<V::Value,Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    //[k1, v1, k2, v2, ...]
+    fn deserialize_map<V: Visitor<'de>>(self,visitor:V) -> Result<V::Value,Error> {
+        let items = match self.frame {
+            Frame::Array(items) => items,
+            Frame::Null | Frame::NullArray => Vec::new(),
+            frame => return Err(unexpected(frame, "a map"))
+        };
+        if items.len() % 2 != 0 {
+            return Err(Error("odd number of elements in map reply".to_string()));
+        }
+        visitor.visit_map(MapAccess { items: items.into_iter(), value: None })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(self,_name:&'static str,_fields:&'static [&'static str],visitor:V) -> Result<V::Value,Error> {
+        self.deserialize_map(visitor)
+    }
+
+    //unit variant是名字，其它是[name, value]
+    fn deserialize_enum<V: Visitor<'de>>(self,_name:&'static str,_variants:&'static [&'static str],visitor:V) -> Result<V::Value,Error> {
+        match self.frame {
+            Frame::Array(items) if items.len() == 2 => {
+                let mut items = items.into_iter();
+                let variant = Deserializer { frame: items.next().unwrap() }.text()?;
+                visitor.visit_enum(EnumAccess { variant, value: items.next() })
+            }
+            frame => {
+                let variant = Deserializer { frame }.text()?;
+                visitor.visit_enum(EnumAccess { variant, value: None })
+            }
+        }
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self,visitor:V) -> Result<V::Value,Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self,visitor:V) -> Result<V::Value,Error> {
+        visitor.visit_unit()
+    }
+}
+
+impl<'de> IntoDeserializer<'de, Error> for Frame {
+    type Deserializer = Deserializer;
+
+    fn into_deserializer(self) -> Deserializer {
+        Deserializer { frame: self }
+    }
+}
+
+struct SeqAccess {
+    items:std::vec::IntoIter<Frame>
+}
+
+impl<'de> de::SeqAccess<'de> for SeqAccess {
+    type Error = Error;
+
+    fn next_element_seed<T: de::DeserializeSeed<'de>>(&mut self,seed:T) -> Result<Option<T::Value>,Error> {
+        match self.items.next() {
+            Some(frame) => seed.deserialize(Deserializer { frame }).map(Some),
+            None => Ok(None)
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.items.len())
+    }
+}
+
+struct MapAccess {
+    items:std::vec::IntoIter<Frame>,
+    value:Option<Frame>
+}
+
+impl<'de> de::MapAccess<'de> for MapAccess {
+    type Error = Error;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(&mut self,seed:K) -> Result<Option<K::Value>,Error> {
+        match (self.items.next(),self.items.next()) {
+            (Some(key),Some(value)) => {
+                self.value = Some(value);
+                seed.deserialize(Deserializer { frame: key }).map(Some)
+            }
+            _ => Ok(None)
+        }
+    }
+
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(&mut self,seed:V) -> Result<V::Value,Error> {
+        match self.value.take() {
+            Some(frame) => seed.deserialize(Deserializer { frame }),
+            None => Err(Error("value is missing".to_string()))
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.items.len() / 2)
+    }
+}
+
+struct EnumAccess {
+    variant:String,
+    value:Option<Frame>
+}
+
+impl<'de> de::EnumAccess<'de> for EnumAccess {
+    type Error = Error;
+    type Variant = VariantAccess;
+
+    fn variant_seed<V: de::DeserializeSeed<'de>>(self,seed:V) -> Result<(V::Value,VariantAccess),Error> {
+        let variant = seed.deserialize(self.variant.into_deserializer())?;
+        Ok((variant, VariantAccess { value: self.value }))
+    }
+}
+
+struct VariantAccess {
+    value:Option<Frame>
+}
+
+impl VariantAccess {
+    fn value(self) -> Result<Deserializer,Error> {
+        match self.value {
+            Some(frame) => Ok(Deserializer { frame }),
+            None => Err(Error("expected [variant, value]".to_string()))
+        }
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for VariantAccess {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(),Error> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(self,seed:T) -> Result<T::Value,Error> {
+        seed.deserialize(self.value()?)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self,_len:usize,visitor:V) -> Result<V::Value,Error> {
+        de::Deserializer::deserialize_seq(self.value()?, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(self,_fields:&'static [&'static str],visitor:V) -> Result<V::Value,Error> {
+        de::Deserializer::deserialize_map(self.value()?, visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug,PartialEq,Serialize,Deserialize)]
+    struct User {
+        name:String,
+        age:u32,
+        score:f64,
+        tags:Vec<String>,
+        nick:Option<String>,
+        role:Role
+    }
+
+    #[derive(Debug,PartialEq,Serialize,Deserialize)]
+    enum Role {
+        Admin,
+        Guest { until:u64 }
+    }
+
+    fn bulk(s:&str) -> Frame {
+        Frame::Bulk(Bytes::copy_from_slice(s.as_bytes()))
+    }
+
+    #[test]
+    fn struct_round_trip() {
+        let user = User {
+            name: "a".to_string(),
+            age: 3,
+            score: 1.5,
+            tags: vec!["x".to_string(),"y".to_string()],
+            nick: None,
+            role: Role::Guest { until: u64::MAX }
+        };
+        let frame = to_frame(&user).unwrap();
+        assert!(matches!(&frame, Frame::Array(items) if items.len() == 12));
+        assert_eq!(from_frame::<User>(frame).unwrap(), user);
+        let admin = to_frame(&Role::Admin).unwrap();
+        assert_eq!(from_frame::<Role>(admin).unwrap(), Role::Admin);
+    }
+
+    #[test]
+    fn numbers_from_bulk_strings() {
+        //HGETALL的回复里数字都是字符串
+        let frame = Frame::Array(vec![bulk("a"),bulk("1"),bulk("b"),bulk("-2")]);
+        let map:BTreeMap<String,i64> = from_frame(frame).unwrap();
+        assert_eq!(map, BTreeMap::from([("a".to_string(),1),("b".to_string(),-2)]));
+        assert_eq!(from_frame::<f64>(bulk("2.5")).unwrap(), 2.5);
+        assert_eq!(from_frame::<u64>(bulk("18446744073709551615")).unwrap(), u64::MAX);
+        assert!(from_frame::<u32>(bulk("x")).is_err());
+    }
+
+    #[test]
+    fn null_and_errors() {
+        assert_eq!(from_frame::<Option<String>>(Frame::Null).unwrap(), None);
+        assert_eq!(from_frame::<Option<String>>(Frame::NullArray).unwrap(), None);
+        assert!(from_frame::<String>(Frame::Error("ERR x".to_string())).is_err());
+        assert!(from_frame::<User>(Frame::Array(vec![bulk("name")])).is_err());
+    }
+}
@@ -1,11 +1,14 @@
-use std::{sync::Arc, future::Future};
-use bytes::Bytes;
-use tracing::{debug, error, info, instrument};
-use tokio::{net::{TcpListener, TcpStream}, sync::{Semaphore, broadcast, mpsc}};
-use crate::db::{Db,DbDropGuard};
+use std::{sync::{Arc, atomic::Ordering}, future::Future, time::Duration};
+use tracing::{error, info, instrument};
+use tokio::{net::TcpListener, sync::{Semaphore, broadcast, mpsc}};
+use crate::config::Config;
+use crate::db::{Db,DbDropGuard,WatchVersion};
+use crate::frame::Frame;
 use crate::shutdown::Shutdown;
 use crate::Connection;
 use crate::Command;
+use crate::replication;
+use crate::cluster;
 const MAX_CONNECTIONS:usize =250;
 
 #[derive(Debug)]
@@ -20,18 +23,68 @@ struct Listener {
 #[derive(Debug)]
 struct Handler {
     db :Db,
-    limit_connections: Arc<Semaphore>,
     connection:Connection,
     shutdown:Shutdown,
-    //_shutdown_complete:mpsc::Sender<()>
+    //MULTI之后进入事务状态，EXEC/DISCARD之后退出
+    transaction:Option<Transaction>,
+    //WATCH时记录下的key和版本号，EXEC时版本号变了就放弃事务
+    watched:Vec<(String,WatchVersion)>,
+    //副本握手时通过REPLCONF listening-port告知的端口
+    replica_port:Option<u16>,
+    //执行完上一条命令时的复制offset，WAIT等副本追上它
+    write_offset:u64,
+    //收到ASKING之后的下一条命令可以访问正在迁入的slot
+    asking:bool,
+    _shutdown_complete:mpsc::Sender<()>
+}
+//排队中的命令和排队时的asking标记，dirty表示排队时有命令解析失败，EXEC时直接放弃整个事务
+#[derive(Debug,Default)]
+struct Transaction {
+    queued:Vec<(Command,bool)>,
+    dirty:bool
 }
-pub async fn run(listener:TcpListener,shutdown:impl Future) {
+pub async fn run(listener:TcpListener,config:Config,shutdown:impl Future) {
     let (notify_shutdown,_) = broadcast::channel(1);
     let (shutdown_complete_tx,shutdown_complete_rx) = mpsc::channel(1);
+    let db_holder = DbDropGuard::new(&config);
+    //先把快照或aof加载完再开始接受连接
+    match db_holder.db().load() {
+        Ok(count) => info!(count,"data loaded from disk"),
+        Err(err) => {
+            error!(cause=%err,"failed to load data from disk");
+            return;
+        }
+    }
+    if let Some(path) = config.load_rdb.as_deref() {
+        match db_holder.db().import_rdb(path) {
+            Ok(count) => info!(keys=count,path=?path,"rdb imported"),
+            Err(err) => {
+                error!(cause=%err,path=?path,"failed to import rdb");
+                return;
+            }
+        }
+    }
+    let db = db_holder.db();
+    match listener.local_addr() {
+        Ok(addr) => {
+            db.replication().set_port(addr.port());
+            if let Some(cluster) = db.cluster() {
+                if let Err(err) = cluster.load(&addr.ip().to_string(), addr.port()) {
+                    error!(cause=%err,"failed to load cluster config");
+                    return;
+                }
+                cluster::spawn_gossip_task(db.clone());
+            }
+        }
+        Err(err) => error!(cause=%err,"failed to get local address")
+    }
+    if let Some((host,port)) = config.replicaof.clone() {
+        replication::start_replication(&db, host, port);
+    }
     let mut server = Listener {
         listener,
-        db_holder:DbDropGuard::new(),
-        limit_connections:Arc::new(Semaphore::new(250)),
+        db_holder,
+        limit_connections:Arc::new(Semaphore::new(MAX_CONNECTIONS)),
         notify_shutdown,
         shutdown_complete_rx,
         shutdown_complete_tx
@@ -46,6 +99,11 @@ pub async fn run(listener:TcpListener,shutdown:impl Future) {
             info!("shutting down");
         }
     }
+    let Listener { mut shutdown_complete_rx, shutdown_complete_tx, notify_shutdown, .. } = server;
+    drop(notify_shutdown);
+    drop(shutdown_complete_tx);
+    //等待所有连接处理完毕
+    let _ = shutdown_complete_rx.recv().await;
 }
 
 impl Listener {
@@ -55,20 +113,29 @@ impl Listener {
             let permit = self.limit_connections
                                             .clone().acquire_owned().await.unwrap();
             let (stream,_) = self.listener.accept().await?;
+            let db = self.db_holder.db();
+            db.counters().total_connections.fetch_add(1, Ordering::Relaxed);
+            db.counters().connected_clients.fetch_add(1, Ordering::Relaxed);
             let mut handler = Handler {
-                db: self.db_holder.db(),
-                limit_connections : self.limit_connections.clone(),
+                db,
                 connection: Connection::new(stream),
-                shutdown: Shutdown::new(self.notify_shutdown.subscribe())
-            };        
+                shutdown: Shutdown::new(self.notify_shutdown.subscribe()),
+                transaction: None,
+                watched: Vec::new(),
+                replica_port: None,
+                write_offset: 0,
+                asking: false,
+                _shutdown_complete: self.shutdown_complete_tx.clone()
+            };
             tokio::spawn(async move {
                 if let Err(err)=handler.run().await {
                     error!(cause =?err,"connection error")
                 }
+                handler.db.counters().connected_clients.fetch_sub(1, Ordering::Relaxed);
                 drop(permit);
             });
         }
-    } 
+    }
 }
 
 impl Handler {
@@ -85,9 +152,164 @@ impl Handler {
                 None => return Ok(()),
                 Some(frame) => frame
             };
-            let command = Command::from_frame(frame)?;
-            command.apply(&self.db,&mut self.connection,&mut self.shutdown).await?; 
+            self.db.counters().total_commands.fetch_add(1, Ordering::Relaxed);
+            let command = match Command::from_frame(frame) {
+                Ok(command) => command,
+                Err(err) => {
+                    if let Some(transaction) = self.transaction.as_mut() {
+                        transaction.dirty = true;
+                    }
+                    let response = Frame::Error(format!("ERR {}",err));
+                    self.connection.write_frame(&response).await?;
+                    continue;
+                }
+            };
+            if let Command::Psync(psync) = command {
+                //这个连接之后只用来给副本发复制流
+                return replication::serve_replica(&self.db, &mut self.connection, &mut self.shutdown, psync, self.replica_port).await;
+            }
+            self.apply(command).await?;
+            //不区分是不是本连接写入的，多算的部分只会让WAIT等得更久一点
+            self.write_offset = self.db.replication().offset();
         }
         Ok(())
     }
-}
\ No newline at end of file
+
+    async fn apply(&mut self,command:Command) -> crate::Result<()> {
+        let asking = std::mem::take(&mut self.asking);
+        if self.transaction.is_none() && self.db.cluster().is_some() && is_routed(&command) {
+            let response = match command {
+                //脚本可能执行很久，和EVAL一样放到blocking线程上
+                Command::Eval(_) | Command::EvalSha(_) | Command::Fcall(_) => {
+                    let db = self.db.clone();
+                    tokio::task::spawn_blocking(move || execute_routed(&db, command, asking)).await?
+                }
+                command => execute_routed(&self.db, command, asking)
+            };
+            return self.connection.write_frame(&response).await;
+        }
+        if let Some(response) = self.redirect(&command, asking) {
+            if let Some(transaction) = self.transaction.as_mut() {
+                transaction.dirty = true;
+            }
+            return self.connection.write_frame(&response).await;
+        }
+        let response = match command {
+            Command::Multi(_) => {
+                if self.transaction.is_some() {
+                    Frame::Error("ERR MULTI calls can not be nested".to_string())
+                } else {
+                    self.transaction = Some(Transaction::default());
+                    Frame::Simple("OK".to_string())
+                }
+            }
+            Command::Exec(_) => match self.transaction.take() {
+                None => Frame::Error("ERR EXEC without MULTI".to_string()),
+                Some(transaction) => {
+                    let watched = std::mem::take(&mut self.watched);
+                    if transaction.dirty {
+                        Frame::Error("EXECABORT Transaction discarded because of previous errors.".to_string())
+                    } else {
+                        let mut db = self.db.lock();
+                        //排队之后slot可能已经迁走，持锁再检查一遍，有一条不归本节点处理就放弃整个事务
+                        let redirect = self.db.cluster().and_then(|cluster| {
+                            transaction.queued.iter().find_map(|(command,asking)| {
+                                cluster.redirect(&command.keys(), *asking, |key| db.version(key).is_some())
+                            })
+                        });
+                        if let Some(redirect) = redirect {
+                            redirect
+                        } else if watched.iter().any(|(key,version)| db.watch_version(key) != *version) {
+                            //watch的key被修改过，返回null array
+                            Frame::NullArray
+                        } else {
+                            let responses = transaction.queued.into_iter()
+                                .map(|(command,_)| command.execute(&mut db))
+                                .collect();
+                            Frame::Array(responses)
+                        }
+                    }
+                }
+            },
+            Command::Discard(_) => match self.transaction.take() {
+                None => Frame::Error("ERR DISCARD without MULTI".to_string()),
+                Some(_) => {
+                    self.watched.clear();
+                    Frame::Simple("OK".to_string())
+                }
+            },
+            Command::Watch(cmd) => {
+                if self.transaction.is_some() {
+                    Frame::Error("ERR WATCH inside MULTI is not allowed".to_string())
+                } else {
+                    let db = self.db.lock();
+                    for key in cmd.keys {
+                        if !self.watched.iter().any(|(watched,_)| *watched == key) {
+                            let version = db.watch_version(&key);
+                            self.watched.push((key,version));
+                        }
+                    }
+                    Frame::Simple("OK".to_string())
+                }
+            }
+            Command::Unwatch(_) if self.transaction.is_none() => {
+                self.watched.clear();
+                Frame::Simple("OK".to_string())
+            }
+            Command::ReplConf(cmd) if self.transaction.is_none() => {
+                if let Some(port) = cmd.listening_port {
+                    self.replica_port = Some(port);
+                }
+                Frame::Simple("OK".to_string())
+            }
+            Command::Wait(cmd) if self.transaction.is_none() => {
+                let timeout = Some(Duration::from_millis(cmd.timeout)).filter(|timeout| !timeout.is_zero());
+                replication::wait(&self.db, &mut self.shutdown, self.write_offset, cmd.numreplicas, timeout).await
+            }
+            Command::Asking(_) if self.transaction.is_none() => {
+                self.asking = true;
+                Frame::Simple("OK".to_string())
+            }
+            command if command.is_write() && self.db.replication().is_read_only() => {
+                if let Some(transaction) = self.transaction.as_mut() {
+                    transaction.dirty = true;
+                }
+                Frame::Error("READONLY You can't write against a read only replica.".to_string())
+            }
+            command => {
+                if let Some(transaction) = self.transaction.as_mut() {
+                    transaction.queued.push((command,asking));
+                    Frame::Simple("QUEUED".to_string())
+                } else {
+                    return command.apply(&self.db,&mut self.connection,&mut self.shutdown).await;
+                }
+            }
+        };
+        self.connection.write_frame(&response).await
+    }
+    //集群模式下key不归本节点处理时返回MOVED/ASK
+    fn redirect(&self,command:&Command,asking:bool) -> Option<Frame> {
+        let cluster = self.db.cluster()?;
+        cluster.redirect(&command.keys(), asking, |key| self.db.lock().version(key).is_some())
+    }
+}
+
+//带key的普通命令，MIGRATE要连目标节点，WATCH由Handler自己处理
+fn is_routed(command:&Command) -> bool {
+    !matches!(command, Command::Migrate(_) | Command::Watch(_)) && !command.keys().is_empty()
+}
+
+//slot检查和执行在同一把锁下，中间插进来的MIGRATE不会让写入在源节点上重新建出已经迁走的key
+fn execute_routed(db:&Db,command:Command,asking:bool) -> Frame {
+    let mut guard = db.lock();
+    let redirect = db.cluster().and_then(|cluster| {
+        cluster.redirect(&command.keys(), asking, |key| guard.version(key).is_some())
+    });
+    if let Some(redirect) = redirect {
+        return redirect;
+    }
+    if command.is_write() && db.replication().is_read_only() {
+        return Frame::Error("READONLY You can't write against a read only replica.".to_string());
+    }
+    command.execute(&mut guard)
+}
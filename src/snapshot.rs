@@ -0,0 +1,349 @@
+use std::{fs, io::{self, Write}, path::PathBuf, sync::{Arc, atomic::{AtomicBool, AtomicU64, Ordering}}, time::{SystemTime, UNIX_EPOCH}};
+use bytes::{Buf, BufMut, Bytes};
+use tracing::{error, info};
+use crate::db::Value;
+
+//快照文件格式:
+//  "MYREDIS" + 版本号(u16)
+//  若干条记录，每条以一个字节的opcode开头
+//    OP_ENTRY:     key长度(u32) key value长度(u32) value 过期时间(u64，unix毫秒，0表示不过期)
+//    OP_LIST/OP_HASH/OP_SET/OP_ZSET: key长度(u32) key 过期时间(u64) 元素个数(u32) 元素
+//      每个元素是长度(u32)+内容，hash的元素是field和value两段，zset是member和score(f64的bit)
+//    OP_FUNCTIONS: 长度(u32) Scripts::function_dump的内容
+//    OP_EOF
+//  最后8个字节是前面所有内容的crc64(和redis一样用jones多项式)，小端
+//所有整数都是大端
+const MAGIC:&[u8] = b"MYREDIS";
+const VERSION:u16 = 1;
+const OP_ENTRY:u8 = 0x01;
+const OP_FUNCTIONS:u8 = 0x02;
+const OP_LIST:u8 = 0x03;
+const OP_HASH:u8 = 0x04;
+const OP_SET:u8 = 0x05;
+const OP_ZSET:u8 = 0x06;
+const OP_EOF:u8 = 0xff;
+
+//某一时刻的数据副本，生成之后就不再依赖db的锁
+#[derive(Debug,Default)]
+pub(crate) struct Snapshot {
+    //key,value,过期时间(unix毫秒)
+    pub(crate) entries:Vec<(String,Value,Option<u64>)>,
+    pub(crate) functions:Bytes
+}
+
+//快照文件的位置和保存状态
+#[derive(Debug)]
+pub(crate) struct Persistence {
+    path:PathBuf,
+    //上次成功保存的时间，unix秒
+    last_save:AtomicU64,
+    //SAVE和BGSAVE都要先占住这个标记，同一时间只有一个在写文件
+    bgsave_in_progress:AtomicBool
+}
+
+impl Snapshot {
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.put_slice(MAGIC);
+        buf.put_u16(VERSION);
+        for (key,value,expire_at) in &self.entries {
+            let expire_at = expire_at.unwrap_or(0);
+            match value {
+                Value::String(data) => {
+                    buf.put_u8(OP_ENTRY);
+                    put_chunk(&mut buf, key.as_bytes());
+                    put_chunk(&mut buf, data);
+                    buf.put_u64(expire_at);
+                }
+                Value::List(list) => {
+                    put_header(&mut buf, OP_LIST, key, expire_at, list.len());
+                    list.iter().for_each(|item| put_chunk(&mut buf, item));
+                }
+                Value::Hash(hash) => {
+                    put_header(&mut buf, OP_HASH, key, expire_at, hash.len());
+                    for (field,value) in hash.iter() {
+                        put_chunk(&mut buf, field);
+                        put_chunk(&mut buf, value);
+                    }
+                }
+                Value::Set(set) => {
+                    put_header(&mut buf, OP_SET, key, expire_at, set.len());
+                    set.iter().for_each(|member| put_chunk(&mut buf, member));
+                }
+                Value::ZSet(zset) => {
+                    put_header(&mut buf, OP_ZSET, key, expire_at, zset.len());
+                    for (score,member) in zset.iter() {
+                        put_chunk(&mut buf, member);
+                        buf.put_u64(score.to_bits());
+                    }
+                }
+            }
+        }
+        if !self.functions.is_empty() {
+            buf.put_u8(OP_FUNCTIONS);
+            put_chunk(&mut buf, &self.functions);
+        }
+        buf.put_u8(OP_EOF);
+        let crc = crc64(0, &buf);
+        buf.put_u64_le(crc);
+        buf
+    }
+
+    pub(crate) fn decode(data:&[u8]) -> crate::Result<Snapshot> {
+        if data.len() < MAGIC.len() + 2 + 1 + 8 || &data[..MAGIC.len()] != MAGIC {
+            return Err("invalid snapshot: bad header".into());
+        }
+        let (body,mut trailer) = data.split_at(data.len() - 8);
+        if crc64(0, body) != trailer.get_u64_le() {
+            return Err("invalid snapshot: checksum mismatch".into());
+        }
+        let mut buf = &body[MAGIC.len()..];
+        let version = buf.get_u16();
+        if version != VERSION {
+            return Err(format!("invalid snapshot: unsupported version {}",version).into());
+        }
+        let mut snapshot = Snapshot::default();
+        loop {
+            if !buf.has_remaining() {
+                return Err("invalid snapshot: unexpected end of file".into());
+            }
+            match buf.get_u8() {
+                OP_ENTRY => {
+                    let key = String::from_utf8(read_chunk(&mut buf)?.to_vec())?;
+                    let value = Bytes::copy_from_slice(read_chunk(&mut buf)?);
+                    let expire_at = read_expire_at(&mut buf)?;
+                    snapshot.entries.push((key,Value::String(value),expire_at));
+                }
+                op @ (OP_LIST | OP_HASH | OP_SET | OP_ZSET) => {
+                    let key = String::from_utf8(read_chunk(&mut buf)?.to_vec())?;
+                    let expire_at = read_expire_at(&mut buf)?;
+                    if buf.remaining() < 4 {
+                        return Err("invalid snapshot: unexpected end of file".into());
+                    }
+                    let len = buf.get_u32() as usize;
+                    let value = match op {
+                        OP_LIST => Value::List(Arc::new((0..len).map(|_| read_bytes(&mut buf)).collect::<crate::Result<_>>()?)),
+                        OP_HASH => Value::Hash(Arc::new((0..len).map(|_| Ok((read_bytes(&mut buf)?,read_bytes(&mut buf)?))).collect::<crate::Result<_>>()?)),
+                        OP_SET => Value::Set(Arc::new((0..len).map(|_| read_bytes(&mut buf)).collect::<crate::Result<_>>()?)),
+                        _ => {
+                            //每个成员至少要8字节的score，len不可信
+                            let mut zset = Vec::with_capacity(len.min(buf.remaining() / 8));
+                            for _ in 0..len {
+                                let member = read_bytes(&mut buf)?;
+                                if buf.remaining() < 8 {
+                                    return Err("invalid snapshot: unexpected end of file".into());
+                                }
+                                zset.push((f64::from_bits(buf.get_u64()),member));
+                            }
+                            Value::ZSet(Arc::new(zset))
+                        }
+                    };
+                    snapshot.entries.push((key,value,expire_at));
+                }
+                OP_FUNCTIONS => {
+                    snapshot.functions = Bytes::copy_from_slice(read_chunk(&mut buf)?);
+                }
+                OP_EOF => break,
+                op => return Err(format!("invalid snapshot: unknown opcode {:#x}",op).into())
+            }
+        }
+        Ok(snapshot)
+    }
+}
+
+fn put_chunk(buf:&mut Vec<u8>,chunk:&[u8]) {
+    buf.put_u32(chunk.len() as u32);
+    buf.put_slice(chunk);
+}
+
+//集合类型的记录头: opcode key 过期时间 元素个数
+fn put_header(buf:&mut Vec<u8>,op:u8,key:&str,expire_at:u64,len:usize) {
+    buf.put_u8(op);
+    put_chunk(buf, key.as_bytes());
+    buf.put_u64(expire_at);
+    buf.put_u32(len as u32);
+}
+
+fn read_bytes(buf:&mut &[u8]) -> crate::Result<Bytes> {
+    read_chunk(buf).map(Bytes::copy_from_slice)
+}
+
+fn read_expire_at(buf:&mut &[u8]) -> crate::Result<Option<u64>> {
+    if buf.remaining() < 8 {
+        return Err("invalid snapshot: unexpected end of file".into());
+    }
+    Ok(Some(buf.get_u64()).filter(|&ms| ms != 0))
+}
+
+fn read_chunk<'a>(buf:&mut &'a [u8]) -> crate::Result<&'a [u8]> {
+    if buf.remaining() < 4 {
+        return Err("invalid snapshot: unexpected end of file".into());
+    }
+    let len = buf.get_u32() as usize;
+    if buf.remaining() < len {
+        return Err("invalid snapshot: unexpected end of file".into());
+    }
+    let (chunk,rest) = buf.split_at(len);
+    *buf = rest;
+    Ok(chunk)
+}
+
+impl Persistence {
+    pub(crate) fn new(path:PathBuf) -> Self {
+        Self { path, last_save: AtomicU64::new(unix_time_secs()), bgsave_in_progress: AtomicBool::new(false) }
+    }
+    pub(crate) fn last_save(&self) -> u64 {
+        self.last_save.load(Ordering::SeqCst)
+    }
+    //写完调用前占住的标记，调用前需要先start_background_save
+    pub(crate) fn save(&self,snapshot:&Snapshot) -> crate::Result<()> {
+        let res = self.write(snapshot);
+        self.bgsave_in_progress.store(false, Ordering::SeqCst);
+        res
+    }
+    //先写临时文件，fsync之后再rename，保证任何时候磁盘上的快照都是完整的
+    fn write(&self,snapshot:&Snapshot) -> crate::Result<()> {
+        static NEXT:AtomicU64 = AtomicU64::new(0);
+        let tmp = self.path.with_file_name(format!("temp-{}-{}.snapshot",std::process::id(),NEXT.fetch_add(1, Ordering::Relaxed)));
+        let res = fs::File::create(&tmp).and_then(|mut file| {
+            file.write_all(&snapshot.encode())?;
+            file.sync_all()
+        }).and_then(|()| fs::rename(&tmp, &self.path));
+        if let Err(err) = res {
+            let _ = fs::remove_file(&tmp);
+            return Err(err.into());
+        }
+        self.last_save.store(unix_time_secs(), Ordering::SeqCst);
+        Ok(())
+    }
+    //已经有SAVE或BGSAVE在进行时返回false
+    pub(crate) fn start_background_save(&self) -> bool {
+        self.bgsave_in_progress.compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst).is_ok()
+    }
+    //序列化和写文件都在blocking线程上做，调用前需要先start_background_save
+    pub(crate) fn background_save(self:&Arc<Self>,snapshot:Snapshot) {
+        let persistence = self.clone();
+        tokio::task::spawn_blocking(move || {
+            match persistence.save(&snapshot) {
+                Ok(()) => info!(path=?persistence.path,"background saving terminated with success"),
+                Err(err) => error!(cause=%err,"background saving error")
+            }
+        });
+    }
+    //文件不存在返回None
+    pub(crate) fn load(&self) -> crate::Result<Option<Snapshot>> {
+        match fs::read(&self.path) {
+            Ok(data) => Ok(Some(Snapshot::decode(&data)?)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into())
+        }
+    }
+}
+
+pub(crate) fn unix_time_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+fn unix_time_secs() -> u64 {
+    unix_time_millis() / 1000
+}
+
+//crc-64-jones，反射形式，初始值0，redis的rdb也用的这个
+const CRC64_POLY:u64 = 0x95ac9329ac4bc9b5;
+const CRC64_TABLE:[u64;256] = {
+    let mut table = [0u64;256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u64;
+        let mut j = 0;
+        while j < 8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ CRC64_POLY } else { crc >> 1 };
+            j += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+pub(crate) fn crc64(mut crc:u64,data:&[u8]) -> u64 {
+    for &b in data {
+        crc = CRC64_TABLE[((crc ^ b as u64) & 0xff) as usize] ^ (crc >> 8);
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::{HashMap, HashSet, VecDeque};
+
+    //重新算crc，构造内容有问题但校验和正确的快照
+    fn seal(mut body:Vec<u8>) -> Vec<u8> {
+        let crc = crc64(0, &body);
+        body.put_u64_le(crc);
+        body
+    }
+
+    fn sample() -> Snapshot {
+        let b = |s:&'static str| Bytes::from_static(s.as_bytes());
+        Snapshot {
+            entries: vec![
+                ("s".to_string(),Value::String(b("v")),Some(1234)),
+                ("l".to_string(),Value::List(Arc::new(VecDeque::from([b("a"),b("")]))),None),
+                ("h".to_string(),Value::Hash(Arc::new(HashMap::from([(b("f"),b("v"))]))),None),
+                ("set".to_string(),Value::Set(Arc::new(HashSet::from([b("m")]))),None),
+                ("z".to_string(),Value::ZSet(Arc::new(vec![(-1.5,b("x")),(2.0,b("y"))])),Some(5))
+            ],
+            functions: b("lib")
+        }
+    }
+
+    #[test]
+    fn crc64_jones() {
+        assert_eq!(crc64(0, b"123456789"), 0xe9c6d914c4b8d9ca);
+        assert_eq!(crc64(0, b""), 0);
+    }
+
+    #[test]
+    fn round_trip() {
+        let data = sample().encode();
+        let snapshot = Snapshot::decode(&data).unwrap();
+        assert_eq!(snapshot.entries.len(), 5);
+        assert_eq!(&snapshot.functions[..], b"lib");
+        assert_eq!(snapshot.encode(), data);
+    }
+
+    #[test]
+    fn rejects_corruption() {
+        let mut data = sample().encode();
+        data[12] ^= 1;
+        assert!(Snapshot::decode(&data).unwrap_err().to_string().contains("checksum"));
+        let data = sample().encode();
+        assert!(Snapshot::decode(&data[..data.len() - 1]).is_err());
+        assert!(Snapshot::decode(b"MYREDIS").is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_body_with_valid_checksum() {
+        let data = sample().encode();
+        let body = &data[..data.len() - 8];
+        //去掉OP_EOF和最后一段
+        for cut in [1, 5, 20] {
+            assert!(Snapshot::decode(&seal(body[..body.len() - cut].to_vec())).is_err(), "cut {}", cut);
+        }
+    }
+
+    #[test]
+    fn forged_zset_length_does_not_preallocate() {
+        let mut body = Vec::new();
+        body.put_slice(MAGIC);
+        body.put_u16(VERSION);
+        body.put_u8(OP_ZSET);
+        put_chunk(&mut body, b"z");
+        body.put_u64(0);
+        body.put_u32(u32::MAX);
+        body.put_u8(OP_EOF);
+        assert!(Snapshot::decode(&seal(body)).is_err());
+    }
+}
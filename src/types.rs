@@ -0,0 +1,287 @@
+use std::{collections::HashMap, hash::Hash};
+use bytes::Bytes;
+use crate::frame::Frame;
+
+//可以作为命令参数的类型，一个值可以展开成多个参数，比如元组和数组
+pub trait ToArgs {
+    fn write_args(&self,out:&mut Vec<Bytes>);
+}
+
+//可以从回复转换出来的类型，错误回复统一转成Err
+pub trait FromReply: Sized {
+    fn from_reply(frame:Frame) -> crate::Result<Self>;
+}
+
+impl<T: ToArgs + ?Sized> ToArgs for &T {
+    fn write_args(&self,out:&mut Vec<Bytes>) {
+        (**self).write_args(out)
+    }
+}
+
+impl ToArgs for str {
+    fn write_args(&self,out:&mut Vec<Bytes>) {
+        out.push(Bytes::copy_from_slice(self.as_bytes()));
+    }
+}
+
+impl ToArgs for String {
+    fn write_args(&self,out:&mut Vec<Bytes>) {
+        self.as_str().write_args(out)
+    }
+}
+
+impl ToArgs for [u8] {
+    fn write_args(&self,out:&mut Vec<Bytes>) {
+        out.push(Bytes::copy_from_slice(self));
+    }
+}
+
+impl ToArgs for Bytes {
+    fn write_args(&self,out:&mut Vec<Bytes>) {
+        out.push(self.clone());
+    }
+}
+
+//整数和浮点数按十进制字符串发送
+macro_rules! number_args {
+    ($($t:ty),*) => {
+        $(impl ToArgs for $t {
+            fn write_args(&self,out:&mut Vec<Bytes>) {
+                out.push(self.to_string().into());
+            }
+        })*
+    };
+}
+number_args!(i8, i16, i32, i64, u16, u32, u64, isize, usize, f32, f64);
+
+//数组的每个元素依次展开，u8没有实现ToArgs，所以&[u8]只会当成一个参数
+impl<T: ToArgs> ToArgs for [T] {
+    fn write_args(&self,out:&mut Vec<Bytes>) {
+        for arg in self {
+            arg.write_args(out);
+        }
+    }
+}
+
+impl<T: ToArgs> ToArgs for Vec<T> {
+    fn write_args(&self,out:&mut Vec<Bytes>) {
+        self.as_slice().write_args(out)
+    }
+}
+
+//None不产生参数，方便写可选的参数
+impl<T: ToArgs> ToArgs for Option<T> {
+    fn write_args(&self,out:&mut Vec<Bytes>) {
+        if let Some(arg) = self {
+            arg.write_args(out);
+        }
+    }
+}
+
+macro_rules! tuple_args {
+    ($($name:ident),*) => {
+        impl<$($name: ToArgs),*> ToArgs for ($($name,)*) {
+            #[allow(non_snake_case)]
+            fn write_args(&self,out:&mut Vec<Bytes>) {
+                let ($($name,)*) = self;
+                $($name.write_args(out);)*
+            }
+        }
+    };
+}
+tuple_args!(A);
+tuple_args!(A, B);
+tuple_args!(A, B, C);
+tuple_args!(A, B, C, D);
+
+impl FromReply for Frame {
+    fn from_reply(frame:Frame) -> crate::Result<Self> {
+        match frame {
+            Frame::Error(msg) => Err(msg.into()),
+            frame => Ok(frame)
+        }
+    }
+}
+
+//OK或者任何非错误的回复
+impl FromReply for () {
+    fn from_reply(frame:Frame) -> crate::Result<Self> {
+        Frame::from_reply(frame).map(|_| ())
+    }
+}
+
+impl FromReply for Bytes {
+    fn from_reply(frame:Frame) -> crate::Result<Self> {
+        match frame {
+            Frame::Bulk(data) => Ok(data),
+            Frame::Simple(s) => Ok(s.into()),
+            Frame::Integer(n) => Ok(n.to_string().into()),
+            Frame::Error(msg) => Err(msg.into()),
+            frame => Err(frame.into_err())
+        }
+    }
+}
+
+impl FromReply for String {
+    fn from_reply(frame:Frame) -> crate::Result<Self> {
+        Ok(String::from_utf8(Bytes::from_reply(frame)?.to_vec())?)
+    }
+}
+
+//整数回复，或者内容是数字的字符串回复(比如GET一个计数器)
+macro_rules! number_reply {
+    ($($t:ty),*) => {
+        $(impl FromReply for $t {
+            fn from_reply(frame:Frame) -> crate::Result<Self> {
+                match frame {
+                    Frame::Integer(n) => Ok(n.try_into()?),
+                    Frame::Bulk(data) => Ok(std::str::from_utf8(&data)?.parse()?),
+                    Frame::Simple(s) => Ok(s.parse()?),
+                    Frame::Error(msg) => Err(msg.into()),
+                    frame => Err(frame.into_err())
+                }
+            }
+        })*
+    };
+}
+number_reply!(i8, i16, i32, i64, u8, u16, u32, u64, isize, usize);
+
+impl FromReply for f64 {
+    fn from_reply(frame:Frame) -> crate::Result<Self> {
+        match frame {
+            Frame::Integer(n) => Ok(n as f64),
+            frame => Ok(String::from_reply(frame)?.parse()?)
+        }
+    }
+}
+
+//整数回复非0为true，OK也算true
+impl FromReply for bool {
+    fn from_reply(frame:Frame) -> crate::Result<Self> {
+        match frame {
+            Frame::Integer(n) => Ok(n != 0),
+            Frame::Simple(s) if s == "OK" => Ok(true),
+            Frame::Null | Frame::NullArray => Ok(false),
+            Frame::Error(msg) => Err(msg.into()),
+            frame => Err(frame.into_err())
+        }
+    }
+}
+
+impl<T: FromReply> FromReply for Option<T> {
+    fn from_reply(frame:Frame) -> crate::Result<Self> {
+        match frame {
+            Frame::Null | Frame::NullArray => Ok(None),
+            frame => T::from_reply(frame).map(Some)
+        }
+    }
+}
+
+//Null当成空数组
+impl<T: FromReply> FromReply for Vec<T> {
+    fn from_reply(frame:Frame) -> crate::Result<Self> {
+        match frame {
+            Frame::Array(items) => items.into_iter().map(T::from_reply).collect(),
+            Frame::Null | Frame::NullArray => Ok(Vec::new()),
+            Frame::Error(msg) => Err(msg.into()),
+            frame => Err(frame.into_err())
+        }
+    }
+}
+
+//[k1, v1, k2, v2, ...]
+impl<K: FromReply + Eq + Hash, V: FromReply> FromReply for HashMap<K,V> {
+    fn from_reply(frame:Frame) -> crate::Result<Self> {
+        let items = match frame {
+            Frame::Array(items) => items,
+            Frame::Null | Frame::NullArray => return Ok(HashMap::new()),
+            Frame::Error(msg) => return Err(msg.into()),
+            frame => return Err(frame.into_err())
+        };
+        if items.len() % 2 != 0 {
+            return Err("odd number of elements in map reply".into());
+        }
+        let mut map = HashMap::with_capacity(items.len() / 2);
+        let mut items = items.into_iter();
+        while let (Some(key),Some(value)) = (items.next(),items.next()) {
+            map.insert(K::from_reply(key)?, V::from_reply(value)?);
+        }
+        Ok(map)
+    }
+}
+
+//元素个数必须和元组一致
+macro_rules! tuple_reply {
+    ($len:expr, $($name:ident),*) => {
+        impl<$($name: FromReply),*> FromReply for ($($name,)*) {
+            fn from_reply(frame:Frame) -> crate::Result<Self> {
+                let items = match frame {
+                    Frame::Array(items) if items.len() == $len => items,
+                    Frame::Error(msg) => return Err(msg.into()),
+                    frame => return Err(frame.into_err())
+                };
+                let mut items = items.into_iter();
+                Ok(($($name::from_reply(items.next().unwrap())?,)*))
+            }
+        }
+    };
+}
+tuple_reply!(1, A);
+tuple_reply!(2, A, B);
+tuple_reply!(3, A, B, C);
+tuple_reply!(4, A, B, C, D);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args<T: ToArgs + ?Sized>(value:&T) -> Vec<Bytes> {
+        let mut out = Vec::new();
+        value.write_args(&mut out);
+        out
+    }
+
+    fn bulk(s:&str) -> Frame {
+        Frame::Bulk(Bytes::copy_from_slice(s.as_bytes()))
+    }
+
+    #[test]
+    fn to_args() {
+        assert_eq!(args("a"), ["a"]);
+        assert_eq!(args(&b"\x00\x01"[..]), [&b"\x00\x01"[..]]);
+        assert_eq!(args(&-5i64), ["-5"]);
+        assert_eq!(args(&1.5f64), ["1.5"]);
+        assert_eq!(args(&("k",1u32,Some("x"))), ["k","1","x"]);
+        assert_eq!(args(&vec![("f1","v1"),("f2","v2")]), ["f1","v1","f2","v2"]);
+        assert!(args(&None::<&str>).is_empty());
+    }
+
+    #[test]
+    fn scalars_from_reply() {
+        assert_eq!(i64::from_reply(Frame::Integer(-3)).unwrap(), -3);
+        assert_eq!(u32::from_reply(bulk("42")).unwrap(), 42);
+        assert!(u8::from_reply(Frame::Integer(300)).is_err());
+        assert!(u64::from_reply(bulk("x")).is_err());
+        assert_eq!(f64::from_reply(bulk("2.5")).unwrap(), 2.5);
+        assert_eq!(String::from_reply(Frame::Simple("OK".into())).unwrap(), "OK");
+        assert!(bool::from_reply(Frame::Simple("OK".into())).unwrap());
+        assert!(!bool::from_reply(Frame::Integer(0)).unwrap());
+        assert_eq!(Option::<String>::from_reply(Frame::Null).unwrap(), None);
+        assert_eq!(Option::<String>::from_reply(bulk("v")).unwrap().as_deref(), Some("v"));
+        assert_eq!(String::from_reply(Frame::Error("ERR x".into())).unwrap_err().to_string(), "ERR x");
+        assert!(String::from_reply(Frame::Null).is_err());
+    }
+
+    #[test]
+    fn collections_from_reply() {
+        let reply = || Frame::Array(vec![bulk("a"),bulk("1"),bulk("b"),bulk("2")]);
+        assert_eq!(Vec::<String>::from_reply(reply()).unwrap(), ["a","1","b","2"]);
+        let map = HashMap::<String,u64>::from_reply(reply()).unwrap();
+        assert_eq!((map["a"],map["b"]), (1,2));
+        assert!(HashMap::<String,u64>::from_reply(Frame::Array(vec![bulk("a")])).is_err());
+        assert!(Vec::<String>::from_reply(Frame::NullArray).unwrap().is_empty());
+        let (key,value):(String,Option<u64>) = FromReply::from_reply(Frame::Array(vec![bulk("k"),Frame::Null])).unwrap();
+        assert_eq!((key.as_str(),value), ("k",None));
+        assert!(<(String,String)>::from_reply(Frame::Array(vec![bulk("k")])).is_err());
+    }
+}
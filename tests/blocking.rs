@@ -0,0 +1,47 @@
+mod support;
+
+use std::{thread, time::Duration};
+
+use bytes::Bytes;
+use my_redis::{blocking_clinet::BlockingClient, client::Reply, config::Config};
+use support::start_server;
+use tokio::runtime::Runtime;
+
+//服务端跑在单独的runtime上，客户端在普通线程里同步调用
+fn server() -> (Runtime,std::net::SocketAddr) {
+    let rt = Runtime::new().unwrap();
+    let addr = rt.block_on(start_server(Config::default()));
+    (rt,addr)
+}
+
+#[test]
+fn blocking_client_from_plain_thread() {
+    let (_rt,addr) = server();
+    thread::spawn(move || {
+        let mut client = BlockingClient::connect(addr).unwrap();
+        client.set("a", Bytes::from("1")).unwrap();
+        assert_eq!(client.get("a").unwrap(), Some(Bytes::from("1")));
+        assert_eq!(client.cmd("DEL").arg("a").arg("missing").query::<u64>().unwrap(), 1);
+        let mut pipeline = client.pipeline();
+        pipeline.set_expires("b", Bytes::from("2"), Duration::from_millis(100)).get("b");
+        let replies:Vec<Reply> = pipeline.execute().unwrap().into_iter().map(Result::unwrap).collect();
+        assert_eq!(replies, vec![Reply::Ok, Reply::Value(Some(Bytes::from("2")))]);
+        thread::sleep(Duration::from_millis(200));
+        assert_eq!(client.get("b").unwrap(), None);
+    }).join().unwrap();
+}
+
+#[test]
+fn subscriber_iterator_yields_messages() {
+    let (_rt,addr) = server();
+    let subscriber = BlockingClient::connect(addr).unwrap().subscribe(vec!["news".to_string()]).unwrap();
+    let publisher = thread::spawn(move || {
+        let mut client = BlockingClient::connect(addr).unwrap();
+        for msg in ["a","b","c"] {
+            assert_eq!(client.publish("news", Bytes::from(msg)).unwrap(), 1);
+        }
+    });
+    let messages:Vec<Bytes> = subscriber.into_iter().take(3).map(|message| message.unwrap().content).collect();
+    assert_eq!(messages, vec![Bytes::from("a"),Bytes::from("b"),Bytes::from("c")]);
+    publisher.join().unwrap();
+}
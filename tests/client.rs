@@ -0,0 +1,252 @@
+mod support;
+
+use std::time::Duration;
+
+use bytes::Bytes;
+use my_redis::{client::{Client, ReconnectPolicy, Timeouts}, frame::Frame, multiplexed_client::MultiplexedClient, pool::{Pool, PoolConfig}};
+use support::{call, connect};
+use tokio::{io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader}, net::{TcpListener, TcpStream}, sync::mpsc};
+
+#[tokio::test]
+async fn pool_closes_connections_left_in_multi() {
+    let (addr,_) = connect().await;
+    let pool = Pool::new(&addr.to_string(), PoolConfig::default());
+    {
+        let mut client = pool.get().await.unwrap();
+        call(&mut client, &["MULTI"]).await.unwrap();
+    }
+    assert_eq!(pool.metrics().closed, 1);
+    assert_eq!(pool.metrics().idle, 0);
+    {
+        let mut client = pool.get().await.unwrap();
+        //新连接不在事务里，命令直接执行
+        assert!(matches!(call(&mut client, &["GET","a"]).await.unwrap(), Frame::Null));
+        call(&mut client, &["MULTI"]).await.unwrap();
+        call(&mut client, &["EXEC"]).await.unwrap();
+    }
+    assert_eq!(pool.metrics().idle, 1);
+}
+
+#[tokio::test]
+async fn pool_closes_connections_left_watching() {
+    let (addr,mut other) = connect().await;
+    let pool = Pool::new(&addr.to_string(), PoolConfig::default());
+    {
+        let mut client = pool.get().await.unwrap();
+        call(&mut client, &["WATCH","a"]).await.unwrap();
+    }
+    assert_eq!(pool.metrics().closed, 1);
+    assert_eq!(pool.metrics().idle, 0);
+    {
+        let mut client = pool.get().await.unwrap();
+        call(&mut client, &["WATCH","a"]).await.unwrap();
+        call(&mut client, &["UNWATCH"]).await.unwrap();
+    }
+    assert_eq!(pool.metrics().idle, 1);
+    //还回去的连接没有在WATCH，别人改了key也不影响下一个事务
+    call(&mut other, &["SET","a","1"]).await.unwrap();
+    {
+        let mut client = pool.get().await.unwrap();
+        call(&mut client, &["MULTI"]).await.unwrap();
+        call(&mut client, &["SET","a","2"]).await.unwrap();
+        assert!(matches!(call(&mut client, &["EXEC"]).await.unwrap(), Frame::Array(_)));
+    }
+    assert_eq!(pool.metrics().idle, 1);
+}
+
+//读一条命令，返回各个参数
+async fn read_command(stream:&mut BufReader<TcpStream>) -> Option<Vec<String>> {
+    let mut line = String::new();
+    if stream.read_line(&mut line).await.ok()? == 0 {
+        return None;
+    }
+    let mut args = Vec::new();
+    for _ in 0..line.trim_end()[1..].parse::<usize>().unwrap() {
+        line.clear();
+        stream.read_line(&mut line).await.unwrap();
+        //参数可能是simple string也可能是bulk string
+        if let Some(arg) = line.strip_prefix('+') {
+            args.push(arg.trim_end().to_string());
+            continue;
+        }
+        let mut arg = vec![0; line.trim_end()[1..].parse::<usize>().unwrap() + 2];
+        stream.read_exact(&mut arg).await.unwrap();
+        arg.truncate(arg.len() - 2);
+        args.push(String::from_utf8(arg).unwrap());
+    }
+    Some(args)
+}
+
+//每个连接收到的命令都发到channel里，第一个连接收到GET时直接断开
+async fn flaky_server() -> (std::net::SocketAddr,mpsc::UnboundedReceiver<(usize,Vec<String>)>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (tx,rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        for conn in 0.. {
+            let (stream,_) = listener.accept().await.unwrap();
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                let mut stream = BufReader::new(stream);
+                while let Some(args) = read_command(&mut stream).await {
+                    let get = args[0] == "GET";
+                    tx.send((conn,args)).unwrap();
+                    if get && conn == 0 {
+                        return;
+                    }
+                    let reply:&[u8] = if get { b"$1\r\nv\r\n" } else { b"+OK\r\n" };
+                    stream.get_mut().write_all(reply).await.unwrap();
+                }
+            });
+        }
+    });
+    (addr,rx)
+}
+
+#[tokio::test]
+async fn reconnect_replays_auth_and_select() {
+    let (addr,mut rx) = flaky_server().await;
+    let mut client = Client::new(addr).await.unwrap();
+    client.set_reconnect_policy(ReconnectPolicy::exponential());
+    client.cmd("AUTH").arg("secret").query::<()>().await.unwrap();
+    client.cmd("SELECT").arg(3).query::<()>().await.unwrap();
+    assert_eq!(client.get("k").await.unwrap(), Some(Bytes::from("v")));
+    let mut commands = Vec::new();
+    while let Ok(command) = rx.try_recv() {
+        commands.push(command);
+    }
+    let expected:Vec<(usize,Vec<&str>)> = vec![
+        (0,vec!["AUTH","secret"]),
+        (0,vec!["SELECT","3"]),
+        (0,vec!["GET","k"]),
+        (1,vec!["AUTH","secret"]),
+        (1,vec!["SELECT","3"]),
+        (1,vec!["GET","k"])
+    ];
+    assert_eq!(commands.iter().map(|(conn,args)| (*conn,args.iter().map(String::as_str).collect())).collect::<Vec<_>>(), expected);
+}
+
+#[tokio::test]
+async fn default_policy_does_not_reconnect() {
+    let (addr,mut rx) = flaky_server().await;
+    let mut client = Client::new(addr).await.unwrap();
+    assert!(client.get("k").await.is_err());
+    assert_eq!(rx.recv().await.unwrap().0, 0);
+    assert!(rx.try_recv().is_err());
+}
+
+//GET key回复key本身，slow晚150ms回复，never不回复
+//stall_first时第一个连接不读也不回，用来让写卡住
+async fn slow_server(stall_first:bool) -> (std::net::SocketAddr,mpsc::UnboundedReceiver<(usize,Vec<String>)>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (tx,rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        for conn in 0.. {
+            let (stream,_) = listener.accept().await.unwrap();
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                let mut stream = BufReader::new(stream);
+                if stall_first && conn == 0 {
+                    return std::future::pending::<()>().await;
+                }
+                while let Some(args) = read_command(&mut stream).await {
+                    let _ = tx.send((conn,args.clone()));
+                    let reply = match (args[0].as_str(),args.get(1).map(String::as_str)) {
+                        ("GET",Some("never")) => continue,
+                        ("GET",Some(key)) => {
+                            if key == "slow" {
+                                tokio::time::sleep(Duration::from_millis(150)).await;
+                            }
+                            format!("${}\r\n{}\r\n",key.len(),key)
+                        }
+                        _ => "+OK\r\n".to_string()
+                    };
+                    stream.get_mut().write_all(reply.as_bytes()).await.unwrap();
+                }
+            });
+        }
+    });
+    (addr,rx)
+}
+
+//读超时之后迟到的回复要丢掉，不能当成下一条命令的回复；一直不来就换连接
+#[tokio::test]
+async fn late_reply_is_not_returned_to_next_command() {
+    let (addr,mut rx) = slow_server(false).await;
+    let timeouts = Timeouts { read: Some(Duration::from_millis(100)), ..Timeouts::default() };
+    let mut client = Client::new_with_timeouts(addr, timeouts).await.unwrap();
+    assert!(client.get("slow").await.is_err());
+    assert_eq!(client.get("a").await.unwrap(), Some(Bytes::from("a")));
+    assert!(client.get("never").await.is_err());
+    assert_eq!(client.get("b").await.unwrap(), Some(Bytes::from("b")));
+    let mut commands = Vec::new();
+    while let Ok((conn,args)) = rx.try_recv() {
+        commands.push((conn,args[1].clone()));
+    }
+    let expected = [(0,"slow"),(0,"a"),(0,"never"),(1,"b")];
+    assert_eq!(commands, expected.map(|(conn,key)| (conn,key.to_string())));
+}
+
+//写到一半被取消，连接上可能留着半条命令，下一条命令要换新连接
+#[tokio::test]
+async fn cancelled_write_reopens_connection() {
+    let (addr,mut rx) = slow_server(true).await;
+    let mut client = Client::new(addr).await.unwrap();
+    let value = Bytes::from(vec![b'x'; 64 << 20]);
+    assert!(tokio::time::timeout(Duration::from_millis(100), client.set("k", value)).await.is_err());
+    assert_eq!(client.get("k").await.unwrap(), Some(Bytes::from("k")));
+    assert_eq!(rx.recv().await.unwrap(), (1,vec!["GET".to_string(),"k".to_string()]));
+}
+
+//很多clone同时读写大value，每个调用方都要拿到自己的回复
+//一边是还没读的大回复、一边是还没写完的大请求时不能卡死
+#[tokio::test]
+async fn multiplexed_clones_get_their_own_replies() {
+    let (addr,_) = connect().await;
+    let client = MultiplexedClient::new(addr).await.unwrap();
+    let big = Bytes::from(vec![b'x'; 8 << 20]);
+    client.set("big", big.clone()).await.unwrap();
+    let mut tasks = Vec::new();
+    for i in 0..16 {
+        let client = client.clone();
+        let big = big.clone();
+        tasks.push(tokio::spawn(async move {
+            let key = format!("key{}",i);
+            let value = Bytes::from(vec![i as u8; 8 << 20]);
+            let (got,set) = tokio::join!(client.get("big"), client.set(&key, value.clone()));
+            assert_eq!(got.unwrap(), Some(big));
+            set.unwrap();
+            assert_eq!(client.get(&key).await.unwrap(), Some(value));
+            assert_eq!(client.del(&[&key]).await.unwrap(), 1);
+        }));
+    }
+    tokio::time::timeout(Duration::from_secs(60), async {
+        for task in tasks {
+            task.await.unwrap();
+        }
+    }).await.unwrap();
+    assert_eq!(client.ping().await.unwrap(), Bytes::from("PONG"));
+}
+
+//连接断开时正在等待回复的调用方都要收到错误，之后的请求也直接失败
+#[tokio::test]
+async fn multiplexed_in_flight_requests_fail_with_connection() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let mut stream = BufReader::new(listener.accept().await.unwrap().0);
+        for _ in 0..3 {
+            read_command(&mut stream).await.unwrap();
+        }
+    });
+    let client = MultiplexedClient::new(addr).await.unwrap();
+    let tasks:Vec<_> = (0..3).map(|i| {
+        let client = client.clone();
+        tokio::spawn(async move { client.get(&i.to_string()).await })
+    }).collect();
+    for task in tasks {
+        assert!(task.await.unwrap().unwrap_err().to_string().starts_with("connection failed"));
+    }
+    assert!(client.get("k").await.is_err());
+}
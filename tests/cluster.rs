@@ -0,0 +1,145 @@
+mod support;
+
+use std::{net::SocketAddr, time::Duration};
+
+use bytes::Bytes;
+use my_redis::{client::Client, cluster_client::ClusterClient, config::Config, frame::Frame};
+use support::{call, call_err, start_server};
+
+async fn cluster_node() -> (SocketAddr,Client) {
+    let mut config = Config::default();
+    config.cluster_enabled = true;
+    let addr = start_server(config).await;
+    (addr,Client::new(addr).await.unwrap())
+}
+
+#[tokio::test]
+async fn unassigned_slot_and_cross_slot() {
+    let (_,mut client) = cluster_node().await;
+    assert!(call_err(&mut client, &["GET","foo"]).await.starts_with("CLUSTERDOWN"));
+    call(&mut client, &["CLUSTER","ADDSLOTSRANGE","0","16383"]).await.unwrap();
+    call(&mut client, &["SET","foo","1"]).await.unwrap();
+    assert!(matches!(call(&mut client, &["CLUSTER","KEYSLOT","foo"]).await.unwrap(), Frame::Integer(12182)));
+    assert!(matches!(call(&mut client, &["CLUSTER","COUNTKEYSINSLOT","12182"]).await.unwrap(), Frame::Integer(1)));
+    assert!(call_err(&mut client, &["DEL","foo","bar"]).await.starts_with("CROSSSLOT"));
+    call(&mut client, &["DEL","{foo}a","{foo}b"]).await.unwrap();
+}
+
+#[tokio::test]
+async fn exec_rechecks_slots_of_queued_commands() {
+    let (addr,mut client) = cluster_node().await;
+    let mut other = Client::new(addr).await.unwrap();
+    call(&mut client, &["CLUSTER","ADDSLOTSRANGE","0","16383"]).await.unwrap();
+    call(&mut client, &["MULTI"]).await.unwrap();
+    call(&mut client, &["SET","foo","1"]).await.unwrap();
+    //排队之后slot不再归本节点
+    call(&mut other, &["CLUSTER","DELSLOTS","12182"]).await.unwrap();
+    assert!(call_err(&mut client, &["EXEC"]).await.starts_with("CLUSTERDOWN"));
+    assert_eq!(call_err(&mut client, &["EXEC"]).await, "ERR EXEC without MULTI");
+    call(&mut other, &["CLUSTER","ADDSLOTS","12182"]).await.unwrap();
+    assert!(matches!(call(&mut client, &["GET","foo"]).await.unwrap(), Frame::Null));
+}
+
+async fn my_id(client:&mut Client) -> String {
+    match call(client, &["CLUSTER","MYID"]).await.unwrap() {
+        Frame::Bulk(id) => String::from_utf8(id.to_vec()).unwrap(),
+        frame => panic!("unexpected {:?}",frame)
+    }
+}
+
+//第一个节点负责所有slot，第二个节点已经通过gossip知道这一点，返回两个节点的地址、连接和id
+async fn two_nodes() -> [(SocketAddr,Client,String);2] {
+    let (source_addr,mut source) = cluster_node().await;
+    let (target_addr,mut target) = cluster_node().await;
+    call(&mut source, &["CLUSTER","ADDSLOTSRANGE","0","16383"]).await.unwrap();
+    call(&mut source, &["CLUSTER","MEET","127.0.0.1",&target_addr.port().to_string()]).await.unwrap();
+    let source_id = my_id(&mut source).await;
+    let target_id = my_id(&mut target).await;
+    while !call_err(&mut target, &["GET","foo"]).await.starts_with("MOVED") {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    [(source_addr,source,source_id),(target_addr,target,target_id)]
+}
+
+//foo和{foo}x都在12182
+#[tokio::test]
+async fn migrate_slot_between_nodes() {
+    let [(source_addr,mut source,source_id),(target_addr,mut target,target_id)] = two_nodes().await;
+    let target_port = target_addr.port().to_string();
+    call(&mut source, &["SET","foo","0"]).await.unwrap();
+    call(&mut source, &["SET","{foo}x","1"]).await.unwrap();
+
+    call(&mut target, &["CLUSTER","SETSLOT","12182","IMPORTING",&source_id]).await.unwrap();
+    call(&mut source, &["CLUSTER","SETSLOT","12182","MIGRATING",&target_id]).await.unwrap();
+    let ask = format!("ASK 12182 127.0.0.1:{}",target_port);
+    assert_eq!(call_err(&mut source, &["GET","{foo}missing"]).await, ask);
+    assert!(call_err(&mut source, &["DEL","foo","{foo}missing"]).await.starts_with("TRYAGAIN"));
+    assert!(call_err(&mut target, &["GET","foo"]).await.starts_with("MOVED"));
+    call(&mut target, &["ASKING"]).await.unwrap();
+    assert!(matches!(call(&mut target, &["GET","foo"]).await.unwrap(), Frame::Null));
+
+    //迁移期间一直有写入，写到key迁走之后收到ASK为止
+    let writer = tokio::spawn(async move {
+        let mut client = Client::new(source_addr).await.unwrap();
+        let mut last = 0;
+        for i in 1.. {
+            match call(&mut client, &["SET","foo",&i.to_string()]).await {
+                Ok(_) => last = i,
+                Err(err) => {
+                    assert!(err.to_string().starts_with("ASK"), "{}", err);
+                    return last;
+                }
+            }
+            tokio::time::sleep(Duration::from_micros(200)).await;
+        }
+        unreachable!()
+    });
+    tokio::time::sleep(Duration::from_millis(5)).await;
+    let migrate = ["MIGRATE","127.0.0.1",&target_port,"","0","1000","REPLACE","KEYS","foo","{foo}x"];
+    loop {
+        match call(&mut source, &migrate).await {
+            Ok(frame) => {
+                assert!(matches!(frame, Frame::Simple(s) if s == "OK"));
+                break;
+            }
+            Err(err) => assert_eq!(err.to_string(), "ERR Keys kept being modified during MIGRATE")
+        }
+    }
+    let last = writer.await.unwrap();
+    assert_eq!(call_err(&mut source, &["GET","foo"]).await, ask);
+    call(&mut target, &["ASKING"]).await.unwrap();
+    assert!(matches!(call(&mut target, &["GET","foo"]).await.unwrap(), Frame::Bulk(v) if v == last.to_string()));
+
+    call(&mut target, &["CLUSTER","SETSLOT","12182","NODE",&target_id]).await.unwrap();
+    call(&mut source, &["CLUSTER","SETSLOT","12182","NODE",&target_id]).await.unwrap();
+    assert_eq!(call_err(&mut source, &["GET","foo"]).await, format!("MOVED 12182 127.0.0.1:{}",target_port));
+    assert!(matches!(call(&mut target, &["GET","{foo}x"]).await.unwrap(), Frame::Bulk(v) if v == "1"));
+    assert!(matches!(call(&mut source, &["CLUSTER","COUNTKEYSINSLOT","12182"]).await.unwrap(), Frame::Integer(0)));
+}
+
+//缓存的映射过期时跟随MOVED，迁移中的slot跟随ASK
+#[tokio::test]
+async fn cluster_client_follows_moved_and_ask() {
+    let [(source_addr,mut source,source_id),(target_addr,mut target,target_id)] = two_nodes().await;
+    let target_port = target_addr.port().to_string();
+    let mut cluster = ClusterClient::new(&[&source_addr.to_string()]).await.unwrap();
+    cluster.cmd("SET").key("foo").arg("1").query::<()>().await.unwrap();
+    cluster.cmd("SET").key("{foo}x").arg("2").query::<()>().await.unwrap();
+    assert!(cluster.cmd("DEL").key(&["foo","bar"][..]).query::<u64>().await.unwrap_err().to_string().starts_with("CROSSSLOT"));
+
+    //foo已经搬到目标节点，源节点回复ASK
+    call(&mut target, &["CLUSTER","SETSLOT","12182","IMPORTING",&source_id]).await.unwrap();
+    call(&mut source, &["CLUSTER","SETSLOT","12182","MIGRATING",&target_id]).await.unwrap();
+    call(&mut source, &["MIGRATE","127.0.0.1",&target_port,"foo","0","1000"]).await.unwrap();
+    assert_eq!(cluster.cmd("GET").key("foo").query::<Option<Bytes>>().await.unwrap(), Some(Bytes::from("1")));
+    assert_eq!(cluster.cmd("GET").key("{foo}x").query::<Option<Bytes>>().await.unwrap(), Some(Bytes::from("2")));
+
+    //迁移完成之后源节点回复MOVED
+    call(&mut source, &["MIGRATE","127.0.0.1",&target_port,"{foo}x","0","1000"]).await.unwrap();
+    call(&mut target, &["CLUSTER","SETSLOT","12182","NODE",&target_id]).await.unwrap();
+    call(&mut source, &["CLUSTER","SETSLOT","12182","NODE",&target_id]).await.unwrap();
+    cluster.cmd("SET").key("foo").arg("3").query::<()>().await.unwrap();
+    assert!(matches!(call(&mut target, &["GET","foo"]).await.unwrap(), Frame::Bulk(v) if v == "3"));
+    assert_eq!(cluster.get("{foo}x").await.unwrap(), Some(Bytes::from("2")));
+    assert_eq!(cluster.cmd("PING").query::<String>().await.unwrap(), "PONG");
+}
@@ -0,0 +1,35 @@
+mod support;
+
+use bytes::Bytes;
+use my_redis::frame::Frame;
+use support::{call, connect};
+
+#[tokio::test]
+async fn dump_and_restore() {
+    let (_,mut client) = connect().await;
+    call(&mut client, &["SET","a","1"]).await.unwrap();
+    let payload:Bytes = client.cmd("DUMP").arg("a").query().await.unwrap();
+    client.cmd("RESTORE").arg("b").arg("0").arg(&payload).query::<()>().await.unwrap();
+    assert!(matches!(call(&mut client, &["GET","b"]).await.unwrap(), Frame::Bulk(v) if v == "1"));
+    let err = client.cmd("RESTORE").arg("b").arg("0").arg(&payload).query::<()>().await.unwrap_err();
+    assert!(err.to_string().starts_with("BUSYKEY"));
+    client.cmd("RESTORE").arg("b").arg("0").arg(&payload).arg("REPLACE").query::<()>().await.unwrap();
+    assert!(matches!(call(&mut client, &["DUMP","missing"]).await.unwrap(), Frame::Null));
+}
+
+#[tokio::test]
+async fn restore_rejects_overflowing_ttl() {
+    let (_,mut client) = connect().await;
+    call(&mut client, &["SET","a","1"]).await.unwrap();
+    let payload:Bytes = client.cmd("DUMP").arg("a").query().await.unwrap();
+    for args in [
+        &["9223372036854775807"][..],
+        &["18446744073709551615"],
+        &["-1"],
+    ] {
+        assert!(client.cmd("RESTORE").arg("b").arg(args[0]).arg(&payload).arg(&args[1..]).query::<()>().await.is_err(), "{:?}", args);
+    }
+    //服务端没有因为溢出panic
+    assert!(matches!(call(&mut client, &["GET","a"]).await.unwrap(), Frame::Bulk(v) if v == "1"));
+    assert!(matches!(call(&mut client, &["GET","b"]).await.unwrap(), Frame::Null));
+}
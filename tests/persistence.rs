@@ -0,0 +1,113 @@
+mod support;
+
+use my_redis::{client::Client, config::Config, frame::Frame};
+use support::{call, start_server, temp_dir};
+
+#[tokio::test]
+async fn snapshot_is_loaded_on_startup() {
+    let mut config = Config::default();
+    config.dir = temp_dir();
+    let mut client = Client::new(start_server(config.clone()).await).await.unwrap();
+    call(&mut client, &["SET","a","1"]).await.unwrap();
+    call(&mut client, &["SET","b","2","EX","100"]).await.unwrap();
+    call(&mut client, &["SAVE"]).await.unwrap();
+
+    let mut client = Client::new(start_server(config).await).await.unwrap();
+    assert!(matches!(call(&mut client, &["GET","a"]).await.unwrap(), Frame::Bulk(v) if v == "1"));
+    assert!(matches!(call(&mut client, &["GET","b"]).await.unwrap(), Frame::Bulk(v) if v == "2"));
+}
+
+//同时发起的SAVE/BGSAVE只有一个在写文件，其余的报错，最后的快照完整并且没有留下临时文件
+#[tokio::test]
+async fn concurrent_saves_do_not_corrupt_snapshot() {
+    let mut config = Config::default();
+    config.dir = temp_dir();
+    let addr = start_server(config.clone()).await;
+    let mut client = Client::new(addr).await.unwrap();
+    for i in 0..1000 {
+        call(&mut client, &["SET",&i.to_string(),&"x".repeat(100)]).await.unwrap();
+    }
+    let mut tasks = Vec::new();
+    for i in 0..8 {
+        tasks.push(tokio::spawn(async move {
+            let mut client = Client::new(addr).await.unwrap();
+            match call(&mut client, &[if i % 2 == 0 { "SAVE" } else { "BGSAVE" }]).await {
+                Ok(_) => {}
+                Err(err) => assert_eq!(err.to_string(), "ERR Background save already in progress")
+            }
+        }));
+    }
+    for task in tasks {
+        task.await.unwrap();
+    }
+    //等后台保存结束
+    while call(&mut client, &["SAVE"]).await.is_err() {
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    let files:Vec<_> = std::fs::read_dir(&config.dir).unwrap().map(|entry| entry.unwrap().file_name().into_string().unwrap()).collect();
+    assert_eq!(files, vec![config.dbfilename.clone()]);
+
+    let mut client = Client::new(start_server(config).await).await.unwrap();
+    assert!(matches!(call(&mut client, &["GET","999"]).await.unwrap(), Frame::Bulk(v) if v.len() == 100));
+}
+
+fn aof_config() -> Config {
+    let mut config = Config::default();
+    config.dir = temp_dir();
+    config.appendonly = true;
+    config
+}
+
+#[tokio::test]
+async fn aof_is_replayed_on_startup() {
+    let config = aof_config();
+    let mut client = Client::new(start_server(config.clone()).await).await.unwrap();
+    call(&mut client, &["SET","a","1"]).await.unwrap();
+    call(&mut client, &["SET","b","2","EX","100"]).await.unwrap();
+    call(&mut client, &["SET","c","3","PX","1"]).await.unwrap();
+    call(&mut client, &["SET","d","4"]).await.unwrap();
+    call(&mut client, &["DEL","d"]).await.unwrap();
+
+    let mut client = Client::new(start_server(config).await).await.unwrap();
+    assert!(matches!(call(&mut client, &["GET","a"]).await.unwrap(), Frame::Bulk(v) if v == "1"));
+    assert!(matches!(call(&mut client, &["GET","b"]).await.unwrap(), Frame::Bulk(v) if v == "2"));
+    assert!(matches!(call(&mut client, &["GET","c"]).await.unwrap(), Frame::Null));
+    assert!(matches!(call(&mut client, &["GET","d"]).await.unwrap(), Frame::Null));
+}
+
+//重写之后文件变小，重写之后的写入也要追加到新文件里
+#[tokio::test]
+async fn bgrewriteaof_compacts_and_keeps_later_writes() {
+    let config = aof_config();
+    let path = config.dir.join(&config.appendfilename);
+    let mut client = Client::new(start_server(config.clone()).await).await.unwrap();
+    for i in 0..100 {
+        call(&mut client, &["SET","a",&i.to_string()]).await.unwrap();
+    }
+    let size = std::fs::metadata(&path).unwrap().len();
+    call(&mut client, &["BGREWRITEAOF"]).await.unwrap();
+    while std::fs::metadata(&path).unwrap().len() >= size {
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    call(&mut client, &["SET","b","2"]).await.unwrap();
+
+    let mut client = Client::new(start_server(config).await).await.unwrap();
+    assert!(matches!(call(&mut client, &["GET","a"]).await.unwrap(), Frame::Bulk(v) if v == "99"));
+    assert!(matches!(call(&mut client, &["GET","b"]).await.unwrap(), Frame::Bulk(v) if v == "2"));
+}
+
+//写到一半崩溃留下的半条命令忽略掉，前面的命令照常加载
+#[tokio::test]
+async fn truncated_aof_tail_is_ignored() {
+    let config = aof_config();
+    let path = config.dir.join(&config.appendfilename);
+    let mut client = Client::new(start_server(config.clone()).await).await.unwrap();
+    call(&mut client, &["SET","a","1"]).await.unwrap();
+    let mut data = std::fs::read(&path).unwrap();
+    data.extend_from_slice(b"*3\r\n$3\r\nSET\r\n$1\r\nb\r\n$1");
+    std::fs::write(&path, data).unwrap();
+
+    let mut client = Client::new(start_server(config).await).await.unwrap();
+    assert!(matches!(call(&mut client, &["GET","a"]).await.unwrap(), Frame::Bulk(v) if v == "1"));
+    assert!(matches!(call(&mut client, &["GET","b"]).await.unwrap(), Frame::Null));
+}
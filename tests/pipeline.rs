@@ -0,0 +1,44 @@
+mod support;
+
+use std::time::Duration;
+
+use my_redis::client::Reply;
+use support::connect;
+
+#[tokio::test]
+async fn pipeline_replies_in_order() {
+    let (_,mut client) = connect().await;
+    let mut pipeline = client.pipeline();
+    pipeline.set("a", "1".into()).get("a").get("missing").del(&["a","b"]).ping();
+    let replies:Vec<Reply> = pipeline.execute().await.unwrap().into_iter().map(Result::unwrap).collect();
+    assert_eq!(replies, vec![
+        Reply::Ok,
+        Reply::Value(Some("1".into())),
+        Reply::Value(None),
+        Reply::Count(1),
+        Reply::Pong("PONG".into()),
+    ]);
+}
+
+#[tokio::test]
+async fn pipeline_set_expires() {
+    let (_,mut client) = connect().await;
+    let mut pipeline = client.pipeline();
+    pipeline.set_expires("a", "1".into(), Duration::from_millis(200)).get("a");
+    let replies = pipeline.execute().await.unwrap();
+    assert_eq!(replies[0].as_ref().unwrap(), &Reply::Ok);
+    assert_eq!(replies[1].as_ref().unwrap(), &Reply::Value(Some("1".into())));
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(client.get("a").await.unwrap(), None);
+}
+
+//key里带\r\n也要原样存取
+#[tokio::test]
+async fn keys_with_crlf() {
+    let (_,mut client) = connect().await;
+    let mut pipeline = client.pipeline();
+    pipeline.set_expires("a\r\nb", "1".into(), Duration::from_secs(10));
+    assert_eq!(pipeline.execute().await.unwrap()[0].as_ref().unwrap(), &Reply::Ok);
+    assert_eq!(client.get("a\r\nb").await.unwrap(), Some("1".into()));
+    assert_eq!(client.get("a").await.unwrap(), None);
+}
@@ -0,0 +1,38 @@
+mod support;
+
+use bytes::Bytes;
+use my_redis::client::Client;
+use support::{call, connect};
+
+#[tokio::test]
+async fn publish_reaches_channel_and_pattern_subscribers() {
+    let (addr,mut client) = connect().await;
+    let mut channel = Client::new(addr).await.unwrap().subscribe(vec!["news".to_string()]).await.unwrap();
+    let mut pattern = Client::new(addr).await.unwrap().psubscribe(vec!["ne*".to_string()]).await.unwrap();
+    assert_eq!(client.publish("news", Bytes::from("hi")).await.unwrap(), 2);
+    let message = channel.next_message().await.unwrap().unwrap();
+    assert_eq!((message.channel.as_str(),&message.content[..],message.pattern), ("news",&b"hi"[..],None));
+    let message = pattern.next_message().await.unwrap().unwrap();
+    assert_eq!(message.pattern.as_deref(), Some("ne*"));
+}
+
+#[tokio::test]
+async fn keyspace_events_follow_config() {
+    let (addr,mut client) = connect().await;
+    let mut subscriber = Client::new(addr).await.unwrap()
+        .psubscribe(vec!["__key*__:*".to_string()]).await.unwrap();
+    call(&mut client, &["CONFIG","SET","notify-keyspace-events","KEA"]).await.unwrap();
+    client.set("k", Bytes::from("v")).await.unwrap();
+    client.del(&["k"]).await.unwrap();
+    let mut events = Vec::new();
+    for _ in 0..4 {
+        let message = subscriber.next_message().await.unwrap().unwrap();
+        events.push((message.channel,String::from_utf8(message.content.to_vec()).unwrap()));
+    }
+    assert_eq!(events, [
+        ("__keyspace@0__:k".to_string(),"set".to_string()),
+        ("__keyevent@0__:set".to_string(),"k".to_string()),
+        ("__keyspace@0__:k".to_string(),"del".to_string()),
+        ("__keyevent@0__:del".to_string(),"k".to_string())
+    ]);
+}
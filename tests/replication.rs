@@ -0,0 +1,77 @@
+mod support;
+
+use std::time::Duration;
+use my_redis::{client::Client, config::Config, frame::Frame};
+use support::{call, call_err, start_server};
+use tokio::{io::{AsyncBufReadExt, AsyncWriteExt, BufReader}, net::TcpStream, time};
+
+#[tokio::test]
+async fn replica_follows_leader() {
+    let leader_addr = start_server(Config::default()).await;
+    let mut leader = Client::new(leader_addr).await.unwrap();
+    call(&mut leader, &["SET","before","1"]).await.unwrap();
+    let mut config = Config::default();
+    config.replicaof = Some((leader_addr.ip().to_string(),leader_addr.port()));
+    let mut replica = Client::new(start_server(config).await).await.unwrap();
+    call(&mut leader, &["SET","after","2"]).await.unwrap();
+    time::timeout(Duration::from_secs(10), async {
+        loop {
+            let before = call(&mut replica, &["GET","before"]).await.unwrap();
+            let after = call(&mut replica, &["GET","after"]).await.unwrap();
+            if matches!(before, Frame::Bulk(_)) && matches!(after, Frame::Bulk(_)) {
+                break;
+            }
+            time::sleep(Duration::from_millis(20)).await;
+        }
+    }).await.unwrap();
+    assert!(call_err(&mut replica, &["SET","x","1"]).await.starts_with("READONLY"));
+}
+
+#[tokio::test]
+async fn wait_counts_acknowledging_replicas() {
+    let leader_addr = start_server(Config::default()).await;
+    let mut leader = Client::new(leader_addr).await.unwrap();
+    let mut config = Config::default();
+    config.replicaof = Some((leader_addr.ip().to_string(),leader_addr.port()));
+    let mut replica = Client::new(start_server(config).await).await.unwrap();
+    call(&mut leader, &["SET","a","1"]).await.unwrap();
+    //等副本完成全量同步并连上复制流
+    time::timeout(Duration::from_secs(10), async {
+        while !matches!(call(&mut replica, &["GET","a"]).await.unwrap(), Frame::Bulk(_)) {
+            time::sleep(Duration::from_millis(20)).await;
+        }
+    }).await.unwrap();
+
+    call(&mut leader, &["SET","b","2"]).await.unwrap();
+    assert!(matches!(call(&mut leader, &["WAIT","1","5000"]).await.unwrap(), Frame::Integer(1)));
+    //只有一个副本，等到超时之后返回已经确认的数量
+    let start = time::Instant::now();
+    call(&mut leader, &["SET","c","3"]).await.unwrap();
+    assert!(matches!(call(&mut leader, &["WAIT","2","200"]).await.unwrap(), Frame::Integer(1)));
+    assert!(start.elapsed() >= Duration::from_millis(200));
+    assert!(matches!(call(&mut replica, &["GET","c"]).await.unwrap(), Frame::Bulk(v) if v == "3"));
+}
+
+//发一条PSYNC，返回第一行回复
+async fn psync(addr:std::net::SocketAddr,replid:&str,offset:&str) -> String {
+    let mut stream = BufReader::new(TcpStream::connect(addr).await.unwrap());
+    let cmd = format!("*3\r\n$5\r\nPSYNC\r\n${}\r\n{}\r\n${}\r\n{}\r\n",replid.len(),replid,offset.len(),offset);
+    stream.get_mut().write_all(cmd.as_bytes()).await.unwrap();
+    let mut line = String::new();
+    stream.read_line(&mut line).await.unwrap();
+    line.trim_end().to_string()
+}
+
+#[tokio::test]
+async fn psync_with_out_of_range_offset_falls_back_to_full_resync() {
+    let addr = start_server(Config::default()).await;
+    let reply = psync(addr, "?", "-1").await;
+    assert!(reply.starts_with("+FULLRESYNC "), "{}", reply);
+    let replid = reply.split(' ').nth(1).unwrap().to_string();
+    for offset in ["-9223372036854775808", "0", "-1", "9223372036854775807"] {
+        let reply = psync(addr, &replid, offset).await;
+        assert!(reply.starts_with("+FULLRESYNC "), "offset {}: {}", offset, reply);
+    }
+    let mut client = Client::new(addr).await.unwrap();
+    assert!(matches!(call(&mut client, &["PING"]).await.unwrap(), Frame::Simple(s) if s == "PONG"));
+}
@@ -0,0 +1,68 @@
+mod support;
+
+use std::collections::HashSet;
+use my_redis::client::Client;
+use support::{call, connect};
+
+async fn scan_all(client:&mut Client,options:&[&str],mut on_batch:impl FnMut(usize)) -> Vec<String> {
+    let mut cursor = "0".to_string();
+    let mut keys = Vec::new();
+    let mut batches = 0;
+    loop {
+        let (next,batch):(String,Vec<String>) = client.cmd("SCAN").arg(&cursor).arg(options).query().await.unwrap();
+        keys.extend(batch);
+        batches += 1;
+        on_batch(batches);
+        if next == "0" {
+            return keys;
+        }
+        cursor = next;
+    }
+}
+
+#[tokio::test]
+async fn scan_returns_every_surviving_key_once() {
+    let (addr,mut client) = connect().await;
+    let mut other = Client::new(addr).await.unwrap();
+    for i in 0..500 {
+        call(&mut client, &["SET",&format!("k{}",i),"v"]).await.unwrap();
+    }
+    let mut deleted = false;
+    let mut batches = 0;
+    let keys = scan_all(&mut client, &["COUNT","17"], |n| batches = n).await;
+    assert!(batches > 1);
+    assert_eq!(keys.iter().collect::<HashSet<_>>().len(), keys.len());
+    assert_eq!(keys.len(), 500);
+
+    //遍历过程中删掉一半，剩下的一定都会返回
+    let mut cursor = "0".to_string();
+    let mut seen = HashSet::new();
+    loop {
+        let (next,batch):(String,Vec<String>) = client.cmd("SCAN").arg(&cursor).arg(&["COUNT","17"][..]).query().await.unwrap();
+        seen.extend(batch);
+        if !deleted {
+            for i in (0..500).step_by(2) {
+                call(&mut other, &["DEL",&format!("k{}",i)]).await.unwrap();
+            }
+            deleted = true;
+        }
+        if next == "0" {
+            break;
+        }
+        cursor = next;
+    }
+    assert!((1..500).step_by(2).all(|i| seen.contains(&format!("k{}",i))));
+}
+
+#[tokio::test]
+async fn scan_match_and_type() {
+    let (_,mut client) = connect().await;
+    call(&mut client, &["SET","user:1","a"]).await.unwrap();
+    call(&mut client, &["SET","user:2","b"]).await.unwrap();
+    call(&mut client, &["SET","other","c"]).await.unwrap();
+    let mut keys = scan_all(&mut client, &["MATCH","user:*"], |_| {}).await;
+    keys.sort();
+    assert_eq!(keys, ["user:1","user:2"]);
+    assert_eq!(scan_all(&mut client, &["TYPE","string"], |_| {}).await.len(), 3);
+    assert!(scan_all(&mut client, &["TYPE","list"], |_| {}).await.is_empty());
+}
@@ -0,0 +1,89 @@
+mod support;
+
+use std::time::{Duration, Instant};
+use my_redis::{client::Client, config::Config, frame::Frame};
+use support::{call, call_err, connect, start_server};
+
+#[tokio::test]
+async fn eval_passes_keys_and_args() {
+    let (_,mut client) = connect().await;
+    let script = "redis.call('SET', KEYS[1], ARGV[1]) return {KEYS[1], redis.call('GET', KEYS[1])}";
+    match call(&mut client, &["EVAL",script,"1","k","v"]).await.unwrap() {
+        Frame::Array(v) => {
+            assert!(matches!(&v[0], Frame::Bulk(k) if k == "k"));
+            assert!(matches!(&v[1], Frame::Bulk(k) if k == "v"));
+        }
+        frame => panic!("unexpected {:?}",frame)
+    }
+}
+
+#[tokio::test]
+async fn eval_validates_numkeys() {
+    let (_,mut client) = connect().await;
+    assert_eq!(call_err(&mut client, &["EVAL","return 1","-1"]).await, "ERR Number of keys can't be negative");
+    assert_eq!(call_err(&mut client, &["EVAL","return 1","2","a"]).await, "ERR Number of keys can't be greater than number of args");
+    assert!(call_err(&mut client, &["EVAL","return 1","99999999999999999999"]).await.starts_with("ERR"));
+    assert!(matches!(call(&mut client, &["EVAL","return 1","0"]).await.unwrap(), Frame::Integer(1)));
+}
+
+#[tokio::test]
+async fn sandbox_blocks_escapes() {
+    let (_,mut client) = connect().await;
+    for script in [
+        "return io.open('/etc/passwd')",
+        "return os.execute('true')",
+        "return loadstring('return 1')()",
+        "return load('return 1')()",
+        "return dofile('/etc/passwd')",
+        "rawset(_G, 'x', 1)",
+        "_G.x = 1",
+        "string.rep = nil",
+        "getmetatable('').__index = nil",
+        "redis.call = nil",
+    ] {
+        call_err(&mut client, &["EVAL",script,"0"]).await;
+    }
+    assert!(matches!(call(&mut client, &["EVAL","return string.rep('a', 2)","0"]).await.unwrap(), Frame::Bulk(v) if v == "aa"));
+}
+
+#[tokio::test]
+async fn globals_do_not_leak_between_scripts() {
+    let (_,mut client) = connect().await;
+    call(&mut client, &["EVAL","leaked = 1 return 1","0"]).await.unwrap();
+    assert!(matches!(call(&mut client, &["EVAL","return leaked","0"]).await.unwrap(), Frame::Null));
+    call(&mut client, &["EVAL","return 1","1","a"]).await.unwrap();
+    assert!(matches!(call(&mut client, &["EVAL","return #KEYS","0"]).await.unwrap(), Frame::Integer(0)));
+}
+
+#[tokio::test]
+async fn evalsha_uses_loaded_script() {
+    let (_,mut client) = connect().await;
+    let sha = match call(&mut client, &["SCRIPT","LOAD","return ARGV[1]"]).await.unwrap() {
+        Frame::Bulk(sha) => String::from_utf8(sha.to_vec()).unwrap(),
+        frame => panic!("unexpected {:?}",frame)
+    };
+    assert!(matches!(call(&mut client, &["EVALSHA",&sha,"0","x"]).await.unwrap(), Frame::Bulk(v) if v == "x"));
+    call(&mut client, &["SCRIPT","FLUSH"]).await.unwrap();
+    assert!(call_err(&mut client, &["EVALSHA",&sha,"0","x"]).await.starts_with("NOSCRIPT"));
+}
+
+#[tokio::test]
+async fn fcall_runs_loaded_function() {
+    let (_,mut client) = connect().await;
+    let code = "#!lua name=lib\nredis.register_function('echo', function(keys, args) return args[1] end)";
+    assert!(matches!(call(&mut client, &["FUNCTION","LOAD",code]).await.unwrap(), Frame::Bulk(v) if v == "lib"));
+    assert!(matches!(call(&mut client, &["FCALL","echo","0","x"]).await.unwrap(), Frame::Bulk(v) if v == "x"));
+    call_err(&mut client, &["FUNCTION","LOAD",code]).await;
+    call(&mut client, &["FUNCTION","LOAD","REPLACE",code]).await.unwrap();
+}
+
+#[tokio::test]
+async fn function_load_times_out_on_infinite_loop() {
+    let mut config = Config::default();
+    config.script_timeout = Duration::from_millis(100);
+    let mut client = Client::new(start_server(config).await).await.unwrap();
+    let start = Instant::now();
+    call_err(&mut client, &["FUNCTION","LOAD","#!lua name=spin\nwhile true do end"]).await;
+    assert!(start.elapsed() < Duration::from_secs(5));
+    assert!(matches!(call(&mut client, &["PING"]).await.unwrap(), Frame::Simple(s) if s == "PONG"));
+}
@@ -0,0 +1,31 @@
+#![cfg(feature = "serde")]
+mod support;
+
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use support::connect;
+
+#[derive(Debug,PartialEq,Serialize,Deserialize)]
+struct Point {
+    x:i64,
+    y:i64
+}
+
+#[tokio::test]
+async fn json_and_bincode_values() {
+    let (_,mut client) = connect().await;
+    let point = Point { x: 1, y: -2 };
+    client.set_json("json", &point).await.unwrap();
+    assert_eq!(client.get_json::<Point>("json").await.unwrap(), Some(point));
+    client.set_bincode("bin", &vec![1u8, 2, 3]).await.unwrap();
+    assert_eq!(client.get_bincode::<Vec<u8>>("bin").await.unwrap(), Some(vec![1, 2, 3]));
+    assert_eq!(client.get_json::<Point>("missing").await.unwrap(), None);
+    assert!(client.get_json::<Point>("bin").await.is_err());
+}
+
+#[tokio::test]
+async fn query_as_converts_replies() {
+    let (_,mut client) = connect().await;
+    let reply:HashMap<String,i64> = client.cmd("EVAL").arg("return {'a', '1', 'b', '2'}").arg(0).query_as().await.unwrap();
+    assert_eq!(reply, HashMap::from([("a".to_string(),1),("b".to_string(),2)]));
+}
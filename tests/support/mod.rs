@@ -0,0 +1,41 @@
+#![allow(dead_code)]
+use std::{net::SocketAddr, path::{Path, PathBuf}, sync::atomic::{AtomicUsize, Ordering}};
+use my_redis::{client::Client, config::Config, frame::Frame, server};
+use tokio::net::TcpListener;
+
+//每次返回一个新的空目录
+pub fn temp_dir() -> PathBuf {
+    static NEXT:AtomicUsize = AtomicUsize::new(0);
+    let dir = std::env::temp_dir().join(format!("my-redis-test-{}-{}",std::process::id(),NEXT.fetch_add(1, Ordering::Relaxed)));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+//在随机端口上启动服务端，没有指定dir时用单独的临时目录
+pub async fn start_server(mut config:Config) -> SocketAddr {
+    if config.dir == Path::new(".") {
+        config.dir = temp_dir();
+    }
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(server::run(listener, config, std::future::pending::<()>()));
+    addr
+}
+
+pub async fn connect() -> (SocketAddr,Client) {
+    let addr = start_server(Config::default()).await;
+    (addr,Client::new(addr).await.unwrap())
+}
+
+//错误回复转成Err，内容是错误信息
+pub async fn call(client:&mut Client,args:&[&str]) -> my_redis::Result<Frame> {
+    let (name,rest) = args.split_first().unwrap();
+    client.cmd(name).arg(rest).query().await
+}
+
+pub async fn call_err(client:&mut Client,args:&[&str]) -> String {
+    match call(client, args).await {
+        Ok(frame) => panic!("{:?} should fail, got {:?}",args,frame),
+        Err(err) => err.to_string()
+    }
+}
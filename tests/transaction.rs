@@ -0,0 +1,126 @@
+mod support;
+
+use my_redis::{client::Client, frame::Frame};
+use support::{call, call_err, connect};
+
+#[tokio::test]
+async fn exec_runs_queued_commands() {
+    let (_,mut client) = connect().await;
+    assert!(matches!(call(&mut client, &["MULTI"]).await.unwrap(), Frame::Simple(s) if s == "OK"));
+    assert!(matches!(call(&mut client, &["SET","a","1"]).await.unwrap(), Frame::Simple(s) if s == "QUEUED"));
+    assert!(matches!(call(&mut client, &["GET","a"]).await.unwrap(), Frame::Simple(s) if s == "QUEUED"));
+    match call(&mut client, &["EXEC"]).await.unwrap() {
+        Frame::Array(replies) => {
+            assert_eq!(replies.len(), 2);
+            assert!(matches!(&replies[1], Frame::Bulk(v) if v == "1"));
+        }
+        frame => panic!("unexpected {:?}",frame)
+    }
+}
+
+#[tokio::test]
+async fn discard_drops_queued_commands() {
+    let (_,mut client) = connect().await;
+    call(&mut client, &["MULTI"]).await.unwrap();
+    call(&mut client, &["SET","a","1"]).await.unwrap();
+    call(&mut client, &["DISCARD"]).await.unwrap();
+    assert!(matches!(call(&mut client, &["GET","a"]).await.unwrap(), Frame::Null));
+    assert_eq!(call_err(&mut client, &["EXEC"]).await, "ERR EXEC without MULTI");
+}
+
+#[tokio::test]
+async fn nested_multi_is_rejected() {
+    let (_,mut client) = connect().await;
+    call(&mut client, &["MULTI"]).await.unwrap();
+    assert_eq!(call_err(&mut client, &["MULTI"]).await, "ERR MULTI calls can not be nested");
+}
+
+#[tokio::test]
+async fn queue_error_aborts_exec() {
+    let (_,mut client) = connect().await;
+    call(&mut client, &["MULTI"]).await.unwrap();
+    call(&mut client, &["SET","a","1"]).await.unwrap();
+    call_err(&mut client, &["SET","b"]).await;
+    assert!(call_err(&mut client, &["EXEC"]).await.starts_with("EXECABORT"));
+    assert!(matches!(call(&mut client, &["GET","a"]).await.unwrap(), Frame::Null));
+}
+
+#[tokio::test]
+async fn set_rejects_invalid_expire_times() {
+    let (_,mut client) = connect().await;
+    for args in [
+        &["SET","a","1","EX","0"][..],
+        &["SET","a","1","EX","-1"],
+        &["SET","a","1","PX","0"],
+        &["SET","a","1","EX","9223372036854775807"],
+        &["SET","a","1","PX","9223372036854775807"],
+    ] {
+        assert_eq!(call_err(&mut client, args).await, "ERR invalid expire time in 'set' command", "{:?}", args);
+    }
+    assert!(matches!(call(&mut client, &["GET","a"]).await.unwrap(), Frame::Null));
+    call(&mut client, &["SET","a","1","EX","100"]).await.unwrap();
+    assert!(matches!(call(&mut client, &["GET","a"]).await.unwrap(), Frame::Bulk(v) if v == "1"));
+}
+
+#[tokio::test]
+async fn set_rejects_unknown_or_repeated_options() {
+    let (_,mut client) = connect().await;
+    for args in [
+        &["SET","a","1","EX","10","NX"][..],
+        &["SET","a","1","NX"],
+        &["SET","a","1","EX","10","PX","100"],
+    ] {
+        assert_eq!(call_err(&mut client, args).await, "ERR syntax error", "{:?}", args);
+    }
+    assert!(matches!(call(&mut client, &["GET","a"]).await.unwrap(), Frame::Null));
+}
+
+#[tokio::test]
+async fn watched_key_modified_returns_null_array() {
+    let (addr,mut client) = connect().await;
+    let mut other = Client::new(addr).await.unwrap();
+    call(&mut client, &["SET","a","1"]).await.unwrap();
+    call(&mut client, &["WATCH","a"]).await.unwrap();
+    call(&mut other, &["SET","a","2"]).await.unwrap();
+    call(&mut client, &["MULTI"]).await.unwrap();
+    call(&mut client, &["SET","a","3"]).await.unwrap();
+    assert!(matches!(call(&mut client, &["EXEC"]).await.unwrap(), Frame::NullArray));
+    assert!(matches!(call(&mut client, &["GET","a"]).await.unwrap(), Frame::Bulk(v) if v == "2"));
+}
+
+#[tokio::test]
+async fn watched_missing_key_created_and_deleted_aborts() {
+    let (addr,mut client) = connect().await;
+    let mut other = Client::new(addr).await.unwrap();
+    call(&mut client, &["WATCH","a"]).await.unwrap();
+    call(&mut other, &["SET","a","1"]).await.unwrap();
+    call(&mut other, &["DEL","a"]).await.unwrap();
+    call(&mut client, &["MULTI"]).await.unwrap();
+    call(&mut client, &["SET","b","1"]).await.unwrap();
+    assert!(matches!(call(&mut client, &["EXEC"]).await.unwrap(), Frame::NullArray));
+    assert!(matches!(call(&mut client, &["GET","b"]).await.unwrap(), Frame::Null));
+}
+
+#[tokio::test]
+async fn unchanged_watch_and_unwatch_let_exec_run() {
+    let (addr,mut client) = connect().await;
+    let mut other = Client::new(addr).await.unwrap();
+    call(&mut client, &["WATCH","a"]).await.unwrap();
+    call(&mut client, &["MULTI"]).await.unwrap();
+    call(&mut client, &["SET","a","1"]).await.unwrap();
+    assert!(matches!(call(&mut client, &["EXEC"]).await.unwrap(), Frame::Array(_)));
+
+    call(&mut client, &["WATCH","a"]).await.unwrap();
+    call(&mut other, &["SET","a","2"]).await.unwrap();
+    call(&mut client, &["UNWATCH"]).await.unwrap();
+    call(&mut client, &["MULTI"]).await.unwrap();
+    call(&mut client, &["SET","a","3"]).await.unwrap();
+    assert!(matches!(call(&mut client, &["EXEC"]).await.unwrap(), Frame::Array(_)));
+}
+
+#[tokio::test]
+async fn watch_inside_multi_is_rejected() {
+    let (_,mut client) = connect().await;
+    call(&mut client, &["MULTI"]).await.unwrap();
+    assert_eq!(call_err(&mut client, &["WATCH","a"]).await, "ERR WATCH inside MULTI is not allowed");
+}